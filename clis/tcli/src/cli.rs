@@ -4,6 +4,8 @@ use flexi_logger::{DeferredNow, FileSpec, Logger, Record, WriteMode};
 use ipnetwork::IpNetwork;
 use log::error;
 use serde::{Deserialize, Serialize};
+#[cfg(unix)]
+use telio::control::ClientError as ControlError;
 use telio::crypto::{PublicKey, SecretKey};
 use telio::device::{Device, DeviceConfig};
 use telio_model::api_config::Features;
@@ -26,7 +28,7 @@ use std::{
     net::{IpAddr, Ipv4Addr, SocketAddr},
     sync::{
         mpsc::{self, Receiver},
-        Arc,
+        Arc, Mutex, MutexGuard, PoisonError,
     },
 };
 
@@ -80,10 +82,22 @@ pub enum Error {
 
     #[error(transparent)]
     Codec(#[from] CodecError),
+
+    #[cfg(unix)]
+    #[error(transparent)]
+    Control(#[from] ControlError),
+
+    #[cfg(unix)]
+    #[error("command needs control socket of tcli --listen")]
+    NotSupported,
+
+    #[cfg(unix)]
+    #[error("{0}")]
+    Remote(String),
 }
 
 pub struct Cli {
-    telio: Device,
+    telio: Arc<Mutex<Device>>,
     resp: Receiver<Resp>,
     auth: Option<OAuth>,
     nord: Option<Nord>,
@@ -108,12 +122,16 @@ struct MeshConf {
 
 #[derive(Parser)]
 #[clap(help_template = "commands:\n{subcommands}")]
-enum Cmd {
+pub(crate) enum Cmd {
     #[clap(subcommand)]
     Status(StatusCmd),
     #[clap(subcommand)]
     Login(LoginCmd),
-    Events,
+    #[clap(about = "Print events received since the last call")]
+    Events {
+        #[clap(subcommand)]
+        cmd: Option<EventsCmd>,
+    },
     #[clap(subcommand)]
     Dev(DevCmd),
     #[clap(subcommand)]
@@ -131,11 +149,28 @@ enum Cmd {
 
 #[derive(Parser)]
 #[clap(about = "Status about telio, nodes, derp")]
-enum StatusCmd {
+pub(crate) enum StatusCmd {
     #[clap(about = "Print status as json string")]
     Simple,
     #[clap(about = "Print status as formatted json")]
     Pretty,
+    #[clap(about = "Print metrics in OpenMetrics text format")]
+    Metrics,
+    #[clap(about = "Print meshnet connectivity matrix collected by nurse")]
+    Matrix,
+}
+
+#[derive(Parser)]
+pub(crate) enum EventsCmd {
+    #[clap(about = "Print events and state transitions recorded in the journal")]
+    History {
+        /// Only entries recorded at or after this time, in milliseconds since UNIX epoch
+        #[clap(long, default_value = "0")]
+        since: u64,
+        /// Comma separated kinds of entries: relay, node, error, state
+        #[clap(long, default_value = "")]
+        filter: String,
+    },
 }
 
 #[derive(Parser)]
@@ -151,10 +186,10 @@ enum LoginCmd {
 
 #[derive(Parser)]
 #[clap(about = "Direct device control")]
-enum DevCmd {
+pub(crate) enum DevCmd {
     Start {
         /// Select adapter type to run
-        #[clap(possible_values = &["boringtun", "wireguard-go", "wireguard-nt", "linux-native", ""], default_value ="")]
+        #[clap(possible_values = &["boringtun", "wireguard-go", "wireguard-nt", "linux-native", "userspace", ""], default_value ="")]
         adapter: String,
         /// Name of device
         #[clap(default_value = DEFAULT_TUNNEL_NAME)]
@@ -188,7 +223,7 @@ enum VpnCmd {
 }
 
 #[derive(Parser)]
-enum MeshCmd {
+pub(crate) enum MeshCmd {
     /// Turn mesnet on
     On {
         name: String,
@@ -205,11 +240,15 @@ enum MeshCmd {
     Config {
         mesh_config: String,
     },
+    /// Report why direct connection to peer is (not) established, as json
+    Diag {
+        public_key: PublicKey,
+    },
     Off,
 }
 
 #[derive(Parser)]
-enum DnsCmd {
+pub(crate) enum DnsCmd {
     /// Turn on DNS module
     On { forward_servers: Vec<String> },
     /// Turn off DNS module
@@ -323,7 +362,7 @@ impl Cli {
         .unwrap();
 
         Cli {
-            telio,
+            telio: Arc::new(Mutex::new(telio)),
             resp,
             auth: None,
             nord: None,
@@ -332,6 +371,15 @@ impl Cli {
         }
     }
 
+    /// Device run by the cli, shared with control server of `--listen`
+    pub fn device(&self) -> Arc<Mutex<Device>> {
+        self.telio.clone()
+    }
+
+    fn telio(&self) -> MutexGuard<'_, Device> {
+        self.telio.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
     pub fn exec(&mut self, cmd: &str) -> Vec<Resp> {
         let mut res = Vec::new();
         let mut args = cli_try![shellwords::split(cmd)];
@@ -355,7 +403,18 @@ impl Cli {
         match cmd {
             Cmd::Status(cmd) => cli_res!(res; (j self.report(cmd))),
             Cmd::Login(cmd) => cli_res!(res; (j self.exec_login(cmd))),
-            Cmd::Events => cli_res!(res; (j self.resp.try_iter().collect())),
+            Cmd::Events { cmd: None } => cli_res!(res; (j self.resp.try_iter().collect())),
+            Cmd::Events {
+                cmd: Some(EventsCmd::History { since, filter }),
+            } => {
+                let kinds: Vec<&str> = filter
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|kind| !kind.is_empty())
+                    .collect();
+                let history = cli_try!(res; self.telio().get_event_history(since, &kinds));
+                cli_res!(res; (i "{}", history));
+            }
             Cmd::Dev(cmd) => cli_res!(res; (j self.exec_dev(cmd))),
             Cmd::Vpn(cmd) => cli_res!(res; (j self.exec_vpn(cmd))),
             Cmd::Mesh(cmd) => cli_res!(res; (j self.exec_mesh(cmd))),
@@ -408,6 +467,7 @@ impl Cli {
                     "wireguard-go" => AdapterType::WireguardGo,
                     "linux-native" => AdapterType::LinuxNativeWg,
                     "wireguard-nt" => AdapterType::WindowsNativeWg,
+                    "userspace" => AdapterType::UserspaceWg,
                     "" => AdapterType::default(),
                     _ => unreachable!(),
                 };
@@ -422,7 +482,7 @@ impl Cli {
                 endpoint,
                 allowed_ips,
             } => {
-                if !self.telio.is_running() {
+                if !self.telio().is_running() {
                     cli_res!(res; (e Error::NotStarted));
                 }
                 let node = ExitNode {
@@ -435,34 +495,34 @@ impl Cli {
                     },
                 };
                 cli_res!(res; (i "connecting to node:\n{:#?}", node));
-                cli_try!(res; self.telio.connect_exit_node(&node));
+                cli_try!(res; self.telio().connect_exit_node(&node));
             }
             Dis { public_key } => {
-                if !self.telio.is_running() {
+                if !self.telio().is_running() {
                     cli_res!(res; (e Error::NotStarted));
                 }
 
                 cli_res!(res; (i "stopping peer {}", public_key));
-                cli_try!(self.telio.disconnect_exit_node(&public_key));
+                cli_try!(self.telio().disconnect_exit_node(&public_key));
             }
             Disall => {
-                if !self.telio.is_running() {
+                if !self.telio().is_running() {
                     cli_res!(res; (e Error::NotStarted));
                 }
 
                 cli_res!(res; (i "stopping all peers"));
-                cli_try!(self.telio.disconnect_exit_nodes());
+                cli_try!(self.telio().disconnect_exit_nodes());
             }
             NotifyNetChange => {
-                if !self.telio.is_running() {
+                if !self.telio().is_running() {
                     cli_res!(res; (e Error::NotStarted));
                 }
 
                 cli_res!(res; (i "notify net change"));
-                cli_try!(self.telio.notify_network_change());
+                cli_try!(self.telio().notify_network_change());
             }
             Stop => {
-                self.telio.stop();
+                self.telio().stop();
                 cli_res!(res; (i "stopped telio."));
             }
         }
@@ -476,7 +536,7 @@ impl Cli {
             On => {
                 let nord = cli_try!(res; self.nord.as_ref().ok_or(Error::NeedsLogin));
                 let server = cli_try!(res; nord.find_server());
-                if !self.telio.is_running() {
+                if !self.telio().is_running() {
                     let adapter = if cfg!(windows) {
                         // TODO: Use "wireguard-nt" as default later
                         "wireguard-go"
@@ -489,10 +549,10 @@ impl Cli {
                         name: DEFAULT_TUNNEL_NAME.to_owned(),
                     })));
                 }
-                cli_try!(self.telio.connect_exit_node(&server));
+                cli_try!(self.telio().connect_exit_node(&server));
             }
             Off => {
-                cli_try!(self.telio.disconnect_exit_nodes());
+                cli_try!(self.telio().disconnect_exit_nodes());
             }
         }
         res
@@ -518,7 +578,7 @@ impl Cli {
 
                 let private_key = conf.sk;
                 cli_try!(res; self.start_telio(name, private_key, AdapterType::default(), &mut res));
-                cli_try!(res; self.telio.set_config(&Some(meshmap)));
+                cli_try!(res; self.telio().set_config(&Some(meshmap)));
                 cli_res!(res; (i "started meshnet"));
             }
             Register { name } => {
@@ -600,7 +660,7 @@ impl Cli {
             }
             // Stunner test
             Stun {} => {
-                let servers_config = self.telio.get_derp_config().unwrap();
+                let servers_config = self.telio().get_derp_config().unwrap();
 
                 let rt = Runtime::new().unwrap();
 
@@ -676,10 +736,14 @@ impl Cli {
             }
             Config { mesh_config } => {
                 let meshmap: MeshMap = cli_try!(serde_json::from_str(&mesh_config));
-                cli_try!(self.telio.set_config(&Some(meshmap)));
+                cli_try!(self.telio().set_config(&Some(meshmap)));
+            }
+            Diag { public_key } => {
+                let diagnostics = cli_try!(res; self.telio().get_peer_diagnostics(&public_key));
+                cli_res!(res; (i "{}", diagnostics));
             }
             Off => {
-                cli_try!(res; self.telio.set_config(&None));
+                cli_try!(res; self.telio().set_config(&None));
             }
         }
 
@@ -691,7 +755,7 @@ impl Cli {
 
         match cmd {
             DnsCmd::On { forward_servers } => {
-                if !self.telio.is_running() {
+                if !self.telio().is_running() {
                     cli_res!(res; (e Error::NotStarted));
                 }
 
@@ -701,10 +765,10 @@ impl Cli {
                     .collect();
 
                 cli_res!(res; (i "starting magic dns with forward servers: {:?}...", forward_servers));
-                cli_try!(res; self.telio.enable_magic_dns(&forward_servers));
+                cli_try!(res; self.telio().enable_magic_dns(&forward_servers));
             }
             DnsCmd::Off => {
-                cli_try!(res; self.telio.disable_magic_dns());
+                cli_try!(res; self.telio().disable_magic_dns());
             }
         }
 
@@ -715,7 +779,7 @@ impl Cli {
         let mut res = Vec::new();
 
         match cmd {
-            DetectCmd::Address { stun_server } => match self.telio.get_nat(stun_server) {
+            DetectCmd::Address { stun_server } => match self.telio().get_nat(stun_server) {
                 Ok(data) => {
                     cli_res!(res; (i"Public Address: {:?}", data.public_ip));
                     cli_res!(res; (i"Nat Type: {:?}", data.nat_type));
@@ -737,7 +801,7 @@ impl Cli {
         adapter_type: AdapterType,
         res: &mut Vec<Resp>,
     ) -> Result<(), Error> {
-        if !self.telio.is_running() {
+        if !self.telio().is_running() {
             let device_config = DeviceConfig {
                 private_key,
                 name: Some(name.clone()),
@@ -745,10 +809,10 @@ impl Cli {
                 ..Default::default()
             };
 
-            self.telio.start(&device_config)?;
+            self.telio().start(&device_config)?;
 
             #[cfg(target_os = "linux")]
-            let _ = self.telio.set_fwmark(FWMARK_VALUE)?;
+            let _ = self.telio().set_fwmark(FWMARK_VALUE)?;
 
             cli_res!(res; (i "started telio with {:?}:{}...", adapter_type, private_key));
         }
@@ -767,22 +831,30 @@ impl Report for Cli {
             cli_res!(res; (i "no login."))
         }
 
-        if self.telio.is_running() {
+        if self.telio().is_running() {
             match cmd {
                 Simple => {
                     cli_res!(res;
                         (i "telio running."),
-                        (i "telio nodes: {}", serde_json::to_string(&self.telio.nodes().unwrap()).unwrap()),
-                        (i "derp status: {}", serde_json::to_string(&self.telio.get_derp_server().unwrap()).unwrap())
+                        (i "telio nodes: {}", serde_json::to_string(&self.telio().nodes().unwrap()).unwrap()),
+                        (i "derp status: {}", serde_json::to_string(&self.telio().get_derp_server().unwrap()).unwrap())
                     );
                 }
                 Pretty => {
                     cli_res!(res;
                         (i "telio running."),
-                        (i "telio nodes:\n{}", serde_json::to_string_pretty(&self.telio.nodes().unwrap()).unwrap()),
-                        (i "derp status:\n{}", serde_json::to_string_pretty(&self.telio.get_derp_server().unwrap()).unwrap())
+                        (i "telio nodes:\n{}", serde_json::to_string_pretty(&self.telio().nodes().unwrap()).unwrap()),
+                        (i "derp status:\n{}", serde_json::to_string_pretty(&self.telio().get_derp_server().unwrap()).unwrap())
                     );
                 }
+                Metrics => {
+                    let metrics = cli_try!(res; self.telio().get_metrics());
+                    cli_res!(res; (i "{}", metrics));
+                }
+                Matrix => {
+                    let matrix = cli_try!(res; self.telio().get_connectivity_matrix());
+                    cli_res!(res; (i "{}", matrix));
+                }
             }
         } else {
             cli_res!(res; (i "stopped."));
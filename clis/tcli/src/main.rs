@@ -3,10 +3,14 @@
 mod cli;
 mod derp;
 mod nord;
+#[cfg(unix)]
+mod remote;
 
 use clap::Parser;
 use regex::Regex;
 use std::io::Write;
+#[cfg(unix)]
+use std::sync::{Arc, Mutex, PoisonError};
 use telio_model::{api_config::Features, event::Event as DevEvent};
 
 #[derive(Parser)]
@@ -16,6 +20,14 @@ struct Args {
     features: Option<String>,
     #[clap(long)]
     less_spam: bool,
+    /// Control device of another process through its control socket, instead of running one
+    #[cfg(unix)]
+    #[clap(long)]
+    control: Option<String>,
+    /// Also serve the device and commands of this cli through control socket at given path
+    #[cfg(unix)]
+    #[clap(long, conflicts_with = "control")]
+    listen: Option<String>,
 }
 
 fn main() {
@@ -26,7 +38,31 @@ fn main() {
         .map(|s| serde_json::from_str(&s).expect("Invalid json"))
         .unwrap_or_default();
 
-    let mut cli = cli::Cli::new(features);
+    #[cfg(unix)]
+    let (mut exec, _server): (Box<dyn FnMut(&str) -> Vec<cli::Resp>>, _) = match &args.control {
+        Some(path) => {
+            let mut remote =
+                remote::RemoteCli::connect(path).expect("Cannot connect to control socket");
+            (Box::new(move |cmd: &str| remote.exec(cmd)), None)
+        }
+        None => {
+            let cli = Arc::new(Mutex::new(cli::Cli::new(features)));
+            let server = args.listen.as_ref().map(|path| {
+                remote::listen(path, cli.clone()).expect("Cannot listen on control socket")
+            });
+            (
+                Box::new(move |cmd: &str| {
+                    cli.lock().unwrap_or_else(PoisonError::into_inner).exec(cmd)
+                }),
+                server,
+            )
+        }
+    };
+    #[cfg(not(unix))]
+    let mut exec = {
+        let mut cli = cli::Cli::new(features);
+        move |cmd: &str| cli.exec(cmd)
+    };
     let mut stdout = std::io::stdout();
 
     let less_spam = args.less_spam;
@@ -56,7 +92,7 @@ fn main() {
             cmd = captures.get(2).unwrap().as_str().to_string();
         }
 
-        for resp in cli.exec(&cmd) {
+        for resp in exec(&cmd) {
             use cli::Resp::*;
             match resp {
                 Info(i) => println!("- {}", i),
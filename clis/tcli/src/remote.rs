@@ -0,0 +1,171 @@
+use clap::Parser;
+use serde_json::{json, Value};
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex, PoisonError};
+use telio::control::{
+    protocol::{code, RpcError},
+    Client, ClientError, ControlServer, Extension,
+};
+use telio_model::mesh::ExitNode;
+
+use crate::cli::{Cli, Cmd, DevCmd, DnsCmd, Error, EventsCmd, MeshCmd, Resp, StatusCmd};
+use crate::{cli_res, cli_try};
+
+/// Method served by `tcli --listen`, running a command line with params `{"command": line}`.
+/// Result is an array of `{"info": text}`, `{"event": event}` and `{"error": text}` objects.
+const COMMAND_METHOD: &str = "tcli_command";
+
+/// Serve device of the `cli` and all of its commands through control socket at `path`
+pub fn listen(path: &str, cli: Arc<Mutex<Cli>>) -> Result<ControlServer, Error> {
+    let device = cli.lock().unwrap_or_else(PoisonError::into_inner).device();
+    let extension: Extension = Arc::new(move |method: &str, params: Value| {
+        (method == COMMAND_METHOD).then(|| {
+            let command = params["command"].as_str().ok_or_else(|| {
+                RpcError::new(code::INVALID_PARAMS, "Missing command string param")
+            })?;
+            let responses = cli
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .exec(command);
+            Ok(responses.into_iter().filter_map(encode).collect())
+        })
+    });
+    Ok(ControlServer::start_with_extension(
+        path,
+        device,
+        Some(extension),
+    )?)
+}
+
+fn encode(resp: Resp) -> Option<Value> {
+    match resp {
+        Resp::Info(info) => Some(json!({ "info": info })),
+        Resp::Event(event) => Some(json!({ "event": event })),
+        Resp::Error(error) => Some(json!({ "error": error.to_string() })),
+        // Client decides when to quit, the listening process keeps running
+        Resp::Quit => None,
+    }
+}
+
+fn decode(resp: &Value) -> Resp {
+    if let Some(info) = resp["info"].as_str() {
+        Resp::Info(info.to_owned())
+    } else if let Some(error) = resp["error"].as_str() {
+        Resp::Error(Box::new(Error::Remote(error.to_owned())))
+    } else {
+        Resp::Info(format!("event: {}", resp["event"]))
+    }
+}
+
+/// Runs the same commands as [Cli](crate::cli::Cli), against a device of another process,
+/// through its control socket. Processes other than `tcli --listen` only serve the commands
+/// mapping to device methods of the control protocol.
+pub struct RemoteCli {
+    client: Client,
+}
+
+impl RemoteCli {
+    pub fn connect(path: &str) -> Result<Self, Error> {
+        let mut client = Client::connect(path)?;
+        client.subscribe(&[], &[])?;
+        Ok(Self { client })
+    }
+
+    pub fn exec(&mut self, line: &str) -> Vec<Resp> {
+        let mut res = Vec::new();
+        let mut args = cli_try![shellwords::split(line)];
+        args.insert(0, "tcli".to_owned());
+
+        match Cmd::try_parse_from(&args) {
+            // Events of the subscription of this connection, quitting only this process
+            Ok(cmd @ Cmd::Events { cmd: None }) | Ok(cmd @ Cmd::Quit) => {
+                cli_res!(res; (j self.exec_cmd(cmd)))
+            }
+            Ok(cmd) => match self
+                .client
+                .call::<_, Vec<Value>>(COMMAND_METHOD, json!({ "command": line }))
+            {
+                Ok(responses) => res.extend(responses.iter().map(decode)),
+                // Not a `tcli --listen`, commands are mapped to device methods then
+                Err(ClientError::Rpc(error)) if error.code == code::METHOD_NOT_FOUND => {
+                    cli_res!(res; (j self.exec_cmd(cmd)))
+                }
+                Err(error) => cli_res!(res; (e error)),
+            },
+            Err(err) => {
+                if err.kind() == clap::ErrorKind::DisplayHelp {
+                    cli_res!(res; (i "{}", err));
+                } else {
+                    cli_res!(res; (e Error::Parser(err.kind())));
+                }
+            }
+        }
+        res
+    }
+
+    /// Commands mapping to device methods of the control protocol
+    fn exec_cmd(&mut self, cmd: Cmd) -> Vec<Resp> {
+        let mut res = Vec::new();
+        match cmd {
+            Cmd::Status(StatusCmd::Simple) => {
+                let nodes = cli_try!(res; self.client.get_status_map());
+                cli_res!(res; (i "telio nodes: {}", nodes));
+            }
+            Cmd::Status(StatusCmd::Pretty) => {
+                let nodes = cli_try!(res; self.client.get_status_map());
+                let nodes = cli_try!(res; serde_json::to_string_pretty(&nodes));
+                cli_res!(res; (i "telio nodes:\n{}", nodes));
+            }
+            Cmd::Events { cmd: None } => {
+                for event in cli_try!(res; self.client.poll_events()) {
+                    cli_res!(res; (i "event: {}", event));
+                }
+            }
+            Cmd::Dev(DevCmd::Con {
+                public_key,
+                endpoint,
+                allowed_ips,
+            }) => {
+                let node = ExitNode {
+                    public_key,
+                    endpoint,
+                    allowed_ips: if allowed_ips.is_empty() {
+                        None
+                    } else {
+                        Some(allowed_ips)
+                    },
+                };
+                cli_res!(res; (i "connecting to node:\n{:#?}", node));
+                cli_try!(res; self.client.connect_to_exit_node(&node));
+            }
+            Cmd::Mesh(MeshCmd::Config { mesh_config }) => {
+                cli_try!(res; self.client.set_meshnet(Some(&mesh_config)));
+            }
+            Cmd::Mesh(MeshCmd::Off) => {
+                cli_try!(res; self.client.set_meshnet(None));
+            }
+            Cmd::Dns(DnsCmd::On { forward_servers }) => {
+                let forward_servers: Vec<IpAddr> = forward_servers
+                    .iter()
+                    .filter_map(|server| server.parse().ok())
+                    .collect();
+
+                cli_res!(res; (i "starting magic dns with forward servers: {:?}...", forward_servers));
+                cli_try!(res; self.client.enable_magic_dns(&forward_servers));
+            }
+            Cmd::Quit => cli_res!(res; q),
+            Cmd::Status(_)
+            | Cmd::Events {
+                cmd: Some(EventsCmd::History { .. }),
+            }
+            | Cmd::Login(_)
+            | Cmd::Dev(_)
+            | Cmd::Vpn(_)
+            | Cmd::Mesh(_)
+            | Cmd::Dns(_)
+            | Cmd::Nat(_)
+            | Cmd::Derp(_) => cli_res!(res; (e Error::NotSupported)),
+        }
+        res
+    }
+}
@@ -40,6 +40,12 @@ pub struct SecretKey(pub [u8; KEY_SIZE]);
 )]
 pub struct PublicKey(pub [u8; KEY_SIZE]);
 
+/// Preshared key type, mixed into WireGuard handshakes of a single peer
+#[derive(
+    Default, PartialOrd, Ord, PartialEq, Eq, Hash, Copy, Clone, DeserializeFromStr, SerializeDisplay,
+)]
+pub struct PresharedKey(pub [u8; KEY_SIZE]);
+
 /// Error returned when parsing fails for SecretKey or PublicKey.
 #[derive(Debug, thiserror::Error)]
 pub enum KeyDecodeError {
@@ -244,7 +250,7 @@ macro_rules! gen_common {
         gen_common!($($tt),+);
     };
 }
-gen_common!(SecretKey, PublicKey);
+gen_common!(SecretKey, PublicKey, PresharedKey);
 
 #[cfg(test)]
 mod tests {
@@ -292,4 +298,12 @@ mod tests {
         assert_eq!(SK, SK_HEX.parse().unwrap());
         assert_eq!(PK, PK_HEX.parse().unwrap());
     }
+
+    #[test]
+    fn convert_preshared_key() {
+        let psk = PresharedKey([0xBAu8; 32]);
+        assert_eq!(psk, SK_B64.parse().unwrap());
+        assert_eq!(psk, SK_HEX.parse().unwrap());
+        assert_eq!(SK_B64, &format!("{}", psk));
+    }
 }
@@ -114,6 +114,36 @@ impl LocalDnsResolver {
             auto_switch_ips,
         })
     }
+
+    /// Point the DNS tunnel at a new telio public key.
+    ///
+    /// The DNS keeps its own key, records and forward servers, so the DNS peer
+    /// configured on the adapter stays valid. Only the name server task is
+    /// restarted if it was running.
+    pub async fn set_telio_public_key(&mut self, public_key: &PublicKey) -> Result<(), String> {
+        telio_log_debug!("Dns - set_telio_public_key: {:?}", public_key);
+        let telio_public_key: Arc<X25519PublicKey> =
+            Arc::new(X25519PublicKey::from(&public_key[..]));
+        let static_private: Arc<X25519SecretKey> = Arc::new(self.secret_key.to_string().parse()?);
+        let peer = Arc::<Tunn>::from(Tunn::new(
+            static_private,
+            telio_public_key,
+            None,
+            None,
+            0,
+            None,
+        )?);
+
+        let running = self.nameserver.read().await.is_running();
+        if running {
+            self.nameserver.stop().await;
+        }
+        self.peer = peer;
+        if running {
+            self.start().await;
+        }
+        Ok(())
+    }
 }
 
 #[async_trait]
@@ -154,6 +184,7 @@ impl DnsResolver for LocalDnsResolver {
             rx_bytes: None,
             tx_bytes: None,
             time_since_last_handshake: None,
+            preshared_key: None,
         }
     }
 
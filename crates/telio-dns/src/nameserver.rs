@@ -65,6 +65,11 @@ impl LocalNameServer {
         })))
     }
 
+    /// Whether the server task was started and not stopped since.
+    pub(crate) fn is_running(&self) -> bool {
+        self.task_handle.is_some()
+    }
+
     async fn lookup(&self, request: &Request, resolver: Resolver) {
         self.zones.lookup(request, None, resolver).await;
     }
@@ -92,7 +97,7 @@ impl NameServer for Arc<RwLock<LocalNameServer>> {
 
     // TODO: maybe report or recover in case of thread panic
     async fn stop(&self) {
-        if let Some(handle) = &self.read().await.task_handle {
+        if let Some(handle) = self.write().await.task_handle.take() {
             handle.abort();
         }
     }
@@ -242,6 +242,50 @@ impl Firewall {
         }
     }
 
+    /// Generates nftables rules of inbound chain, enforcing the same policy for kernel
+    /// WireGuard interfaces, whose packets do not pass through this firewall.
+    /// Peers are given with their allowed IPs, as kernel cannot match packets by public key,
+    /// while cryptokey routing ensures, that packets from allowed IPs came from that peer
+    pub fn nft_inbound_rules(&self, peers: &[(PublicKey, Vec<IpNetwork>)]) -> Vec<String> {
+        let whitelist = unwrap_lock_or_return!(self.whitelist.read(), vec!["drop".to_owned()]);
+        let mut rules = Vec::new();
+
+        for (_, allowed_ips) in peers
+            .iter()
+            .filter(|(peer, _)| whitelist.peer_whitelist.contains(peer))
+        {
+            for ip in allowed_ips {
+                let family = if ip.is_ipv4() { "ip" } else { "ip6" };
+                rules.push(format!("{} saddr {} accept", family, ip));
+            }
+        }
+
+        // Only IPv4 connections are tracked, the rest is dropped
+        rules.push("meta nfproto != ipv4 drop".to_owned());
+        rules.push("meta l4proto { tcp, udp } ct state established,related accept".to_owned());
+
+        let mut networks: Vec<_> = whitelist
+            .network_whitelist
+            .iter()
+            .filter(|ip_net| ip_net.is_ipv4())
+            .map(|ip_net| ip_net.to_string())
+            .collect();
+        networks.sort();
+        for ip_net in networks {
+            rules.push(format!(
+                "ip saddr {} meta l4proto {{ tcp, udp, icmp }} accept",
+                ip_net
+            ));
+        }
+
+        rules.push(
+            "icmp type != { echo-request, timestamp-request, info-request, address-mask-request } accept"
+                .to_owned(),
+        );
+        rules.push("drop".to_owned());
+        rules
+    }
+
     fn handle_outbound_udp(&self, ip: &Ipv4Packet, buffer: &[u8]) {
         let ip_header_len_bytes = (ip.get_header_length() as usize) * 4; //IPv4->IHL to bytes
         let udp_packet = unwrap_option_or_return!(UdpPacket::new(&buffer[ip_header_len_bytes..]));
@@ -1033,4 +1077,58 @@ mod tests {
         assert_eq!(fw.process_inbound_packet(&make_peer(), &make_icmp("100.100.100.101", "127.0.0.1",&IcmpTypes::EchoRequest)), false);
         assert_eq!(fw.process_inbound_packet(&make_peer(), &make_tcp("100.100.100.101:1234", "127.0.0.1:1111", TcpFlags::PSH)), false);
     }
+
+    #[test]
+    fn firewall_nft_inbound_rules() {
+        let fw = Firewall::new();
+        let peer: PublicKey = (&make_peer()).into();
+        let other = PublicKey([2; 32]);
+        let peers = vec![
+            (
+                peer,
+                vec![
+                    "100.64.0.1/32".parse().unwrap(),
+                    "fd74:656c:696f::1/128".parse().unwrap(),
+                ],
+            ),
+            (other, vec!["100.64.0.2/32".parse().unwrap()]),
+        ];
+
+        let common = [
+            "meta nfproto != ipv4 drop",
+            "meta l4proto { tcp, udp } ct state established,related accept",
+        ];
+        let tail = [
+            "icmp type != { echo-request, timestamp-request, info-request, address-mask-request } accept",
+            "drop",
+        ];
+
+        assert_eq!(
+            fw.nft_inbound_rules(&peers),
+            [&common[..], &tail[..]].concat()
+        );
+
+        fw.add_to_peer_whitelist(peer);
+        fw.add_to_network_whitelist("100.100.100.101/32".parse().unwrap());
+        fw.add_to_network_whitelist("10.0.0.0/8".parse().unwrap());
+        // IPv6 packets are not inspected by userspace firewall, only whitelisted peers pass
+        fw.add_to_network_whitelist("fd00::/8".parse().unwrap());
+
+        assert_eq!(
+            fw.nft_inbound_rules(&peers),
+            [
+                &[
+                    "ip saddr 100.64.0.1/32 accept",
+                    "ip6 saddr fd74:656c:696f::1/128 accept",
+                ][..],
+                &common[..],
+                &[
+                    "ip saddr 10.0.0.0/8 meta l4proto { tcp, udp, icmp } accept",
+                    "ip saddr 100.100.100.101/32 meta l4proto { tcp, udp, icmp } accept",
+                ][..],
+                &tail[..],
+            ]
+            .concat()
+        );
+    }
 }
@@ -1,7 +1,10 @@
 #![deny(missing_docs)]
-//! Contains macros to simplify using Moose functions from within libtelio
+//! Publishes analytics events of libtelio through a sink selected at runtime:
+//! moose tracker, JSON-lines file or a callback provided by the app
 
-use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, RwLock};
+
+use lazy_static::lazy_static;
 
 pub use telio_utils::telio_log_warn;
 
@@ -10,104 +13,98 @@ pub use telio_utils::telio_log_warn;
 pub mod event_log;
 pub use event_log::*;
 
+/// Analytics sinks module
+pub mod sink;
+pub use sink::{CallbackSink, EventSink, FileSink, LanaEvent, MooseSink, SinkError};
+
 /// App name used to initialize moose with
 pub const LANA_APP_NAME: &str = "libtelio";
 /// Version of the tracker used, should be updated everytime the tracker library is updated
 pub const LANA_MOOSE_VERSION: &str = "0.5.2";
 
-static MOOSE_INITIALIZED: AtomicBool = AtomicBool::new(false);
-const DEFAULT_ORDERING: Ordering = Ordering::SeqCst;
-
-/// Wrapper to call a moose function with optional arguments,
-/// returns the result of the call while also logging any error.
-///
-/// # Parameters:
-/// * func - Name of the moose function to call.
-/// * arg - Contains the arguments passed to the called function, if any.
-/// # Returns:
-/// * result - Moose function call result (successful or not).
-/// * Err() - moose::Error::NotInitiatedError if lana was not initialized.
-#[macro_export]
-macro_rules! lana {
-    (
-        $func:ident
-        $(,$arg:expr)*
-    ) => {{
-            if is_lana_initialized() {
-                let result = moose::$func($($arg),*);
-                if let Some(error) = result.as_ref().err() {
-                    telio_log_warn!(
-                        "[Moose] Error: {} on call to `{}`",
-                        error,
-                        stringify!($func));
-                }
-
-                result
-            } else {
-                Err(moose::Error::NotInitiatedError)
-            }
-    }};
+lazy_static! {
+    static ref SINK: RwLock<Option<Arc<dyn EventSink>>> = RwLock::new(None);
+    static ref APP_SINK: RwLock<Option<Arc<dyn EventSink>>> = RwLock::new(None);
 }
 
-/// Initialize lana with the given configuration
+/// Initialize lana to publish events through the given sink
 ///
 /// # Parameters:
-/// * event_path - path of the DB file where events will be stored. If such file does not exist, it will be created, otherwise reused.
+/// * sink - Destination of published events.
 /// * app_version - Indicates the semantic version of the application.
-/// * prod - wether the events should be sent to production or not
-pub fn init_lana(
-    event_path: String,
-    app_version: String,
-    prod: bool,
-) -> Result<moose::Result, moose::Error> {
-    if !is_lana_initialized() {
-        let result = moose::init(
-            event_path,
-            LANA_APP_NAME.to_string(),
-            app_version,
-            LANA_MOOSE_VERSION.to_string(),
-            prod,
-        );
-        if let Some(error) = result.as_ref().err() {
-            telio_log_warn!("[Moose] Error: {} on call to `{}`", error, "init");
-        }
-
-        if result.is_ok() {
-            MOOSE_INITIALIZED.store(true, DEFAULT_ORDERING);
-            init_device_info();
-        }
-
-        result
-    } else {
-        Ok(moose::Result::AlreadyInitiated)
+/// # Returns:
+/// * Ok(()) - If lana was initialized now or before.
+/// * Err() - If the sink failed to initialize.
+pub fn init_lana(sink: Arc<dyn EventSink>, app_version: &str) -> Result<(), SinkError> {
+    let mut current = SINK
+        .write()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    if current.is_some() {
+        return Ok(());
+    }
+
+    if let Err(error) = sink.init(app_version) {
+        telio_log_warn!("[Lana] Error: {} on sink initialization", error);
+        return Err(error);
     }
+    *current = Some(sink);
+    Ok(())
 }
 
 /// Deinitialize lana
 ///
 /// # Returns:
-/// * Result(Success) - If lana was deinitialized successfully
-/// * Result(Error) - Otherwise
-pub fn deinit_lana() -> Result<moose::Result, moose::Error> {
-    if is_lana_initialized() {
-        let result = moose::moose_deinit();
-        if let Some(error) = result.as_ref().err() {
-            telio_log_warn!("[Moose] Error: {} on call to `{}`", error, "moose_deinit");
-        }
-
-        MOOSE_INITIALIZED.store(false, DEFAULT_ORDERING);
-
-        result
-    } else {
-        Err(moose::Error::NotInitiatedError)
-    }
+/// * Ok(()) - If lana was deinitialized successfully
+/// * Err(SinkError::NotInitialized) - If lana was not initialized
+pub fn deinit_lana() -> Result<(), SinkError> {
+    let sink = SINK
+        .write()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+        .take()
+        .ok_or(SinkError::NotInitialized)?;
+    sink.deinit();
+    Ok(())
 }
 
 /// Has lana been initialized, generally should not be called manually,
-/// is used to verify that the feature was enabled when using the lana! macro.
+/// is used to verify that analytics are enabled before collecting them.
 ///
 /// Returns:
 /// bool - True if lana was initialized, false otherwise.
 pub fn is_lana_initialized() -> bool {
-    MOOSE_INITIALIZED.load(DEFAULT_ORDERING)
+    SINK.read().map(|sink| sink.is_some()).unwrap_or_default()
+}
+
+/// Publish an event through the sink lana was initialized with, logging any error.
+///
+/// # Returns:
+/// * Err(SinkError::NotInitialized) - If lana was not initialized.
+pub fn send(event: LanaEvent) -> Result<(), SinkError> {
+    let sink = sink().ok_or(SinkError::NotInitialized)?;
+    let result = sink.send(&event);
+    if let Some(error) = result.as_ref().err() {
+        telio_log_warn!("[Lana] Error: {} on sending {:?}", error, event);
+    }
+    result
+}
+
+/// Meshnet id stored by the sink from previous runs, if it keeps one
+pub fn meshnet_id() -> Option<String> {
+    sink()?.meshnet_id()
+}
+
+/// Register sink provided by the app, used when callback sink is selected
+pub fn set_app_sink(sink: Option<Arc<dyn EventSink>>) {
+    *APP_SINK
+        .write()
+        .unwrap_or_else(|poisoned| poisoned.into_inner()) = sink;
+}
+
+/// Sink registered by the app, if any
+pub fn app_sink() -> Option<Arc<dyn EventSink>> {
+    APP_SINK.read().ok()?.clone()
+}
+
+fn sink() -> Option<Arc<dyn EventSink>> {
+    SINK.read().ok()?.clone()
 }
@@ -0,0 +1,299 @@
+//! Destinations of analytics events, selected at runtime.
+//!
+use std::{
+    fs::OpenOptions,
+    io::{self, Write},
+    path::PathBuf,
+    sync::Mutex,
+};
+
+use serde::Serialize;
+use time::format_description::well_known::Rfc3339;
+
+use crate::{moose, LANA_APP_NAME, LANA_MOOSE_VERSION};
+
+/// Errors of analytics sinks
+#[derive(thiserror::Error, Debug)]
+pub enum SinkError {
+    /// Lana was not initialized with a sink
+    #[error("Lana was not initialized")]
+    NotInitialized,
+    /// Callback sink was selected, but the app did not provide a callback
+    #[error("Analytics callback was not set")]
+    NoCallback,
+    /// Moose call failed
+    #[error(transparent)]
+    Moose(#[from] moose::Error),
+    /// Writing to event file failed
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    /// Event could not be serialized
+    #[error(transparent)]
+    Serialize(#[from] serde_json::Error),
+}
+
+/// Analytics event published by libtelio components
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum LanaEvent {
+    /// Nominated id of the meshnet
+    MeshnetId {
+        /// Id of the meshnet
+        id: String,
+    },
+    /// Fingerprints of internal meshnet nodes
+    MeshnetMembers {
+        /// Comma-separated list of fingerprints
+        members: String,
+    },
+    /// Connectivity matrix of the meshnet
+    ConnectivityMatrix {
+        /// Serialized matrix
+        matrix: String,
+    },
+    /// Links between this meshnet and external nodes
+    ExternalLinks {
+        /// Comma-separated list of `meshnet_id:fingerprint:connection_state`
+        links: String,
+    },
+    /// Periodic heartbeat with QoS data of meshnet nodes
+    Heartbeat {
+        /// Seconds each node was connected
+        connection_duration: String,
+        /// Seconds between heartbeats
+        heartbeat_interval: i32,
+        /// Percentiles of received throughput of each node
+        rx: String,
+        /// Percentiles of RTT of each node
+        rtt: String,
+        /// Percentiles of sent throughput of each node
+        tx: String,
+    },
+}
+
+#[derive(Serialize)]
+struct Record<'a> {
+    timestamp: String,
+    #[serde(flatten)]
+    event: &'a LanaEvent,
+}
+
+impl LanaEvent {
+    /// Serialize event as single line JSON object, with time when it was published
+    pub fn to_json(&self) -> Result<String, SinkError> {
+        let timestamp = time::OffsetDateTime::now_utc()
+            .format(&Rfc3339)
+            .unwrap_or_default();
+        Ok(serde_json::to_string(&Record {
+            timestamp,
+            event: self,
+        })?)
+    }
+}
+
+/// Destination of analytics events
+pub trait EventSink: Send + Sync {
+    /// Prepare sink for publishing, called once when lana is initialized
+    fn init(&self, _app_version: &str) -> Result<(), SinkError> {
+        Ok(())
+    }
+
+    /// Publish an event
+    fn send(&self, event: &LanaEvent) -> Result<(), SinkError>;
+
+    /// Meshnet id stored by the sink from previous runs, if it keeps one
+    fn meshnet_id(&self) -> Option<String> {
+        None
+    }
+
+    /// Release resources of sink, called once when lana is deinitialized
+    fn deinit(&self) {}
+}
+
+/// Sink forwarding events to moose tracker
+pub struct MooseSink {
+    event_path: String,
+    prod: bool,
+}
+
+impl MooseSink {
+    /// Create moose sink
+    ///
+    /// # Parameters:
+    /// * event_path - path of the DB file where events will be stored.
+    /// * prod - wether the events should be sent to production or not
+    pub fn new(event_path: String, prod: bool) -> Self {
+        Self { event_path, prod }
+    }
+}
+
+impl EventSink for MooseSink {
+    fn init(&self, app_version: &str) -> Result<(), SinkError> {
+        moose::init(
+            self.event_path.clone(),
+            LANA_APP_NAME.to_string(),
+            app_version.to_string(),
+            LANA_MOOSE_VERSION.to_string(),
+            self.prod,
+        )?;
+        crate::init_device_info();
+        Ok(())
+    }
+
+    fn send(&self, event: &LanaEvent) -> Result<(), SinkError> {
+        match event.clone() {
+            LanaEvent::MeshnetId { id } => {
+                moose::set_context_application_config_currentState_internalMeshnet_fp(id)
+            }
+            LanaEvent::MeshnetMembers { members } => {
+                moose::set_context_application_config_currentState_internalMeshnet_members(
+                    members,
+                )
+            }
+            LanaEvent::ConnectivityMatrix { matrix } => {
+                moose::set_context_application_config_currentState_internalMeshnet_connectivityMatrix(matrix)
+            }
+            LanaEvent::ExternalLinks { links } => {
+                moose::set_context_application_config_currentState_externalLinks(links)
+            }
+            LanaEvent::Heartbeat {
+                connection_duration,
+                heartbeat_interval,
+                rx,
+                rtt,
+                tx,
+            } => moose::send_serviceQuality_node_heartbeat(
+                connection_duration,
+                heartbeat_interval,
+                rx,
+                rtt,
+                tx,
+            ),
+        }?;
+        Ok(())
+    }
+
+    fn meshnet_id(&self) -> Option<String> {
+        moose::fetch_context()
+            .ok()
+            .and_then(|context| context.application.config.current_state.internal_meshnet.fp)
+    }
+
+    fn deinit(&self) {
+        if let Err(error) = moose::moose_deinit() {
+            crate::telio_log_warn!("[Moose] Error: {} on call to `moose_deinit`", error);
+        }
+    }
+}
+
+/// Sink appending events to a file, one JSON object per line
+pub struct FileSink {
+    path: PathBuf,
+    lock: Mutex<()>,
+}
+
+impl FileSink {
+    /// Create file sink, file is created on the first event if it does not exist
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            lock: Mutex::new(()),
+        }
+    }
+}
+
+impl EventSink for FileSink {
+    fn send(&self, event: &LanaEvent) -> Result<(), SinkError> {
+        let mut line = event.to_json()?;
+        line.push('\n');
+
+        let _guard = self
+            .lock
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?
+            .write_all(line.as_bytes())?;
+        Ok(())
+    }
+}
+
+/// Sink passing events serialized as JSON to a callback provided by the app
+pub struct CallbackSink<F> {
+    callback: F,
+}
+
+impl<F: Fn(&str) + Send + Sync> CallbackSink<F> {
+    /// Create callback sink
+    pub fn new(callback: F) -> Self {
+        Self { callback }
+    }
+}
+
+impl<F: Fn(&str) + Send + Sync> EventSink for CallbackSink<F> {
+    fn send(&self, event: &LanaEvent) -> Result<(), SinkError> {
+        (self.callback)(&event.to_json()?);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    #[test]
+    fn file_sink_writes_json_lines() {
+        let path = std::env::temp_dir().join(format!("lana-{}.jsonl", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        let sink = FileSink::new(&path);
+        sink.send(&LanaEvent::MeshnetId { id: "id".into() })
+            .unwrap();
+        sink.send(&LanaEvent::Heartbeat {
+            connection_duration: "10".into(),
+            heartbeat_interval: 3600,
+            rx: "1:2".into(),
+            rtt: "3:4".into(),
+            tx: "5:6".into(),
+        })
+        .unwrap();
+
+        let content = std::fs::read_to_string(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+
+        let lines: Vec<serde_json::Value> = content
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0]["event"], "meshnet_id");
+        assert_eq!(lines[0]["id"], "id");
+        assert!(lines[0]["timestamp"].is_string());
+        assert_eq!(lines[1]["event"], "heartbeat");
+        assert_eq!(lines[1]["heartbeat_interval"], 3600);
+        assert_eq!(lines[1]["rtt"], "3:4");
+    }
+
+    #[test]
+    fn callback_sink_passes_json() {
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let sink = CallbackSink::new({
+            let received = received.clone();
+            move |json: &str| received.lock().unwrap().push(json.to_owned())
+        });
+
+        sink.send(&LanaEvent::ExternalLinks {
+            links: "a:b:1".into(),
+        })
+        .unwrap();
+
+        let received = received.lock().unwrap();
+        assert_eq!(received.len(), 1);
+        let json: serde_json::Value = serde_json::from_str(&received[0]).unwrap();
+        assert_eq!(json["event"], "external_links");
+        assert_eq!(json["links"], "a:b:1");
+    }
+}
@@ -2,6 +2,11 @@
 //! telio configurable features via API
 
 use serde::{Deserialize, Serialize};
+use serde_with::DurationMilliSeconds;
+use std::{
+    net::{IpAddr, SocketAddr},
+    time::Duration,
+};
 use strum_macros::EnumCount;
 
 #[serde_with::serde_as]
@@ -13,6 +18,12 @@ pub struct FeatureQoS {
     /// Number of tries for each node. Default value is 3.
     pub rtt_tries: Option<u32>,
     /// Types of rtt analytics. Default is Ping.
+    /// * `Ping` - ICMP echo to the endpoint of the node, outside of the tunnel
+    /// * `Pinger` - telio ping to the meshnet IP of the node, through the tunnel
+    /// * `Echo` - TCP handshake with the meshnet IP of the node, through the tunnel
+    ///
+    /// Tunnel types need the node to run nurse with the same type enabled and to accept
+    /// incoming connections from this node.
     pub rtt_types: Option<Vec<String>>,
     /// Number of buckets used for rtt and throughput. Default value is 5.
     pub buckets: Option<u32>,
@@ -27,6 +38,28 @@ pub struct FeatureNurse {
     pub fingerprint: String,
     /// QoS configuration for Nurse
     pub qos: Option<FeatureQoS>,
+    /// Reject heartbeat messages, which are not signed by their sender. Default value is false,
+    /// so nodes which do not sign them yet are still accepted and only counted.
+    #[serde(default)]
+    pub enforce_heartbeat_auth: bool,
+}
+
+/// Destination of analytics events
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LanaSink {
+    /// Moose tracker
+    Moose,
+    /// File at `event_path`, one JSON object per line
+    File,
+    /// Callback registered by the app with `telio_set_analytics_cb`
+    Callback,
+}
+
+impl Default for LanaSink {
+    fn default() -> Self {
+        LanaSink::Moose
+    }
 }
 
 /// Configurable features for Lana module
@@ -36,6 +69,9 @@ pub struct FeatureLana {
     pub event_path: String,
     /// Whether the events should be sent to produciton or not
     pub prod: bool,
+    /// Where events are published. Default is moose.
+    #[serde(default)]
+    pub sink: LanaSink,
 }
 
 /// Configurable features for exit Dns
@@ -46,6 +82,117 @@ pub struct FeatureExitDns {
     pub auto_switch_dns_ips: Option<bool>,
 }
 
+/// Configurable features for relaying traffic on behalf of other meshnet nodes
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+pub struct FeaturePeerRelay {
+    /// Total bandwidth in bytes per second, relayed for other nodes. Default value is 1 MiB/s.
+    #[serde(default = "FeaturePeerRelay::default_bandwidth_limit")]
+    pub bandwidth_limit: u64,
+    /// Bandwidth in bytes per second, relayed for a single node. Default value is 256 KiB/s.
+    #[serde(default = "FeaturePeerRelay::default_peer_bandwidth_limit")]
+    pub peer_bandwidth_limit: u64,
+}
+
+impl FeaturePeerRelay {
+    fn default_bandwidth_limit() -> u64 {
+        1024 * 1024
+    }
+
+    fn default_peer_bandwidth_limit() -> u64 {
+        256 * 1024
+    }
+}
+
+impl Default for FeaturePeerRelay {
+    fn default() -> Self {
+        Self {
+            bandwidth_limit: Self::default_bandwidth_limit(),
+            peer_bandwidth_limit: Self::default_peer_bandwidth_limit(),
+        }
+    }
+}
+
+/// Tunable timers of UDP hole punching, all values are in milliseconds
+#[serde_with::serde_as]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct FeatureTraversal {
+    /// How often to measure latency of direct connection. Default value is 25000.
+    #[serde_as(as = "DurationMilliSeconds<u64>")]
+    pub ping_metric_interval: Duration,
+    /// How long to wait for ping replies. Default value is 5000.
+    #[serde_as(as = "DurationMilliSeconds<u64>")]
+    pub ping_timeout: Duration,
+    /// Direct connection is dropped, if nothing is received for this long. Default value is 60000.
+    #[serde_as(as = "DurationMilliSeconds<u64>")]
+    pub no_data_timeout: Duration,
+    /// How often peers' states are checked. Default value is 5000.
+    #[serde_as(as = "DurationMilliSeconds<u64>")]
+    pub check_peer_states_interval: Duration,
+    /// How often public endpoint is refreshed via STUN. Default value is 60000.
+    #[serde_as(as = "DurationMilliSeconds<u64>")]
+    pub stun_interval: Duration,
+    /// How long to wait for CallMeMaybe reply. Default value is 5000.
+    #[serde_as(as = "DurationMilliSeconds<u64>")]
+    pub call_me_maybe_timeout: Duration,
+    /// How long to wait before retrying broken direct connection. Default value is 5000.
+    #[serde_as(as = "DurationMilliSeconds<u64>")]
+    pub disconnected_grace_period: Duration,
+}
+
+impl Default for FeatureTraversal {
+    fn default() -> Self {
+        Self {
+            ping_metric_interval: Duration::from_secs(25),
+            ping_timeout: Duration::from_secs(5),
+            no_data_timeout: Duration::from_secs(60),
+            check_peer_states_interval: Duration::from_secs(5),
+            // TODO: stun_interval should be minimized to ~60 seconds, after succesful STUN response
+            stun_interval: Duration::from_secs(60),
+            call_me_maybe_timeout: Duration::from_secs(5),
+            disconnected_grace_period: Duration::from_secs(5),
+        }
+    }
+}
+
+impl FeatureTraversal {
+    /// Check, if timers are consistent with each other
+    pub fn validate(&self) -> Result<(), String> {
+        let timers = [
+            ("ping_metric_interval", self.ping_metric_interval),
+            ("ping_timeout", self.ping_timeout),
+            ("no_data_timeout", self.no_data_timeout),
+            (
+                "check_peer_states_interval",
+                self.check_peer_states_interval,
+            ),
+            ("stun_interval", self.stun_interval),
+            ("call_me_maybe_timeout", self.call_me_maybe_timeout),
+            ("disconnected_grace_period", self.disconnected_grace_period),
+        ];
+
+        if let Some((name, _)) = timers.iter().find(|(_, t)| t.is_zero()) {
+            return Err(format!("{} must be greater than zero", name));
+        }
+
+        // Latency is measured by pinging, so previous measurement has to time out first
+        if self.ping_timeout >= self.ping_metric_interval {
+            return Err(String::from(
+                "ping_timeout must be less than ping_metric_interval",
+            ));
+        }
+
+        // Connection would be dropped before its state is even checked
+        if self.no_data_timeout <= self.check_peer_states_interval {
+            return Err(String::from(
+                "no_data_timeout must be greater than check_peer_states_interval",
+            ));
+        }
+
+        Ok(())
+    }
+}
+
 /// Mesh connection path type
 #[derive(Clone, Copy, Debug, EnumCount, PartialEq, Eq, Hash, Serialize, Deserialize)]
 #[serde(rename_all = "kebab-case")]
@@ -54,6 +201,8 @@ pub enum PathType {
     Relay,
     /// Nodes connected directly via hole punching
     UdpHolePunch,
+    /// Nodes connected through a third, consenting meshnet node
+    PeerRelay,
 }
 
 impl Default for PathType {
@@ -71,6 +220,158 @@ pub struct FeaturePaths {
     pub force: Option<PathType>,
 }
 
+/// Terminate meshnet traffic in embedded userspace TCP/IP stack, instead of kernel interface
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct FeatureNetstack {
+    /// Local address of SOCKS5 proxy, through which applications reach meshnet peers
+    pub socks5: Option<SocketAddr>,
+    /// Local listeners, forwarding TCP connections to meshnet peers
+    pub forwards: Vec<PortForward>,
+}
+
+/// TCP port forward from local listener to meshnet peer
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+pub struct PortForward {
+    /// Local address to listen on
+    pub listen: SocketAddr,
+    /// Address of meshnet peer to forward connections to
+    pub remote: SocketAddr,
+}
+
+/// Persistent keepalive intervals of peers by their class, all values are in seconds.
+/// Zero disables keepalive
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct FeatureKeepalive {
+    /// Towards exit node. Default value is 15.
+    pub exit: u32,
+    /// Towards peers behind NAT on direct paths, keeping NAT mappings open.
+    /// Default value is 15.
+    pub direct: u32,
+    /// Towards peers on direct paths within local network, which have no NAT mappings
+    /// to keep open. Default value is 0.
+    pub local: u32,
+    /// Towards peers reached over relay. Default value is 25.
+    pub relay: u32,
+    /// Leave peers reached over relay without keepalive, as relay connection is kept
+    /// alive on its own. Default value is false.
+    pub power_saving: bool,
+}
+
+impl Default for FeatureKeepalive {
+    fn default() -> Self {
+        Self {
+            exit: 15,
+            direct: 15,
+            local: 0,
+            relay: 25,
+            power_saving: false,
+        }
+    }
+}
+
+impl FeatureKeepalive {
+    /// Keepalive interval of peer, reached at `endpoint`. Zero, if peer is not kept alive
+    pub fn interval(&self, is_exit: bool, path: PathType, endpoint: Option<IpAddr>) -> u32 {
+        match path {
+            _ if is_exit => self.exit,
+            PathType::Relay if self.power_saving => 0,
+            PathType::Relay => self.relay,
+            PathType::UdpHolePunch | PathType::PeerRelay => match endpoint {
+                Some(ip) if is_local(ip) => self.local,
+                _ => self.direct,
+            },
+        }
+    }
+}
+
+/// Address within local network, so peer reached at it is not behind NAT
+fn is_local(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => ip.is_private() || ip.is_link_local() || ip.is_loopback(),
+        // Unique local fc00::/7 and link local fe80::/10
+        IpAddr::V6(ip) => {
+            (ip.segments()[0] & 0xfe00) == 0xfc00
+                || (ip.segments()[0] & 0xffc0) == 0xfe80
+                || ip.is_loopback()
+        }
+    }
+}
+
+/// Bounded on-disk journal of events and state transitions
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+pub struct FeatureJournal {
+    /// Path of the journal file. If such file exists, entries from previous runs are kept
+    pub path: String,
+    /// Number of entries kept, oldest are dropped first. Default value is 10000.
+    #[serde(default = "FeatureJournal::default_max_entries")]
+    pub max_entries: u32,
+}
+
+impl FeatureJournal {
+    fn default_max_entries() -> u32 {
+        10000
+    }
+}
+
+/// How much of keys, IPs and hostnames is left in log messages
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LogPrivacy {
+    /// Logged as they are
+    Off,
+    /// Keys and hostnames hashed, IPs truncated to their network part
+    Standard,
+    /// Keys, IPs and hostnames hashed
+    Strict,
+}
+
+impl Default for LogPrivacy {
+    fn default() -> Self {
+        LogPrivacy::Off
+    }
+}
+
+/// Structured logging configuration, shared by all instances in the process
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+pub struct FeatureLogging {
+    /// Per module log levels, e.g. `telio_relay=trace,telio_traversal=debug`.
+    /// Directive without module overrides the level passed to `telio_new`.
+    pub filter: Option<String>,
+    /// Pass log records to the callback as JSON lines, instead of plain text
+    #[serde(default)]
+    pub json: bool,
+    /// Also write log records to a file
+    pub file: Option<FeatureLogFile>,
+    /// Redaction of sensitive data, can be changed later with `telio_set_log_privacy`
+    #[serde(default)]
+    pub privacy: LogPrivacy,
+}
+
+/// Log file rotated by size
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+pub struct FeatureLogFile {
+    /// Path of the log file, rotated files get `.1`, `.2`, ... suffixes
+    pub path: String,
+    /// Size in bytes at which the file is rotated. Default value is 10 MiB.
+    #[serde(default = "FeatureLogFile::default_max_size")]
+    pub max_size: u64,
+    /// Number of rotated files kept. Default value is 5.
+    #[serde(default = "FeatureLogFile::default_max_files")]
+    pub max_files: u32,
+}
+
+impl FeatureLogFile {
+    fn default_max_size() -> u64 {
+        10 * 1024 * 1024
+    }
+
+    fn default_max_files() -> u32 {
+        5
+    }
+}
+
 #[derive(Clone, Debug, Default, PartialEq, Deserialize)]
 /// Encompasses all of the possible features that can be enabled
 pub struct Features {
@@ -82,6 +383,18 @@ pub struct Features {
     pub paths: Option<FeaturePaths>,
     /// Configure options for exit dns
     pub exit_dns: Option<FeatureExitDns>,
+    /// Relay traffic on behalf of other meshnet nodes (requires [PathType::PeerRelay] path)
+    pub peer_relay: Option<FeaturePeerRelay>,
+    /// Tune UDP hole punching timers
+    pub traversal: Option<FeatureTraversal>,
+    /// Run without kernel interface, using userspace TCP/IP stack
+    pub netstack: Option<FeatureNetstack>,
+    /// Persistent keepalive policy of peers
+    pub keepalive: Option<FeatureKeepalive>,
+    /// Keep journal of events and state transitions on disk, for troubleshooting
+    pub journal: Option<FeatureJournal>,
+    /// Structured logging, with per module levels and optional log file
+    pub logging: Option<FeatureLogging>,
 }
 
 impl FeaturePaths {
@@ -102,6 +415,18 @@ impl FeaturePaths {
                 })
         }
     }
+
+    /// Check, if every enabled path has the paths it depends on
+    pub fn validate(&self) -> Result<(), String> {
+        let paths = self.paths();
+
+        // Peer relay frames are carried over direct connections to relaying nodes
+        if paths.contains(&PathType::PeerRelay) && !paths.contains(&PathType::UdpHolePunch) {
+            return Err(String::from("peer-relay path requires udp-hole-punch path"));
+        }
+
+        Ok(())
+    }
 }
 
 #[cfg(test)]
@@ -191,10 +516,17 @@ mod tests {
                     buckets: Some(5),
                     heartbeat_interval: Some(3600),
                 }),
+                enforce_heartbeat_auth: false,
             }),
             lana: None,
             paths: None,
             exit_dns: None,
+            peer_relay: None,
+            traversal: None,
+            netstack: None,
+            keepalive: None,
+            journal: None,
+            logging: None,
         };
 
         let empty_qos_features = Features {
@@ -207,20 +539,34 @@ mod tests {
                     buckets: None,
                     heartbeat_interval: None,
                 }),
+                enforce_heartbeat_auth: false,
             }),
             lana: None,
             paths: None,
             exit_dns: None,
+            peer_relay: None,
+            traversal: None,
+            netstack: None,
+            keepalive: None,
+            journal: None,
+            logging: None,
         };
 
         let no_qos_features = Features {
             nurse: Some(FeatureNurse {
                 fingerprint: String::from("fingerprint_test"),
                 qos: None,
+                enforce_heartbeat_auth: false,
             }),
             lana: None,
             paths: None,
             exit_dns: None,
+            peer_relay: None,
+            traversal: None,
+            netstack: None,
+            keepalive: None,
+            journal: None,
+            logging: None,
         };
 
         assert_eq!(
@@ -258,6 +604,12 @@ mod tests {
             exit_dns: Some(FeatureExitDns {
                 auto_switch_dns_ips: Some(true),
             }),
+            peer_relay: None,
+            traversal: None,
+            netstack: None,
+            keepalive: None,
+            journal: None,
+            logging: None,
         };
 
         let empty_features = Features {
@@ -267,6 +619,75 @@ mod tests {
             exit_dns: Some(FeatureExitDns {
                 auto_switch_dns_ips: None,
             }),
+            peer_relay: None,
+            traversal: None,
+            netstack: None,
+            keepalive: None,
+            journal: None,
+            logging: None,
+        };
+
+        assert_eq!(
+            serde_json::from_str::<Features>(full_json).unwrap(),
+            full_features
+        );
+        assert_eq!(
+            serde_json::from_str::<Features>(empty_json).unwrap(),
+            empty_features
+        );
+    }
+
+    #[test]
+    fn test_json_to_peer_relay_feature_set() {
+        let full_json = r#"
+        {
+            "paths": {
+                "priority": ["udp-hole-punch", "peer-relay"]
+            },
+            "peer_relay": {
+                "bandwidth_limit": 2048,
+                "peer_bandwidth_limit": 1024
+            }
+        }"#;
+
+        let empty_json = r#"
+        {
+            "peer_relay": {}
+        }"#;
+
+        let full_features = Features {
+            nurse: None,
+            lana: None,
+            paths: Some(FeaturePaths {
+                priority: vec![PathType::UdpHolePunch, PathType::PeerRelay],
+                force: None,
+            }),
+            exit_dns: None,
+            peer_relay: Some(FeaturePeerRelay {
+                bandwidth_limit: 2048,
+                peer_bandwidth_limit: 1024,
+            }),
+            traversal: None,
+            netstack: None,
+            keepalive: None,
+            journal: None,
+            logging: None,
+        };
+
+        let empty_features = Features {
+            nurse: None,
+            lana: None,
+            paths: None,
+            exit_dns: None,
+            peer_relay: Some(FeaturePeerRelay {
+                bandwidth_limit: 1024 * 1024,
+                peer_bandwidth_limit: 256 * 1024,
+            }),
+            traversal: None,
+            netstack: None,
+            keepalive: None,
+            journal: None,
+            logging: None,
         };
 
         assert_eq!(
@@ -279,6 +700,88 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_json_to_traversal_feature_set() {
+        let partial_json = r#"
+        {
+            "traversal": {
+                "ping_timeout": 1000,
+                "check_peer_states_interval": 500
+            }
+        }"#;
+
+        let expected = FeatureTraversal {
+            ping_timeout: Duration::from_secs(1),
+            check_peer_states_interval: Duration::from_millis(500),
+            ..Default::default()
+        };
+
+        let features = serde_json::from_str::<Features>(partial_json).unwrap();
+        assert_eq!(features.traversal, Some(expected));
+        assert_eq!(expected.validate(), Ok(()));
+
+        assert_eq!(
+            serde_json::from_str::<FeatureTraversal>("{}").unwrap(),
+            FeatureTraversal::default()
+        );
+    }
+
+    #[test]
+    fn test_invalid_traversal_feature_set() {
+        assert!(FeatureTraversal {
+            stun_interval: Duration::ZERO,
+            ..Default::default()
+        }
+        .validate()
+        .is_err());
+
+        assert!(FeatureTraversal {
+            ping_timeout: Duration::from_secs(30),
+            ..Default::default()
+        }
+        .validate()
+        .is_err());
+
+        assert!(FeatureTraversal {
+            no_data_timeout: Duration::from_secs(1),
+            ..Default::default()
+        }
+        .validate()
+        .is_err());
+    }
+
+    #[test]
+    fn test_json_to_netstack_feature_set() {
+        let json = r#"
+        {
+            "netstack": {
+                "socks5": "127.0.0.1:1080",
+                "forwards": [
+                    { "listen": "127.0.0.1:2222", "remote": "100.64.0.2:22" }
+                ]
+            }
+        }"#;
+
+        let netstack = FeatureNetstack {
+            socks5: Some(([127, 0, 0, 1], 1080).into()),
+            forwards: vec![PortForward {
+                listen: ([127, 0, 0, 1], 2222).into(),
+                remote: ([100, 64, 0, 2], 22).into(),
+            }],
+        };
+
+        assert_eq!(
+            serde_json::from_str::<Features>(json).unwrap().netstack,
+            Some(netstack)
+        );
+        assert_eq!(
+            serde_json::from_str::<Features>(r#"{ "netstack": {} }"#)
+                .unwrap()
+                .netstack,
+            Some(FeatureNetstack::default())
+        );
+    }
+
     #[test]
     fn test_json_to_feature_set() {
         let json = r#"
@@ -304,10 +807,12 @@ mod tests {
             nurse: Some(FeatureNurse {
                 fingerprint: "fingerprint_test".to_string(),
                 qos: None,
+                enforce_heartbeat_auth: false,
             }),
             lana: Some(FeatureLana {
                 event_path: "path/to/some/event/data".to_string(),
                 prod: true,
+                sink: LanaSink::Moose,
             }),
             paths: Some(FeaturePaths {
                 priority: vec![PathType::Relay, PathType::UdpHolePunch],
@@ -316,6 +821,12 @@ mod tests {
             exit_dns: Some(FeatureExitDns {
                 auto_switch_dns_ips: None,
             }),
+            peer_relay: None,
+            traversal: None,
+            netstack: None,
+            keepalive: None,
+            journal: None,
+            logging: None,
         };
 
         assert_eq!(serde_json::from_str::<Features>(json).unwrap(), features);
@@ -328,11 +839,121 @@ mod tests {
             lana: None,
             paths: None,
             exit_dns: None,
+            peer_relay: None,
+            traversal: None,
+            netstack: None,
+            keepalive: None,
+            journal: None,
+            logging: None,
         };
 
         assert_eq!(Features::default(), expected_defaults);
     }
 
+    #[test]
+    fn journal_max_entries_has_default() {
+        let features: Features =
+            serde_json::from_str(r#"{"journal": {"path": "/tmp/telio.journal"}}"#).unwrap();
+        assert_eq!(
+            features.journal,
+            Some(FeatureJournal {
+                path: "/tmp/telio.journal".to_owned(),
+                max_entries: 10000,
+            })
+        );
+    }
+
+    #[test]
+    fn logging_file_has_defaults() {
+        let json = r#"{"logging": {"filter": "telio_relay=trace", "file": {"path": "telio.log"}}}"#;
+
+        assert_eq!(
+            serde_json::from_str::<Features>(json).unwrap().logging,
+            Some(FeatureLogging {
+                filter: Some("telio_relay=trace".to_owned()),
+                json: false,
+                file: Some(FeatureLogFile {
+                    path: "telio.log".to_owned(),
+                    max_size: 10 * 1024 * 1024,
+                    max_files: 5,
+                }),
+                privacy: LogPrivacy::Off,
+            })
+        );
+    }
+
+    #[test]
+    fn lana_sink_is_selectable() {
+        let json = r#"{"lana": {"event_path": "events.jsonl", "prod": false, "sink": "file"}}"#;
+
+        assert_eq!(
+            serde_json::from_str::<Features>(json).unwrap().lana,
+            Some(FeatureLana {
+                event_path: "events.jsonl".to_string(),
+                prod: false,
+                sink: LanaSink::File,
+            })
+        );
+    }
+
+    #[test]
+    fn keepalive_depends_on_peer_class() {
+        let json = r#"
+        {
+            "keepalive": {
+                "relay": 60,
+                "power_saving": true
+            }
+        }"#;
+        let keepalive = serde_json::from_str::<Features>(json)
+            .unwrap()
+            .keepalive
+            .unwrap();
+        assert_eq!(
+            keepalive,
+            FeatureKeepalive {
+                relay: 60,
+                power_saving: true,
+                ..Default::default()
+            }
+        );
+
+        let public = Some("198.51.100.7".parse().unwrap());
+        let lan = Some("192.168.1.7".parse().unwrap());
+
+        // Exit node is kept alive on any path
+        assert_eq!(keepalive.interval(true, PathType::Relay, None), 15);
+        assert_eq!(keepalive.interval(true, PathType::UdpHolePunch, lan), 15);
+        assert_eq!(
+            keepalive.interval(false, PathType::UdpHolePunch, public),
+            15
+        );
+        assert_eq!(keepalive.interval(false, PathType::PeerRelay, None), 15);
+        assert_eq!(keepalive.interval(false, PathType::Relay, public), 0);
+
+        // Peers on local network are not behind NAT
+        assert_eq!(keepalive.interval(false, PathType::UdpHolePunch, lan), 0);
+        for ip in ["10.0.0.2", "169.254.0.2", "fd00::2", "fe80::2"] {
+            let ip = Some(ip.parse().unwrap());
+            assert_eq!(keepalive.interval(false, PathType::UdpHolePunch, ip), 0);
+        }
+        assert_eq!(
+            keepalive.interval(
+                false,
+                PathType::UdpHolePunch,
+                Some("2001:db8::2".parse().unwrap())
+            ),
+            Some(15)
+        );
+
+        let keepalive = FeatureKeepalive {
+            direct: 0,
+            ..Default::default()
+        };
+        assert_eq!(keepalive.interval(false, PathType::Relay, public), 25);
+        assert_eq!(keepalive.interval(false, PathType::UdpHolePunch, public), 0);
+    }
+
     #[test]
     fn get_paths_from_feature_paths() {
         assert_eq!(
@@ -368,4 +989,26 @@ mod tests {
             vec![PathType::UdpHolePunch]
         );
     }
+
+    #[test]
+    fn peer_relay_path_needs_direct_path() {
+        assert!(FeaturePaths {
+            priority: vec![PathType::UdpHolePunch, PathType::PeerRelay],
+            force: None
+        }
+        .validate()
+        .is_ok());
+        assert!(FeaturePaths {
+            priority: vec![PathType::PeerRelay],
+            force: None
+        }
+        .validate()
+        .is_err());
+        assert!(FeaturePaths {
+            priority: vec![PathType::UdpHolePunch, PathType::PeerRelay],
+            force: Some(PathType::PeerRelay)
+        }
+        .validate()
+        .is_err());
+    }
 }
@@ -4,7 +4,7 @@ use serde::Deserialize;
 
 use std::{net::IpAddr, ops::Deref};
 
-use telio_crypto::PublicKey;
+use telio_crypto::{PresharedKey, PublicKey};
 use telio_relay::derp::Server as DerpServer;
 
 /// Characterstics descriping a peer
@@ -30,6 +30,12 @@ pub struct Peer {
     pub is_local: bool,
     /// Flag to control whether the peer allows incoming connections
     pub allow_incoming_connections: bool,
+    /// Flag to control whether traffic can be relayed through the peer, or on behalf of it
+    #[serde(default)]
+    pub allow_peer_relay: bool,
+    /// Preshared key, e.g. derived by the app from a post-quantum key exchange
+    #[serde(default)]
+    pub preshared_key: Option<PresharedKey>,
 }
 
 /// Representation of DNS configuration
@@ -104,7 +110,8 @@ mod tests {
                   "user_email": "alice@example.com",
                   "allow_incoming_connections": true,
                   "peer_allows_traffic_routing": false,
-                  "allow_peer_traffic_routing": true
+                  "allow_peer_traffic_routing": true,
+                  "preshared_key": "urq6urq6urq6urq6urq6urq6urq6urq6urq6urq6uro="
                 }
               ],
               "dns": {
@@ -154,6 +161,12 @@ mod tests {
                 },
                 is_local: true,
                 allow_incoming_connections: true,
+                allow_peer_relay: false,
+                preshared_key: Some(
+                    "urq6urq6urq6urq6urq6urq6urq6urq6urq6urq6uro="
+                        .parse()
+                        .unwrap(),
+                ),
             }]),
             derp_servers: Some(vec![DerpServer {
                 region_code: "lt".to_owned(),
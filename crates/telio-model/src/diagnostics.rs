@@ -0,0 +1,69 @@
+//! Connectivity diagnostics of meshnet peers, collected for support tickets
+
+use serde::Serialize;
+use std::net::SocketAddr;
+use telio_crypto::PublicKey;
+
+/// Explanation of why a peer is (or is not) reachable through direct path
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum Verdict {
+    /// Direct path to peer is established
+    Connected,
+    /// Hole punching towards peer was not started yet
+    NotAttempted,
+    /// CallMeMaybe request was sent, waiting for peer's response
+    AwaitingCallMeMaybe,
+    /// Peer's endpoints are being pinged
+    Pinging,
+    /// Peer did not offer any endpoints in reply to our CallMeMaybe request
+    NoCallMeMaybeResponse,
+    /// None of peer's endpoints replied to our pings
+    PingsUnanswered,
+    /// Direct path was established, but has broken since
+    ConnectionLost,
+}
+
+/// Ping sent to one of peer's endpoints during the last hole punching attempt
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct PingReport {
+    /// Endpoint, which was pinged
+    pub endpoint: SocketAddr,
+    /// Was the ping answered with pong
+    pub answered: bool,
+    /// Measured round trip time in milliseconds, if answered
+    pub latency_ms: Option<u64>,
+}
+
+/// NAT, which the local node is behind
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct NatReport {
+    /// Public address, as seen by STUN server
+    pub public_ip: SocketAddr,
+    /// Nat type (full cone, symmetric, etc.)
+    pub nat_type: String,
+}
+
+/// Connectivity diagnostics of a single peer
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct PeerDiagnostics {
+    /// Public key of the peer
+    pub public_key: PublicKey,
+    /// Current state of the direct route to peer
+    pub state: String,
+    /// Time spent in the current state, in milliseconds
+    pub state_duration_ms: u64,
+    /// Version of hole punching messages, spoken by peer
+    pub protocol: String,
+    /// Endpoints offered by us to the peer
+    pub local_candidates: Vec<SocketAddr>,
+    /// Endpoints offered by the peer to us
+    pub remote_candidates: Vec<SocketAddr>,
+    /// Pings sent during the last hole punching attempt
+    pub pings: Vec<PingReport>,
+    /// NAT of the local node, if it could be detected
+    pub nat: Option<NatReport>,
+    /// Tunnel MTU fitting into the direct path, if it was probed
+    pub mtu: Option<u32>,
+    /// Final verdict of the direct path
+    pub verdict: Verdict,
+}
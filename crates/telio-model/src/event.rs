@@ -2,9 +2,10 @@
 
 use super::mesh::Node;
 use modifier::Modifier;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 
 pub use modifier::Set;
+use telio_crypto::PublicKey;
 use telio_relay::derp::Server as Relay;
 
 /// Macro used to report events
@@ -116,6 +117,18 @@ pub enum Event {
     },
 }
 
+/// Type of event, same as the `type` field of its JSON form
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EventType {
+    /// [Event::Relay]
+    Relay,
+    /// [Event::Node]
+    Node,
+    /// [Event::Error]
+    Error,
+}
+
 impl Event {
     /// Returns an event object. Use `modifier` for initiating all necessary fields.
     ///
@@ -145,6 +158,23 @@ impl Event {
     pub fn to_json(&self) -> serde_json::Result<String> {
         serde_json::to_string(self)
     }
+
+    /// Type of the event
+    pub fn event_type(&self) -> EventType {
+        match self {
+            Event::Relay { .. } => EventType::Relay,
+            Event::Node { .. } => EventType::Node,
+            Event::Error { .. } => EventType::Error,
+        }
+    }
+
+    /// Public key of the node the event is about, only node events have one
+    pub fn peer(&self) -> Option<PublicKey> {
+        match self {
+            Event::Node { body } => body.as_ref().map(|node| node.public_key),
+            _ => None,
+        }
+    }
 }
 
 impl Modifier<Event> for Relay {
@@ -215,7 +245,7 @@ impl Set for Event {}
 mod tests {
     use super::super::mesh::*;
     use super::*;
-    use telio_crypto::{PublicKey, KEY_SIZE};
+    use telio_crypto::KEY_SIZE;
     use telio_relay::derp::{RelayState, Server};
 
     #[test]
@@ -233,6 +263,7 @@ mod tests {
             hostname: Some(String::from("example.com")),
             allow_incoming_connections: false,
             path: crate::api_config::PathType::Relay,
+            interface: 0,
         };
 
         let server = Server {
@@ -297,4 +328,16 @@ mod tests {
         assert_eq!(conn_json, conn_event.to_json().unwrap());
         assert_eq!(node_json, node_event.to_json().unwrap());
     }
+
+    #[test]
+    fn node_on_additional_interface_is_tagged() {
+        let node = Node {
+            public_key: PublicKey([1_u8; KEY_SIZE]),
+            interface: 2,
+            ..Default::default()
+        };
+
+        let json = Event::new::<Node>().set(node).to_json().unwrap();
+        assert!(json.contains(r#""interface":2"#));
+    }
 }
@@ -5,7 +5,7 @@ use super::EndpointMap as RelayEndpointMap;
 use crate::api_config::PathType;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
-use telio_crypto::PublicKey;
+use telio_crypto::{PresharedKey, PublicKey};
 
 use telio_wg::uapi::{Event as PeerEvent, Peer as UapiPeer, PeerState};
 
@@ -46,11 +46,21 @@ pub struct Node {
     pub allow_incoming_connections: bool,
     /// Connection type in the network mesh (through Relay or hole punched directly)
     pub path: PathType,
+    /// Handle of WireGuard interface the node belongs to, omitted for the default interface
+    #[serde(skip_serializing_if = "is_default_interface")]
+    pub interface: u32,
+    /// Preshared key of the node, never reported back to the app
+    #[serde(skip)]
+    pub preshared_key: Option<PresharedKey>,
+}
+
+fn is_default_interface(interface: &u32) -> bool {
+    *interface == 0
 }
 
 /// Description of the Exit Node
 /// It is the gateway node to the internet
-#[derive(Debug, Default, Clone, Serialize)]
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
 pub struct ExitNode {
     /// The public key of the exit node
     pub public_key: PublicKey,
@@ -162,6 +172,7 @@ impl From<&Peer> for Node {
             endpoints: vec![],
             hostname: Some(peer.hostname.to_owned()),
             allow_incoming_connections: peer.allow_incoming_connections,
+            preshared_key: peer.preshared_key,
             ..Default::default()
         }
     }
@@ -245,6 +256,7 @@ impl From<&Node> for UapiPeer {
                 .find(|e| e.primary)
                 .map(|e| e.sockaddr),
             persistent_keepalive_interval: Some(25),
+            preshared_key: other.preshared_key,
             ..Default::default()
         }
     }
@@ -0,0 +1,80 @@
+//! smoltcp device, backed by in-memory packet queues
+
+use smoltcp::{
+    phy::{self, DeviceCapabilities, Medium},
+    time::Instant,
+};
+use std::collections::VecDeque;
+
+/// MTU of meshnet interface
+pub const MTU: usize = 1420;
+
+/// Packets received from WireGuard, which were not processed yet, are dropped beyond this
+const RX_QUEUE_LIMIT: usize = 1024;
+
+/// IP device, whose packets are shuttled to and from WireGuard adapter by the netstack task
+#[derive(Default)]
+pub struct QueueDevice {
+    rx: VecDeque<Vec<u8>>,
+    tx: VecDeque<Vec<u8>>,
+}
+
+impl QueueDevice {
+    /// Queue packet, received from WireGuard, for processing
+    pub fn push_rx(&mut self, packet: Vec<u8>) {
+        if self.rx.len() < RX_QUEUE_LIMIT {
+            self.rx.push_back(packet);
+        }
+    }
+
+    /// Take packets, which should be sent through WireGuard
+    pub fn take_tx(&mut self) -> Vec<Vec<u8>> {
+        self.tx.drain(..).collect()
+    }
+}
+
+impl phy::Device for QueueDevice {
+    type RxToken<'a> = RxToken;
+    type TxToken<'a> = TxToken<'a>;
+
+    fn receive(&mut self, _timestamp: Instant) -> Option<(Self::RxToken<'_>, Self::TxToken<'_>)> {
+        let packet = self.rx.pop_front()?;
+        Some((RxToken(packet), TxToken(&mut self.tx)))
+    }
+
+    fn transmit(&mut self, _timestamp: Instant) -> Option<Self::TxToken<'_>> {
+        Some(TxToken(&mut self.tx))
+    }
+
+    fn capabilities(&self) -> DeviceCapabilities {
+        let mut caps = DeviceCapabilities::default();
+        caps.medium = Medium::Ip;
+        caps.max_transmission_unit = MTU;
+        caps
+    }
+}
+
+pub struct RxToken(Vec<u8>);
+
+impl phy::RxToken for RxToken {
+    fn consume<R, F>(mut self, f: F) -> R
+    where
+        F: FnOnce(&mut [u8]) -> R,
+    {
+        f(&mut self.0)
+    }
+}
+
+pub struct TxToken<'a>(&'a mut VecDeque<Vec<u8>>);
+
+impl<'a> phy::TxToken for TxToken<'a> {
+    fn consume<R, F>(self, len: usize, f: F) -> R
+    where
+        F: FnOnce(&mut [u8]) -> R,
+    {
+        let mut packet = vec![0u8; len];
+        let res = f(&mut packet);
+        self.0.push_back(packet);
+        res
+    }
+}
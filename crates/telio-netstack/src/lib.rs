@@ -0,0 +1,12 @@
+#![deny(missing_docs)]
+//! Userspace TCP/IP stack, terminating meshnet traffic without any kernel interface
+//!
+//! Packets are exchanged with userspace WireGuard adapter through [telio_wg::PacketIo], while
+//! applications reach meshnet peers through local SOCKS5 proxy and port forward listeners.
+//! Only TCP is supported.
+
+mod device;
+mod netstack;
+mod socks;
+
+pub use crate::netstack::*;
@@ -0,0 +1,712 @@
+use async_trait::async_trait;
+use futures::{future::pending, stream::FuturesUnordered, StreamExt};
+use smoltcp::{
+    iface::{Config, Interface, SocketHandle, SocketSet},
+    socket::tcp,
+    time::{Duration as SmolDuration, Instant as SmolInstant},
+    wire::{HardwareAddress, IpAddress, IpCidr},
+};
+use std::{
+    collections::HashMap,
+    io,
+    net::{IpAddr, SocketAddr},
+    sync::{Arc, RwLock},
+    time::Duration,
+};
+use telio_model::api_config::FeatureNetstack;
+use telio_task::{task_exec, Runtime, RuntimeExt, Task, WaitResponse};
+use telio_utils::{telio_log_debug, telio_log_info, telio_log_warn};
+use telio_wg::PacketIo;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+    sync::{mpsc, oneshot},
+    task::JoinHandle,
+    time,
+};
+
+use crate::{
+    device::{QueueDevice, MTU},
+    socks,
+};
+
+/// Size of TCP socket's receive and transmit buffers
+const SOCKET_BUFFER: usize = 64 * 1024;
+/// Chunks of received data, queued for the local client
+const STREAM_CAPACITY: usize = 16;
+/// Connection is aborted, if peer does not respond for this long
+const TCP_TIMEOUT_SECS: u64 = 30;
+/// Largest packet, which can be received from WireGuard
+const MAX_PACKET: usize = 65536;
+/// Delay before accepting again, after listener has failed
+const ACCEPT_BACKOFF: Duration = Duration::from_millis(100);
+/// Local ports of outgoing connections
+const EPHEMERAL_PORTS: std::ops::RangeInclusive<u16> = 49152..=65535;
+
+#[derive(Debug, thiserror::Error)]
+/// Custom `Netstack` error
+pub enum Error {
+    /// Netstack has no meshnet address of the required family
+    #[error("Netstack has no address to connect from")]
+    NoAddress,
+    /// Peer did not accept the connection
+    #[error("Failed to connect to {0}")]
+    ConnectFailed(SocketAddr),
+    /// Connection was closed, while data was being sent
+    #[error("Connection was closed")]
+    ConnectionClosed,
+    /// Netstack was stopped
+    #[error("Netstack was stopped")]
+    Stopped,
+    /// TCP/IP stack error
+    #[error("TCP/IP stack error: {0}")]
+    Smoltcp(#[from] tcp::ConnectError),
+    /// IO error
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    /// Task execution failed
+    #[error(transparent)]
+    Task(#[from] telio_task::ExecError),
+}
+
+/// Userspace TCP/IP stack, with SOCKS5 and port forward listeners
+pub struct Netstack {
+    task: Task<State>,
+    commands: mpsc::UnboundedSender<Command>,
+    hosts: Arc<RwLock<HashMap<String, IpAddr>>>,
+    listeners: Vec<JoinHandle<()>>,
+}
+
+/// Where connections of a local listener are forwarded to
+#[derive(Clone)]
+enum Target {
+    /// Destination is requested by SOCKS5 client, names are resolved through shared hosts
+    Socks5(Arc<RwLock<HashMap<String, IpAddr>>>),
+    /// Fixed destination
+    Remote(SocketAddr),
+}
+
+type Connected = Result<(SocketHandle, mpsc::Receiver<Vec<u8>>), Error>;
+
+enum Command {
+    Connect {
+        remote: SocketAddr,
+        reply: oneshot::Sender<Connected>,
+    },
+    Write {
+        handle: SocketHandle,
+        data: Vec<u8>,
+        written: oneshot::Sender<()>,
+    },
+    Close(SocketHandle),
+}
+
+struct State {
+    io: Arc<dyn PacketIo>,
+    stack: Option<Stack>,
+    addresses: Vec<IpAddr>,
+    connections: HashMap<SocketHandle, Connection>,
+    commands: mpsc::UnboundedReceiver<Command>,
+    next_port: u16,
+    packet: Vec<u8>,
+}
+
+/// smoltcp interface, together with its device and sockets
+struct Stack {
+    iface: Interface,
+    device: QueueDevice,
+    sockets: SocketSet<'static>,
+}
+
+struct Connection {
+    remote: SocketAddr,
+    // Connect request, answered once the handshake completes
+    connecting: Option<(oneshot::Sender<Connected>, mpsc::Receiver<Vec<u8>>)>,
+    to_client: Option<mpsc::Sender<Vec<u8>>>,
+    // Received data waits for the client to make room in its queue
+    blocked: bool,
+    pending: Option<PendingWrite>,
+    closing: bool,
+}
+
+struct PendingWrite {
+    data: Vec<u8>,
+    offset: usize,
+    written: oneshot::Sender<()>,
+}
+
+/// Connection through the netstack, as seen by local client
+struct Stream {
+    writer: StreamWriter,
+    data: mpsc::Receiver<Vec<u8>>,
+}
+
+struct StreamWriter {
+    handle: SocketHandle,
+    commands: mpsc::UnboundedSender<Command>,
+}
+
+enum Wake {
+    Packet(io::Result<usize>),
+    Command(Command),
+    Unblocked,
+    Timer,
+}
+
+impl Netstack {
+    /// Start netstack, exchanging packets with WireGuard through `io`, and its local listeners
+    pub async fn start(io: Arc<dyn PacketIo>, config: &FeatureNetstack) -> Result<Self, Error> {
+        let hosts = Arc::new(RwLock::new(HashMap::new()));
+
+        let mut listeners = Vec::new();
+        if let Some(addr) = config.socks5 {
+            listeners.push((
+                TcpListener::bind(addr).await?,
+                Target::Socks5(hosts.clone()),
+            ));
+            telio_log_info!("Netstack: SOCKS5 proxy listens on {}", addr);
+        }
+        for forward in &config.forwards {
+            listeners.push((
+                TcpListener::bind(forward.listen).await?,
+                Target::Remote(forward.remote),
+            ));
+            telio_log_info!(
+                "Netstack: forwarding {} to {}",
+                forward.listen,
+                forward.remote
+            );
+        }
+
+        let (commands, commands_rx) = mpsc::unbounded_channel();
+        let listeners = listeners
+            .into_iter()
+            .map(|(listener, target)| tokio::spawn(serve(listener, target, commands.clone())))
+            .collect();
+
+        Ok(Self {
+            task: Task::start(State {
+                io,
+                stack: None,
+                addresses: Vec::new(),
+                connections: HashMap::new(),
+                commands: commands_rx,
+                next_port: *EPHEMERAL_PORTS.start(),
+                packet: vec![0u8; MAX_PACKET],
+            }),
+            commands,
+            hosts,
+            listeners,
+        })
+    }
+
+    /// Update netstack's own meshnet addresses and names of meshnet peers
+    pub async fn configure(
+        &self,
+        addresses: Vec<IpAddr>,
+        hosts: HashMap<String, IpAddr>,
+    ) -> Result<(), Error> {
+        if let Ok(mut current) = self.hosts.write() {
+            *current = hosts
+                .into_iter()
+                .map(|(name, ip)| (name.trim_end_matches('.').to_lowercase(), ip))
+                .collect();
+        }
+
+        task_exec!(&self.task, async move |s| {
+            s.set_addresses(addresses);
+            Ok(())
+        })
+        .await?;
+        Ok(())
+    }
+
+    /// Stop netstack and its listeners
+    pub async fn stop(self) {
+        for listener in &self.listeners {
+            listener.abort();
+        }
+        let _ = self.task.stop().await.resume_unwind();
+    }
+}
+
+impl State {
+    fn set_addresses(&mut self, addresses: Vec<IpAddr>) {
+        if addresses == self.addresses {
+            return;
+        }
+        telio_log_debug!("Netstack: addresses changed to {:?}", addresses);
+
+        // Connections are bound to the old addresses, dropping them closes client streams
+        self.connections.clear();
+        self.addresses = addresses;
+        self.stack = self.build_stack();
+    }
+
+    fn build_stack(&self) -> Option<Stack> {
+        if self.addresses.is_empty() {
+            return None;
+        }
+
+        let mut config = Config::new(HardwareAddress::Ip);
+        // Initial TCP sequence numbers are derived from the seed, so they must not be guessable
+        config.random_seed = rand::random();
+
+        let mut device = QueueDevice::default();
+        let mut iface = Interface::new(config, &mut device, SmolInstant::now());
+        for ip in &self.addresses {
+            let cidr = match ip {
+                IpAddr::V4(ip) => IpCidr::new(IpAddress::from(*ip), 32),
+                IpAddr::V6(ip) => IpCidr::new(IpAddress::from(*ip), 128),
+            };
+            iface.update_ip_addrs(|addrs| {
+                if addrs.push(cidr).is_err() {
+                    telio_log_warn!("Netstack: no room for address {}", ip);
+                }
+            });
+
+            // Every packet leaves through WireGuard, thus our own address is the gateway
+            let res = match ip {
+                IpAddr::V4(ip) => iface.routes_mut().add_default_ipv4_route((*ip).into()),
+                IpAddr::V6(ip) => iface.routes_mut().add_default_ipv6_route((*ip).into()),
+            };
+            if let Err(e) = res {
+                telio_log_warn!("Netstack: failed to add route via {}: {}", ip, e);
+            }
+        }
+
+        Some(Stack {
+            iface,
+            device,
+            sockets: SocketSet::new(vec![]),
+        })
+    }
+
+    fn handle(&mut self, command: Command) {
+        match command {
+            Command::Connect { remote, reply } => match self.open(remote) {
+                Ok(handle) => {
+                    let (to_client, data) = mpsc::channel(STREAM_CAPACITY);
+                    self.connections.insert(
+                        handle,
+                        Connection {
+                            remote,
+                            connecting: Some((reply, data)),
+                            to_client: Some(to_client),
+                            blocked: false,
+                            pending: None,
+                            closing: false,
+                        },
+                    );
+                }
+                Err(e) => {
+                    let _ = reply.send(Err(e));
+                }
+            },
+            Command::Write {
+                handle,
+                data,
+                written,
+            } => {
+                // Dropping `written` of unknown connection fails the write
+                if let Some(conn) = self.connections.get_mut(&handle) {
+                    if conn.pending.is_none() && !conn.closing {
+                        conn.pending = Some(PendingWrite {
+                            data,
+                            offset: 0,
+                            written,
+                        });
+                    }
+                }
+            }
+            Command::Close(handle) => {
+                if let Some(conn) = self.connections.get_mut(&handle) {
+                    conn.closing = true;
+                }
+            }
+        }
+    }
+
+    fn open(&mut self, remote: SocketAddr) -> Result<SocketHandle, Error> {
+        let local = self
+            .addresses
+            .iter()
+            .find(|ip| ip.is_ipv4() == remote.is_ipv4())
+            .copied()
+            .ok_or(Error::NoAddress)?;
+        let local_port = self.allocate_port();
+        let stack = self.stack.as_mut().ok_or(Error::NoAddress)?;
+
+        let mut socket = tcp::Socket::new(
+            tcp::SocketBuffer::new(vec![0u8; SOCKET_BUFFER]),
+            tcp::SocketBuffer::new(vec![0u8; SOCKET_BUFFER]),
+        );
+        socket.set_timeout(Some(SmolDuration::from_secs(TCP_TIMEOUT_SECS)));
+        socket.connect(
+            stack.iface.context(),
+            remote,
+            (IpAddress::from(local), local_port),
+        )?;
+
+        Ok(stack.sockets.add(socket))
+    }
+
+    fn allocate_port(&mut self) -> u16 {
+        let port = self.next_port;
+        self.next_port = if port == *EPHEMERAL_PORTS.end() {
+            *EPHEMERAL_PORTS.start()
+        } else {
+            port + 1
+        };
+        port
+    }
+
+    /// Time until smoltcp has to be polled for its timers, if it has any
+    fn poll_delay(&mut self) -> Option<Duration> {
+        let stack = self.stack.as_mut()?;
+        stack
+            .iface
+            .poll_delay(SmolInstant::now(), &stack.sockets)
+            .map(Duration::from)
+    }
+
+    fn poll(&mut self) {
+        if let Some(stack) = self.stack.as_mut() {
+            stack
+                .iface
+                .poll(SmolInstant::now(), &mut stack.device, &mut stack.sockets);
+        }
+    }
+
+    /// Move data between TCP sockets and their clients
+    fn service(&mut self) {
+        let stack = match self.stack.as_mut() {
+            Some(stack) => stack,
+            None => return,
+        };
+
+        let mut finished = Vec::new();
+        for (handle, conn) in self.connections.iter_mut() {
+            if conn.service(*handle, stack.sockets.get_mut::<tcp::Socket>(*handle)) {
+                finished.push(*handle);
+            }
+        }
+
+        for handle in finished {
+            self.connections.remove(&handle);
+            stack.sockets.remove(handle);
+        }
+    }
+
+    async fn flush(&mut self) {
+        let packets = match self.stack.as_mut() {
+            Some(stack) => stack.device.take_tx(),
+            None => return,
+        };
+
+        for packet in packets {
+            if let Err(e) = self.io.send(&packet).await {
+                telio_log_debug!("Netstack: failed to send packet: {}", e);
+            }
+        }
+    }
+}
+
+impl Connection {
+    /// Returns `true`, once connection is finished and its socket can be removed
+    fn service(&mut self, handle: SocketHandle, socket: &mut tcp::Socket) -> bool {
+        if let Some((reply, data)) = self.connecting.take() {
+            if socket.may_send() {
+                let _ = reply.send(Ok((handle, data)));
+            } else if socket.state() == tcp::State::Closed {
+                let _ = reply.send(Err(Error::ConnectFailed(self.remote)));
+                return true;
+            } else {
+                self.connecting = Some((reply, data));
+                return false;
+            }
+        }
+
+        // Client -> peer
+        if let Some(pending) = &mut self.pending {
+            if socket.can_send() {
+                pending.offset += socket
+                    .send_slice(&pending.data[pending.offset..])
+                    .unwrap_or_default();
+            }
+            if pending.offset == pending.data.len() {
+                if let Some(pending) = self.pending.take() {
+                    let _ = pending.written.send(());
+                }
+            }
+        }
+        if self.closing && self.pending.is_none() {
+            socket.close();
+        }
+
+        // Peer -> client
+        self.blocked = false;
+        if let Some(to_client) = &self.to_client {
+            while socket.can_recv() {
+                let permit = match to_client.try_reserve() {
+                    Ok(permit) => permit,
+                    Err(_) => {
+                        self.blocked = true;
+                        break;
+                    }
+                };
+                if let Ok(data) = socket.recv(|buf| (buf.len(), buf.to_vec())) {
+                    permit.send(data);
+                }
+            }
+
+            let eof = !socket.may_recv() && !socket.can_recv();
+            if eof || to_client.is_closed() {
+                // Dropping the sender signals end of stream to the client
+                self.to_client = None;
+            }
+        }
+
+        match socket.state() {
+            // Client is gone, and peer is not closing its side
+            tcp::State::FinWait2 if self.closing && self.to_client.is_none() => {
+                socket.abort();
+                false
+            }
+            tcp::State::Closed | tcp::State::TimeWait => {
+                self.to_client.is_none() || !socket.can_recv()
+            }
+            _ => false,
+        }
+    }
+}
+
+#[async_trait]
+impl Runtime for State {
+    const NAME: &'static str = "Netstack";
+
+    type Err = ();
+
+    async fn wait(&mut self) -> WaitResponse<'_, Self::Err> {
+        let delay = self.poll_delay();
+
+        // Stack is polled only when there is something to do: a packet arrives, local client
+        // makes a request or room for received data, or one of smoltcp's timers expires
+        let wake = {
+            let mut unblocked: FuturesUnordered<_> = self
+                .connections
+                .values()
+                .filter(|conn| conn.blocked)
+                .filter_map(|conn| conn.to_client.as_ref())
+                .map(|to_client| to_client.reserve())
+                .collect();
+
+            tokio::select! {
+                res = self.io.recv(&mut self.packet) => Wake::Packet(res),
+                Some(command) = self.commands.recv() => Wake::Command(command),
+                Some(_) = unblocked.next() => Wake::Unblocked,
+                _ = sleep(delay) => Wake::Timer,
+            }
+        };
+
+        match wake {
+            Wake::Packet(Ok(len)) => {
+                if let Some(stack) = self.stack.as_mut() {
+                    stack.device.push_rx(self.packet[..len].to_vec());
+                }
+            }
+            Wake::Packet(Err(e)) if e.kind() == io::ErrorKind::BrokenPipe => {
+                telio_log_warn!("Netstack: packet I/O was closed");
+                return Self::error(());
+            }
+            Wake::Packet(Err(e)) => {
+                telio_log_debug!("Netstack: failed to receive packet: {}", e);
+            }
+            Wake::Command(command) => self.handle(command),
+            Wake::Unblocked | Wake::Timer => (),
+        }
+
+        Self::guard(async move {
+            self.poll();
+            self.service();
+            // Serviced sockets may have queued data or state changes
+            self.poll();
+            self.flush().await;
+            Ok(())
+        })
+    }
+}
+
+impl StreamWriter {
+    /// Send data to peer, returns once it is queued in socket's buffer
+    async fn write(&self, data: Vec<u8>) -> Result<(), Error> {
+        let (written, done) = oneshot::channel();
+        self.commands
+            .send(Command::Write {
+                handle: self.handle,
+                data,
+                written,
+            })
+            .map_err(|_| Error::Stopped)?;
+        done.await.map_err(|_| Error::ConnectionClosed)
+    }
+}
+
+impl Drop for StreamWriter {
+    fn drop(&mut self) {
+        let _ = self.commands.send(Command::Close(self.handle));
+    }
+}
+
+/// Sleep for the delay, or forever if there is none
+async fn sleep(delay: Option<Duration>) {
+    match delay {
+        Some(delay) => time::sleep(delay).await,
+        None => pending().await,
+    }
+}
+
+async fn connect(
+    commands: &mpsc::UnboundedSender<Command>,
+    remote: SocketAddr,
+) -> Result<Stream, Error> {
+    let (reply, connected) = oneshot::channel();
+    commands
+        .send(Command::Connect { remote, reply })
+        .map_err(|_| Error::Stopped)?;
+    let (handle, data) = connected.await.map_err(|_| Error::Stopped)??;
+
+    Ok(Stream {
+        writer: StreamWriter {
+            handle,
+            commands: commands.clone(),
+        },
+        data,
+    })
+}
+
+async fn serve(listener: TcpListener, target: Target, commands: mpsc::UnboundedSender<Command>) {
+    loop {
+        let (client, addr) = match listener.accept().await {
+            Ok(accepted) => accepted,
+            Err(e) => {
+                telio_log_warn!("Netstack: failed to accept connection: {}", e);
+                time::sleep(ACCEPT_BACKOFF).await;
+                continue;
+            }
+        };
+
+        let target = target.clone();
+        let commands = commands.clone();
+        tokio::spawn(async move {
+            if let Err(e) = handle_client(client, target, commands).await {
+                telio_log_debug!("Netstack: connection from {} failed: {}", addr, e);
+            }
+        });
+    }
+}
+
+async fn handle_client(
+    mut client: TcpStream,
+    target: Target,
+    commands: mpsc::UnboundedSender<Command>,
+) -> Result<(), Error> {
+    let stream = match target {
+        Target::Remote(remote) => connect(&commands, remote).await?,
+        Target::Socks5(hosts) => {
+            let hosts = hosts.read().map(|h| h.clone()).unwrap_or_default();
+            let remote = socks::handshake(&mut client, &hosts).await?;
+            match connect(&commands, remote).await {
+                Ok(stream) => {
+                    socks::reply(&mut client, socks::SUCCEEDED).await?;
+                    stream
+                }
+                Err(e) => {
+                    let code = match e {
+                        Error::ConnectFailed(_) | Error::NoAddress => socks::HOST_UNREACHABLE,
+                        _ => socks::GENERAL_FAILURE,
+                    };
+                    socks::reply(&mut client, code).await?;
+                    return Err(e);
+                }
+            }
+        }
+    };
+
+    splice(client, stream).await
+}
+
+/// Copy data both ways between local client and connection through netstack
+async fn splice(client: TcpStream, stream: Stream) -> Result<(), Error> {
+    let Stream { writer, mut data } = stream;
+    let (mut client_rx, mut client_tx) = client.into_split();
+
+    let upload = async move {
+        let mut buf = vec![0u8; MTU * 8];
+        loop {
+            let len = client_rx.read(&mut buf).await?;
+            if len == 0 {
+                // Dropping the writer closes our side of connection
+                return Ok::<_, Error>(());
+            }
+            writer.write(buf[..len].to_vec()).await?;
+        }
+    };
+
+    let download = async move {
+        while let Some(chunk) = data.recv().await {
+            client_tx.write_all(&chunk).await?;
+        }
+        client_tx.shutdown().await?;
+        Ok::<_, Error>(())
+    };
+
+    let (upload, download) = tokio::join!(upload, download);
+    upload.and(download)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use telio_wg::packet_io::packet_pipe;
+
+    #[tokio::test]
+    async fn connect_sends_syn_through_packet_io() {
+        let (ours, theirs) = packet_pipe();
+        let netstack = Netstack::start(Arc::new(ours), &FeatureNetstack::default())
+            .await
+            .unwrap();
+
+        let remote = SocketAddr::from(([100, 64, 0, 2], 22));
+        assert!(matches!(
+            connect(&netstack.commands, remote).await,
+            Err(Error::NoAddress)
+        ));
+
+        netstack
+            .configure(vec![IpAddr::from([100, 64, 0, 1])], HashMap::new())
+            .await
+            .unwrap();
+
+        let commands = netstack.commands.clone();
+        let connecting = tokio::spawn(async move { connect(&commands, remote).await.is_ok() });
+
+        let mut buf = vec![0u8; MAX_PACKET];
+        let len = time::timeout(Duration::from_secs(5), theirs.recv(&mut buf))
+            .await
+            .unwrap()
+            .unwrap();
+        let packet = &buf[..len];
+        let tcp = &packet[((packet[0] & 0x0f) * 4) as usize..];
+
+        assert_eq!(4, packet[0] >> 4);
+        assert_eq!(6, packet[9]);
+        assert_eq!(&[100, 64, 0, 1], &packet[12..16]);
+        assert_eq!(&[100, 64, 0, 2], &packet[16..20]);
+        assert_eq!(&22u16.to_be_bytes(), &tcp[2..4]);
+        assert_ne!(0, tcp[13] & 0x02, "SYN flag is set");
+
+        connecting.abort();
+        netstack.stop().await;
+    }
+}
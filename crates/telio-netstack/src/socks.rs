@@ -0,0 +1,163 @@
+//! Minimal SOCKS5 server side (RFC 1928), only CONNECT without authentication is supported
+
+use std::{
+    collections::HashMap,
+    io,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+const VERSION: u8 = 5;
+
+const NO_AUTH: u8 = 0x00;
+const NO_ACCEPTABLE_METHODS: u8 = 0xff;
+
+const CONNECT: u8 = 1;
+
+const ATYP_IPV4: u8 = 1;
+const ATYP_DOMAIN: u8 = 3;
+const ATYP_IPV6: u8 = 4;
+
+/// Reply codes
+pub const SUCCEEDED: u8 = 0x00;
+pub const GENERAL_FAILURE: u8 = 0x01;
+pub const HOST_UNREACHABLE: u8 = 0x04;
+const COMMAND_NOT_SUPPORTED: u8 = 0x07;
+const ADDRESS_TYPE_NOT_SUPPORTED: u8 = 0x08;
+
+/// Negotiate with SOCKS5 client, returning address it wants to connect to. Domain names are
+/// resolved through `hosts`, as only meshnet peers are reachable.
+pub async fn handshake<S>(client: &mut S, hosts: &HashMap<String, IpAddr>) -> io::Result<SocketAddr>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let mut header = [0u8; 2];
+    client.read_exact(&mut header).await?;
+    if header[0] != VERSION {
+        return Err(invalid("unsupported SOCKS version"));
+    }
+
+    let mut methods = vec![0u8; header[1] as usize];
+    client.read_exact(&mut methods).await?;
+    if !methods.contains(&NO_AUTH) {
+        client.write_all(&[VERSION, NO_ACCEPTABLE_METHODS]).await?;
+        return Err(invalid("client requires authentication"));
+    }
+    client.write_all(&[VERSION, NO_AUTH]).await?;
+
+    let mut request = [0u8; 4];
+    client.read_exact(&mut request).await?;
+    let ip = match request[3] {
+        ATYP_IPV4 => {
+            let mut ip = [0u8; 4];
+            client.read_exact(&mut ip).await?;
+            Some(Ipv4Addr::from(ip).into())
+        }
+        ATYP_IPV6 => {
+            let mut ip = [0u8; 16];
+            client.read_exact(&mut ip).await?;
+            Some(Ipv6Addr::from(ip).into())
+        }
+        ATYP_DOMAIN => {
+            let mut len = [0u8; 1];
+            client.read_exact(&mut len).await?;
+            let mut name = vec![0u8; len[0] as usize];
+            client.read_exact(&mut name).await?;
+            resolve(&String::from_utf8_lossy(&name), hosts)
+        }
+        _ => {
+            reply(client, ADDRESS_TYPE_NOT_SUPPORTED).await?;
+            return Err(invalid("unsupported address type"));
+        }
+    };
+
+    let mut port = [0u8; 2];
+    client.read_exact(&mut port).await?;
+
+    if request[1] != CONNECT {
+        reply(client, COMMAND_NOT_SUPPORTED).await?;
+        return Err(invalid("unsupported command"));
+    }
+
+    match ip {
+        Some(ip) => Ok(SocketAddr::new(ip, u16::from_be_bytes(port))),
+        None => {
+            reply(client, HOST_UNREACHABLE).await?;
+            Err(invalid("unknown host"))
+        }
+    }
+}
+
+/// Finish negotiation with the client
+pub async fn reply<S>(client: &mut S, code: u8) -> io::Result<()>
+where
+    S: AsyncWrite + Unpin,
+{
+    // Bound address is meaningless for the client, as connections are made from the netstack
+    client
+        .write_all(&[VERSION, code, 0, ATYP_IPV4, 0, 0, 0, 0, 0, 0])
+        .await
+}
+
+fn resolve(name: &str, hosts: &HashMap<String, IpAddr>) -> Option<IpAddr> {
+    let name = name.trim_end_matches('.');
+    name.parse()
+        .ok()
+        .or_else(|| hosts.get(&name.to_lowercase()).copied())
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::duplex;
+
+    #[tokio::test]
+    async fn negotiates_connect_to_meshnet_host() {
+        let hosts = vec![(String::from("peer.nord"), IpAddr::from([100, 64, 0, 2]))]
+            .into_iter()
+            .collect();
+        let (mut client, mut server) = duplex(64);
+
+        client.write_all(&[VERSION, 1, NO_AUTH]).await.unwrap();
+        let mut request = vec![VERSION, CONNECT, 0, ATYP_DOMAIN, 10];
+        request.extend_from_slice(b"peer.nord.");
+        request.extend_from_slice(&22u16.to_be_bytes());
+        client.write_all(&request).await.unwrap();
+
+        assert_eq!(
+            SocketAddr::from(([100, 64, 0, 2], 22)),
+            handshake(&mut server, &hosts).await.unwrap()
+        );
+
+        let mut method = [0u8; 2];
+        client.read_exact(&mut method).await.unwrap();
+        assert_eq!([VERSION, NO_AUTH], method);
+    }
+
+    #[tokio::test]
+    async fn rejects_unsupported_requests() {
+        let hosts = HashMap::new();
+        let (mut client, mut server) = duplex(64);
+
+        client.write_all(&[VERSION, 1, 0x02]).await.unwrap();
+        assert!(handshake(&mut server, &hosts).await.is_err());
+        let mut method = [0u8; 2];
+        client.read_exact(&mut method).await.unwrap();
+        assert_eq!([VERSION, NO_ACCEPTABLE_METHODS], method);
+
+        let (mut client, mut server) = duplex(64);
+        client.write_all(&[VERSION, 1, NO_AUTH]).await.unwrap();
+        client
+            .write_all(&[VERSION, 2, 0, ATYP_IPV4, 100, 64, 0, 2, 0, 22])
+            .await
+            .unwrap();
+        assert!(handshake(&mut server, &hosts).await.is_err());
+        let mut response = [0u8; 12];
+        client.read_exact(&mut response).await.unwrap();
+        assert_eq!(COMMAND_NOT_SUPPORTED, response[3]);
+    }
+}
@@ -0,0 +1,284 @@
+use std::collections::HashMap;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crypto_box::{
+    aead::{Aead, Nonce, Payload},
+    ChaChaBox,
+};
+use telio_crypto::{PublicKey, SecretKey};
+use telio_proto::HeartbeatMessage;
+
+/// Reason why a heartbeat message was rejected
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Rejection {
+    /// Message is not signed, while signatures are enforced
+    Unsigned,
+    /// Tag does not match the keys of sender and receiver
+    Unauthenticated,
+    /// Timestamp is too far from the local clock
+    Stale,
+    /// Nonce was already used by an accepted message
+    Replayed,
+}
+
+/// Number of rejected heartbeat messages, by reason
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Rejections {
+    /// Messages without authentication tag, while signatures are enforced
+    pub unsigned: u64,
+    /// Messages with invalid authentication tag
+    pub unauthenticated: u64,
+    /// Messages with timestamp outside of the accepted window
+    pub stale: u64,
+    /// Messages with already seen nonce
+    pub replayed: u64,
+}
+
+/// Authenticates heartbeat messages exchanged with other nodes.
+///
+/// The tag is a Poly1305 tag of the box between keys of sender and receiver, over all other
+/// fields of the message, so only the node owning the sender's key could have produced it.
+/// Timestamp and nonce are covered by the tag and protect against replaying captured messages.
+///
+/// Nodes, which do not sign their messages yet, are accepted unless signatures are enforced,
+/// while messages with a tag are always verified.
+pub struct Authenticator {
+    secret_key: SecretKey,
+    max_clock_skew: Duration,
+    enforce: bool,
+    // Number of received messages without tag, accepted or not
+    unsigned: u64,
+    // Nonces of accepted messages, mapped to their timestamps, kept until they would be stale anyway
+    seen_nonces: HashMap<Vec<u8>, u64>,
+    rejections: Rejections,
+}
+
+impl Authenticator {
+    const NONCE_SIZE: usize = 24;
+
+    /// Create authenticator for node with the given key
+    ///
+    /// # Arguments
+    ///
+    /// * `secret_key` - Private key of this node.
+    /// * `max_clock_skew` - Maximum accepted difference between timestamp of a message and local clock.
+    /// * `enforce` - Reject messages, which are not signed.
+    pub fn new(secret_key: SecretKey, max_clock_skew: Duration, enforce: bool) -> Self {
+        Self {
+            secret_key,
+            max_clock_skew,
+            enforce,
+            unsigned: 0,
+            seen_nonces: HashMap::new(),
+            rejections: Rejections::default(),
+        }
+    }
+
+    /// Update the private key of this node
+    pub fn set_secret_key(&mut self, secret_key: SecretKey) {
+        self.secret_key = secret_key;
+    }
+
+    /// Counts of rejected messages since the start
+    pub fn rejections(&self) -> Rejections {
+        self.rejections
+    }
+
+    /// Count of received messages without tag since the start, including rejected ones
+    pub fn unsigned(&self) -> u64 {
+        self.unsigned
+    }
+
+    /// Stamp the message with current time and a fresh nonce, and authenticate it for the receiver
+    pub fn sign(&self, receiver: PublicKey, message: &mut HeartbeatMessage) {
+        let nonce = crypto_box::generate_nonce(&mut rand::thread_rng());
+        message.set_timestamp_and_nonce(Self::now(), nonce.to_vec());
+        if let Some(tag) = self.tag(receiver, message) {
+            message.set_tag(tag);
+        }
+    }
+
+    /// Check that the message was authenticated by the sender for this node and was not seen before
+    pub fn verify(
+        &mut self,
+        sender: PublicKey,
+        message: &HeartbeatMessage,
+    ) -> Result<(), Rejection> {
+        let result = self.check(sender, message);
+        match result {
+            Err(Rejection::Unsigned) => self.rejections.unsigned += 1,
+            Err(Rejection::Unauthenticated) => self.rejections.unauthenticated += 1,
+            Err(Rejection::Stale) => self.rejections.stale += 1,
+            Err(Rejection::Replayed) => self.rejections.replayed += 1,
+            Ok(()) => (),
+        }
+        result
+    }
+
+    fn check(&mut self, sender: PublicKey, message: &HeartbeatMessage) -> Result<(), Rejection> {
+        if message.get_tag().is_empty() {
+            self.unsigned += 1;
+            return match self.enforce {
+                true => Err(Rejection::Unsigned),
+                false => Ok(()),
+            };
+        }
+        if message.get_nonce().len() != Self::NONCE_SIZE {
+            return Err(Rejection::Unauthenticated);
+        }
+        // Verify the tag first, so forged messages cannot fill the nonce cache
+        if self.tag(sender, message).as_deref() != Some(message.get_tag()) {
+            return Err(Rejection::Unauthenticated);
+        }
+
+        let now = Self::now();
+        let max_skew = self.max_clock_skew.as_millis() as u64;
+        if distance(now, message.get_timestamp()) > max_skew {
+            return Err(Rejection::Stale);
+        }
+
+        self.seen_nonces
+            .retain(|_, timestamp| distance(now, *timestamp) <= max_skew);
+        if self
+            .seen_nonces
+            .insert(message.get_nonce().to_vec(), message.get_timestamp())
+            .is_some()
+        {
+            return Err(Rejection::Replayed);
+        }
+
+        Ok(())
+    }
+
+    fn tag(&self, peer: PublicKey, message: &HeartbeatMessage) -> Option<Vec<u8>> {
+        let nonce = message.get_nonce();
+        if nonce.len() != Self::NONCE_SIZE {
+            return None;
+        }
+        let data = message.authenticated_data().ok()?;
+
+        // Box is symmetric, both nodes derive the same key from their own private and other's public key
+        ChaChaBox::new(&peer.into(), &self.secret_key.into())
+            .encrypt(
+                Nonce::<ChaChaBox>::from_slice(nonce),
+                Payload {
+                    msg: &[],
+                    aad: &data,
+                },
+            )
+            .ok()
+    }
+
+    fn now() -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or_default()
+    }
+}
+
+fn distance(a: u64, b: u64) -> u64 {
+    a.max(b) - a.min(b)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SKEW: Duration = Duration::from_secs(60);
+
+    fn pair() -> (Authenticator, PublicKey, Authenticator, PublicKey) {
+        let (a, b) = (SecretKey::gen(), SecretKey::gen());
+        (
+            Authenticator::new(a, SKEW, false),
+            a.public(),
+            Authenticator::new(b, SKEW, false),
+            b.public(),
+        )
+    }
+
+    #[test]
+    fn signed_message_is_accepted_once() {
+        let (alice, alice_pk, mut bob, bob_pk) = pair();
+
+        let mut message = HeartbeatMessage::request();
+        alice.sign(bob_pk, &mut message);
+
+        assert_eq!(bob.verify(alice_pk, &message), Ok(()));
+        assert_eq!(bob.verify(alice_pk, &message), Err(Rejection::Replayed));
+        assert_eq!(
+            bob.rejections(),
+            Rejections {
+                replayed: 1,
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn forged_and_tampered_messages_are_rejected() {
+        let (alice, alice_pk, mut bob, bob_pk) = pair();
+        let mallory = Authenticator::new(SecretKey::gen(), SKEW, false);
+
+        let mut forged = HeartbeatMessage::request();
+        mallory.sign(bob_pk, &mut forged);
+        assert_eq!(
+            bob.verify(alice_pk, &forged),
+            Err(Rejection::Unauthenticated)
+        );
+
+        let mut tampered = HeartbeatMessage::request();
+        alice.sign(bob_pk, &mut tampered);
+        let (tag, nonce) = (tampered.get_tag().to_vec(), tampered.get_nonce().to_vec());
+        tampered.set_timestamp_and_nonce(tampered.get_timestamp() + 1, nonce);
+        tampered.set_tag(tag);
+        assert_eq!(
+            bob.verify(alice_pk, &tampered),
+            Err(Rejection::Unauthenticated)
+        );
+
+        let mut bad_nonce = HeartbeatMessage::request();
+        alice.sign(bob_pk, &mut bad_nonce);
+        let tag = bad_nonce.get_tag().to_vec();
+        bad_nonce.set_timestamp_and_nonce(bad_nonce.get_timestamp(), vec![0; 8]);
+        bad_nonce.set_tag(tag);
+        assert_eq!(
+            bob.verify(alice_pk, &bad_nonce),
+            Err(Rejection::Unauthenticated)
+        );
+
+        assert_eq!(bob.rejections().unauthenticated, 3);
+    }
+
+    #[test]
+    fn unsigned_message_is_accepted_unless_enforced() {
+        let (_, alice_pk, mut bob, _) = pair();
+        let mut strict = Authenticator::new(SecretKey::gen(), SKEW, true);
+
+        let unsigned = HeartbeatMessage::request();
+        assert_eq!(bob.verify(alice_pk, &unsigned), Ok(()));
+        assert_eq!(bob.verify(alice_pk, &unsigned), Ok(()));
+        assert_eq!(bob.unsigned(), 2);
+        assert_eq!(bob.rejections(), Rejections::default());
+
+        assert_eq!(strict.verify(alice_pk, &unsigned), Err(Rejection::Unsigned));
+        assert_eq!(strict.unsigned(), 1);
+        assert_eq!(strict.rejections().unsigned, 1);
+    }
+
+    #[test]
+    fn stale_message_is_rejected() {
+        let (alice, alice_pk, mut bob, bob_pk) = pair();
+
+        let mut message = HeartbeatMessage::request();
+        message.set_timestamp_and_nonce(
+            Authenticator::now() - 2 * SKEW.as_millis() as u64,
+            vec![7; 24],
+        );
+        let tag = alice.tag(bob_pk, &message).unwrap();
+        message.set_tag(tag);
+
+        assert_eq!(bob.verify(alice_pk, &message), Err(Rejection::Stale));
+        assert_eq!(bob.rejections().stale, 1);
+    }
+}
@@ -1,4 +1,7 @@
+use std::net::IpAddr;
+
 use telio_model::api_config::{FeatureNurse, FeatureQoS};
+use telio_utils::telio_log_warn;
 use tokio::time::Duration;
 
 use crate::qos::RttType;
@@ -35,6 +38,12 @@ pub struct HeartbeatConfig {
 
     /// The unique identifier of the device, used for meshnet ID
     pub fingerprint: String,
+
+    /// Maximum accepted difference between timestamp of a received heartbeat message and local clock
+    pub max_clock_skew: Duration,
+
+    /// Reject heartbeat messages, which are not signed by their sender
+    pub enforce_auth: bool,
 }
 
 impl HeartbeatConfig {
@@ -52,6 +61,7 @@ impl HeartbeatConfig {
         Self {
             fingerprint: features.fingerprint.clone(),
             collect_interval,
+            enforce_auth: features.enforce_heartbeat_auth,
             ..Default::default()
         }
     }
@@ -64,6 +74,8 @@ impl Default for HeartbeatConfig {
             collect_interval: Duration::from_secs(3600),
             collect_answer_timeout: Duration::from_secs(5),
             fingerprint: String::new(),
+            max_clock_skew: Duration::from_secs(300),
+            enforce_auth: false,
         }
     }
 }
@@ -81,6 +93,9 @@ pub struct QoSConfig {
 
     /// Number of buckets
     pub buckets: u32,
+
+    /// Meshnet IP of this node, RTT is measured through the tunnel only when it is known
+    pub mesh_ip: Option<IpAddr>,
 }
 
 impl QoSConfig {
@@ -88,6 +103,9 @@ impl QoSConfig {
     const DEFAULT_RTT_TRIES: u32 = 3;
     const DEFAULT_BUCKETS: u32 = 5;
 
+    /// Port of nurses for measuring RTT through the tunnel, same for all nodes
+    pub const TUNNEL_RTT_PORT: u16 = 5152;
+
     /// Create a new QoS config
     fn new(qos_features: &Option<FeatureQoS>) -> Self {
         qos_features
@@ -107,8 +125,10 @@ impl QoSConfig {
                         let mut v = Vec::new();
 
                         for ty in types {
-                            if let Ok(t) = serde_json::from_str::<RttType>(ty) {
-                                v.push(t);
+                            match serde_json::from_value::<RttType>(ty.as_str().into()) {
+                                Ok(t) if !v.contains(&t) => v.push(t),
+                                Ok(_) => (),
+                                Err(_) => telio_log_warn!("Unknown rtt type: {}", ty),
                             }
                         }
 
@@ -127,6 +147,7 @@ impl QoSConfig {
                     rtt_tries,
                     rtt_types,
                     buckets,
+                    mesh_ip: None,
                 }
             })
             .unwrap_or_default()
@@ -140,6 +161,34 @@ impl Default for QoSConfig {
             rtt_tries: Self::DEFAULT_RTT_TRIES,
             rtt_types: vec![RttType::Ping],
             buckets: Self::DEFAULT_BUCKETS,
+            mesh_ip: None,
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn qos_config_parses_rtt_types() {
+        let features = FeatureQoS {
+            rtt_types: Some(vec![
+                "Pinger".to_owned(),
+                "Echo".to_owned(),
+                "Pinger".to_owned(),
+                "Unknown".to_owned(),
+            ]),
+            ..Default::default()
+        };
+        let config = QoSConfig::new(&Some(features));
+        assert_eq!(config.rtt_types, vec![RttType::Pinger, RttType::Echo]);
+
+        let features = FeatureQoS {
+            rtt_types: Some(vec!["Unknown".to_owned()]),
+            ..Default::default()
+        };
+        let config = QoSConfig::new(&Some(features));
+        assert_eq!(config.rtt_types, vec![RttType::Ping]);
+    }
+}
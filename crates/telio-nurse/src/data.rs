@@ -1,3 +1,4 @@
+use serde::Serialize;
 use telio_crypto::PublicKey;
 use telio_model::config::Config;
 
@@ -20,6 +21,46 @@ pub struct HeartbeatInfo {
     pub external_links: String,
 }
 
+/// Connection state of a link between two meshnet nodes
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct LinkState {
+    /// Nodes have a WireGuard connection
+    pub wg: bool,
+    /// Nodes are both connected to a relay server
+    pub derp: bool,
+}
+
+/// Internal node of the meshnet
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct MatrixNode {
+    /// Public key of the node
+    pub public_key: PublicKey,
+    /// Fingerprint reported by the node
+    pub fingerprint: String,
+}
+
+/// Link between two internal nodes of the meshnet, regardless of direction
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct MatrixLink {
+    /// Public key of the node with lower key
+    pub node_a: PublicKey,
+    /// Public key of the node with higher key
+    pub node_b: PublicKey,
+    /// Worse of the states reported by both nodes
+    pub state: LinkState,
+}
+
+/// Connectivity matrix of the meshnet, aggregated from the last heartbeat collection
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct ConnectivityMatrix {
+    /// The id of the meshnet
+    pub meshnet_id: String,
+    /// Internal nodes, sorted by public key
+    pub nodes: Vec<MatrixNode>,
+    /// Links between every pair of internal nodes
+    pub links: Vec<MatrixLink>,
+}
+
 /// Analytics data
 pub enum AnalyticsMessage {
     /// Heartbeat analytics message
@@ -15,12 +15,17 @@ use telio_task::{
     io::{chan, mc_chan, Chan},
     Runtime, RuntimeExt, WaitResponse,
 };
-use telio_utils::{telio_log_debug, telio_log_error, telio_log_trace, telio_log_warn};
+use telio_utils::{telio_log_debug, telio_log_error, telio_log_trace, telio_log_warn, telio_span};
 use tokio::time::{interval_at, sleep, Duration, Instant, Interval, Sleep};
 use uuid::Uuid;
 
+use crate::auth::Authenticator;
 use crate::config::HeartbeatConfig;
-use crate::data::{AnalyticsMessage, HeartbeatInfo, MeshConfigUpdateEvent};
+use crate::data::{
+    AnalyticsMessage, ConnectivityMatrix, HeartbeatInfo, LinkState, MatrixLink, MatrixNode,
+    MeshConfigUpdateEvent,
+};
+use crate::metrics::{MetricType, Metrics};
 
 /// Approximately 30 years worth of time, used to pause timers and periods
 const FAR_FUTURE: Duration = Duration::from_secs(86400 * 365 * 30);
@@ -47,6 +52,15 @@ struct MeshLink {
     pub connection_state: MeshConnectionState,
 }
 
+impl From<&MeshLink> for LinkState {
+    fn from(link: &MeshLink) -> Self {
+        LinkState {
+            wg: link.connection_state.contains(MeshConnectionState::WG),
+            derp: link.connection_state.contains(MeshConnectionState::DERP),
+        }
+    }
+}
+
 impl Default for MeshLink {
     fn default() -> Self {
         MeshLink {
@@ -111,6 +125,8 @@ impl Default for NodeInfo {
 /// * A hashmap of meshnet ID's that were received over one collection cycle, used to deduct the "winning" meshnet ID
 /// * A hashmap containing the fingerprints of all of the nodes in the meshnet, indexed by the public keys of the nodes
 /// * The state of DERP connection
+/// * Connectivity matrix built during the last aggregation
+/// * Authenticator of heartbeat messages, signing sent and verifying received ones
 pub struct Analytics {
     task_interval: Interval,
     collect_period: Pin<Box<Sleep>>,
@@ -136,6 +152,10 @@ pub struct Analytics {
     config_local_nodes: HashSet<PublicKey>,
 
     derp_connection: bool,
+
+    connectivity_matrix: Option<ConnectivityMatrix>,
+
+    authenticator: Authenticator,
 }
 
 #[async_trait]
@@ -164,7 +184,7 @@ impl Runtime for Analytics {
 
             // Received data from node, Should always listen for incoming data
             Some((pk, msg)) = self.io.chan.rx.recv() =>
-                Self::guard(async move { self.handle_receive((pk, msg)).await; telio_log_trace!("tokio::select! self.io.chan.rx.recv() branch");Ok(()) }),
+                Self::guard(async move { telio_span!(peer = pk).instrument(self.handle_receive((pk, msg))).await; telio_log_trace!("tokio::select! self.io.chan.rx.recv() branch");Ok(()) }),
 
             // Time to aggregate collected data, only update when in the `Collecting` state
             _ = &mut self.collect_period, if self.state == RuntimeState::Collecting =>
@@ -180,7 +200,7 @@ impl Analytics {
     ///
     /// # Arguments
     ///
-    /// * `public_key` - The public key of the node.
+    /// * `secret_key` - The private key of the node, used to authenticate heartbeat messages.
     /// * `meshnet_id` - The ID of the meshnet the node is in.
     /// * `config` - Configuration for node heart beats.
     /// * `io` - A collection of channel to get different kinds of events.
@@ -188,7 +208,9 @@ impl Analytics {
     /// # Returns
     ///
     /// An empty Analytics instance with the given config
-    pub fn new(public_key: PublicKey, meshnet_id: Uuid, config: HeartbeatConfig, io: Io) -> Self {
+    pub fn new(secret_key: SecretKey, meshnet_id: Uuid, config: HeartbeatConfig, io: Io) -> Self {
+        let public_key = secret_key.public();
+
         let start_time = if let Some(initial_timeout) = config.initial_collect_interval {
             Instant::now() + initial_timeout
         } else {
@@ -199,6 +221,9 @@ impl Analytics {
         // Add self in config_local_nodes hashset
         config_local_nodes.insert(public_key);
 
+        let authenticator =
+            Authenticator::new(secret_key, config.max_clock_skew, config.enforce_auth);
+
         Self {
             task_interval: interval_at(start_time, config.collect_interval),
             collect_period: Box::pin(sleep(FAR_FUTURE)),
@@ -215,6 +240,8 @@ impl Analytics {
             local_nodes: HashMap::new(),
             config_local_nodes,
             derp_connection: false,
+            connectivity_matrix: None,
+            authenticator,
         }
     }
 
@@ -230,6 +257,7 @@ impl Analytics {
                 let old_public_key = self.public_key;
 
                 self.public_key = private_key.public();
+                self.authenticator.set_secret_key(private_key);
 
                 self.config_local_nodes.remove(&old_public_key);
                 self.config_local_nodes.insert(self.public_key);
@@ -283,6 +311,81 @@ impl Analytics {
         }
     }
 
+    /// Write relay state and states of links with local nodes to metrics
+    pub fn write_metrics(&self, metrics: &mut Metrics) {
+        metrics.family(
+            "telio_relay_connected",
+            MetricType::Gauge,
+            "Whether connection to relay server is established",
+        );
+        metrics.sample("telio_relay_connected", &[], self.derp_connection as u8);
+
+        let mut nodes: Vec<_> = self.local_nodes.iter().collect();
+        nodes.sort_by_key(|(public_key, _)| **public_key);
+
+        metrics.family(
+            "telio_mesh_link_up",
+            MetricType::Gauge,
+            "Whether link with the node is up, by type of connection",
+        );
+        for (public_key, node) in nodes {
+            let peer = public_key.to_string();
+            let state = node.mesh_link().connection_state;
+            for (connection, flag) in &[
+                ("wg", MeshConnectionState::WG),
+                ("derp", MeshConnectionState::DERP),
+            ] {
+                metrics.sample(
+                    "telio_mesh_link_up",
+                    &[("peer", &peer), ("connection", connection)],
+                    state.contains(*flag) as u8,
+                );
+            }
+        }
+
+        metrics.family(
+            "telio_mesh_external_links",
+            MetricType::Gauge,
+            "Number of links between other nodes, reported during the last collection",
+        );
+        metrics.sample("telio_mesh_external_links", &[], self.external_links.len());
+
+        let rejections = self.authenticator.rejections();
+        metrics.family(
+            "telio_heartbeat_rejected",
+            MetricType::Counter,
+            "Received heartbeat messages dropped by authentication, by reason",
+        );
+        for (reason, count) in &[
+            ("unsigned", rejections.unsigned),
+            ("unauthenticated", rejections.unauthenticated),
+            ("stale", rejections.stale),
+            ("replayed", rejections.replayed),
+        ] {
+            metrics.sample(
+                "telio_heartbeat_rejected_total",
+                &[("reason", reason)],
+                count,
+            );
+        }
+
+        metrics.family(
+            "telio_heartbeat_unsigned",
+            MetricType::Counter,
+            "Received heartbeat messages without signature, accepted unless signatures are enforced",
+        );
+        metrics.sample(
+            "telio_heartbeat_unsigned_total",
+            &[],
+            self.authenticator.unsigned(),
+        );
+    }
+
+    /// Connectivity matrix built during the last aggregation, `None` if no collection has finished yet
+    pub fn connectivity_matrix(&self) -> Option<ConnectivityMatrix> {
+        self.connectivity_matrix.clone()
+    }
+
     async fn handle_wg_event(&mut self, event: Event) {
         if let Event::Node { body: Some(node) } = event {
             if let Some(state) = node.state {
@@ -336,7 +439,8 @@ impl Analytics {
                 .entry(*pk)
                 .or_insert_with(|| DEFAULT_NODE_FINGERPRINT.to_string());
 
-            let heartbeat = HeartbeatMessage::request();
+            let mut heartbeat = HeartbeatMessage::request();
+            self.authenticator.sign(*pk, &mut heartbeat);
 
             #[allow(mpsc_blocking_send)]
             if self.io.chan.tx.send((*pk, heartbeat)).await.is_err() {
@@ -359,6 +463,12 @@ impl Analytics {
     async fn handle_receive(&mut self, data: (PublicKey, HeartbeatMessage)) {
         let pk = data.0;
 
+        // Drop messages failing authentication, before they can affect any state
+        if let Err(rejection) = self.authenticator.verify(pk, &data.1) {
+            telio_log_warn!("Rejected heartbeat message from {:?}: {:?}", pk, rejection);
+            return;
+        }
+
         // Since we already got a message from the other node, we can assume that the derp connection is functioning from them to us
         self.external_links
             .entry((pk, self.public_key))
@@ -415,11 +525,12 @@ impl Analytics {
             links.push(status);
         }
 
-        let heartbeat = HeartbeatMessage::response(
+        let mut heartbeat = HeartbeatMessage::response(
             self.meshnet_id.into_bytes().to_vec(),
             self.config.fingerprint.clone(),
             &links,
         );
+        self.authenticator.sign(pk, &mut heartbeat);
 
         #[allow(mpsc_blocking_send)]
         if self.io.chan.tx.send((pk, heartbeat)).await.is_err() {
@@ -676,6 +787,30 @@ impl Analytics {
 
         heartbeat_info.connectivity_matrix = connectivity_matrix;
 
+        // Keep the whole matrix, including links in the default state, for the application to query
+        let mut links = Vec::new();
+        for (i, a) in internal_sorted_public_keys.iter().enumerate() {
+            for b in internal_sorted_public_keys.iter().skip(i + 1) {
+                let link = combined_map[&(**a, **b)].min(combined_map[&(**b, **a)]);
+                links.push(MatrixLink {
+                    node_a: **a,
+                    node_b: **b,
+                    state: link.into(),
+                });
+            }
+        }
+        self.connectivity_matrix = Some(ConnectivityMatrix {
+            meshnet_id: self.meshnet_id.to_string(),
+            nodes: internal_sorted_public_keys
+                .iter()
+                .map(|pk| MatrixNode {
+                    public_key: **pk,
+                    fingerprint: self.node_fingerprints[*pk].clone(),
+                })
+                .collect(),
+            links,
+        });
+
         // External links
         let mut external_links = String::new();
         for key in external_sorted_public_keys {
@@ -749,3 +884,134 @@ impl Analytics {
         self.update_nodes().await;
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use telio_task::io::McChan;
+
+    fn analytics(secret_key: SecretKey, meshnet_id: Uuid) -> Analytics {
+        let io = Io {
+            chan: Chan::default(),
+            derp_event_channel: McChan::default().rx,
+            wg_event_channel: McChan::default().rx,
+            config_update_channel: McChan::default().rx,
+            analytics_channel: Chan::default().tx,
+        };
+        let config = HeartbeatConfig {
+            fingerprint: "own".to_owned(),
+            ..Default::default()
+        };
+        Analytics::new(secret_key, meshnet_id, config, io)
+    }
+
+    #[tokio::test]
+    async fn aggregation_keeps_connectivity_matrix() {
+        let (peer, silent) = (PublicKey([0xfe; 32]), PublicKey([0xff; 32]));
+        // Keep own key first in the sorted matrix
+        let secret_key = loop {
+            let secret_key = SecretKey::gen();
+            if secret_key.public() < peer {
+                break secret_key;
+            }
+        };
+        let own = secret_key.public();
+        let meshnet_id = Uuid::new_v4();
+        let mut analytics = analytics(secret_key, meshnet_id);
+        assert_eq!(analytics.connectivity_matrix(), None);
+
+        analytics.derp_connection = true;
+        for (pk, connection_state) in &[
+            (own, MeshConnectionState::NONE),
+            (peer, MeshConnectionState::WG),
+            (silent, MeshConnectionState::NONE),
+        ] {
+            analytics.local_nodes.insert(
+                *pk,
+                NodeInfo::Node {
+                    mesh_link: MeshLink {
+                        connection_state: *connection_state,
+                    },
+                    meshnet_id: Some(meshnet_id),
+                },
+            );
+            analytics.collected_meshnet_ids.insert(*pk, meshnet_id);
+        }
+        analytics.node_fingerprints.insert(peer, "peer".to_owned());
+        analytics
+            .node_fingerprints
+            .insert(silent, "silent".to_owned());
+        analytics.external_links.insert(
+            (peer, own),
+            MeshLink {
+                connection_state: MeshConnectionState::WG | MeshConnectionState::DERP,
+            },
+        );
+
+        analytics.handle_aggregation().await;
+
+        let matrix = analytics.connectivity_matrix().unwrap();
+        assert_eq!(matrix.meshnet_id, meshnet_id.to_string());
+        assert_eq!(
+            matrix
+                .nodes
+                .iter()
+                .map(|node| node.fingerprint.as_str())
+                .collect::<Vec<_>>(),
+            vec!["own", "peer", "silent"]
+        );
+        let up = LinkState {
+            wg: true,
+            derp: true,
+        };
+        assert_eq!(
+            matrix
+                .links
+                .iter()
+                .map(|link| (link.node_a, link.node_b, link.state))
+                .collect::<Vec<_>>(),
+            vec![
+                (own, peer, up),
+                (own, silent, LinkState::default()),
+                (peer, silent, LinkState::default()),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn unauthenticated_messages_are_dropped_unsigned_counted() {
+        let (own, other) = (SecretKey::gen(), SecretKey::gen());
+        let mut analytics = analytics(own, Uuid::new_v4());
+        let other_authenticator = Authenticator::new(other, Duration::from_secs(300), false);
+
+        // Signed with key of another node
+        let mut forged = HeartbeatMessage::request();
+        Authenticator::new(SecretKey::gen(), Duration::from_secs(300), false)
+            .sign(own.public(), &mut forged);
+        analytics.handle_receive((other.public(), forged)).await;
+        assert!(analytics.local_nodes.is_empty());
+
+        let mut signed = HeartbeatMessage::request();
+        other_authenticator.sign(own.public(), &mut signed);
+        analytics
+            .handle_receive((other.public(), signed.clone()))
+            .await;
+        assert!(analytics.local_nodes.contains_key(&other.public()));
+
+        analytics.local_nodes.clear();
+        analytics.handle_receive((other.public(), signed)).await;
+        assert!(analytics.local_nodes.is_empty());
+
+        // Node, which does not sign its messages yet
+        let unsigned = HeartbeatMessage::request();
+        analytics.handle_receive((other.public(), unsigned)).await;
+        assert!(analytics.local_nodes.contains_key(&other.public()));
+
+        let mut metrics = Metrics::default();
+        analytics.write_metrics(&mut metrics);
+        let text = metrics.finish();
+        assert!(text.contains("telio_heartbeat_rejected_total{reason=\"unauthenticated\"} 1\n"));
+        assert!(text.contains("telio_heartbeat_rejected_total{reason=\"replayed\"} 1\n"));
+        assert!(text.contains("telio_heartbeat_unsigned_total 1\n"));
+    }
+}
@@ -7,7 +7,7 @@
 //! * `start` - Starts nurse, without listening to any nodes
 //! * `stop` - Stops a running nurse
 //! * `set_nodes` - Adds and removes nodes from nurse health checks. Useful if nodes were added to or removed from the meshnet
-//! * `set_private_key` - To update the private (and public) key for the meshnet
+//! * `set_private_key` - To update the private (and public) key for the meshnet, also used to authenticate heartbeats
 //!
 //! The intended workflow would be to first start a nurse and set which nodes it should listen to, and when the application exits stop the nurse. For concrete examples, check `relay.rs` in `telio-traversal`.
 
@@ -17,7 +17,10 @@ pub mod config;
 pub mod data;
 /// Nurse error module
 pub mod error;
+/// Nurse metrics module
+pub mod metrics;
 
+mod auth;
 mod heartbeat;
 mod nurse;
 mod qos;
@@ -0,0 +1,127 @@
+use std::fmt::{Display, Write};
+
+/// Type of metric family
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MetricType {
+    /// Value which can go up and down
+    Gauge,
+    /// Monotonically increasing value, sample names must end with `_total`
+    Counter,
+    /// Quantiles of observed values
+    Summary,
+}
+
+impl MetricType {
+    fn as_str(&self) -> &'static str {
+        match self {
+            MetricType::Gauge => "gauge",
+            MetricType::Counter => "counter",
+            MetricType::Summary => "summary",
+        }
+    }
+}
+
+/// Writer of metrics in OpenMetrics text format, understood by Prometheus.
+///
+/// Families are written one after another, so samples must follow the family they belong to.
+/// Metrics written by different components can be joined with [`Metrics::append`].
+#[derive(Debug, Default)]
+pub struct Metrics {
+    text: String,
+}
+
+impl Metrics {
+    /// Start a new metric family
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - Name of the family, without `_total` suffix for counters.
+    /// * `kind` - Type of the family.
+    /// * `help` - Description of the family.
+    pub fn family(&mut self, name: &str, kind: MetricType, help: &str) {
+        let _ = writeln!(self.text, "# TYPE {} {}", name, kind.as_str());
+        let _ = writeln!(self.text, "# HELP {} {}", name, Self::escape(help, false));
+    }
+
+    /// Add a sample to the current family
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - Name of the sample, including suffix like `_total` or `_count`.
+    /// * `labels` - Label names and their values.
+    /// * `value` - Value of the sample.
+    pub fn sample(&mut self, name: &str, labels: &[(&str, &str)], value: impl Display) {
+        self.text.push_str(name);
+        if !labels.is_empty() {
+            self.text.push('{');
+            for (i, (label, value)) in labels.iter().enumerate() {
+                if i > 0 {
+                    self.text.push(',');
+                }
+                let _ = write!(self.text, "{}=\"{}\"", label, Self::escape(value, true));
+            }
+            self.text.push('}');
+        }
+        let _ = writeln!(self.text, " {}", value);
+    }
+
+    /// Append families written by another component
+    pub fn append(&mut self, other: Metrics) {
+        self.text.push_str(&other.text);
+    }
+
+    /// Finish exposition, returning the text to be served to a scraper
+    pub fn finish(mut self) -> String {
+        self.text.push_str("# EOF\n");
+        self.text
+    }
+
+    fn escape(value: &str, quote: bool) -> String {
+        let mut escaped = String::with_capacity(value.len());
+        for c in value.chars() {
+            match c {
+                '\\' => escaped.push_str("\\\\"),
+                '\n' => escaped.push_str("\\n"),
+                '"' if quote => escaped.push_str("\\\""),
+                c => escaped.push(c),
+            }
+        }
+        escaped
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn metrics_are_written_in_openmetrics_format() {
+        let mut relay = Metrics::default();
+        relay.family("telio_relay_connected", MetricType::Gauge, "Relay state");
+        relay.sample("telio_relay_connected", &[], 1);
+
+        let mut metrics = Metrics::default();
+        metrics.family(
+            "telio_peer_rx_bytes",
+            MetricType::Counter,
+            "Bytes received\nfrom \"peer\"",
+        );
+        metrics.sample(
+            "telio_peer_rx_bytes_total",
+            &[("peer", "a\"b\\c"), ("path", "relay")],
+            42,
+        );
+        metrics.append(relay);
+
+        assert_eq!(
+            metrics.finish(),
+            "# TYPE telio_peer_rx_bytes counter\n\
+             # HELP telio_peer_rx_bytes Bytes received\\nfrom \"peer\"\n\
+             telio_peer_rx_bytes_total{peer=\"a\\\"b\\\\c\",path=\"relay\"} 42\n\
+             # TYPE telio_relay_connected gauge\n\
+             # HELP telio_relay_connected Relay state\n\
+             telio_relay_connected 1\n\
+             # EOF\n"
+        );
+    }
+}
@@ -1,7 +1,7 @@
 use async_trait::async_trait;
 use std::collections::HashSet;
 use telio_crypto::{PublicKey, SecretKey};
-use telio_lana::*;
+use telio_lana::LanaEvent;
 use telio_model::event::Event;
 use telio_proto::HeartbeatMessage;
 use telio_relay::{multiplexer::Multiplexer, Server};
@@ -13,8 +13,9 @@ use telio_utils::{telio_log_debug, telio_log_error, telio_log_trace, telio_log_w
 use telio_wg::uapi::AnalyticsEvent;
 use uuid::Uuid;
 
-use crate::data::{AnalyticsMessage, HeartbeatInfo};
+use crate::data::{AnalyticsMessage, ConnectivityMatrix, HeartbeatInfo};
 use crate::error::Error;
+use crate::metrics::Metrics;
 use crate::{config::Config, data::MeshConfigUpdateEvent};
 
 use crate::heartbeat::Analytics as HeartbeatAnalytics;
@@ -41,7 +42,7 @@ impl Nurse {
     ///
     /// # Arguments
     ///
-    /// * `public_key` - Used for heartbeat requests.
+    /// * `secret_key` - Used to authenticate heartbeat messages.
     /// * `config` - Contains configuration for heartbeats and QoS.
     /// * `derp_event_channel` - Used by heartbeat to get derp events.
     /// * `wg_event_channel` - Used by heartbeat to get Wireguard events.
@@ -52,7 +53,7 @@ impl Nurse {
     ///
     /// A Nurse instance.
     pub async fn new(
-        public_key: PublicKey,
+        secret_key: SecretKey,
         config: Config,
         derp_event_channel: &mc_chan::Tx<Box<Server>>,
         wg_event_channel: &mc_chan::Tx<Box<Event>>,
@@ -81,7 +82,7 @@ impl Nurse {
         };
 
         let heartbeat = HeartbeatAnalytics::new(
-            public_key,
+            secret_key,
             meshnet_id,
             config.heartbeat_config,
             heartbeat_io,
@@ -145,29 +146,63 @@ impl Nurse {
         .await;
     }
 
+    /// Collect metrics of heartbeat and QoS components
+    ///
+    /// # Returns
+    ///
+    /// Metrics families of the components, to be appended to the exposition.
+    pub async fn get_metrics(&self) -> Metrics {
+        let mut metrics = task_exec!(&self.heartbeat, async move |state| {
+            let mut metrics = Metrics::default();
+            state.write_metrics(&mut metrics);
+            Ok(metrics)
+        })
+        .await
+        .unwrap_or_default();
+
+        if let Some(qos) = self.qos.as_ref() {
+            metrics.append(
+                task_exec!(qos, async move |state| {
+                    let mut metrics = Metrics::default();
+                    state.write_metrics(&mut metrics);
+                    Ok(metrics)
+                })
+                .await
+                .unwrap_or_default(),
+            );
+        }
+
+        metrics
+    }
+
+    /// Connectivity matrix aggregated during the last heartbeat collection
+    pub async fn get_connectivity_matrix(&self) -> Option<ConnectivityMatrix> {
+        task_exec!(&self.heartbeat, async move |state| Ok(
+            state.connectivity_matrix()
+        ))
+        .await
+        .unwrap_or_default()
+    }
+
     async fn handle_heartbeat_event(&self, info: HeartbeatInfo) {
-        // Send off nominated fingerprint to moose
-        let _ = lana!(
-            set_context_application_config_currentState_internalMeshnet_fp,
-            info.meshnet_id.to_string()
-        );
+        // Send off nominated fingerprint to the analytics sink
+        let _ = telio_lana::send(LanaEvent::MeshnetId {
+            id: info.meshnet_id.to_string(),
+        });
 
         // We pray that nothing goes wrong here
-        let _ = lana!(
-            set_context_application_config_currentState_internalMeshnet_members,
-            info.fingerprints
-        );
+        let _ = telio_lana::send(LanaEvent::MeshnetMembers {
+            members: info.fingerprints,
+        });
 
-        // And send this off to moose
-        let _ = lana!(
-            set_context_application_config_currentState_internalMeshnet_connectivityMatrix,
-            info.connectivity_matrix
-        );
+        // And send this off to the analytics sink
+        let _ = telio_lana::send(LanaEvent::ConnectivityMatrix {
+            matrix: info.connectivity_matrix,
+        });
 
-        let _ = lana!(
-            set_context_application_config_currentState_externalLinks,
-            info.external_links
-        );
+        let _ = telio_lana::send(LanaEvent::ExternalLinks {
+            links: info.external_links,
+        });
 
         // TODO: Make it better
         let internal_sorted_public_keys = info.internal_sorted_public_keys;
@@ -187,33 +222,28 @@ impl Nurse {
 
         let qos_data = QoSData::merge(internal_qos_data, external_qos_data);
 
-        let _ = lana!(
-            send_serviceQuality_node_heartbeat,
-            qos_data.connection_duration,
-            info.heartbeat_interval,
-            qos_data.rx,
-            qos_data.rtt,
-            qos_data.tx
-        );
+        let _ = telio_lana::send(LanaEvent::Heartbeat {
+            connection_duration: qos_data.connection_duration,
+            heartbeat_interval: info.heartbeat_interval,
+            rx: qos_data.rx,
+            rtt: qos_data.rtt,
+            tx: qos_data.tx,
+        });
     }
 
     fn meshnet_id() -> Uuid {
-        lana!(fetch_context)
-            .map(|context| context.application.config.current_state.internal_meshnet.fp)
-            .unwrap_or(None)
+        telio_lana::meshnet_id()
             .map(|fp| {
                 Uuid::parse_str(&fp).unwrap_or_else(|_| {
                     telio_log_error!(
-                        "Failed to parse moose meshnet id ({:?}), generating a new one",
+                        "Failed to parse stored meshnet id ({:?}), generating a new one",
                         fp
                     );
                     Uuid::new_v4()
                 })
             })
             .unwrap_or_else(|| {
-                telio_log_trace!(
-                    "Could not find stored meshnet id by moose, generating a fresh one"
-                );
+                telio_log_trace!("Could not find stored meshnet id, generating a fresh one");
                 Uuid::new_v4()
             })
     }
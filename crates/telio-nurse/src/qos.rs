@@ -1,25 +1,37 @@
 use async_trait::async_trait;
+use futures::future::{join_all, pending};
+use futures::Future;
 use histogram::Histogram;
 use serde::Deserialize;
 use std::collections::HashMap;
+use std::convert::TryInto;
 use std::net::IpAddr;
-use std::time::Instant;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
-use tokio::time::{interval_at, Interval};
+use ipnetwork::IpNetwork;
+use tokio::task::JoinHandle;
+use tokio::time::{interval_at, timeout_at, Interval};
 
 use telio_crypto::PublicKey;
 use telio_task::{io::mc_chan, Runtime, RuntimeExt, WaitResponse};
+use telio_utils::telio_log_warn;
 use telio_wg::uapi::{AnalyticsEvent, PeerState};
 
 use crate::config::QoSConfig;
+use crate::metrics::{MetricType, Metrics};
 
-use crate::rtt::ping::Ping;
+use crate::rtt::{echo::Echo, ping::Ping, pinger::Pinger};
 
 /// Enum denoting ways to calculate RTT.
 #[derive(Eq, PartialEq, Debug, Deserialize)]
 pub enum RttType {
     /// Simple ping request.
     Ping,
+    /// Telio ping request to the nurse of the node, sent through the tunnel.
+    Pinger,
+    /// TCP handshake with the nurse of the node, made through the tunnel.
+    Echo,
 }
 
 /// Information about a node in the meshnet.
@@ -35,6 +47,7 @@ pub struct NodeInfo {
 
     // RTT
     pub endpoint: IpAddr,
+    pub mesh_ip: Option<IpAddr>,
     pub rtt_histogram: Histogram,
 
     // Throughput
@@ -45,6 +58,23 @@ pub struct NodeInfo {
 }
 
 impl NodeInfo {
+    /// Meshnet IP of a node is the single host address among its allowed IPs
+    fn mesh_ip(allowed_ips: &[IpNetwork]) -> Option<IpAddr> {
+        allowed_ips
+            .iter()
+            .find(|net| match net {
+                IpNetwork::V4(net) => net.prefix() == 32,
+                IpNetwork::V6(net) => net.prefix() == 128,
+            })
+            .map(|net| net.ip())
+    }
+
+    fn record_rtt(&mut self, rtt: Duration) {
+        let _ = self
+            .rtt_histogram
+            .increment(rtt.as_millis().try_into().unwrap_or(0u64));
+    }
+
     fn update_throughput_info(&mut self, event: &AnalyticsEvent) {
         if let Some(duration) = event.timestamp.checked_duration_since(self.last_event) {
             let mut duration_as_seconds = duration.as_secs();
@@ -97,6 +127,7 @@ impl From<AnalyticsEvent> for NodeInfo {
             last_state_change: event.timestamp,
             connected_time: 0,
             endpoint: event.endpoint.ip(),
+            mesh_ip: NodeInfo::mesh_ip(&event.allowed_ips),
             rtt_histogram: Histogram::new(),
             last_tx_bytes: 0,
             last_rx_bytes: 0,
@@ -147,13 +178,20 @@ pub struct Io {
     pub wg_channel: mc_chan::Rx<Box<AnalyticsEvent>>,
 }
 
+/// Round of RTT measurements running in the background, yielding RTTs measured per node
+type RttRound = JoinHandle<Vec<(PublicKey, Vec<Duration>)>>;
+
 /// Analytics data about a meshnet.
 pub struct Analytics {
     rtt_interval: Interval,
     io: Io,
     nodes: HashMap<PublicKey, NodeInfo>,
-    ping_backend: Option<Ping>,
+    ping_backend: Option<Arc<Ping>>,
+    pinger_backend: Option<Arc<Pinger>>,
+    echo_backend: Option<Arc<Echo>>,
+    rtt_round: Option<RttRound>,
     buckets: u32,
+    config: QoSConfig,
 }
 
 #[async_trait]
@@ -178,11 +216,33 @@ impl Runtime for Analytics {
                     Ok(())
                 })
             }
+
+            rtts = rtt_round_finished(&mut self.rtt_round) => {
+                for (public_key, rtts) in rtts {
+                    if let Some(node) = self.nodes.get_mut(&public_key) {
+                        rtts.into_iter().for_each(|rtt| node.record_rtt(rtt));
+                    }
+                }
+                Self::next()
+            }
+
+            // Requests of other nodes measuring RTT through the tunnel
+            _ = respond_pinger(self.pinger_backend.as_deref()) => Self::next(),
+            _ = respond_echo(self.echo_backend.as_deref()) => Self::next(),
+        }
+    }
+
+    async fn stop(self) {
+        if let Some(round) = self.rtt_round {
+            round.abort();
         }
     }
 }
 
 impl Analytics {
+    /// Enough for the default number of tries of a node, which does not answer
+    const RTT_DEADLINE: Duration = Duration::from_secs(15);
+
     /// Create empty analytics instance.
     ///
     /// # Arguments
@@ -195,7 +255,7 @@ impl Analytics {
     /// A new `Analytics` instance with the given configuration but with no nodes.
     pub fn new(config: QoSConfig, io: Io) -> Self {
         let ping_backend = if config.rtt_types.contains(&RttType::Ping) {
-            Ping::new(config.rtt_tries).ok()
+            Ping::new(config.rtt_tries).ok().map(Arc::new)
         } else {
             None
         };
@@ -205,7 +265,11 @@ impl Analytics {
             io,
             nodes: HashMap::new(),
             ping_backend,
+            pinger_backend: None,
+            echo_backend: None,
+            rtt_round: None,
             buckets: config.buckets,
+            config,
         }
     }
 
@@ -310,6 +374,7 @@ impl Analytics {
 
                 // Update rtt info
                 n.endpoint = event.endpoint.ip();
+                n.mesh_ip = NodeInfo::mesh_ip(&event.allowed_ips);
 
                 // Update throughput info
                 n.update_throughput_info(&event);
@@ -320,11 +385,143 @@ impl Analytics {
             .or_insert_with(|| NodeInfo::from(event));
     }
 
+    /// Tunnel backends are bound to the meshnet IP, which may be assigned to the
+    /// interface later than nurse starts, so they are started on demand
+    async fn start_tunnel_backends(&mut self) {
+        let mesh_ip = match self.config.mesh_ip {
+            Some(mesh_ip) => mesh_ip,
+            None => return,
+        };
+
+        if self.pinger_backend.is_none() && self.config.rtt_types.contains(&RttType::Pinger) {
+            self.pinger_backend =
+                Pinger::new(mesh_ip, QoSConfig::TUNNEL_RTT_PORT, self.config.rtt_tries)
+                    .await
+                    .map_err(|e| telio_log_warn!("Failed to start tunnel pinger: {}", e))
+                    .ok()
+                    .map(Arc::new);
+        }
+
+        if self.echo_backend.is_none() && self.config.rtt_types.contains(&RttType::Echo) {
+            self.echo_backend =
+                Echo::new(mesh_ip, QoSConfig::TUNNEL_RTT_PORT, self.config.rtt_tries)
+                    .await
+                    .map_err(|e| telio_log_warn!("Failed to start tunnel echo: {}", e))
+                    .ok()
+                    .map(Arc::new);
+        }
+    }
+
+    /// Start measuring RTT of all nodes with all backends at once in the background, so
+    /// events keep being handled meanwhile. A round of measurements lasts no longer than
+    /// [`Analytics::RTT_DEADLINE`], no matter how many nodes are unreachable, and a new
+    /// one is not started, until the previous one is finished.
     async fn perform_ping(&mut self) {
-        if let Some(pinger) = &self.ping_backend {
-            for (_, node) in self.nodes.iter_mut() {
-                pinger.perform(node).await;
+        if self.rtt_round.is_some() {
+            return;
+        }
+
+        self.start_tunnel_backends().await;
+
+        let deadline = tokio::time::Instant::now() + Self::RTT_DEADLINE;
+        let (ping, pinger, echo) = (
+            self.ping_backend.clone(),
+            self.pinger_backend.clone(),
+            self.echo_backend.clone(),
+        );
+        let nodes: Vec<_> = self
+            .nodes
+            .values()
+            .map(|node| (node.public_key, node.endpoint, node.mesh_ip))
+            .collect();
+
+        self.rtt_round = Some(tokio::spawn(async move {
+            join_all(nodes.into_iter().map(|(public_key, endpoint, mesh_ip)| {
+                let (ping, pinger, echo) = (ping.as_deref(), pinger.as_deref(), echo.as_deref());
+                async move {
+                    let rtts = tokio::join!(
+                        within(
+                            deadline,
+                            ping.map(|ping| ping.perform_average_rtt(endpoint))
+                        ),
+                        within(
+                            deadline,
+                            pinger
+                                .zip(mesh_ip)
+                                .map(|(pinger, mesh_ip)| pinger.perform_average_rtt(mesh_ip))
+                        ),
+                        within(
+                            deadline,
+                            echo.zip(mesh_ip)
+                                .map(|(echo, mesh_ip)| echo.perform_average_rtt(mesh_ip))
+                        ),
+                    );
+                    let rtts: Vec<_> = [rtts.0, rtts.1, rtts.2].iter().flatten().copied().collect();
+                    (public_key, rtts)
+                }
+            }))
+            .await
+        }));
+    }
+
+    /// Write per node histograms to metrics, without clearing them.
+    ///
+    /// Histograms are cleared on every heartbeat, so they cover the time since the last one.
+    pub fn write_metrics(&self, metrics: &mut Metrics) {
+        let mut nodes: Vec<_> = self.nodes.values().collect();
+        nodes.sort_by_key(|node| node.public_key);
+
+        let summaries: [(&str, &str, fn(&NodeInfo) -> &Histogram); 3] = [
+            (
+                "telio_qos_rtt_milliseconds",
+                "Round trip time to the node",
+                |node| &node.rtt_histogram,
+            ),
+            (
+                "telio_qos_tx_bytes_per_second",
+                "Throughput of traffic sent to the node",
+                |node| &node.tx_histogram,
+            ),
+            (
+                "telio_qos_rx_bytes_per_second",
+                "Throughput of traffic received from the node",
+                |node| &node.rx_histogram,
+            ),
+        ];
+
+        for (name, help, histogram) in summaries.iter() {
+            metrics.family(name, MetricType::Summary, help);
+            for node in &nodes {
+                let peer = node.public_key.to_string();
+                let histogram = histogram(node);
+                for (quantile, percentile) in &[("0.5", 50.0), ("0.9", 90.0), ("0.99", 99.0)] {
+                    if let Ok(value) = histogram.percentile(*percentile) {
+                        metrics.sample(name, &[("peer", &peer), ("quantile", quantile)], value);
+                    }
+                }
+                metrics.sample(
+                    &format!("{}_count", name),
+                    &[("peer", &peer)],
+                    histogram.entries(),
+                );
+            }
+        }
+
+        metrics.family(
+            "telio_qos_connected_seconds",
+            MetricType::Gauge,
+            "Time the node was connected",
+        );
+        for node in &nodes {
+            let mut connected_time = node.connected_time;
+            if node.peer_state == PeerState::Connected {
+                connected_time += node.last_state_change.elapsed().as_secs();
             }
+            metrics.sample(
+                "telio_qos_connected_seconds",
+                &[("peer", &node.public_key.to_string())],
+                connected_time,
+            );
         }
     }
 
@@ -370,11 +567,46 @@ impl Analytics {
     }
 }
 
+/// RTT measured by backend, if it is enabled and finishes before deadline
+async fn within(
+    deadline: tokio::time::Instant,
+    rtt: Option<impl Future<Output = Option<Duration>>>,
+) -> Option<Duration> {
+    timeout_at(deadline, rtt?).await.ok().flatten()
+}
+
+/// RTTs measured by the running round, once it finishes, otherwise wait forever
+async fn rtt_round_finished(round: &mut Option<RttRound>) -> Vec<(PublicKey, Vec<Duration>)> {
+    let rtts = match round {
+        Some(handle) => handle.await.unwrap_or_default(),
+        None => pending().await,
+    };
+    *round = None;
+    rtts
+}
+
+/// Answer tunnel pinger requests, if enabled, otherwise wait forever
+async fn respond_pinger(pinger: Option<&Pinger>) {
+    match pinger {
+        Some(pinger) => pinger.respond().await,
+        None => pending().await,
+    }
+}
+
+/// Accept tunnel echo connections, if enabled, otherwise wait forever
+async fn respond_echo(echo: Option<&Echo>) {
+    match echo {
+        Some(echo) => echo.respond().await,
+        None => pending().await,
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use histogram::Histogram;
     use std::net::{IpAddr, Ipv4Addr};
+    use telio_task::io::McChan;
 
     use telio_crypto::PublicKey;
 
@@ -433,6 +665,76 @@ mod tests {
         );
     }
 
+    #[tokio::test]
+    async fn qos_metrics() {
+        let public_key = PublicKey(*b"AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA");
+        let histogram = default_histogram();
+
+        let mut nodes = HashMap::new();
+        nodes.insert(public_key, dummy_node(&public_key, &Histogram::new(), 100));
+        nodes.get_mut(&public_key).unwrap().rtt_histogram = histogram;
+
+        let analytics = Analytics {
+            rtt_interval: interval_at(
+                tokio::time::Instant::now(),
+                QoSConfig::default().rtt_interval,
+            ),
+            io: Io {
+                wg_channel: McChan::default().rx,
+            },
+            nodes,
+            ping_backend: None,
+            pinger_backend: None,
+            echo_backend: None,
+            rtt_round: None,
+            buckets: 5,
+            config: QoSConfig::default(),
+        };
+
+        let mut metrics = Metrics::default();
+        analytics.write_metrics(&mut metrics);
+        let text = metrics.finish();
+
+        let peer = "peer=\"QUFBQUFBQUFBQUFBQUFBQUFBQUFBQUFBQUFBQUFBQUE=\"";
+        assert!(text.contains(&format!(
+            "telio_qos_rtt_milliseconds{{{},quantile=\"0.5\"}} 60\n",
+            peer
+        )));
+        assert!(text.contains(&format!(
+            "telio_qos_rtt_milliseconds_count{{{}}} 10\n",
+            peer
+        )));
+        assert!(text.contains(&format!(
+            "telio_qos_tx_bytes_per_second_count{{{}}} 0\n",
+            peer
+        )));
+        assert!(text.contains(&format!("telio_qos_connected_seconds{{{}}} 100\n", peer)));
+    }
+
+    #[tokio::test]
+    async fn rtt_round_runs_in_background() {
+        let public_key = PublicKey(*b"AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA");
+        let mut analytics = Analytics::new(
+            QoSConfig {
+                rtt_types: Vec::new(),
+                ..Default::default()
+            },
+            Io {
+                wg_channel: McChan::default().rx,
+            },
+        );
+        analytics
+            .nodes
+            .insert(public_key, dummy_node(&public_key, &Histogram::new(), 0));
+
+        analytics.perform_ping().await;
+        assert!(analytics.rtt_round.is_some());
+
+        let rtts = rtt_round_finished(&mut analytics.rtt_round).await;
+        assert_eq!(rtts, vec![(public_key, Vec::new())]);
+        assert!(analytics.rtt_round.is_none());
+    }
+
     fn default_histogram() -> Histogram {
         let mut a = Histogram::new();
         for i in 1..11 {
@@ -449,6 +751,7 @@ mod tests {
             last_state_change: Instant::now(),
             connected_time,
             endpoint: IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)),
+            mesh_ip: None,
             rtt_histogram: histogram.clone(),
             last_rx_bytes: 0,
             last_tx_bytes: 0,
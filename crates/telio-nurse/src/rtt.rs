@@ -1 +1,3 @@
+pub mod echo;
 pub mod ping;
+pub mod pinger;
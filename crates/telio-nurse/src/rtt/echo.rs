@@ -0,0 +1,94 @@
+use std::net::{IpAddr, SocketAddr};
+use std::time::{Duration, Instant};
+
+use telio_utils::telio_log_debug;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::time::timeout;
+
+/// Measures RTT through the tunnel, by timing TCP handshakes with the nurse of the other node.
+///
+/// Connection is established to the meshnet IP of the node, so a single handshake takes
+/// one round trip over the path WireGuard currently uses for that node.
+pub struct Echo {
+    listener: TcpListener,
+    port: u16,
+    no_of_tries: u32,
+}
+
+impl Echo {
+    const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+    /// Create new instance of `Echo`, which also accepts connections of other nodes.
+    ///
+    /// # Arguments
+    ///
+    /// * `mesh_ip` - Meshnet IP of this node, to bind the listener to.
+    /// * `port` - TCP port, used by nurses of all nodes.
+    /// * `no_of_tries` - How many handshakes should be made.
+    pub async fn new(mesh_ip: IpAddr, port: u16, no_of_tries: u32) -> std::io::Result<Self> {
+        Ok(Self {
+            listener: TcpListener::bind((mesh_ip, port)).await?,
+            port,
+            no_of_tries,
+        })
+    }
+
+    /// Wait for a connection from the other nodes and close it right away.
+    pub async fn respond(&self) {
+        let _ = self.listener.accept().await;
+    }
+
+    /// Perform the configured number of handshakes against the meshnet IP of a node,
+    /// returning average RTT of the successful ones.
+    ///
+    /// # Arguments
+    ///
+    /// * `mesh_ip` - Meshnet IP of the node to connect to.
+    pub async fn perform_average_rtt(&self, mesh_ip: IpAddr) -> Option<Duration> {
+        let addr = SocketAddr::new(mesh_ip, self.port);
+
+        let mut sum = Duration::default();
+        let mut successful_handshakes = 0;
+
+        for _ in 0..self.no_of_tries {
+            let start = Instant::now();
+            match timeout(Self::CONNECT_TIMEOUT, TcpStream::connect(addr)).await {
+                Ok(Ok(_)) => {
+                    sum = sum.saturating_add(start.elapsed());
+                    successful_handshakes += 1;
+                }
+                Ok(Err(e)) => telio_log_debug!("Failed to connect to {}: {}", addr, e),
+                Err(_) => telio_log_debug!("Connection to {} timed out", addr),
+            }
+        }
+
+        sum.checked_div(successful_handshakes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::Ipv4Addr;
+
+    #[tokio::test]
+    async fn echo_measures_rtt_of_other_nurse() {
+        let localhost = IpAddr::V4(Ipv4Addr::LOCALHOST);
+        let remote = Echo::new(localhost, 0, 3).await.unwrap();
+        let local = Echo::new(localhost, 0, 3).await.unwrap();
+        // Nurses of the nodes share the port, so connect to the port of the other one
+        let port = remote.listener.local_addr().unwrap().port();
+        let local = Echo { port, ..local };
+
+        let responder = tokio::spawn(async move {
+            loop {
+                remote.respond().await;
+            }
+        });
+
+        let rtt = local.perform_average_rtt(localhost).await;
+        responder.abort();
+
+        assert!(rtt.is_some());
+    }
+}
@@ -3,8 +3,6 @@ use std::time::Duration;
 use std::{convert::TryInto, net::IpAddr};
 use surge_ping::{Client, Config, PingIdentifier, PingSequence, ICMP};
 
-use crate::qos::NodeInfo;
-
 /// Information needed to check the reachability of endpoints.
 ///
 /// Can be used with both IPv4 and IPv6 addresses.
@@ -30,21 +28,13 @@ impl Ping {
         })
     }
 
-    /// Perform the configured number of pings against the endpoint specified in the argument node.
+    /// Perform the configured number of pings against the endpoint of a node, returning
+    /// average RTT of the successful ones.
     ///
     /// # Arguments
     ///
-    /// * `node` - `NodeInfo` instance to get endpoint to ping and to store RTT information.
-    pub async fn perform(&self, node: &mut NodeInfo) {
-        let avg = self.perform_average_rtt(node.endpoint).await;
-
-        if let Some(avg) = avg {
-            let u64_avg = avg.as_millis().try_into().unwrap_or(0u64);
-            let _ = node.rtt_histogram.increment(u64_avg);
-        }
-    }
-
-    async fn perform_average_rtt(&self, node: IpAddr) -> Option<Duration> {
+    /// * `node` - Endpoint of the node to ping.
+    pub async fn perform_average_rtt(&self, node: IpAddr) -> Option<Duration> {
         let client = match node {
             IpAddr::V4(_) => self.client_v4.clone(),
             IpAddr::V6(_) => self.client_v6.clone(),
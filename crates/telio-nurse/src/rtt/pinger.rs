@@ -0,0 +1,149 @@
+use std::net::{IpAddr, SocketAddr};
+use std::time::{Duration, Instant};
+
+use telio_proto::{Codec, Packet, PingType, PingerMsg, Session, WGPort};
+use telio_utils::telio_log_debug;
+use tokio::{net::UdpSocket, time::timeout_at};
+
+/// Measures RTT through the tunnel, by exchanging `PingerMsg` with the nurse of the other node.
+///
+/// Requests are sent to the meshnet IP of the node, so they take whatever path
+/// (direct or relayed) WireGuard currently uses for that node.
+pub struct Pinger {
+    socket: UdpSocket,
+    port: u16,
+    no_of_tries: u32,
+}
+
+impl Pinger {
+    const PING_TIMEOUT: Duration = Duration::from_secs(5);
+    const MAX_PACKET_SIZE: usize = 1500;
+
+    /// Create new instance of `Pinger`, which also answers pings of other nodes.
+    ///
+    /// # Arguments
+    ///
+    /// * `mesh_ip` - Meshnet IP of this node, to bind the socket to.
+    /// * `port` - UDP port, used by nurses of all nodes.
+    /// * `no_of_tries` - How many pings should be sent.
+    pub async fn new(mesh_ip: IpAddr, port: u16, no_of_tries: u32) -> std::io::Result<Self> {
+        Ok(Self {
+            socket: UdpSocket::bind((mesh_ip, port)).await?,
+            port,
+            no_of_tries,
+        })
+    }
+
+    /// Wait for a packet from the other nodes and answer it, if it is a ping.
+    pub async fn respond(&self) {
+        let mut buf = [0u8; Self::MAX_PACKET_SIZE];
+        if let Ok((len, addr)) = self.socket.recv_from(&mut buf).await {
+            self.handle_packet(&buf[..len], addr).await;
+        }
+    }
+
+    /// Perform the configured number of pings against the meshnet IP of a node, returning
+    /// average RTT of the successful ones.
+    ///
+    /// Pings are sent from a socket of their own, so nodes can be pinged concurrently,
+    /// while pings of other nodes are answered by [`Pinger::respond`].
+    ///
+    /// # Arguments
+    ///
+    /// * `mesh_ip` - Meshnet IP of the node to ping.
+    pub async fn perform_average_rtt(&self, mesh_ip: IpAddr) -> Option<Duration> {
+        let addr = SocketAddr::new(mesh_ip, self.port);
+        let session: Session = rand::random();
+        let socket = match UdpSocket::bind((self.socket.local_addr().ok()?.ip(), 0)).await {
+            Ok(socket) => socket,
+            Err(e) => {
+                telio_log_debug!("Failed to bind socket to ping {}: {}", addr, e);
+                return None;
+            }
+        };
+
+        let mut sum = Duration::default();
+        let mut successful_pings = 0;
+
+        for ts in 0..self.no_of_tries {
+            if let Some(rtt) = Self::ping(&socket, addr, session, ts.into()).await {
+                sum = sum.saturating_add(rtt);
+                successful_pings += 1;
+            }
+        }
+
+        sum.checked_div(successful_pings)
+    }
+
+    async fn ping(
+        socket: &UdpSocket,
+        addr: SocketAddr,
+        session: Session,
+        ts: u64,
+    ) -> Option<Duration> {
+        // Port of WireGuard has no meaning inside the tunnel
+        let ping = PingerMsg::ping(WGPort(0), session, ts).encode().ok()?;
+
+        let start = Instant::now();
+        if let Err(e) = socket.send_to(&ping, addr).await {
+            telio_log_debug!("Failed to send ping to {}: {}", addr, e);
+            return None;
+        }
+
+        let deadline = tokio::time::Instant::from_std(start + Self::PING_TIMEOUT);
+        let mut buf = [0u8; Self::MAX_PACKET_SIZE];
+        loop {
+            let (len, from) = timeout_at(deadline, socket.recv_from(&mut buf))
+                .await
+                .ok()?
+                .ok()?;
+            if let Ok(Packet::Pinger(pong)) = Packet::decode(&buf[..len]) {
+                if from == addr
+                    && pong.get_message_type() == PingType::PONG
+                    && pong.get_session() == session
+                    && pong.get_start_timestamp() == ts
+                {
+                    return Some(start.elapsed());
+                }
+            }
+        }
+    }
+
+    /// Answers pings, other packets are ignored
+    async fn handle_packet(&self, buf: &[u8], addr: SocketAddr) {
+        if let Ok(Packet::Pinger(msg)) = Packet::decode(buf) {
+            if msg.get_message_type() == PingType::PING {
+                if let Some(pong) = msg.pong(msg.get_wg_port()).and_then(|p| p.encode().ok()) {
+                    let _ = self.socket.send_to(&pong, addr).await;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::Ipv4Addr;
+
+    #[tokio::test]
+    async fn pinger_measures_rtt_of_other_nurse() {
+        let localhost = IpAddr::V4(Ipv4Addr::LOCALHOST);
+        let remote = Pinger::new(localhost, 0, 2).await.unwrap();
+        let local = Pinger::new(localhost, 0, 2).await.unwrap();
+        // Nurses of the nodes share the port, so ping the port of the other one
+        let port = remote.socket.local_addr().unwrap().port();
+        let local = Pinger { port, ..local };
+
+        let responder = tokio::spawn(async move {
+            loop {
+                remote.respond().await;
+            }
+        });
+
+        let rtt = local.perform_average_rtt(localhost).await;
+        responder.abort();
+
+        assert!(rtt.is_some());
+    }
+}
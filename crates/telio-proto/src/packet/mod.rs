@@ -2,13 +2,15 @@ mod data;
 mod generation;
 mod natter;
 mod nurse;
+mod peer_relay;
 mod pinger;
 
 use crate::{Codec, CodecError, CodecResult};
 
 pub use self::{
     data::DataMsg, generation::Generation, natter::CallMeMaybeMsg,
-    natter::CallMeMaybeMsgDeprecated, nurse::HeartbeatMessage, pinger::PingerMsg,
+    natter::CallMeMaybeMsgDeprecated, nurse::HeartbeatMessage, peer_relay::PeerRelayMsg,
+    peer_relay::PeerRelayType, peer_relay::PEER_KEY_SIZE, pinger::PingerMsg,
     pinger::PingerMsgDeprecated, pinger::Timestamp,
 };
 
@@ -61,6 +63,8 @@ pub enum PacketType {
     CallMeMaybe = 0x06,
     /// Pinger packets (oneway, ping_latency, simple pings ...)
     Pinger = 0x07,
+    /// Data packets relayed through a third meshnet node
+    PeerRelay = 0x08,
     /// Reserved for future, in case we use all byte values for types.
     Reserved = 0xfe,
 
@@ -89,6 +93,8 @@ pub enum Packet {
     CallMeMaybe(CallMeMaybeMsg),
     /// Pinging and checking the remote endpoints
     Pinger(PingerMsg),
+    /// Data relayed through a third meshnet node
+    PeerRelay(PeerRelayMsg),
 }
 
 impl Codec for Packet {
@@ -100,6 +106,7 @@ impl Codec for Packet {
         PacketType::PingerDeprecated,
         PacketType::CallMeMaybe,
         PacketType::Pinger,
+        PacketType::PeerRelay,
     ];
 
     fn decode(bytes: &[u8]) -> CodecResult<Self>
@@ -121,6 +128,7 @@ impl Codec for Packet {
                 CallMeMaybeMsgDeprecated::decode(bytes)?,
             )),
             PingerDeprecated => Ok(Self::PingerDeprecated(PingerMsgDeprecated::decode(bytes)?)),
+            PeerRelay => Ok(Self::PeerRelay(PeerRelayMsg::decode(bytes)?)),
             // At this point a package already should be decrypted if is not Data
             Reserved | Invalid | Encrypted => Err(CodecError::DecodeFailed),
         }
@@ -134,6 +142,7 @@ impl Codec for Packet {
             Self::Pinger(msg) => msg.encode(),
             Self::CallMeMaybeDeprecated(msg) => msg.encode(),
             Self::PingerDeprecated(msg) => msg.encode(),
+            Self::PeerRelay(msg) => msg.encode(),
         }
     }
 
@@ -145,6 +154,7 @@ impl Codec for Packet {
             Self::Pinger(msg) => msg.packet_type(),
             Self::CallMeMaybeDeprecated(msg) => msg.packet_type(),
             Self::PingerDeprecated(msg) => msg.packet_type(),
+            Self::PeerRelay(msg) => msg.packet_type(),
         }
     }
 }
@@ -205,6 +215,24 @@ impl From<PingerMsgDeprecated> for Packet {
     }
 }
 
+impl From<CallMeMaybeMsg> for Packet {
+    fn from(other: CallMeMaybeMsg) -> Self {
+        Self::CallMeMaybe(other)
+    }
+}
+
+impl From<PingerMsg> for Packet {
+    fn from(other: PingerMsg) -> Self {
+        Self::Pinger(other)
+    }
+}
+
+impl From<PeerRelayMsg> for Packet {
+    fn from(other: PeerRelayMsg) -> Self {
+        Self::PeerRelay(other)
+    }
+}
+
 impl From<HeartbeatMessage> for Packet {
     fn from(other: HeartbeatMessage) -> Self {
         Self::Heartbeat(other)
@@ -67,6 +67,40 @@ impl HeartbeatMessage {
     pub fn get_meshnet_id(&self) -> &[u8] {
         self.0.get_meshnet_id()
     }
+
+    /// Returns the time when the message was sent, in milliseconds since UNIX epoch
+    pub fn get_timestamp(&self) -> u64 {
+        self.0.get_timestamp()
+    }
+
+    /// Returns the nonce of the message
+    pub fn get_nonce(&self) -> &[u8] {
+        self.0.get_nonce()
+    }
+
+    /// Returns the authentication tag of the message
+    pub fn get_tag(&self) -> &[u8] {
+        self.0.get_tag()
+    }
+
+    /// Set the time when the message was sent and its nonce, clearing the authentication tag
+    pub fn set_timestamp_and_nonce(&mut self, timestamp: u64, nonce: Vec<u8>) {
+        self.0.set_timestamp(timestamp);
+        self.0.set_nonce(nonce);
+        self.0.clear_tag();
+    }
+
+    /// Set the authentication tag of the message
+    pub fn set_tag(&mut self, tag: Vec<u8>) {
+        self.0.set_tag(tag);
+    }
+
+    /// Returns the bytes covered by the authentication tag, which is every field except the tag
+    pub fn authenticated_data(&self) -> CodecResult<Vec<u8>> {
+        let mut heartbeat = self.0.clone();
+        heartbeat.clear_tag();
+        heartbeat.write_to_bytes().map_err(|_| CodecError::Encode)
+    }
 }
 
 impl Codec for HeartbeatMessage {
@@ -148,4 +182,23 @@ mod tests {
 
         assert_eq!(message.encode().unwrap(), bytes);
     }
+
+    #[test]
+    fn authenticated_data_excludes_tag() {
+        let mut message = HeartbeatMessage::request();
+        message.set_timestamp_and_nonce(1, vec![2; 24]);
+        let data = message.authenticated_data().unwrap();
+
+        message.set_tag(vec![3; 16]);
+        assert_eq!(message.authenticated_data().unwrap(), data);
+
+        let decoded = HeartbeatMessage::decode(&message.clone().encode().unwrap()).unwrap();
+        assert_eq!(decoded.get_timestamp(), 1);
+        assert_eq!(decoded.get_nonce(), &[2; 24]);
+        assert_eq!(decoded.get_tag(), &[3; 16]);
+
+        message.set_timestamp_and_nonce(2, vec![2; 24]);
+        assert!(message.get_tag().is_empty());
+        assert_ne!(message.authenticated_data().unwrap(), data);
+    }
 }
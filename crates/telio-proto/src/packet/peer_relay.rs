@@ -0,0 +1,285 @@
+use bytes::BufMut;
+use std::convert::TryInto;
+
+use crate::{
+    Codec, CodecError, CodecResult, DataMsg, DowncastPacket, Packet, PacketType, Session,
+    MAX_PACKET_SIZE,
+};
+
+/// Size of peer's public key in [`PeerRelayMsg`]
+pub const PEER_KEY_SIZE: usize = 32;
+
+const SESSION_SIZE: usize = 8;
+const PEER_OFFSET: usize = 2 + SESSION_SIZE;
+const HEADER_SIZE: usize = PEER_OFFSET + PEER_KEY_SIZE;
+
+/// Direction of [`PeerRelayMsg`]
+#[repr(u8)]
+#[derive(Debug, PartialEq, Eq, Copy, Clone, strum::FromRepr)]
+pub enum PeerRelayType {
+    /// Sender --> relaying node, `peer` is the destination node
+    Forward = 0x00,
+    /// Relaying node --> destination, `peer` is the original sender
+    Deliver = 0x01,
+    /// Relaying node --> sender, frame for `peer` was dropped
+    Reject = 0x02,
+}
+
+/// Packet encapsulating [`DataMsg`], which is relayed through a third meshnet node
+/// PeerRelay: [ type: 0x08u8, kind: u8, session: u64, peer: [u8; 32], payload: DataMsg ]
+///
+/// `session` authenticates the frame on each direct connection hop, it is the one agreed on
+/// by both ends of the connection in CallMeMaybe exchange
+/// # Examples
+/// Forwarding and delivering data packet:
+/// ```rust
+/// # use crate::telio_proto::{Codec, DataMsg, PacketType, PeerRelayMsg, PeerRelayType};
+/// let msg = PeerRelayMsg::forward([2u8; 32], DataMsg::new(&[1, 2, 3]));
+/// assert_eq!(msg.packet_type(), PacketType::PeerRelay);
+/// assert_eq!(msg.get_message_type(), PeerRelayType::Forward);
+/// assert_eq!(msg.get_peer(), [2u8; 32]);
+///
+/// let msg = msg.with_session(7);
+/// assert_eq!(msg.get_session(), 7);
+/// let bytes = msg.clone().encode().unwrap();
+/// assert_eq!(PeerRelayMsg::decode(&bytes).unwrap(), msg);
+///
+/// let msg = msg.relay([1u8; 32]).expect("Failed to relay packet");
+/// assert_eq!(msg.get_message_type(), PeerRelayType::Deliver);
+/// assert_eq!(msg.get_peer(), [1u8; 32]);
+/// assert_eq!(msg.get_data().unwrap(), DataMsg::new(&[1, 2, 3]));
+/// ```
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct PeerRelayMsg {
+    bytes: Vec<u8>,
+}
+
+impl PeerRelayMsg {
+    /// Creates new message, to be forwarded by relaying node to `dst`
+    pub fn forward(dst: [u8; PEER_KEY_SIZE], data: DataMsg) -> Self {
+        Self::new(PeerRelayType::Forward, dst, data)
+    }
+
+    /// Creates new message, delivered by relaying node from `src`
+    pub fn deliver(src: [u8; PEER_KEY_SIZE], data: DataMsg) -> Self {
+        Self::new(PeerRelayType::Deliver, src, data)
+    }
+
+    /// Creates new message, notifying sender, that relaying to `dst` is refused
+    pub fn reject(dst: [u8; PEER_KEY_SIZE]) -> Self {
+        let mut bytes = Vec::with_capacity(HEADER_SIZE);
+
+        bytes.put_u8(PacketType::PeerRelay as u8);
+        bytes.put_u8(PeerRelayType::Reject as u8);
+        bytes.put_u64(0);
+        bytes.put(&dst[..]);
+
+        Self { bytes }
+    }
+
+    fn new(kind: PeerRelayType, peer: [u8; PEER_KEY_SIZE], data: DataMsg) -> Self {
+        // Encoding DataMsg never fails, it is a plain byte buffer
+        let payload = data.encode().unwrap_or_default();
+        let mut bytes = Vec::with_capacity(HEADER_SIZE + payload.len());
+
+        bytes.put_u8(PacketType::PeerRelay as u8);
+        bytes.put_u8(kind as u8);
+        bytes.put_u64(0);
+        bytes.put(&peer[..]);
+        bytes.put(payload.as_slice());
+
+        Self { bytes }
+    }
+
+    /// Returns [`PeerRelayType`] of the message
+    pub fn get_message_type(&self) -> PeerRelayType {
+        // Type is validated during construction and decoding
+        PeerRelayType::from_repr(self.bytes[1]).unwrap_or(PeerRelayType::Reject)
+    }
+
+    /// Returns peer's public key: destination for [`PeerRelayType::Forward`] and
+    /// [`PeerRelayType::Reject`], original sender for [`PeerRelayType::Deliver`]
+    pub fn get_peer(&self) -> [u8; PEER_KEY_SIZE] {
+        let mut peer = [0u8; PEER_KEY_SIZE];
+        peer.copy_from_slice(&self.bytes[PEER_OFFSET..HEADER_SIZE]);
+        peer
+    }
+
+    /// Returns session of direct connection hop, the frame was sent over
+    pub fn get_session(&self) -> Session {
+        let mut session = [0u8; SESSION_SIZE];
+        session.copy_from_slice(&self.bytes[2..PEER_OFFSET]);
+        Session::from_be_bytes(session)
+    }
+
+    /// Sets session of direct connection hop, the frame is about to be sent over
+    pub fn with_session(mut self, session: Session) -> Self {
+        self.bytes[2..PEER_OFFSET].copy_from_slice(&session.to_be_bytes());
+        self
+    }
+
+    /// Returns relayed [`DataMsg`]
+    pub fn get_data(&self) -> CodecResult<DataMsg> {
+        DataMsg::decode(&self.bytes[HEADER_SIZE..])
+    }
+
+    /// Returns length of relayed payload in bytes
+    pub fn payload_len(&self) -> usize {
+        self.bytes.len() - HEADER_SIZE
+    }
+
+    /// Turns [`PeerRelayType::Forward`] message into [`PeerRelayType::Deliver`] one,
+    /// received from `src`, without copying the payload
+    pub fn relay(mut self, src: [u8; PEER_KEY_SIZE]) -> CodecResult<Self> {
+        if self.get_message_type() != PeerRelayType::Forward {
+            return Err(CodecError::InvalidType);
+        }
+
+        self.bytes[1] = PeerRelayType::Deliver as u8;
+        self.bytes[PEER_OFFSET..HEADER_SIZE].copy_from_slice(&src);
+
+        Ok(self)
+    }
+}
+
+impl Codec for PeerRelayMsg {
+    const TYPES: &'static [PacketType] = &[PacketType::PeerRelay];
+
+    fn decode(bytes: &[u8]) -> CodecResult<Self>
+    where
+        Self: Sized,
+    {
+        if bytes.is_empty() {
+            return Err(CodecError::InvalidLength);
+        }
+
+        if PacketType::from(bytes[0]) != PacketType::PeerRelay {
+            return Err(CodecError::DecodeFailed);
+        }
+
+        if bytes.len() < HEADER_SIZE {
+            return Err(CodecError::InvalidLength);
+        }
+
+        let kind = PeerRelayType::from_repr(bytes[1]).ok_or(CodecError::DecodeFailed)?;
+
+        // Only rejects are allowed to come without payload
+        if kind != PeerRelayType::Reject && bytes.len() == HEADER_SIZE {
+            return Err(CodecError::InvalidLength);
+        }
+
+        Ok(Self {
+            bytes: bytes.to_vec(),
+        })
+    }
+
+    fn encode(self) -> CodecResult<Vec<u8>>
+    where
+        Self: Sized,
+    {
+        if self.bytes.len() > MAX_PACKET_SIZE {
+            return Err(CodecError::Encode);
+        }
+
+        Ok(self.bytes)
+    }
+
+    fn packet_type(&self) -> PacketType {
+        PacketType::PeerRelay
+    }
+}
+
+impl DowncastPacket for PeerRelayMsg {
+    fn downcast(packet: Packet) -> std::result::Result<Self, Packet>
+    where
+        Self: Sized,
+    {
+        match packet {
+            Packet::PeerRelay(msg) => Ok(msg),
+            packet => Err(packet),
+        }
+    }
+}
+
+impl std::fmt::Display for PeerRelayMsg {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let peer: [u8; 4] = self.bytes[PEER_OFFSET..PEER_OFFSET + 4]
+            .try_into()
+            .map_err(|_| std::fmt::Error)?;
+
+        write!(
+            f,
+            "PeerRelay: type: {:?}, peer: {:02x?}.., payload len: {}",
+            self.get_message_type(),
+            peer,
+            self.payload_len(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Generation, PeerId};
+
+    #[test]
+    fn fail_to_decode_small_packet() {
+        assert_eq!(PeerRelayMsg::decode(&[]), Err(CodecError::InvalidLength));
+        assert_eq!(
+            PeerRelayMsg::decode(&[PacketType::PeerRelay as u8, 0, 1, 2]),
+            Err(CodecError::InvalidLength)
+        );
+
+        // Forward without payload
+        let mut bytes = vec![PacketType::PeerRelay as u8, PeerRelayType::Forward as u8];
+        bytes.extend([1u8; SESSION_SIZE + PEER_KEY_SIZE]);
+        assert_eq!(PeerRelayMsg::decode(&bytes), Err(CodecError::InvalidLength));
+    }
+
+    #[test]
+    fn fail_to_decode_packet_of_wrong_type() {
+        let mut bytes = vec![PacketType::Data as u8, PeerRelayType::Forward as u8];
+        bytes.extend([1u8; SESSION_SIZE + PEER_KEY_SIZE + 1]);
+        assert_eq!(PeerRelayMsg::decode(&bytes), Err(CodecError::DecodeFailed));
+
+        let mut bytes = vec![PacketType::PeerRelay as u8, 0x42];
+        bytes.extend([1u8; SESSION_SIZE + PEER_KEY_SIZE + 1]);
+        assert_eq!(PeerRelayMsg::decode(&bytes), Err(CodecError::DecodeFailed));
+    }
+
+    #[test]
+    fn encode_decode_reject() {
+        let msg = PeerRelayMsg::reject([7u8; PEER_KEY_SIZE]);
+        let bytes = msg.clone().encode().unwrap();
+
+        assert_eq!(bytes.len(), HEADER_SIZE);
+        assert_eq!(&bytes[..2], &[PacketType::PeerRelay as u8, 0x02]);
+        assert_eq!(&bytes[2..PEER_OFFSET], &[0u8; SESSION_SIZE]);
+
+        let decoded = PeerRelayMsg::decode(&bytes).unwrap();
+        assert_eq!(decoded, msg);
+        assert_eq!(decoded.get_message_type(), PeerRelayType::Reject);
+        assert_eq!(decoded.get_peer(), [7u8; PEER_KEY_SIZE]);
+        assert_eq!(decoded.payload_len(), 0);
+    }
+
+    #[test]
+    fn relay_keeps_gen_data() {
+        let data = DataMsg::with_generation(b"namas", Generation(3), PeerId(42));
+        let msg = PeerRelayMsg::forward([2u8; PEER_KEY_SIZE], data.clone())
+            .with_session(0x0102_0304_0506_0708)
+            .relay([1u8; PEER_KEY_SIZE])
+            .unwrap();
+
+        assert_eq!(msg.get_message_type(), PeerRelayType::Deliver);
+        assert_eq!(msg.get_session(), 0x0102_0304_0506_0708);
+        assert_eq!(msg.get_peer(), [1u8; PEER_KEY_SIZE]);
+        assert_eq!(msg.get_data().unwrap(), data);
+
+        // Only forwarded messages can be relayed
+        assert_eq!(
+            msg.relay([3u8; PEER_KEY_SIZE]),
+            Err(CodecError::InvalidType)
+        );
+    }
+}
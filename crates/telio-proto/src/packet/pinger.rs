@@ -11,6 +11,9 @@ use protobuf::Message;
 /// Unix timestamp in milliseconds
 pub type Timestamp = u64;
 
+// Packet type and WG port, preceding protobuf message
+const HEADER_SIZE: usize = 3;
+
 /// Packet encapsulating containing WG packets
 /// # Examples
 /// Decoding ping message:
@@ -60,6 +63,23 @@ impl PingerMsg {
         }
     }
 
+    /// Create new ping packet, padded to be encoded into `size` bytes, for probing path MTU.
+    /// Packet may end up a few bytes shorter, if `size` is too small to be hit exactly
+    pub fn probe(wg_port: WGPort, session: Session, ts: Timestamp, size: u32) -> Self {
+        let mut probe = Self::ping(wg_port, session, ts);
+        probe.msg.probe_size = size;
+
+        // Padding field costs a tag byte and a length prefix on top of its content
+        let unpadded = HEADER_SIZE + probe.msg.compute_size() as usize + 1;
+        let mut padding = (size as usize).saturating_sub(unpadded);
+        while padding > 0 && unpadded + varint_size(padding) + padding > size as usize {
+            padding -= 1;
+        }
+        probe.msg.padding = vec![0; padding];
+
+        probe
+    }
+
     /// Create new pong packet (response)
     pub fn pong(&self, wg_port: WGPort) -> Option<Self> {
         if self.msg.get_message_type() == Pinger_Type::PING {
@@ -69,6 +89,8 @@ impl PingerMsg {
                     message_type: Pinger_Type::PONG,
                     session: self.msg.session,
                     start_timestamp: self.msg.get_start_timestamp(),
+                    // Pong is not padded, it only confirms probe's size
+                    probe_size: self.msg.get_probe_size(),
                     ..Default::default()
                 },
             });
@@ -82,6 +104,12 @@ impl PingerMsg {
         WGPort(self.wg_port.0 as u16)
     }
 
+    /// Get size of MTU probe, [`None`] if it is an ordinary ping, or a pong from a node,
+    /// which does not support probing
+    pub fn get_probe_size(&self) -> Option<u32> {
+        Some(self.msg.get_probe_size()).filter(|size| *size > 0)
+    }
+
     /// Returns [`Pinger_Type`] of the message
     pub fn get_message_type(&self) -> Pinger_Type {
         self.msg.get_message_type()
@@ -143,6 +171,16 @@ impl Codec for PingerMsg {
     }
 }
 
+/// Number of bytes, taken by varint encoding of `value`
+fn varint_size(mut value: usize) -> usize {
+    let mut size = 1;
+    while value >= 0x80 {
+        value >>= 7;
+        size += 1;
+    }
+    size
+}
+
 impl DowncastPacket for PingerMsg {
     fn downcast(packet: Packet) -> Result<Self, Packet>
     where
@@ -334,6 +372,32 @@ mod tests {
         assert_eq!(pong.encode().unwrap(), pong_bytes);
     }
 
+    #[test]
+    fn probe_is_padded_to_requested_size() {
+        for size in [64, 1252, 1280, 1472] {
+            let probe = PingerMsg::probe(WGPort(2), 3_u64, 10_u64, size);
+            let bytes = probe.clone().encode().unwrap();
+            assert_eq!(bytes.len(), size as usize);
+
+            let decoded = PingerMsg::decode(&bytes).unwrap();
+            assert_eq!(decoded, probe);
+            assert_eq!(decoded.get_probe_size(), Some(size));
+        }
+    }
+
+    #[test]
+    fn pong_echoes_probe_size_without_padding() {
+        let probe = PingerMsg::probe(WGPort(2), 3_u64, 10_u64, 1280);
+        let pong = probe.pong(WGPort(3)).unwrap();
+        assert_eq!(pong.get_probe_size(), Some(1280));
+        assert!(pong.encode().unwrap().len() < 20);
+
+        let pong = PingerMsg::ping(WGPort(2), 3_u64, 10_u64)
+            .pong(WGPort(3))
+            .unwrap();
+        assert_eq!(pong.get_probe_size(), None);
+    }
+
     #[test]
     fn deprecated_decode_packet() {
         let ping_bytes = &[4, 0, 8, 16, 9, 24, 128, 2];
@@ -247,6 +247,7 @@ impl DerpRelay {
                 return Ok(());
             }
 
+            let rekeyed = s.config.secret_key != config.secret_key;
             s.config = config;
 
             // Prepare new config
@@ -255,8 +256,9 @@ impl DerpRelay {
             // Restart connection
             match s.server.as_ref() {
                 Some(server) => {
-                    // Current server not found in new config
-                    if !s.config.servers.contains(server) {
+                    // Current server not found in new config, or the server
+                    // still knows us by the old key
+                    if rekeyed || !s.config.servers.contains(server) {
                         s.disconnect().await;
                     }
                 }
@@ -4,6 +4,7 @@ pub mod native;
 pub mod protector;
 pub mod socket_params;
 
+pub use native::dont_fragment;
 pub use protector::{NativeProtector, Protect, Protector};
 pub use socket_params::{SocketBufSizes, TcpParams, UdpParams};
 pub use socket_pool::{External, SocketPool};
@@ -64,3 +64,93 @@ pub fn interface_index_from_tun(tun_fd: RawFd) -> std::io::Result<u64> {
     }
     Ok(index as u64)
 }
+
+/// Don't Fragment flag set on a socket, previous setting is restored once dropped
+#[cfg(unix)]
+pub struct DontFragment {
+    fd: RawFd,
+    level: libc::c_int,
+    name: libc::c_int,
+    previous: libc::c_int,
+}
+
+/// Set Don't Fragment on packets sent by `socket`, so oversized ones are dropped on the way
+/// instead of being fragmented. Setting lasts until returned guard is dropped
+#[cfg(unix)]
+pub fn dont_fragment<T: AsNativeSocket>(socket: &T, ipv6: bool) -> std::io::Result<DontFragment> {
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    let (level, name, value) = if ipv6 {
+        (
+            libc::IPPROTO_IPV6,
+            libc::IPV6_MTU_DISCOVER,
+            libc::IPV6_PMTUDISC_DO,
+        )
+    } else {
+        (
+            libc::IPPROTO_IP,
+            libc::IP_MTU_DISCOVER,
+            libc::IP_PMTUDISC_DO,
+        )
+    };
+    #[cfg(not(any(target_os = "linux", target_os = "android")))]
+    let (level, name, value) = if ipv6 {
+        (libc::IPPROTO_IPV6, libc::IPV6_DONTFRAG, 1)
+    } else {
+        (libc::IPPROTO_IP, libc::IP_DONTFRAG, 1)
+    };
+
+    let fd = socket.as_native_socket();
+    let mut previous: libc::c_int = 0;
+    let mut len = std::mem::size_of::<libc::c_int>() as libc::socklen_t;
+    let size = len;
+    unsafe {
+        if libc::getsockopt(
+            fd,
+            level,
+            name,
+            &mut previous as *mut libc::c_int as *mut libc::c_void,
+            &mut len,
+        ) != 0
+            || libc::setsockopt(
+                fd,
+                level,
+                name,
+                &value as *const libc::c_int as *const libc::c_void,
+                size,
+            ) != 0
+        {
+            return Err(std::io::Error::last_os_error());
+        }
+    }
+
+    Ok(DontFragment {
+        fd,
+        level,
+        name,
+        previous,
+    })
+}
+
+/// Set Don't Fragment on packets sent by `socket`, not supported on this platform
+#[cfg(windows)]
+pub fn dont_fragment<T: AsNativeSocket>(_socket: &T, _ipv6: bool) -> std::io::Result<()> {
+    Err(std::io::Error::new(
+        std::io::ErrorKind::Other,
+        "Setting Don't Fragment is not supported on this platform",
+    ))
+}
+
+#[cfg(unix)]
+impl Drop for DontFragment {
+    fn drop(&mut self) {
+        unsafe {
+            libc::setsockopt(
+                self.fd,
+                self.level,
+                self.name,
+                &self.previous as *const libc::c_int as *const libc::c_void,
+                std::mem::size_of::<libc::c_int>() as libc::socklen_t,
+            );
+        }
+    }
+}
@@ -41,6 +41,10 @@ impl Protector for NativeProtector {
             *my_fwmark = fwmark;
         }
     }
+
+    fn get_fwmark(&self) -> Option<u32> {
+        self.fwmark.lock().ok().map(|fwmark| *fwmark)
+    }
 }
 
 fn set_fwmark(fd: i32, fwmark: u32) -> io::Result<()> {
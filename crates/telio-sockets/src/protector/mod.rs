@@ -37,6 +37,10 @@ pub trait Protector: Send + Sync {
     #[cfg(target_os = "linux")]
     fn set_fwmark(&self, fwmark: u32);
 
+    /// Fwmark, with which sockets are protected, `None` if protection is not fwmark based
+    #[cfg(target_os = "linux")]
+    fn get_fwmark(&self) -> Option<u32>;
+
     #[cfg(any(target_os = "macos", target_os = "ios", windows))]
     fn set_tunnel_interface(&self, interface: u64);
 }
@@ -57,6 +61,11 @@ impl Protector for Protect {
     #[cfg(target_os = "linux")]
     fn set_fwmark(&self, _fwmark: u32) {}
 
+    #[cfg(target_os = "linux")]
+    fn get_fwmark(&self) -> Option<u32> {
+        None
+    }
+
     #[cfg(any(target_os = "macos", target_os = "ios", windows))]
     fn set_tunnel_interface(&self, _: u64) {}
 }
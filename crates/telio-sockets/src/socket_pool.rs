@@ -188,6 +188,12 @@ impl SocketPool {
         self.protect.set_fwmark(fwmark);
     }
 
+    /// Fwmark of protected sockets, `None` if custom protection is used instead
+    #[cfg(target_os = "linux")]
+    pub fn get_fwmark(&self) -> Option<u32> {
+        self.protect.get_fwmark()
+    }
+
     #[cfg(any(target_os = "macos", target_os = "ios", windows))]
     pub fn set_tunnel_interface(&self, interface: u64) {
         self.protect.set_tunnel_interface(interface);
@@ -312,6 +318,8 @@ mod tests {
             fn clean(&self, socket: NativeSocket);
             #[cfg(target_os = "linux")]
             fn set_fwmark(&self, fwmark: u32);
+            #[cfg(target_os = "linux")]
+            fn get_fwmark(&self) -> Option<u32>;
             #[cfg(any(target_os = "macos", windows))]
             fn set_tunnel_interface(&self, interface: u64);
         }
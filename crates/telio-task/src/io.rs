@@ -62,10 +62,31 @@ impl<T: Send> Default for Chan<T> {
 
 /// Types for multi producer, multi consumer channels
 pub mod mc_chan {
+    use futures::{stream, Stream};
+    use telio_utils::telio_log_warn;
+    use tokio::sync::broadcast::error::RecvError;
+
     /// Default sender channel
     pub type Tx<T> = tokio::sync::broadcast::Sender<T>;
     /// Default reciever channel
     pub type Rx<T> = tokio::sync::broadcast::Receiver<T>;
+
+    /// Turn reciever into a stream, ending when all senders are dropped.
+    ///
+    /// Values, which were dropped because the reciever lagged behind, are skipped.
+    pub fn into_stream<T: Clone + Send + 'static>(rx: Rx<T>) -> impl Stream<Item = T> + Send {
+        stream::unfold(rx, |mut rx| async move {
+            loop {
+                match rx.recv().await {
+                    Ok(value) => return Some((value, rx)),
+                    Err(RecvError::Lagged(skipped)) => {
+                        telio_log_warn!("Stream lagged behind, skipped {} values", skipped)
+                    }
+                    Err(RecvError::Closed) => return None,
+                }
+            }
+        })
+    }
 }
 
 /// Channel encapsulating multi producer, multi consumer send and reciever sides
@@ -97,6 +118,13 @@ impl<T: Clone> McChan<T> {
     }
 }
 
+impl<T: Clone + Send + 'static> McChan<T> {
+    /// New stream of values sent to this channel from now on, see [mc_chan::into_stream].
+    pub fn subscribe(&self) -> impl futures::Stream<Item = T> + Send {
+        mc_chan::into_stream(self.tx.subscribe())
+    }
+}
+
 /// Wait for chan::Tx to be ready, and only then await wanted future
 pub async fn wait_for_tx<T: Send, F, O>(tx: &chan::Tx<T>, fut: F) -> Option<(OwnedPermit<T>, O)>
 where
@@ -239,6 +267,24 @@ mod tests {
         .expect("Should have not deadlocked!!");
     }
 
+    #[tokio::test]
+    async fn test_mc_chan_subscribers() {
+        use futures::StreamExt;
+
+        let chan = McChan::new(2);
+        let first = chan.subscribe();
+        let second = chan.subscribe();
+
+        for value in 0..4 {
+            chan.tx.send(value).unwrap();
+        }
+        drop(chan);
+
+        // Both subscribers see the values independently, lagged ones are skipped
+        assert_eq!(first.collect::<Vec<_>>().await, vec![2, 3]);
+        assert_eq!(second.collect::<Vec<_>>().await, vec![2, 3]);
+    }
+
     async fn delay<T>(fut: impl std::future::Future<Output = T>) -> T {
         sleep(SPEED * 3).await;
         fut.await
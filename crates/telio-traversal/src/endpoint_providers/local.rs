@@ -30,6 +30,48 @@ impl GetIfAddrs for SystemGetIfAddrs {
     }
 }
 
+/// Interface listing, which can be shared and replaced at runtime, e.g. to simulate network changes.
+/// Lists system interfaces by default
+#[derive(Clone, Default)]
+pub struct SharedGetIfAddrs(Option<Arc<GetIfAddrsFn>>);
+
+type GetIfAddrsFn = dyn Fn() -> std::io::Result<Vec<if_addrs::Interface>> + Send + Sync;
+
+impl SharedGetIfAddrs {
+    pub fn new(
+        get: impl Fn() -> std::io::Result<Vec<if_addrs::Interface>> + Send + Sync + 'static,
+    ) -> Self {
+        Self(Some(Arc::new(get)))
+    }
+}
+
+impl GetIfAddrs for SharedGetIfAddrs {
+    fn get(&self) -> std::io::Result<Vec<if_addrs::Interface>> {
+        match &self.0 {
+            Some(get) => get(),
+            None => SystemGetIfAddrs.get(),
+        }
+    }
+}
+
+/// Get local interfaces, which can be used as endpoint candidates
+pub fn gather_local_interfaces<G: GetIfAddrs>(
+    get_if_addr: &G,
+) -> Result<Vec<if_addrs::Interface>, Error> {
+    let shared_range: Ipv4Net = Ipv4Net::new(Ipv4Addr::new(100, 64, 0, 0), 10)?;
+    Ok(get_if_addr
+        .get()?
+        .into_iter()
+        .filter(|x| !x.addr.is_loopback())
+        .filter(|x| match x.addr.ip() {
+            // Filter 100.64/10 libtelio's meshnet network.
+            IpAddr::V4(v4) => !shared_range.contains(&v4),
+            // Filter IPv6
+            _ => false,
+        })
+        .collect())
+}
+
 pub struct LocalInterfacesEndpointProvider<T: WireGuard, G: GetIfAddrs = SystemGetIfAddrs> {
     task: Task<State<T, G>>,
 }
@@ -144,19 +186,7 @@ impl<T: WireGuard, G: GetIfAddrs> State<T, G> {
     }
 
     fn gather_local_interfaces(&self) -> Result<Vec<if_addrs::Interface>, Error> {
-        let shared_range: Ipv4Net = Ipv4Net::new(Ipv4Addr::new(100, 64, 0, 0), 10)?;
-        Ok(self
-            .get_if_addr
-            .get()?
-            .into_iter()
-            .filter(|x| !x.addr.is_loopback())
-            .filter(|x| match x.addr.ip() {
-                // Filter 100.64/10 libtelio's meshnet network.
-                IpAddr::V4(v4) => !shared_range.contains(&v4),
-                // Filter IPv6
-                _ => false,
-            })
-            .collect())
+        gather_local_interfaces(&self.get_if_addr)
     }
 
     async fn poll_local_endpoints(&mut self) -> Result<(), Error> {
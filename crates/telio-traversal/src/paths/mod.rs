@@ -1,3 +1,4 @@
+mod peer_relay;
 pub mod relay;
 mod set;
 mod set_builder;
@@ -12,7 +13,10 @@ use futures::{
 };
 use strum::EnumCount;
 use telio_crypto::PublicKey;
-use telio_model::api_config::{FeaturePaths, PathType};
+use telio_model::{
+    api_config::{FeaturePaths, FeaturePeerRelay, FeatureTraversal, PathType},
+    diagnostics::PeerDiagnostics,
+};
 use telio_proto::{DataMsg, Generation};
 use telio_task::{
     io::{
@@ -24,7 +28,7 @@ use telio_task::{
 use telio_utils::{
     map::MapExt, sleep::PinnedSleep, telio_log_debug, telio_log_info, telio_log_trace,
 };
-use tokio::time::Duration;
+use tokio::time::{Duration, Instant};
 
 use super::route_type::RouteType;
 use crate::route::{Configure, Route};
@@ -36,6 +40,8 @@ pub use self::{
 };
 
 pub const CONN_UPGRADE_TIMEOUT: Duration = Duration::from_secs(30);
+/// Longest time data is mirrored through relay, while direct path is migrating
+pub const MIGRATION_TIMEOUT: Duration = Duration::from_secs(15);
 
 pub type ConnectionTimer = Option<PinnedSleep<PublicKey>>;
 
@@ -80,6 +86,8 @@ pub struct Connection {
     generation: Option<Generation>,
     active: HashSet<PathType>,
     state: ConnectionState,
+    // Direct path is migrating to a new network since then, data is mirrored through relay
+    migrating: Option<Instant>,
 }
 
 pub struct Io {
@@ -88,8 +96,17 @@ pub struct Io {
 }
 
 impl Paths {
-    pub fn start(features: FeaturePaths, io: Io, set_io: PathSetIo) -> Result<Self, Error> {
-        Self::start_with(io, PathSetBuilderDefault::new(set_io, features.paths()))
+    pub fn start(
+        features: FeaturePaths,
+        peer_relay: Option<FeaturePeerRelay>,
+        tunnel_mtu: u32,
+        io: Io,
+        set_io: PathSetIo,
+    ) -> Result<Self, Error> {
+        Self::start_with(
+            io,
+            PathSetBuilderDefault::new(set_io, features.paths(), peer_relay, tunnel_mtu),
+        )
     }
 }
 
@@ -110,6 +127,27 @@ impl Paths {
         task_exec!(&self.task, async move |s| Ok(s.configure(config).await)).await?
     }
 
+    pub async fn notify_network_change(&self) -> Result<(), Error> {
+        task_exec!(&self.task, async move |s| Ok(s
+            .notify_network_change()
+            .await))
+        .await?
+    }
+
+    pub async fn set_traversal(&self, config: FeatureTraversal) -> Result<(), Error> {
+        task_exec!(&self.task, async move |s| Ok(s.set_traversal(config).await)).await?
+    }
+
+    /// Diagnostics of peer's direct path, if hole punching is enabled
+    pub async fn get_diagnostics(&self, pk: PublicKey) -> Result<Option<PeerDiagnostics>, Error> {
+        task_exec!(&self.task, async move |s| Ok(s.get_diagnostics(pk).await)).await?
+    }
+
+    /// Tunnel MTU fitting into the active path of each peer, [None] if it was not probed
+    pub async fn get_path_mtus(&self) -> Result<HashMap<PublicKey, Option<u32>>, Error> {
+        task_exec!(&self.task, async move |s| Ok(s.get_path_mtus().await)).await?
+    }
+
     pub async fn stop(self) {
         let _ = self.task.stop().await.resume_unwind();
     }
@@ -130,10 +168,14 @@ impl State {
             c.state = ConnectionState::Init;
             c.active.clear();
             c.generation = None;
+            c.migrating = None;
         });
 
         for (pt, route) in routes.iter() {
             route.configure(config.derp.clone()).await;
+            if let RouteType::PeerRelay { peer_relay } = route {
+                peer_relay.set_relays(config.peer_relays.clone()).await?;
+            }
             route.update_nodes(conns.keys().cloned().collect()).await?;
             // ACT: [ ] Use FuturesOrdered
             for (p, c) in conns.iter_mut() {
@@ -151,6 +193,76 @@ impl State {
         Ok(())
     }
 
+    async fn notify_network_change(&mut self) -> Result<(), Error> {
+        for (pk, c) in self.connections.iter_mut() {
+            // Keep traffic flowing through relay, while direct path is being migrated
+            c.migrating = (matches!(c.path, Some(path) if path != PathType::Relay)
+                && c.active.contains(&PathType::Relay))
+            .then(Instant::now);
+
+            if c.migrating.is_some() {
+                telio_log_debug!(
+                    "({}) Peer ({:?}) migrating {:?}, mirroring data through {:?}",
+                    Self::NAME,
+                    &pk,
+                    c.path,
+                    PathType::Relay,
+                );
+            }
+        }
+
+        for (_, route) in routes(&self.pathset) {
+            route.notify_network_change().await?;
+        }
+
+        Ok(())
+    }
+
+    async fn set_traversal(&mut self, config: FeatureTraversal) -> Result<(), Error> {
+        for (_, route) in routes(&self.pathset) {
+            if let RouteType::UdpHolePunch { udp_hole_punch } = route {
+                udp_hole_punch.set_timers(config).await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn get_diagnostics(&self, pk: PublicKey) -> Result<Option<PeerDiagnostics>, Error> {
+        for (_, route) in routes(&self.pathset) {
+            if let RouteType::UdpHolePunch { udp_hole_punch } = route {
+                return Ok(Some(udp_hole_punch.get_diagnostics(pk).await?));
+            }
+        }
+
+        Ok(None)
+    }
+
+    async fn get_path_mtus(&self) -> Result<HashMap<PublicKey, Option<u32>>, Error> {
+        let mut mtus = HashMap::new();
+        for (pk, conn) in &self.connections {
+            let mut mtu = None;
+            // Peer relay frames are carried over direct paths of the relaying node, so
+            // they are not probed
+            for (_, route) in routes(&self.pathset) {
+                match route {
+                    RouteType::UdpHolePunch { udp_hole_punch }
+                        if conn.path == Some(PathType::UdpHolePunch) =>
+                    {
+                        mtu = udp_hole_punch.get_mtu(*pk).await?;
+                    }
+                    RouteType::Relay { relay } if conn.path == Some(PathType::Relay) => {
+                        mtu = relay.get_mtu(*pk).await?;
+                    }
+                    _ => (),
+                }
+            }
+            mtus.insert(*pk, mtu);
+        }
+
+        Ok(mtus)
+    }
+
     async fn check_conns(
         conns: &mut HashMap<PublicKey, Connection>,
         conns_wait: &mut HashMap<PublicKey, ConnectionTimer>,
@@ -244,12 +356,22 @@ impl State {
         tokio::select! {
             // Tx side
             Some((pk, mut msg)) = pathset.permits.ready_all().then(|_| data_rx.recv()) => {
-                if let Some(con) = conns.get(&pk) {
+                if let Some(con) = conns.get_mut(&pk) {
                     if let Some(path) = con.path {
                         if let Some(gen) = con.generation {
                             msg.set_generation(gen);
                         }
 
+                        if con.migrating.map_or(false, |since| since.elapsed() >= MIGRATION_TIMEOUT) {
+                            telio_log_debug!("({}) Peer ({:?}) migration timeout, stop mirroring data", Self::NAME, &pk);
+                            con.migrating = None;
+                        }
+
+                        if con.migrating.is_some() && path != PathType::Relay {
+                            telio_log_trace!("({}) Peer ({:?}) Data ({}) --> {:?} (migrating)", Self::NAME, &pk, msg, PathType::Relay);
+                            pathset.permits.send(PathType::Relay, (pk, msg.clone()));
+                        }
+
                         telio_log_trace!("({}) Peer ({:?}) Data ({}) --> {:?}", Self::NAME, &pk, msg, path);
                         pathset.permits.send(path, (pk, msg));
                     } else {
@@ -283,6 +405,9 @@ impl State {
                         con.active.remove(&path_type);
                     }
 
+                    // Migration is over, either path has settled or it got disconnected
+                    con.migrating = None;
+
                     let old_path = con.path;
                     if con.select_best_path(pathset.prio.iter().cloned(), &pk) {
                         telio_log_debug!(
@@ -348,6 +473,7 @@ impl Connection {
             generation: None,
             active: HashSet::with_capacity(PathType::COUNT),
             state: ConnectionState::Init,
+            migrating: None,
         }
     }
 
@@ -358,6 +484,7 @@ impl Connection {
         self.active.clear();
         self.state = ConnectionState::Init;
         self.generation = None;
+        self.migrating = None;
 
         for (pt, route) in routes(pathset) {
             route.reset_nodes(vec![*pk]).await?;
@@ -586,7 +713,7 @@ mod tests {
     }
 
     mod util {
-        use crate::paths::relay::Default;
+        use crate::paths::relay::Relay;
         use crate::Configure;
 
         use super::*;
@@ -750,7 +877,9 @@ mod tests {
                         send: None,
                     };
                     let mut path = Path {
-                        route: RouteType::Relay { relay: Default },
+                        route: RouteType::Relay {
+                            relay: Relay::start(None, 1420),
+                        },
                         channel: rdata,
                         changes: None,
                     };
@@ -0,0 +1,27 @@
+use crate::routes::peer_relay::{Error, PeerRelay};
+use telio_crypto::PublicKey;
+use telio_model::api_config::FeaturePeerRelay;
+use telio_proto::PeerRelayMsg;
+use telio_task::io::Chan;
+
+use super::{Path, RouteType};
+
+pub fn build(
+    transport: Chan<(PublicKey, PeerRelayMsg)>,
+    features: Option<FeaturePeerRelay>,
+) -> Result<Path, Error> {
+    let Chan {
+        tx: event_tx,
+        rx: event_rx,
+    } = Chan::default();
+
+    let (ldata, rdata) = Chan::pipe();
+    match PeerRelay::start(transport, ldata, event_tx, features) {
+        Ok(peer_relay) => Ok(Path {
+            route: RouteType::PeerRelay { peer_relay },
+            channel: rdata,
+            changes: Some(event_rx),
+        }),
+        Err(error) => Err(error),
+    }
+}
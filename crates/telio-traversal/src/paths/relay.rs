@@ -1,29 +1,200 @@
-use std::time::Duration;
+use std::collections::{HashMap, HashSet};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 use crate::route_type::RouteType;
 use crate::{Configure, Route, RouteResult};
 use async_trait::async_trait;
+use futures::Future;
 use telio_crypto::PublicKey;
-use telio_proto::DataMsg;
-use telio_task::io::Chan;
+use telio_proto::{DataMsg, PingerMsg, Session, WGPort};
+use telio_task::{io::Chan, task_exec, BoxAction, Runtime, Task};
+use telio_utils::{telio_log_debug, telio_log_trace};
+use tokio::time::{interval_at, Instant, Interval};
 
 use super::Path;
 
-pub struct Default;
+/// Tunnel MTUs probed on relayed path below tunnel's own one, down to the minimum of IPv6
+const PROBED_TUNNEL_MTUS: [u32; 3] = [1380, 1340, 1280];
+/// Relayed data packet type, followed by WireGuard's data header and tag
+const RELAY_OVERHEAD: u32 = 1 + 32;
+/// Relay servers and their connections may change, so path MTU is periodically probed again
+const RELAY_MTU_PROBE_INTERVAL: Duration = Duration::from_secs(600);
+
+/// Relayed path. Relay server carries data as it is, while padded pings sent through it
+/// probe the largest packets relay passes towards each node
+pub struct Relay {
+    prober: Option<Task<Prober>>,
+}
+
+struct Prober {
+    pinger: Chan<(PublicKey, PingerMsg)>,
+    nodes: HashSet<PublicKey>,
+    /// Largest answered probe of each node, as tunnel MTU
+    mtus: HashMap<PublicKey, u32>,
+    /// Session of the latest probing
+    session: Session,
+    interval: Interval,
+    /// MTU of tunnel, the largest one probed
+    tunnel_mtu: u32,
+}
+
+impl Relay {
+    /// Start relayed path, probing its MTU up to `tunnel_mtu` if relay carries pings
+    pub fn start(pinger: Option<Chan<(PublicKey, PingerMsg)>>, tunnel_mtu: u32) -> Self {
+        Self {
+            prober: pinger.map(|pinger| {
+                Task::start(Prober {
+                    pinger,
+                    nodes: HashSet::new(),
+                    mtus: HashMap::new(),
+                    session: 0,
+                    interval: interval_at(
+                        Instant::now() + RELAY_MTU_PROBE_INTERVAL,
+                        RELAY_MTU_PROBE_INTERVAL,
+                    ),
+                    tunnel_mtu,
+                })
+            }),
+        }
+    }
+
+    /// Tunnel MTU fitting into node's relayed path, [None] if it was not probed or node
+    /// does not answer probes
+    pub async fn get_mtu(&self, node: PublicKey) -> RouteResult<Option<u32>> {
+        match &self.prober {
+            Some(prober) => {
+                Ok(task_exec!(prober, async move |s| Ok(s.mtus.get(&node).copied())).await?)
+            }
+            None => Ok(None),
+        }
+    }
+
+    pub async fn stop(self) {
+        if let Some(prober) = self.prober {
+            let _ = prober.stop().await;
+        }
+    }
+
+    /// Replace probed nodes, probing the new ones right away
+    async fn probe_nodes(&self, nodes: Vec<PublicKey>, keep: bool) -> RouteResult<()> {
+        if let Some(prober) = &self.prober {
+            task_exec!(prober, async move |s| {
+                let new: Vec<_> = nodes
+                    .iter()
+                    .filter(|node| !s.nodes.contains(node))
+                    .copied()
+                    .collect();
+                if !keep {
+                    s.nodes = nodes.iter().copied().collect();
+                    let nodes = &s.nodes;
+                    s.mtus.retain(|node, _| nodes.contains(node));
+                } else {
+                    s.nodes.extend(nodes);
+                }
+                s.probe(&new).await;
+                Ok(())
+            })
+            .await?;
+        }
+        Ok(())
+    }
+}
+
+impl Prober {
+    /// Tunnel's own MTU first, followed by the smaller ones
+    fn probed_mtus(tunnel_mtu: u32) -> impl Iterator<Item = u32> {
+        std::iter::once(tunnel_mtu)
+            .chain(IntoIterator::into_iter(PROBED_TUNNEL_MTUS).filter(move |mtu| *mtu < tunnel_mtu))
+    }
+
+    async fn probe(&mut self, nodes: &[PublicKey]) {
+        if nodes.is_empty() {
+            return;
+        }
+
+        self.session = rand::random::<Session>();
+        let ts = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_micros() as u64;
+        for node in nodes {
+            telio_log_trace!("Probing MTU of {:?} relayed path", node);
+            for mtu in Self::probed_mtus(self.tunnel_mtu) {
+                let probe = PingerMsg::probe(WGPort(0), self.session, ts, mtu + RELAY_OVERHEAD);
+                if self.pinger.tx.send((*node, probe)).await.is_err() {
+                    return;
+                }
+            }
+        }
+    }
+
+    async fn handle_rx(&mut self, node: PublicKey, msg: PingerMsg) {
+        if let Some(pong) = msg.pong(WGPort(0)) {
+            let _ = self.pinger.tx.send((node, pong)).await;
+            return;
+        }
+
+        // Answers of earlier probings are stale, while nodes not supporting probes
+        // answer without probe size
+        if msg.get_session() != self.session {
+            return;
+        }
+        if let Some(size) = msg.get_probe_size() {
+            let mtu = size.saturating_sub(RELAY_OVERHEAD).min(self.tunnel_mtu);
+            let probed = self.mtus.entry(node).or_insert(0);
+            if *probed < mtu {
+                telio_log_debug!("Peer's {:?} relayed path MTU is {}", node, mtu);
+                *probed = mtu;
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl Runtime for Prober {
+    const NAME: &'static str = "RelayMtuProber";
+
+    type Err = ();
+
+    async fn wait_with_update<F>(&mut self, update: F) -> std::result::Result<(), Self::Err>
+    where
+        F: Future<Output = BoxAction<Self, std::result::Result<(), Self::Err>>> + Send,
+    {
+        tokio::select! {
+            Some((node, msg)) = self.pinger.rx.recv() => {
+                self.handle_rx(node, msg).await;
+            }
+            _ = self.interval.tick() => {
+                // Nodes, whose relayed path got narrower, report the new MTU only
+                self.mtus.clear();
+                let nodes: Vec<_> = self.nodes.iter().copied().collect();
+                self.probe(&nodes).await;
+            }
+            update = update => {
+                return update(self).await;
+            }
+            else => {
+                return Ok(());
+            },
+        };
+
+        Ok(())
+    }
+}
 
 #[async_trait]
-impl Configure for Default {
+impl Configure for Relay {
     async fn configure(&self, _config: telio_relay::Config) {}
 }
 
 #[async_trait]
-impl Route for Default {
-    async fn set_nodes(&self, _nodes: Vec<PublicKey>) -> RouteResult<()> {
-        Ok(())
+impl Route for Relay {
+    async fn set_nodes(&self, nodes: Vec<PublicKey>) -> RouteResult<()> {
+        self.probe_nodes(nodes, false).await
     }
 
-    async fn update_nodes(&self, _nodes: Vec<PublicKey>) -> RouteResult<()> {
-        Ok(())
+    async fn update_nodes(&self, nodes: Vec<PublicKey>) -> RouteResult<()> {
+        self.probe_nodes(nodes, true).await
     }
 
     async fn reset_nodes(&self, _nodes: Vec<PublicKey>) -> RouteResult<()> {
@@ -39,10 +210,60 @@ impl Route for Default {
     }
 }
 
-pub fn build(relay: Chan<(PublicKey, DataMsg)>) -> Path {
+pub fn build(
+    relay: Chan<(PublicKey, DataMsg)>,
+    pinger: Option<Chan<(PublicKey, PingerMsg)>>,
+    tunnel_mtu: u32,
+) -> Path {
     Path {
-        route: RouteType::Relay { relay: Default },
+        route: RouteType::Relay {
+            relay: Relay::start(pinger, tunnel_mtu),
+        },
         channel: relay,
         changes: None,
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use telio_crypto::SecretKey;
+
+    #[tokio::test]
+    async fn probes_relayed_path_mtu() {
+        let (ours, mut theirs) = Chan::pipe();
+        let relay = Relay::start(Some(ours), 1420);
+        let node = SecretKey::gen().public();
+
+        relay.update_nodes(vec![node]).await.unwrap();
+        assert_eq!(relay.get_mtu(node).await.unwrap(), None);
+
+        // Relay passes only probes up to 1380 tunnel MTU
+        let mut answered = 0;
+        for _ in 0..4 {
+            let (to, probe) = theirs.rx.recv().await.unwrap();
+            assert_eq!(to, node);
+            let size = probe.get_probe_size().unwrap();
+            if size <= 1380 + RELAY_OVERHEAD {
+                answered += 1;
+                theirs
+                    .tx
+                    .send((node, probe.pong(WGPort(0)).unwrap()))
+                    .await
+                    .unwrap();
+            }
+        }
+        assert_eq!(answered, 3);
+
+        // Probes of other nodes are answered
+        let other = SecretKey::gen().public();
+        let probe = PingerMsg::probe(WGPort(0), 7, 0, 1280);
+        theirs.tx.send((other, probe)).await.unwrap();
+        let (to, pong) = theirs.rx.recv().await.unwrap();
+        assert_eq!(to, other);
+        assert_eq!(pong.get_probe_size(), Some(1280));
+
+        assert_eq!(relay.get_mtu(node).await.unwrap(), Some(1380));
+        relay.stop().await;
+    }
+}
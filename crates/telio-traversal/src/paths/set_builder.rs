@@ -1,13 +1,14 @@
 use telio_crypto::PublicKey;
-use telio_model::api_config::PathType;
-use telio_proto::{CallMeMaybeMsgDeprecated, DataMsg};
+use telio_model::api_config::{FeaturePeerRelay, PathType};
+use telio_proto::{CallMeMaybeMsg, CallMeMaybeMsgDeprecated, DataMsg, PingerMsg};
 use telio_sockets::External;
 use telio_task::io::Chan;
-use telio_utils::telio_log_trace;
+use telio_utils::{telio_log_trace, telio_log_warn};
 use tokio::net::UdpSocket;
 
 use crate::{
-    paths::{relay, udp_hole_punch},
+    endpoint_providers::local::SharedGetIfAddrs,
+    paths::{peer_relay, relay, udp_hole_punch},
     Error,
 };
 
@@ -19,20 +20,37 @@ pub trait PathSetBuilder: Send + Sized {
 
 pub struct PathSetIo {
     pub relay: Chan<(PublicKey, DataMsg)>,
+    /// Pings through relay, probing relayed path's MTU
+    pub relay_pinger: Option<Chan<(PublicKey, PingerMsg)>>,
     pub udp_hole_punch: (
         External<UdpSocket>,
+        Chan<(PublicKey, CallMeMaybeMsg)>,
         Chan<(PublicKey, CallMeMaybeMsgDeprecated)>,
     ),
+    /// Local interfaces, offered to peers when direct paths migrate
+    pub local_interfaces: SharedGetIfAddrs,
 }
 
 pub struct PathSetBuilderDefault {
     io: PathSetIo,
     priority: Vec<PathType>,
+    peer_relay: Option<FeaturePeerRelay>,
+    tunnel_mtu: u32,
 }
 
 impl PathSetBuilderDefault {
-    pub fn new(io: PathSetIo, priority: Vec<PathType>) -> Self {
-        Self { io, priority }
+    pub fn new(
+        io: PathSetIo,
+        priority: Vec<PathType>,
+        peer_relay: Option<FeaturePeerRelay>,
+        tunnel_mtu: u32,
+    ) -> Self {
+        Self {
+            io,
+            priority,
+            peer_relay,
+            tunnel_mtu,
+        }
     }
 }
 
@@ -40,21 +58,57 @@ impl PathSetBuilder for PathSetBuilderDefault {
     fn build(self) -> Result<PathSet, Error> {
         let mut paths = PathSet::new();
 
-        let mut relay = Some(self.io.relay);
-        let mut uhp = Some(self.io.udp_hole_punch);
+        let mut relay = Some((self.io.relay, self.io.relay_pinger));
+        let mut uhp = Some((self.io.udp_hole_punch, self.io.local_interfaces));
+
+        // Peer relay frames are carried over direct connections
+        let (mut uhp_peer_relay, mut peer_relay_transport) =
+            if self.priority.contains(&PathType::UdpHolePunch)
+                && self.priority.contains(&PathType::PeerRelay)
+            {
+                let (l, r) = Chan::pipe();
+                (Some(l), Some(r))
+            } else {
+                (None, None)
+            };
 
         for path_type in self.priority.iter() {
             match *path_type {
                 PathType::Relay => {
-                    if let Some(data) = relay.take() {
-                        paths.add_next(*path_type, relay::build(data));
+                    if let Some((data, pinger)) = relay.take() {
+                        paths.add_next(*path_type, relay::build(data, pinger, self.tunnel_mtu));
                     }
                 }
                 PathType::UdpHolePunch => {
-                    if let Some((sock, cmm)) = uhp.take() {
-                        paths.add_next(*path_type, udp_hole_punch::build(cmm, sock)?);
+                    if let Some(((sock, cmm, cmm_deprecated), local_interfaces)) = uhp.take() {
+                        paths.add_next(
+                            *path_type,
+                            udp_hole_punch::build(
+                                cmm,
+                                cmm_deprecated,
+                                sock,
+                                uhp_peer_relay.take(),
+                                local_interfaces,
+                            )?,
+                        );
                     }
                 }
+                PathType::PeerRelay => match peer_relay_transport.take() {
+                    Some(transport) => {
+                        paths.add_next(
+                            *path_type,
+                            peer_relay::build(transport, self.peer_relay.clone())?,
+                        );
+                    }
+                    None => {
+                        telio_log_warn!(
+                            "Path {:?} requires {:?} path, skipping",
+                            path_type,
+                            PathType::UdpHolePunch
+                        );
+                        continue;
+                    }
+                },
             }
             telio_log_trace!("Added path: {:?}", path_type);
         }
@@ -1,6 +1,9 @@
+#[cfg(test)]
+use crate::endpoint_providers::local::MockGetIfAddrs;
+use crate::endpoint_providers::local::SharedGetIfAddrs;
 use crate::routes::udp_hole_punch::{Error, UdpHolePunch};
 use telio_crypto::PublicKey;
-use telio_proto::CallMeMaybeMsgDeprecated;
+use telio_proto::{CallMeMaybeMsg, CallMeMaybeMsgDeprecated, PeerRelayMsg};
 use telio_sockets::External;
 use telio_task::io::Chan;
 use tokio::net::UdpSocket;
@@ -8,8 +11,11 @@ use tokio::net::UdpSocket;
 use super::{Path, RouteType};
 
 pub fn build(
-    cmm: Chan<(PublicKey, CallMeMaybeMsgDeprecated)>,
+    cmm: Chan<(PublicKey, CallMeMaybeMsg)>,
+    cmm_deprecated: Chan<(PublicKey, CallMeMaybeMsgDeprecated)>,
     udp_sock: External<UdpSocket>,
+    peer_relay: Option<Chan<(PublicKey, PeerRelayMsg)>>,
+    #[cfg_attr(test, allow(unused_variables))] get_if_addrs: SharedGetIfAddrs,
 ) -> Result<Path, Error> {
     let Chan {
         tx: event_tx,
@@ -23,7 +29,13 @@ pub fn build(
         udp_sock,
         ldata,
         cmm,
+        cmm_deprecated,
         event_tx,
+        peer_relay,
+        #[cfg(not(test))]
+        get_if_addrs,
+        #[cfg(test)]
+        MockGetIfAddrs::default(),
         #[cfg(test)]
         dummy,
     ) {
@@ -38,6 +38,11 @@ pub trait Route: Configure {
 
     /// Check peer's path metric
     async fn rtt(&self, node: PublicKey) -> RouteResult<Duration>;
+
+    /// Notify route about changed local network, so it could migrate its connected paths
+    async fn notify_network_change(&self) -> RouteResult<()> {
+        Ok(())
+    }
 }
 
 #[async_trait]
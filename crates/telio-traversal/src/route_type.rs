@@ -2,22 +2,29 @@ use async_trait::async_trait;
 use telio_crypto::PublicKey;
 use tokio::time::Duration;
 
-use crate::paths::relay::Default;
+use crate::paths::relay::Relay;
+use crate::routes::peer_relay::PeerRelay;
 use crate::routes::udp_hole_punch::UdpHolePunch;
 use crate::{Configure, Route, RouteResult};
 
 pub enum RouteType {
-    Relay { relay: Default },
+    Relay { relay: Relay },
     UdpHolePunch { udp_hole_punch: UdpHolePunch },
+    PeerRelay { peer_relay: PeerRelay },
 }
 
 impl RouteType {
     pub async fn stop(self) {
         match self {
-            RouteType::Relay { relay: _ } => {}
+            RouteType::Relay { relay } => {
+                relay.stop().await;
+            }
             RouteType::UdpHolePunch { udp_hole_punch } => {
                 udp_hole_punch.stop().await;
             }
+            RouteType::PeerRelay { peer_relay } => {
+                peer_relay.stop().await;
+            }
         }
     }
 }
@@ -28,6 +35,7 @@ impl Route for RouteType {
         match self {
             RouteType::Relay { relay } => relay.set_nodes(nodes).await,
             RouteType::UdpHolePunch { udp_hole_punch } => udp_hole_punch.set_nodes(nodes).await,
+            RouteType::PeerRelay { peer_relay } => peer_relay.set_nodes(nodes).await,
         }
     }
 
@@ -35,6 +43,7 @@ impl Route for RouteType {
         match self {
             RouteType::Relay { relay } => relay.update_nodes(nodes).await,
             RouteType::UdpHolePunch { udp_hole_punch } => udp_hole_punch.update_nodes(nodes).await,
+            RouteType::PeerRelay { peer_relay } => peer_relay.update_nodes(nodes).await,
         }
     }
 
@@ -42,6 +51,7 @@ impl Route for RouteType {
         match self {
             RouteType::Relay { relay } => relay.reset_nodes(nodes).await,
             RouteType::UdpHolePunch { udp_hole_punch } => udp_hole_punch.reset_nodes(nodes).await,
+            RouteType::PeerRelay { peer_relay } => peer_relay.reset_nodes(nodes).await,
         }
     }
 
@@ -49,6 +59,7 @@ impl Route for RouteType {
         match self {
             RouteType::Relay { relay } => relay.is_reachable(node).await,
             RouteType::UdpHolePunch { udp_hole_punch } => udp_hole_punch.is_reachable(node).await,
+            RouteType::PeerRelay { peer_relay } => peer_relay.is_reachable(node).await,
         }
     }
 
@@ -56,6 +67,17 @@ impl Route for RouteType {
         match self {
             RouteType::Relay { relay } => relay.rtt(node).await,
             RouteType::UdpHolePunch { udp_hole_punch } => udp_hole_punch.rtt(node).await,
+            RouteType::PeerRelay { peer_relay } => peer_relay.rtt(node).await,
+        }
+    }
+
+    async fn notify_network_change(&self) -> RouteResult<()> {
+        match self {
+            RouteType::Relay { relay } => relay.notify_network_change().await,
+            RouteType::UdpHolePunch { udp_hole_punch } => {
+                udp_hole_punch.notify_network_change().await
+            }
+            RouteType::PeerRelay { peer_relay } => peer_relay.notify_network_change().await,
         }
     }
 }
@@ -66,6 +88,7 @@ impl Configure for RouteType {
         match self {
             RouteType::Relay { relay } => relay.configure(config).await,
             RouteType::UdpHolePunch { udp_hole_punch } => udp_hole_punch.configure(config).await,
+            RouteType::PeerRelay { peer_relay } => peer_relay.configure(config).await,
         }
     }
 }
@@ -1,8 +1,11 @@
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 
 use derive_builder::Builder;
 use telio_crypto::PublicKey;
-use telio_model::api_config::{FeaturePaths, PathType};
+use telio_model::{
+    api_config::{FeaturePaths, FeaturePeerRelay, FeatureTraversal, PathType},
+    diagnostics::PeerDiagnostics,
+};
 use telio_proto::DataMsg;
 use telio_proxy::{Config as ProxyConfig, Io as ProxyIo, UdpProxy};
 use telio_relay::Config as DerpConfig;
@@ -37,6 +40,8 @@ pub enum Error {
     Route(#[from] crate::RouteError),
     #[error(transparent)]
     UdpHolePunch(#[from] crate::udp_hole_punch::Error),
+    #[error(transparent)]
+    PeerRelay(#[from] crate::peer_relay::Error),
 }
 
 #[derive(Builder, Default, Clone)]
@@ -47,6 +52,9 @@ pub struct Config {
     pub peers: HashSet<PublicKey>,
     #[builder(default)]
     pub derp: DerpConfig,
+    /// Nodes, which consented to relay traffic
+    #[builder(default)]
+    pub peer_relays: HashSet<PublicKey>,
 }
 
 pub struct Router {
@@ -55,8 +63,11 @@ pub struct Router {
 }
 
 impl Router {
+    /// Start paths of `features`, probing their MTUs up to `tunnel_mtu`
     pub fn start(
         features: FeaturePaths,
+        peer_relay: Option<FeaturePeerRelay>,
+        tunnel_mtu: u32,
         path_set_io: PathSetIo,
         path_change_tx: Tx<(PublicKey, PathType)>,
     ) -> Result<Self, Error> {
@@ -66,6 +77,8 @@ impl Router {
             proxy: UdpProxy::start(ProxyIo { relay: to_paths }),
             paths: Paths::start(
                 features,
+                peer_relay,
+                tunnel_mtu,
                 PathsIo {
                     data: to_proxy,
                     events: path_change_tx,
@@ -81,6 +94,26 @@ impl Router {
         Ok(())
     }
 
+    /// Migrate direct paths to the changed local network
+    pub async fn notify_network_change(&self) -> Result<(), Error> {
+        self.paths.notify_network_change().await
+    }
+
+    /// Apply hole punching timers, without restarting the paths
+    pub async fn set_traversal(&self, config: FeatureTraversal) -> Result<(), Error> {
+        self.paths.set_traversal(config).await
+    }
+
+    /// Diagnostics of peer's direct path, [None] if hole punching is disabled
+    pub async fn get_diagnostics(&self, pk: PublicKey) -> Result<Option<PeerDiagnostics>, Error> {
+        self.paths.get_diagnostics(pk).await
+    }
+
+    /// Tunnel MTU fitting into the active path of each peer, [None] if it was not probed
+    pub async fn get_path_mtus(&self) -> Result<HashMap<PublicKey, Option<u32>>, Error> {
+        self.paths.get_path_mtus().await
+    }
+
     pub async fn stop(self) {
         self.paths.stop().await;
         self.proxy.stop().await;
@@ -2,10 +2,11 @@ use sm::sm;
 
 use multi_map::MultiMap;
 use telio_crypto::PublicKey;
-use telio_proto::{PeerId, Session, Timestamp};
+use telio_model::diagnostics::{PeerDiagnostics, PingReport, Verdict};
+use telio_proto::{PeerId, Session, Timestamp, WGPort};
 
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet},
     fmt, iter,
     net::SocketAddr,
     time::{SystemTime, UNIX_EPOCH},
@@ -17,7 +18,10 @@ use tokio::{
     time::{Duration, Instant},
 };
 
-use telio_proto::{CallMeMaybeMsgDeprecated, Codec, DataMsg, PingerMsgDeprecated};
+use telio_proto::{
+    CallMeMaybeMsg, CallMeMaybeMsgDeprecated, Codec, DataMsg, PingerMsg, PingerMsgDeprecated,
+};
+use telio_sockets::dont_fragment;
 use telio_task::io::{chan::*, Chan, ChanSendError};
 use telio_utils::telio_log_debug;
 
@@ -26,6 +30,14 @@ use crate::RouteError;
 pub type TxPeerId = PeerId;
 pub type RxPeerId = PeerId;
 
+/// Link MTUs probed on direct path: Ethernet, PPPoE, common tunnels and mobile networks,
+/// down to the minimum of IPv6
+const PROBED_LINK_MTUS: [u32; 6] = [1500, 1492, 1460, 1420, 1380, 1280];
+/// Path MTU may change with network, so it is periodically probed again
+pub const MTU_PROBE_INTERVAL: Duration = Duration::from_secs(600);
+/// Generation header of [`DataMsg`], followed by WireGuard's data header and tag
+const ENCAPSULATION_OVERHEAD: u32 = 4 + 32;
+
 type Result<T> = std::result::Result<T, Error>;
 
 /// Posible [Database] errors.
@@ -39,6 +51,9 @@ pub enum Error {
     ControlChanErr(#[from] ChanSendError<(PublicKey, CallMeMaybeMsgDeprecated)>),
     #[error(transparent)]
     ControlChanTryErr(#[from] ChanTrySendError<(PublicKey, CallMeMaybeMsgDeprecated)>),
+    /// Control channel of current protocol is closed (or overflowed)
+    #[error(transparent)]
+    CurrentControlChanTryErr(#[from] ChanTrySendError<(PublicKey, CallMeMaybeMsg)>),
     /// TxPeerId is not populated on peer's entry
     #[error("No TxPeerId info")]
     NoTxPeerId,
@@ -57,6 +72,9 @@ pub enum Error {
     /// Received packet, that wasn't expected at this state
     #[error("Received unexpected packet")]
     UnexpectedPacket,
+    /// Node speaks protocol version, which does not support the request
+    #[error("Not supported by node's protocol")]
+    UnexpectedProtocol,
     /// UDP socket error
     #[error(transparent)]
     Socket(#[from] std::io::Error),
@@ -128,6 +146,105 @@ sm! {
 pub type AbsRouteState = RouteState::Variant;
 pub type CurrentRouteState = (RouteState::Variant, Duration);
 
+/// Version of hole punching messages, spoken by remote node
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Protocol {
+    /// No CallMeMaybe was accepted from node yet
+    Unknown,
+    /// Node speaks [`CallMeMaybeMsgDeprecated`] and [`PingerMsgDeprecated`], identified by [`PeerId`]
+    Deprecated,
+    /// Node speaks [`CallMeMaybeMsg`] and [`PingerMsg`], identified by its endpoints
+    Current,
+}
+
+/// Slot in deprecated CallMeMaybe channel, reserved before the request is handled
+pub type DeprecatedPermit = OwnedPermit<(PublicKey, CallMeMaybeMsgDeprecated)>;
+
+/// Peer id, carried by packets of current protocol. It is derived from the session of
+/// CallMeMaybe exchange, which is known only to both ends of it. It may collide with peer
+/// ids given to nodes of deprecated protocol, so packets are matched by session first.
+pub fn session_peer_id(session: Session) -> PeerId {
+    PeerId(session as u16)
+}
+
+/// Channels, used to send CallMeMaybe messages between two UDP Hole Punching route instances
+pub struct Control {
+    /// Channel of current protocol
+    pub current: Chan<(PublicKey, CallMeMaybeMsg)>,
+    /// Channel of deprecated protocol, spoken by nodes of older versions
+    pub deprecated: Chan<(PublicKey, CallMeMaybeMsgDeprecated)>,
+}
+
+impl Control {
+    /// Send CallMeMaybe in the protocol version, spoken by node. While it is unknown,
+    /// both versions are sent with the same session, so node can answer the one it speaks
+    /// `permit` is reserved in advance for deprecated response, so it is not lost to full channel.
+    #[allow(clippy::too_many_arguments)]
+    pub fn send(
+        &self,
+        pk: PublicKey,
+        protocol: Protocol,
+        initiator: bool,
+        addrs: &[SocketAddr],
+        session: Session,
+        rx_peer_id: RxPeerId,
+        permit: Option<DeprecatedPermit>,
+    ) -> Result<()> {
+        let current_msg = || CallMeMaybeMsg::new(initiator, addrs.iter().copied(), session);
+        let deprecated_msg =
+            || CallMeMaybeMsgDeprecated::new(initiator, addrs.iter().copied(), session, rx_peer_id);
+        let send_deprecated = |permit: Option<DeprecatedPermit>| match permit {
+            Some(permit) => {
+                let _ = permit.send((pk, deprecated_msg()));
+                Ok(())
+            }
+            None => self.deprecated.tx.try_send((pk, deprecated_msg())),
+        };
+
+        match protocol {
+            Protocol::Current => self.current.tx.try_send((pk, current_msg()))?,
+            Protocol::Deprecated => send_deprecated(permit)?,
+            Protocol::Unknown => {
+                let current = self.current.tx.try_send((pk, current_msg()));
+                let deprecated = send_deprecated(permit);
+
+                // It is enough for node to receive one of them
+                if let (Err(_), Err(e)) = (current, deprecated) {
+                    return Err(e.into());
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Ping message builder for the protocol version, spoken by node
+#[derive(Debug, Clone, Copy)]
+enum Pinger {
+    Deprecated(TxPeerId),
+    Current(WGPort),
+}
+
+impl Pinger {
+    fn ping(self, session: Session, ts: Timestamp) -> Result<Vec<u8>> {
+        Ok(match self {
+            Pinger::Deprecated(peer_id) => {
+                PingerMsgDeprecated::ping(peer_id, session, ts).encode()?
+            }
+            Pinger::Current(wg_port) => PingerMsg::ping(wg_port, session, ts).encode()?,
+        })
+    }
+
+    /// Padded ping, only nodes speaking current protocol are able to echo its size
+    fn probe(self, session: Session, ts: Timestamp, size: u32) -> Result<Vec<u8>> {
+        match self {
+            Pinger::Deprecated(_) => Err(Error::InvalidCurrentState),
+            Pinger::Current(wg_port) => Ok(PingerMsg::probe(wg_port, session, ts, size).encode()?),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy)]
 pub enum Latency {
     Unknown,
@@ -166,6 +283,80 @@ impl fmt::Debug for Metric {
     }
 }
 
+/// Path MTU discovery of a connected route, by pinging it with padded probes of
+/// [`PROBED_LINK_MTUS`] sizes. Largest answered probe is the path's MTU.
+#[derive(Clone)]
+pub struct PathMtu {
+    /// Current probing session (Some() - if it is ongoing)
+    probe_session: Option<Session>,
+    /// Start of probing or of the last probing result
+    last_probe: Instant,
+    /// Largest probe answered during ongoing probing, as tunnel MTU
+    answered: Option<u32>,
+    /// Tunnel MTU, which fits into the path (None - if not probed yet)
+    mtu: Option<u32>,
+    /// Node answered probes with plain pongs, so it doesn't support probing
+    unsupported: bool,
+}
+
+impl Default for PathMtu {
+    fn default() -> Self {
+        Self {
+            probe_session: None,
+            last_probe: Instant::now(),
+            answered: None,
+            mtu: None,
+            unsupported: false,
+        }
+    }
+}
+
+impl fmt::Debug for PathMtu {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Probe session: {:?}, ", self.probe_session)?;
+        write!(f, "MTU: {:?}, ", self.mtu)?;
+        write!(f, "Unsupported: {:?}", self.unsupported)
+    }
+}
+
+/// UDP payload of a probe, which fills link MTU towards endpoint
+fn probe_size(link_mtu: u32, endpoint: &SocketAddr) -> u32 {
+    let ip_header = if endpoint.is_ipv4() { 20 } else { 40 };
+    link_mtu - ip_header - 8
+}
+
+/// Tunnel MTU, with which encapsulated packets fit into UDP payload of `probe_size`.
+/// It may exceed MTU of the tunnel itself, which caps it
+fn tunnel_mtu(probe_size: u32) -> u32 {
+    probe_size.saturating_sub(ENCAPSULATION_OVERHEAD)
+}
+
+/// Ongoing endpoint migration of a connected route, started after a network change.
+/// The route stays connected on its old endpoint, until a new one is chosen.
+#[derive(Clone)]
+pub struct Migration {
+    /// Traversal session of migration
+    session: Session,
+    /// Start of migration
+    started: Instant,
+    /// Start of pinging phase (Some() - if responder's endpoints were received)
+    pinging: Option<Instant>,
+    /// Candidates for new endpoint
+    candidates: HashMap<SocketAddr, Option<Duration>>,
+}
+
+impl fmt::Debug for Migration {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Session: {:?}, ", self.session)?;
+        write!(
+            f,
+            "Started: {} millis ago, ",
+            self.started.elapsed().as_millis()
+        )?;
+        write!(f, "Candidates: {:?}", self.candidates)
+    }
+}
+
 /// Entry of nodes database.
 #[derive(Debug, Clone)]
 pub struct Entry {
@@ -189,6 +380,27 @@ pub struct Entry {
     pub pk: PublicKey,
     /// Peer's path's metric
     metric: Option<Metric>,
+    /// Peer's path's MTU
+    path_mtu: Option<PathMtu>,
+    /// Our own endpoint migration (Some() - if it is ongoing)
+    migration: Option<Migration>,
+    /// Endpoints offered by remote node, while it is migrating its own endpoint
+    roaming: Option<HashSet<SocketAddr>>,
+    /// Version of hole punching messages, spoken by node
+    protocol: Protocol,
+    /// Session of the last CallMeMaybe request, accepted from node
+    remote_session: Option<Session>,
+    /// Sessions of the last two CallMeMaybe exchanges with node, identifying its packets
+    /// of current protocol. Previous one is kept, until both ends switch to the new one.
+    pair_sessions: [Option<Session>; 2],
+    /// Endpoints offered by remote node in its last CallMeMaybe request
+    offered: HashSet<SocketAddr>,
+    /// Endpoints offered by us in the last CallMeMaybe request or response
+    local_candidates: Vec<SocketAddr>,
+    /// Endpoints offered by remote node in the last CallMeMaybe request or response
+    remote_candidates: Vec<SocketAddr>,
+    /// Ping results of the last finished traversal session
+    last_pings: HashMap<SocketAddr, Option<Duration>>,
 }
 
 impl Entry {
@@ -205,12 +417,22 @@ impl Entry {
             candidates: None,
             pk,
             metric: None,
+            path_mtu: None,
+            migration: None,
+            roaming: None,
+            protocol: Protocol::Unknown,
+            remote_session: None,
+            pair_sessions: [None; 2],
+            offered: HashSet::new(),
+            local_candidates: Vec::new(),
+            remote_candidates: Vec::new(),
+            last_pings: HashMap::new(),
         }
     }
 
     pub async fn start_sent_call_me_maybe<N: Iterator<Item = SocketAddr>>(
         &mut self,
-        chan: &Chan<(PublicKey, CallMeMaybeMsgDeprecated)>,
+        chan: &Control,
         addrs: N,
         events: &Tx<(PublicKey, bool)>,
     ) -> Result<()> {
@@ -237,14 +459,21 @@ impl Entry {
         self.last_sm_transition = Instant::now();
 
         // Creating new traverse session
-        let session = self.trav_session.insert(Self::new_session());
+        let session = *self.trav_session.insert(Self::new_session());
 
         // Creating and sending CallMeMaybe request
-        let msg = CallMeMaybeMsgDeprecated::new(true, addrs, *session, self.rx_peer_id);
-
-        chan.tx
-            .try_send((self.pk, msg))
-            .map_or_else(|e| Err(Error::ControlChanTryErr(e)), |_| Ok(()))
+        self.local_candidates = addrs.collect();
+        self.remote_candidates.clear();
+        self.last_pings.clear();
+        chan.send(
+            self.pk,
+            self.protocol,
+            true,
+            &self.local_candidates,
+            session,
+            self.rx_peer_id,
+            None,
+        )
     }
 
     pub async fn start_pinging<N: Iterator<Item = SocketAddr>>(
@@ -257,6 +486,8 @@ impl Entry {
             return Err(Error::InvalidCurrentState);
         }
 
+        let pinger = self.pinger(socket)?;
+
         // Changing state
         self.state = match self.state.clone() {
             RouteState::Variant::SentCallMeMaybeByStart(m) => {
@@ -270,9 +501,10 @@ impl Entry {
         };
 
         // Creating candidates list
+        self.remote_candidates = endpoints.collect();
         let candidates_map = self.candidates.insert(HashMap::new());
-        for endpoint in endpoints {
-            candidates_map.insert(endpoint, None);
+        for endpoint in &self.remote_candidates {
+            candidates_map.insert(*endpoint, None);
         }
 
         // Fetching session id
@@ -280,7 +512,7 @@ impl Entry {
 
         // Pinging endpoints
         let _ = Self::ping_endpoints(
-            self.tx_peer_id.ok_or(Error::NoTxPeerId)?,
+            pinger,
             candidates_map.iter_mut().map(|(addr, _)| *addr),
             socket,
             *session,
@@ -305,12 +537,15 @@ impl Entry {
         let _ = self.remote_endpoint.insert(*endpoint);
 
         self.trav_session = None;
-        self.candidates = None;
+        self.keep_last_pings();
+        self.migration = None;
+        self.roaming = None;
         self.switch_route(true);
         self.last_rx = Instant::now();
 
         self.metric = Some(Metric::default());
         self.update_metric(latency)?;
+        self.path_mtu = Some(PathMtu::default());
 
         let _ = events.try_send((self.pk, true));
 
@@ -327,8 +562,11 @@ impl Entry {
     pub fn disconnect_route(&mut self, events: &Tx<(PublicKey, bool)>) -> Result<()> {
         self.remote_endpoint = None;
         self.trav_session = None;
-        self.candidates = None;
+        self.keep_last_pings();
         self.metric = None;
+        self.path_mtu = None;
+        self.migration = None;
+        self.roaming = None;
         self.switch_route(false);
         let _ = events.try_send((self.pk, false));
 
@@ -385,6 +623,158 @@ impl Entry {
         Err(Error::EndpointCandidateMissing)
     }
 
+    /// Start migrating connected route to a new endpoint, without breaking the current one.
+    /// Sends CallMeMaybe request with freshly gathered endpoints, while data keeps flowing
+    /// through the old endpoint.
+    pub fn start_migration<N: Iterator<Item = SocketAddr>>(
+        &mut self,
+        chan: &Control,
+        addrs: N,
+    ) -> Result<()> {
+        if self.is_connected().is_none() {
+            return Err(Error::InvalidCurrentState);
+        }
+
+        let session = Self::new_session();
+        self.migration = Some(Migration {
+            session,
+            started: Instant::now(),
+            pinging: None,
+            candidates: HashMap::new(),
+        });
+
+        telio_log_debug!(
+            "Peer {:?} starting endpoint migration, session: {}",
+            self.pk,
+            session
+        );
+
+        // Creating and sending CallMeMaybe request
+        let addrs: Vec<SocketAddr> = addrs.collect();
+        chan.send(
+            self.pk,
+            self.protocol,
+            true,
+            &addrs,
+            session,
+            self.rx_peer_id,
+            None,
+        )
+    }
+
+    /// Ping endpoints, offered by remote node in reply to our migration request
+    pub async fn start_migration_pinging<N: Iterator<Item = SocketAddr>>(
+        &mut self,
+        endpoints: N,
+        socket: &UdpSocket,
+    ) -> Result<()> {
+        if self.is_migrating().is_none() || self.is_migration_pinging().is_some() {
+            return Err(Error::InvalidCurrentState);
+        }
+
+        let pinger = self.pinger(socket)?;
+        let migration = self.migration.as_mut().ok_or(Error::InvalidCurrentState)?;
+
+        migration.candidates = endpoints.map(|endpoint| (endpoint, None)).collect();
+        migration.pinging = Some(Instant::now());
+
+        Self::ping_endpoints(
+            pinger,
+            migration.candidates.keys().copied(),
+            socket,
+            migration.session,
+        )
+        .await
+    }
+
+    /// Move migrating route to the endpoint with lowest latency, which has answered our pings.
+    /// Route stays connected during the whole procedure.
+    pub fn complete_migration(&mut self, events: &Tx<(PublicKey, bool)>) -> Result<()> {
+        if self.is_migration_pinging().is_none() {
+            return Err(Error::InvalidCurrentState);
+        }
+
+        let (endpoint, latency) = self
+            .migration
+            .as_ref()
+            .and_then(|m| {
+                m.candidates
+                    .iter()
+                    .filter_map(|(addr, latency)| latency.map(|l| (*addr, l)))
+                    .min_by_key(|(_, latency)| *latency)
+            })
+            .ok_or(Error::EndpointCandidateMissing)?;
+
+        telio_log_debug!(
+            "Peer {:?} migrating endpoint {:?} -> {} with latency {} millis",
+            self.pk,
+            self.remote_endpoint,
+            endpoint,
+            latency.as_millis()
+        );
+
+        let _ = self.remote_endpoint.insert(endpoint);
+        self.migration = None;
+        self.last_rx = Instant::now();
+        self.update_metric(Latency::Measured(latency))?;
+        // New network may have a different MTU
+        self.path_mtu = Some(PathMtu::default());
+
+        let _ = events.try_send((self.pk, true));
+
+        Ok(())
+    }
+
+    /// Give up failed endpoint migration, keeping the route connected on its old endpoint.
+    /// If the old endpoint is gone as well, route gets disconnected by the usual no data timeout.
+    pub fn abort_migration(&mut self, events: &Tx<(PublicKey, bool)>) -> Result<()> {
+        if self.is_migrating().is_none() {
+            return Err(Error::InvalidCurrentState);
+        }
+
+        telio_log_debug!(
+            "Peer {:?} aborting endpoint migration, keeping {:?}",
+            self.pk,
+            self.remote_endpoint
+        );
+
+        self.migration = None;
+
+        // Let paths know migration is over, so they stop mirroring data
+        let _ = events.try_send((self.pk, true));
+
+        Ok(())
+    }
+
+    /// Returns [`Some(Duration)`] since endpoint migration was started, if it is ongoing,
+    /// otherwise - [`None`]
+    pub fn is_migrating(&self) -> Option<Duration> {
+        if self.is_connected().is_some() {
+            if let Some(migration) = &self.migration {
+                return Some(migration.started.elapsed());
+            }
+        }
+
+        None
+    }
+
+    /// Returns [`Some(Duration)`] since remote endpoints were pinged during migration,
+    /// otherwise - [`None`]
+    pub fn is_migration_pinging(&self) -> Option<Duration> {
+        if self.is_connected().is_some() {
+            if let Some(pinging) = self.migration.as_ref().and_then(|m| m.pinging) {
+                return Some(pinging.elapsed());
+            }
+        }
+
+        None
+    }
+
+    /// Returns [`Option<Session>`] of ongoing endpoint migration
+    pub fn get_migration_session(&self) -> Option<Session> {
+        self.migration.as_ref().map(|m| m.session)
+    }
+
     /// Returns [`Some(Duration)`] since measuring started if it is in actually measuring,
     /// otherwise - [`None`]
     pub async fn start_measuring_metric(&mut self, socket: &UdpSocket) -> Result<()> {
@@ -394,6 +784,8 @@ impl Entry {
         }
 
         if self.is_connected().is_some() {
+            let pinger = self.pinger(socket)?;
+
             if let Some(metric) = &mut self.metric {
                 // Fetching session id
                 let session = metric.ping_session.get_or_insert(Self::new_session());
@@ -402,7 +794,7 @@ impl Entry {
 
                 // Pinging endpoints
                 return Self::ping_endpoints(
-                    self.tx_peer_id.ok_or(Error::NoTxPeerId)?,
+                    pinger,
                     iter::once(
                         self.remote_endpoint
                             .ok_or(Error::EndpointCandidateMissing)?,
@@ -457,6 +849,101 @@ impl Entry {
         Err(Error::InvalidCurrentState)
     }
 
+    /// Probe path MTU of connected route, by pinging its endpoint with padded probes
+    pub async fn start_probing_mtu(&mut self, socket: &UdpSocket) -> Result<()> {
+        if self.is_connected().is_none() {
+            return Err(Error::InvalidCurrentState);
+        }
+
+        let pinger = self.pinger(socket)?;
+        let endpoint = self
+            .remote_endpoint
+            .ok_or(Error::EndpointCandidateMissing)?;
+        let path_mtu = self.path_mtu.as_mut().ok_or(Error::InvalidCurrentState)?;
+
+        let session = Self::new_session();
+        path_mtu.probe_session = Some(session);
+        path_mtu.last_probe = Instant::now();
+        path_mtu.answered = None;
+
+        telio_log_debug!("Peer {:?} starting to probe path MTU ...", self.pk);
+
+        // Fragmented probes would be answered, however large the path's MTU is
+        let _dont_fragment = dont_fragment(socket, endpoint.is_ipv6()).map_err(|e| {
+            telio_log_debug!("Peer {:?} MTU probes may be fragmented: {}", self.pk, e);
+        });
+
+        for link_mtu in PROBED_LINK_MTUS {
+            let msg = pinger.probe(
+                session,
+                Self::get_timestamp(),
+                probe_size(link_mtu, &endpoint),
+            )?;
+            // Oversized probe may be refused by local stack already
+            if let Err(e) = socket.send_to(&msg, endpoint).await {
+                telio_log_debug!("Peer {:?} MTU probe not sent: {}", self.pk, e);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns [`Some(Duration, Session)`] since MTU probing started, if it is ongoing,
+    /// otherwise - [`None`]
+    pub fn is_probing_mtu(&self) -> Option<(Duration, Session)> {
+        if self.is_connected().is_some() {
+            if let Some(path_mtu) = &self.path_mtu {
+                if let Some(session) = path_mtu.probe_session {
+                    return Some((path_mtu.last_probe.elapsed(), session));
+                }
+            }
+        }
+
+        None
+    }
+
+    /// Returns [`Some(Duration)`] since last completed MTU probing, [`None`] if it is ongoing,
+    /// route is not connected or node doesn't support probing
+    pub fn last_mtu_probe(&self) -> Option<Duration> {
+        if self.is_connected().is_some() && self.is_probing_mtu().is_none() {
+            if let Some(path_mtu) = self.path_mtu.as_ref().filter(|p| !p.unsupported) {
+                // Fresh route is probed right away
+                if path_mtu.mtu.is_none() {
+                    return Some(MTU_PROBE_INTERVAL);
+                }
+                return Some(path_mtu.last_probe.elapsed());
+            }
+        }
+
+        None
+    }
+
+    /// End MTU probing, taking the largest answered probe as path's MTU. If none was answered,
+    /// previous MTU is kept, as probes might have been lost same as any other packets
+    pub fn complete_mtu_probe(&mut self) -> Result<()> {
+        let path_mtu = self.path_mtu.as_mut().ok_or(Error::InvalidCurrentState)?;
+
+        if let Some(mtu) = path_mtu.answered.take() {
+            if path_mtu.mtu != Some(mtu) {
+                telio_log_debug!("Peer's {:?} path MTU is {}", self.pk, mtu);
+            }
+            path_mtu.mtu = Some(mtu);
+        }
+        path_mtu.probe_session = None;
+        path_mtu.last_probe = Instant::now();
+
+        Ok(())
+    }
+
+    /// Returns tunnel MTU, which fits into connected route, if it was probed
+    pub fn get_mtu(&self) -> Option<u32> {
+        if self.is_connected().is_some() {
+            return self.path_mtu.as_ref().and_then(|p| p.mtu);
+        }
+
+        None
+    }
+
     /// Returns [`Some(Duration)`] since transition to [`RouteState::Variant::Pinging`] if it is in that state,
     /// otherwise [`None`] if it is other state
     pub fn is_pinging(&self) -> Option<Duration> {
@@ -523,9 +1010,51 @@ impl Entry {
     }
 
     /// Returns [`true`] if peer has received cmm init or response,
-    /// and updated its tx_peer_id
+    /// and updated its tx_peer_id (if node speaks deprecated protocol)
     pub fn is_cmm_handshake_complete(&self) -> bool {
-        self.tx_peer_id.is_some()
+        match self.protocol {
+            Protocol::Deprecated => self.tx_peer_id.is_some(),
+            Protocol::Current => true,
+            Protocol::Unknown => false,
+        }
+    }
+
+    /// Returns [`Protocol`], spoken by node
+    pub fn get_protocol(&self) -> Protocol {
+        self.protocol
+    }
+
+    /// Accept received CallMeMaybe and adopt its protocol version.
+    /// Returns [`false`] if the message is not expected at current state,
+    /// or it is a copy of already accepted one, sent in other protocol version
+    pub fn accept_cmm(&mut self, protocol: Protocol, initiator: bool, session: Session) -> bool {
+        let expected = if initiator {
+            self.remote_session != Some(session)
+        } else {
+            (self.is_sent_cmm().is_some() && self.trav_session == Some(session))
+                || (self.is_migrating().is_some()
+                    && self.is_migration_pinging().is_none()
+                    && self.get_migration_session() == Some(session))
+        };
+
+        if !expected {
+            return false;
+        }
+
+        if initiator {
+            self.remote_session = Some(session);
+        }
+
+        if self.pair_sessions[0] != Some(session) {
+            self.pair_sessions = [Some(session), self.pair_sessions[0]];
+        }
+
+        if self.protocol != protocol {
+            telio_log_debug!("Peer {:?} speaks {:?} protocol", self.pk, protocol);
+            self.protocol = protocol;
+        }
+
+        true
     }
 
     /// Returns [`Some(Latency)`] of entry in microseconds, if the entry is connected
@@ -554,6 +1083,56 @@ impl Entry {
         self.tx_peer_id.ok_or(Error::NoTxPeerId)
     }
 
+    /// Returns [`true`] if node may send packets from this endpoint
+    fn is_known_endpoint(&self, endpoint: &SocketAddr) -> bool {
+        self.remote_endpoint == Some(*endpoint)
+            || self.offered.contains(endpoint)
+            || self
+                .candidates
+                .as_ref()
+                .map_or(false, |c| c.contains_key(endpoint))
+            || self
+                .migration
+                .as_ref()
+                .map_or(false, |m| m.candidates.contains_key(endpoint))
+    }
+
+    /// Returns [`true`] if this session is ongoing traversal, migration or metric measurement
+    fn is_known_session(&self, session: Session) -> bool {
+        self.trav_session == Some(session)
+            || self.get_migration_session() == Some(session)
+            || self.metric.as_ref().and_then(|m| m.ping_session) == Some(session)
+            || self.path_mtu.as_ref().and_then(|p| p.probe_session) == Some(session)
+    }
+
+    /// Returns [`true`] if this session was agreed on with node in CallMeMaybe exchange, or
+    /// was offered to node by us, as node may use it before its response reaches us
+    fn is_pair_session(&self, session: Session) -> bool {
+        self.pair_sessions.contains(&Some(session))
+            || self.trav_session == Some(session)
+            || self.get_migration_session() == Some(session)
+    }
+
+    /// Returns [`true`] if Ping of current protocol was sent by node. Ping has to carry
+    /// session agreed on with node, or come from endpoint of connected route, as metric
+    /// and MTU probes carry sessions of their own.
+    fn is_ping_sender(&self, session: Session, endpoint: &SocketAddr) -> bool {
+        (self.is_pair_session(session) && self.is_known_endpoint(endpoint))
+            || (self.is_connected().is_some() && self.remote_endpoint == Some(*endpoint))
+    }
+
+    /// Returns [`true`] if packet of current protocol with given peer id was sent by node
+    fn is_data_sender(&self, peer_id: PeerId, endpoint: &SocketAddr) -> bool {
+        self.protocol == Protocol::Current
+            && self
+                .pair_sessions
+                .iter()
+                .chain([self.trav_session, self.get_migration_session()].iter())
+                .flatten()
+                .any(|session| session_peer_id(*session) == peer_id)
+            && self.is_known_endpoint(endpoint)
+    }
+
     /// Update state of entry, if it has received a packet from other end
     pub async fn handle_data_packet_rx(
         &mut self,
@@ -573,6 +1152,25 @@ impl Entry {
                 return Ok(());
             }
 
+            // Remote node has migrated to one of its freshly offered endpoints
+            if self
+                .roaming
+                .as_ref()
+                .map_or(false, |roaming| roaming.contains(remote_addr))
+            {
+                telio_log_debug!(
+                    "Peer {:?} roamed from {} to {} endpoint",
+                    self.pk,
+                    endp,
+                    remote_addr
+                );
+
+                let _ = self.remote_endpoint.insert(*remote_addr);
+                self.roaming = None;
+                self.last_rx = Instant::now();
+                return Ok(());
+            }
+
             return Err(Error::EndpointMismatch);
         }
 
@@ -610,7 +1208,28 @@ impl Entry {
                 .insert(latency);
 
             telio_log_debug!(
-                "Peer {:?} received PingerMsgDeprecated::Pong from {} endpoint, latency: {} millis",
+                "Peer {:?} received Pong from {} endpoint, latency: {} millis",
+                self.pk,
+                remote_addr,
+                latency.as_millis()
+            );
+
+            self.last_rx = Instant::now();
+
+            return Ok(());
+        } else if self.is_migration_pinging().is_some()
+            && Some(pong_sess) == self.get_migration_session()
+        {
+            // Handle Pong from endpoint migration
+            let _ = self
+                .migration
+                .as_mut()
+                .and_then(|m| m.candidates.get_mut(remote_addr))
+                .ok_or(Error::UnexpectedPacket)?
+                .insert(latency);
+
+            telio_log_debug!(
+                "Peer {:?} received migration Pong from {} endpoint, latency: {} millis",
                 self.pk,
                 remote_addr,
                 latency.as_millis()
@@ -639,43 +1258,156 @@ impl Entry {
         Err(Error::InvalidCurrentState)
     }
 
+    /// Handle Pong of MTU probe, `probe_size` is [`None`] if node does not support probing
+    pub fn handle_probe_pong_rx(
+        &mut self,
+        remote_addr: &SocketAddr,
+        pong_sess: Session,
+        probe_size: Option<u32>,
+    ) -> Result<()> {
+        match self.is_probing_mtu() {
+            Some((_, session)) if session == pong_sess => (),
+            Some(_) => return Err(Error::SessionMismatch),
+            None => return Err(Error::InvalidCurrentState),
+        }
+        if self.remote_endpoint != Some(*remote_addr) {
+            return Err(Error::EndpointMismatch);
+        }
+
+        let pk = self.pk;
+        let path_mtu = self.path_mtu.as_mut().ok_or(Error::InvalidCurrentState)?;
+        match probe_size {
+            Some(size) => {
+                let mtu = tunnel_mtu(size);
+                path_mtu.answered = path_mtu.answered.max(Some(mtu));
+            }
+            None => {
+                telio_log_debug!("Peer {:?} does not support path MTU probing", pk);
+                path_mtu.unsupported = true;
+                path_mtu.probe_session = None;
+            }
+        }
+
+        self.last_rx = Instant::now();
+
+        Ok(())
+    }
+
     pub async fn handle_cmm_init_rx<'a, N: Iterator<Item = SocketAddr>>(
-        &self,
+        &mut self,
         offered_addrs: N,
         our_addrs: N,
         sess: Session,
         socket: &UdpSocket,
-        permit: OwnedPermit<(PublicKey, CallMeMaybeMsgDeprecated)>,
+        chan: &Control,
+        permit: Option<DeprecatedPermit>,
     ) -> Result<()> {
+        let offered_addrs: Vec<SocketAddr> = offered_addrs.collect();
+
+        // Initiator is already connected to us, so it is migrating its endpoint
+        if self.is_connected().is_some() {
+            self.roaming = Some(offered_addrs.iter().copied().collect());
+        }
+
+        // Packets of current protocol are recognized by these endpoints
+        self.offered = offered_addrs.iter().copied().collect();
+        self.remote_candidates = offered_addrs.clone();
+
         // Pinging endpoints (session is `0`, because this `Ping` is only serving a prupose to punch a hole in 'our' NAT)
-        let _ = Self::ping_endpoints(
-            self.tx_peer_id.ok_or(Error::NoTxPeerId)?,
-            offered_addrs,
-            socket,
-            0,
+        let _ =
+            Self::ping_endpoints(self.pinger(socket)?, offered_addrs.into_iter(), socket, 0).await;
+
+        // Sending CallMeMaybe response
+        self.local_candidates = our_addrs.collect();
+        chan.send(
+            self.pk,
+            self.protocol,
+            false,
+            &self.local_candidates,
+            sess,
+            self.rx_peer_id,
+            permit,
         )
-        .await;
+    }
 
-        // Creating and sending CallMeMaybe request
-        let msg = CallMeMaybeMsgDeprecated::new(false, our_addrs, sess, self.rx_peer_id);
+    /// Collect diagnostics of the direct path to node, for finding out why it is not connected
+    pub fn get_diagnostics(&self) -> PeerDiagnostics {
+        let (state, duration) = self.get_state();
+        let pings = self.candidates.as_ref().unwrap_or(&self.last_pings);
+
+        let verdict = match state {
+            RouteState::Variant::ConnectedByActivate(_) => Verdict::Connected,
+            RouteState::Variant::SentCallMeMaybeByStart(_) => Verdict::AwaitingCallMeMaybe,
+            RouteState::Variant::PingingByPing(_) => Verdict::Pinging,
+            _ if self.local_candidates.is_empty() && self.remote_candidates.is_empty() => {
+                Verdict::NotAttempted
+            }
+            _ if self.remote_candidates.is_empty() => Verdict::NoCallMeMaybeResponse,
+            _ if pings.values().all(|latency| latency.is_none()) => Verdict::PingsUnanswered,
+            _ => Verdict::ConnectionLost,
+        };
 
-        // Sending CallMeMaybe response
-        permit.send((self.pk, msg));
+        let mut pings: Vec<PingReport> = pings
+            .iter()
+            .map(|(endpoint, latency)| PingReport {
+                endpoint: *endpoint,
+                answered: latency.is_some(),
+                latency_ms: latency.map(|l| l.as_millis() as u64),
+            })
+            .collect();
+        pings.sort_by_key(|p| p.endpoint);
+
+        PeerDiagnostics {
+            public_key: self.pk,
+            state: Self::state_name(&state).to_owned(),
+            state_duration_ms: duration.as_millis() as u64,
+            protocol: format!("{:?}", self.protocol),
+            local_candidates: self.local_candidates.clone(),
+            remote_candidates: self.remote_candidates.clone(),
+            pings,
+            nat: None,
+            mtu: self.get_mtu(),
+            verdict,
+        }
+    }
 
-        Ok(())
+    /// Remember ping results of finished traversal session, for diagnostics
+    fn keep_last_pings(&mut self) {
+        if let Some(candidates) = self.candidates.take() {
+            self.last_pings = candidates;
+        }
+    }
+
+    fn state_name(state: &RouteState::Variant) -> &'static str {
+        match state {
+            RouteState::Variant::DisconnectedByBreak(_) => "Disconnected",
+            RouteState::Variant::InitialDisconnected(_) => "InitialDisconnected",
+            RouteState::Variant::SentCallMeMaybeByStart(_) => "SentCallMeMaybe",
+            RouteState::Variant::PingingByPing(_) => "Pinging",
+            RouteState::Variant::ConnectedByActivate(_) => "Connected",
+        }
+    }
+
+    /// Ping message builder for the protocol version, spoken by node
+    fn pinger(&self, socket: &UdpSocket) -> Result<Pinger> {
+        match self.protocol {
+            Protocol::Current => Ok(Pinger::Current(WGPort(socket.local_addr()?.port()))),
+            _ => Ok(Pinger::Deprecated(
+                self.tx_peer_id.ok_or(Error::NoTxPeerId)?,
+            )),
+        }
     }
 
     // Ping a list of endpoints
     async fn ping_endpoints<N: Iterator<Item = SocketAddr>>(
-        tx_peer_id: TxPeerId,
+        pinger: Pinger,
         endpoints: N,
         socket: &UdpSocket,
         sess: Session,
     ) -> Result<()> {
         // Pinging endpoints
         for endpoint in endpoints {
-            let msg =
-                PingerMsgDeprecated::ping(tx_peer_id, sess, Self::get_timestamp()).encode()?;
+            let msg = pinger.ping(sess, Self::get_timestamp())?;
             socket.send_to(&msg, endpoint).await?;
         }
 
@@ -759,6 +1491,9 @@ impl fmt::Display for Entry {
         write!(f, "Traversal session: {:?}, ", self.trav_session)?;
         write!(f, "PublicKey: {:?}, ", self.pk)?;
         write!(f, "Metrics: {:?}, ", self.metric)?;
+        write!(f, "Path MTU: {:?}, ", self.path_mtu)?;
+        write!(f, "Migration: {:?}, ", self.migration)?;
+        write!(f, "Protocol: {:?}, ", self.protocol)?;
         write!(f, "State: ")?;
 
         match &mut self.get_state() {
@@ -862,14 +1597,39 @@ impl Database {
             .ok_or(Error::PublicKeyNotFound)
     }
 
-    /// Update entrie's tx_peer_id.
+    /// Get mutable entry of node, which has sent Ping of current protocol from `endpoint`.
+    /// Endpoints may overlap between nodes (i.e. same private LAN addresses), so Ping is
+    /// attributed only by the session, agreed on with a single node.
+    pub fn get_mut_entry_by_ping(
+        &mut self,
+        session: Session,
+        endpoint: &SocketAddr,
+    ) -> Result<&mut Entry> {
+        self.entries
+            .iter_mut()
+            .map(|(_, (_, entry))| entry)
+            .find(|entry| entry.is_ping_sender(session, endpoint))
+            .ok_or(Error::PublicKeyNotFound)
+    }
+
+    /// Get mutable entry, which has pinged `endpoint` within its ongoing `session`
+    pub fn get_mut_entry_by_pong(
+        &mut self,
+        session: Session,
+        endpoint: &SocketAddr,
+    ) -> Result<&mut Entry> {
+        self.entries
+            .iter_mut()
+            .map(|(_, (_, entry))| entry)
+            .find(|entry| entry.is_known_session(session) && entry.is_known_endpoint(endpoint))
+            .ok_or(Error::PublicKeyNotFound)
+    }
+
+    /// Update entrie's tx_peer_id. Only deprecated protocol carries peer ids.
     pub fn update_tx_peer_id(&mut self, pk: &PublicKey, tx_peer_id: TxPeerId) -> Result<()> {
-        let _ = self
-            .entries
-            .get_mut(pk)
-            .ok_or(Error::PublicKeyNotFound)?
-            .tx_peer_id
-            .insert(tx_peer_id);
+        let entry = self.entries.get_mut(pk).ok_or(Error::PublicKeyNotFound)?;
+        let _ = entry.tx_peer_id.insert(tx_peer_id);
+        entry.protocol = Protocol::Deprecated;
 
         telio_log_debug!("Associated peer {:?} with {:?}", pk, tx_peer_id);
 
@@ -881,9 +1641,18 @@ impl Database {
         match self.entries.get(pub_key) {
             Some(entry) => {
                 if entry.is_connected().is_some() {
+                    // Nodes of current protocol are given no peer ids, the ones derived from
+                    // agreed session are used instead
+                    let tx_peer_id = match entry.protocol {
+                        Protocol::Current => entry.pair_sessions[0]
+                            .map(session_peer_id)
+                            .ok_or(Error::NoTxPeerId)?,
+                        _ => entry.tx_peer_id.ok_or(Error::NoTxPeerId)?,
+                    };
+
                     return Ok((
                         entry.remote_endpoint.ok_or(Error::InvalidCurrentState)?,
-                        entry.tx_peer_id.ok_or(Error::NoTxPeerId)?,
+                        tx_peer_id,
                     ));
                 }
 
@@ -893,6 +1662,42 @@ impl Database {
         }
     }
 
+    /// Information for Tx'ing peer relay frame: endpoint and session, authenticating the frame
+    pub fn get_peer_relay_info_tx(&self, pub_key: &PublicKey) -> Result<(SocketAddr, Session)> {
+        let entry = self.entries.get(pub_key).ok_or(Error::PublicKeyNotFound)?;
+        if entry.is_connected().is_none() {
+            return Err(Error::PathDisconnected);
+        }
+
+        // Nodes of deprecated protocol agree on no sessions, nor relay frames
+        match (entry.protocol, entry.pair_sessions[0]) {
+            (Protocol::Current, Some(session)) => Ok((
+                entry.remote_endpoint.ok_or(Error::InvalidCurrentState)?,
+                session,
+            )),
+            _ => Err(Error::UnexpectedProtocol),
+        }
+    }
+
+    /// Find connected node, which has sent peer relay frame from its endpoint, carrying
+    /// session agreed on with it. Endpoint alone can be spoofed.
+    pub fn get_peer_relay_sender(
+        &self,
+        session: Session,
+        endpoint: &SocketAddr,
+    ) -> Result<PublicKey> {
+        self.entries
+            .iter()
+            .find(|(_, (_, entry))| {
+                entry.is_connected().is_some()
+                    && entry.protocol == Protocol::Current
+                    && entry.remote_endpoint == Some(*endpoint)
+                    && entry.is_pair_session(session)
+            })
+            .map(|(pk, _)| *pk)
+            .ok_or(Error::PublicKeyNotFound)
+    }
+
     /// Information for Rx'ing packet
     pub async fn get_packet_info_rx(
         &mut self,
@@ -900,14 +1705,29 @@ impl Database {
         events: &Tx<(PublicKey, bool)>,
         socket: &UdpSocket,
     ) -> Result<PublicKey> {
+        // Nodes of current protocol are recognized by agreed session and their endpoints.
+        // They go first, as peer ids given to nodes of deprecated protocol may collide with
+        // the ones derived from sessions
+        if let Some(entry) = self
+            .entries
+            .iter_mut()
+            .map(|(_, (_, entry))| entry)
+            .find(|entry| entry.is_data_sender(net_tuple.0, &net_tuple.1))
+        {
+            entry
+                .handle_data_packet_rx(&net_tuple.1, events, socket)
+                .await?;
+            return Ok(entry.pk);
+        }
+
         match self.entries.get_mut_alt_with_key(&net_tuple.0) {
-            Some((key, entry)) => {
+            Some((key, entry)) if entry.protocol != Protocol::Current => {
                 entry
                     .handle_data_packet_rx(&net_tuple.1, events, socket)
                     .await?;
                 Ok(*key)
             }
-            None => Err(Error::PublicKeyNotFound),
+            _ => Err(Error::PublicKeyNotFound),
         }
     }
 
@@ -955,6 +1775,7 @@ impl Database {
 #[allow(dead_code)]
 mod tests {
     use super::*;
+    use telio_crypto::SecretKey;
 
     #[test]
     fn db_generate_peer_id() {
@@ -1028,4 +1849,265 @@ mod tests {
         assert_eq!(dst_addr, "127.0.0.1:8080".parse().unwrap());
         assert_eq!(tx_peer_id, PeerId(3));
     }
+
+    #[tokio::test]
+    async fn db_rx_packet_info_prefers_session_over_colliding_peer_id() {
+        let (deprecated_pk, current_pk) = (SecretKey::gen().public(), SecretKey::gen().public());
+        let socket = UdpSocket::bind((std::net::Ipv4Addr::LOCALHOST, 0))
+            .await
+            .expect("Cannot create UdpSocket: ");
+        let Chan { tx, mut rx } = Chan::default();
+        tokio::spawn(async move { while let Some(_) = rx.recv().await {} });
+
+        let mut db = Database::default();
+        db.insert(vec![deprecated_pk, current_pk].into_iter());
+
+        // Session of current node derives the peer id, given to deprecated node
+        let peer_id = db.entries.get(&deprecated_pk).unwrap().rx_peer_id;
+        let session = 0x1_0000 + peer_id.0 as Session;
+        let endpoint: SocketAddr = "127.0.0.1:8080".parse().unwrap();
+        {
+            let entry = db.entries.get_mut(&current_pk).unwrap();
+            entry.protocol = Protocol::Current;
+            entry.pair_sessions[0] = Some(session);
+            entry.remote_endpoint = Some(endpoint);
+        }
+        assert_eq!(session_peer_id(session), peer_id);
+
+        let pk = db
+            .get_packet_info_rx(&(peer_id, endpoint), &tx, &socket)
+            .await
+            .unwrap();
+        assert_eq!(pk, current_pk);
+        assert!(db
+            .entries
+            .get(&deprecated_pk)
+            .unwrap()
+            .is_connected()
+            .is_none());
+
+        // Deprecated node is still found by its peer id
+        let pk = db
+            .get_packet_info_rx(&(peer_id, "127.0.0.1:9090".parse().unwrap()), &tx, &socket)
+            .await
+            .unwrap();
+        assert_eq!(pk, deprecated_pk);
+    }
+
+    #[tokio::test]
+    async fn db_peer_relay_sender_is_authenticated_by_session() {
+        let pk = SecretKey::gen().public();
+        let socket = UdpSocket::bind((std::net::Ipv4Addr::LOCALHOST, 0))
+            .await
+            .expect("Cannot create UdpSocket: ");
+        let control = Control {
+            current: Chan::default(),
+            deprecated: Chan::default(),
+        };
+        let events = Chan::default();
+        let local: Vec<SocketAddr> = vec!["10.0.0.1:1000".parse().unwrap()];
+        let endpoint: SocketAddr = "127.0.0.1:2000".parse().unwrap();
+
+        let mut db = Database::default();
+        db.insert(vec![pk].into_iter());
+        {
+            let entry = db.entries.get_mut(&pk).unwrap();
+            entry.tx_peer_id = Some(PeerId(2));
+            entry.protocol = Protocol::Deprecated;
+            entry
+                .start_sent_call_me_maybe(&control, local.into_iter(), &events.tx)
+                .await
+                .unwrap();
+            entry
+                .start_pinging(vec![endpoint].into_iter(), &socket)
+                .await
+                .unwrap();
+            let session = entry.get_traversal_session().unwrap();
+            entry
+                .handle_pong_rx(&endpoint, session, Entry::get_timestamp() - 1000)
+                .unwrap();
+            entry.choose_route(&events.tx, &socket).await.unwrap();
+            assert!(entry.is_connected().is_some());
+        }
+
+        // Deprecated nodes neither agree on sessions, nor relay frames
+        assert!(matches!(
+            db.get_peer_relay_info_tx(&pk),
+            Err(Error::UnexpectedProtocol)
+        ));
+        assert!(matches!(
+            db.get_peer_relay_sender(0, &endpoint),
+            Err(Error::PublicKeyNotFound)
+        ));
+
+        let session = 0x1234;
+        {
+            let entry = db.entries.get_mut(&pk).unwrap();
+            entry.protocol = Protocol::Current;
+            entry.pair_sessions[0] = Some(session);
+        }
+        assert_eq!(db.get_peer_relay_info_tx(&pk).unwrap(), (endpoint, session));
+        assert_eq!(db.get_peer_relay_sender(session, &endpoint).unwrap(), pk);
+
+        // Frame from node's endpoint, which does not carry its session, is spoofed
+        assert!(matches!(
+            db.get_peer_relay_sender(session + 1, &endpoint),
+            Err(Error::PublicKeyNotFound)
+        ));
+        assert!(matches!(
+            db.get_peer_relay_sender(session, &"127.0.0.1:3000".parse().unwrap()),
+            Err(Error::PublicKeyNotFound)
+        ));
+    }
+
+    #[tokio::test]
+    async fn entry_diagnostics_verdict() {
+        let pk = "REjdn4zY2TFx2AMujoNGPffo9vDiRDXpGG4jHPtx2AY="
+            .parse::<PublicKey>()
+            .unwrap();
+        let socket = UdpSocket::bind((std::net::Ipv4Addr::LOCALHOST, 0))
+            .await
+            .expect("Cannot create UdpSocket: ");
+        let control = Control {
+            current: Chan::default(),
+            deprecated: Chan::default(),
+        };
+        let events = Chan::default();
+
+        let local: Vec<SocketAddr> = vec!["10.0.0.1:1000".parse().unwrap()];
+        let remote: Vec<SocketAddr> = vec![
+            "127.0.0.1:2000".parse().unwrap(),
+            "127.0.0.1:3000".parse().unwrap(),
+        ];
+
+        let mut entry = Entry::new(PeerId(1), pk);
+        entry.tx_peer_id = Some(PeerId(2));
+        entry.protocol = Protocol::Deprecated;
+        assert_eq!(entry.get_diagnostics().verdict, Verdict::NotAttempted);
+
+        // Peer does not respond to our CallMeMaybe
+        entry
+            .start_sent_call_me_maybe(&control, local.clone().into_iter(), &events.tx)
+            .await
+            .unwrap();
+        let diagnostics = entry.get_diagnostics();
+        assert_eq!(diagnostics.verdict, Verdict::AwaitingCallMeMaybe);
+        assert_eq!(diagnostics.local_candidates, local);
+
+        entry.disconnect_route(&events.tx).unwrap();
+        assert_eq!(
+            entry.get_diagnostics().verdict,
+            Verdict::NoCallMeMaybeResponse
+        );
+
+        // Peer responds, but its endpoints stay silent
+        entry
+            .start_sent_call_me_maybe(&control, local.clone().into_iter(), &events.tx)
+            .await
+            .unwrap();
+        entry
+            .start_pinging(remote.clone().into_iter(), &socket)
+            .await
+            .unwrap();
+        assert_eq!(entry.get_diagnostics().verdict, Verdict::Pinging);
+
+        entry.disconnect_route(&events.tx).unwrap();
+        let diagnostics = entry.get_diagnostics();
+        assert_eq!(diagnostics.verdict, Verdict::PingsUnanswered);
+        assert_eq!(diagnostics.remote_candidates, remote);
+        assert_eq!(diagnostics.pings.len(), 2);
+        assert!(diagnostics.pings.iter().all(|p| !p.answered));
+
+        // One of the endpoints answers
+        entry
+            .start_sent_call_me_maybe(&control, local.clone().into_iter(), &events.tx)
+            .await
+            .unwrap();
+        entry
+            .start_pinging(remote.clone().into_iter(), &socket)
+            .await
+            .unwrap();
+        let session = entry.get_traversal_session().unwrap();
+        entry
+            .handle_pong_rx(&remote[1], session, Entry::get_timestamp() - 1000)
+            .unwrap();
+        entry.choose_route(&events.tx, &socket).await.unwrap();
+
+        let diagnostics = entry.get_diagnostics();
+        assert_eq!(diagnostics.verdict, Verdict::Connected);
+        assert_eq!(
+            diagnostics.pings,
+            vec![
+                PingReport {
+                    endpoint: remote[0],
+                    answered: false,
+                    latency_ms: None,
+                },
+                PingReport {
+                    endpoint: remote[1],
+                    answered: true,
+                    latency_ms: diagnostics.pings[1].latency_ms,
+                },
+            ]
+        );
+
+        entry.disconnect_route(&events.tx).unwrap();
+        assert_eq!(entry.get_diagnostics().verdict, Verdict::ConnectionLost);
+    }
+
+    #[tokio::test]
+    async fn entry_probes_path_mtu() {
+        let pk = "REjdn4zY2TFx2AMujoNGPffo9vDiRDXpGG4jHPtx2AY="
+            .parse::<PublicKey>()
+            .unwrap();
+        let socket = UdpSocket::bind((std::net::Ipv4Addr::LOCALHOST, 0))
+            .await
+            .expect("Cannot create UdpSocket: ");
+        let events = Chan::default();
+        let remote: SocketAddr = "127.0.0.1:2000".parse().unwrap();
+
+        let mut entry = Entry::new(PeerId(1), pk);
+        entry.protocol = Protocol::Current;
+        entry
+            .connect_route(
+                &remote,
+                Latency::Measured(Duration::from_millis(1)),
+                &events.tx,
+                &socket,
+            )
+            .await
+            .unwrap();
+        assert_eq!(entry.get_mtu(), None);
+        assert_eq!(entry.last_mtu_probe(), Some(MTU_PROBE_INTERVAL));
+
+        // Only the smaller probes make it through
+        entry.start_probing_mtu(&socket).await.unwrap();
+        let (_, session) = entry.is_probing_mtu().unwrap();
+        for link_mtu in [1380, 1280] {
+            entry
+                .handle_probe_pong_rx(&remote, session, Some(probe_size(link_mtu, &remote)))
+                .unwrap();
+        }
+        assert!(entry
+            .handle_probe_pong_rx(&"127.0.0.1:3000".parse().unwrap(), session, Some(1472))
+            .is_err());
+        entry.complete_mtu_probe().unwrap();
+        assert_eq!(entry.get_mtu(), Some(1380 - 20 - 8 - 36));
+        assert_eq!(entry.get_diagnostics().mtu, entry.get_mtu());
+
+        // Unanswered probing keeps the last known MTU
+        entry.start_probing_mtu(&socket).await.unwrap();
+        entry.complete_mtu_probe().unwrap();
+        assert_eq!(entry.get_mtu(), Some(1316));
+
+        // Node of older version answers with plain pongs
+        entry.start_probing_mtu(&socket).await.unwrap();
+        let (_, session) = entry.is_probing_mtu().unwrap();
+        entry.handle_probe_pong_rx(&remote, session, None).unwrap();
+        assert_eq!(entry.is_probing_mtu(), None);
+        assert_eq!(entry.last_mtu_probe(), None);
+
+        entry.disconnect_route(&events.tx).unwrap();
+        assert_eq!(entry.get_mtu(), None);
+    }
 }
@@ -1,4 +1,5 @@
 mod database;
 
+pub mod peer_relay;
 pub mod stunner;
 pub mod udp_hole_punch;
@@ -0,0 +1,808 @@
+use async_trait::async_trait;
+use futures::Future;
+
+use std::collections::{HashMap, HashSet};
+use telio_crypto::PublicKey;
+use telio_model::api_config::FeaturePeerRelay;
+use telio_proto::{DataMsg, PeerRelayMsg, PeerRelayType};
+use telio_task::{
+    io::{chan::*, Chan, ChanSendError},
+    task_exec, BoxAction, Runtime, Task,
+};
+use telio_utils::{
+    repeated_actions::Error as RAError, telio_log_debug, telio_log_trace, telio_log_warn,
+    RepeatedActions,
+};
+use tokio::{
+    sync::mpsc::error::TrySendError as ChanTrySendError,
+    time::{Duration, Instant},
+};
+
+use crate::{route::Configure, Route, RouteError, RouteResult};
+
+// Time constants of peer relay path
+mod constants {
+    #[cfg(test)]
+    pub mod test {
+        use tokio::time::Duration;
+
+        pub const PROBE_INTERVAL: Duration = Duration::from_millis(100);
+        pub const NO_DATA_TIMEOUT: Duration = Duration::from_millis(300);
+    }
+
+    #[allow(dead_code)]
+    pub mod prod {
+        use tokio::time::Duration;
+
+        pub const PROBE_INTERVAL: Duration = Duration::from_secs(5);
+        pub const NO_DATA_TIMEOUT: Duration = Duration::from_secs(15);
+    }
+}
+
+#[cfg(test)]
+use constants::test::*;
+
+#[cfg(not(test))]
+use constants::prod::*;
+
+/// Payload of probe reply, too short to be mistaken for WireGuard packet.
+/// Probe itself carries empty payload.
+const PROBE_REPLY: &[u8] = &[0];
+
+/// Posible [PeerRelay] errors.
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    /// Repeated action errors
+    #[error(transparent)]
+    RepeatedActionError(#[from] RAError),
+    /// Someting is wrong with serializing/deserializing packet
+    #[error(transparent)]
+    PacketCodecError(#[from] telio_proto::CodecError),
+    /// Transport channel is closed (or overflowed)
+    #[error(transparent)]
+    TransportChanTryErr(#[from] ChanTrySendError<(PublicKey, PeerRelayMsg)>),
+    /// Data channel is closed (or overflowed)
+    #[error(transparent)]
+    DataChanErr(#[from] ChanSendError<(PublicKey, DataMsg)>),
+    /// Node is not known to peer relay path
+    #[error("Node not found")]
+    NodeNotFound,
+    /// Node is not reachable through any relaying node
+    #[error("Node not reachable")]
+    NodeNotReachable,
+    /// Task encountered an error while running
+    #[error(transparent)]
+    Task(#[from] telio_task::ExecError),
+}
+
+type Result<T> = std::result::Result<T, Error>;
+
+/// Path, where data is relayed through a third, consenting meshnet node.
+///
+/// Relayed frames are exchanged with relaying node through `transport`, which
+/// is expected to reach it directly (i.e. hole punched path). Nodes probe each
+/// other through relay candidates with empty [`DataMsg`]s, which are answered
+/// with probe replies, and the peer is reachable as long as those keep getting
+/// delivered. Round trip of the last answered probe is the latency of the path.
+pub struct PeerRelay {
+    task: Task<State>,
+}
+
+#[async_trait]
+impl Configure for PeerRelay {
+    async fn configure(&self, _config: telio_relay::Config) {}
+}
+
+#[async_trait]
+impl Route for PeerRelay {
+    async fn set_nodes(&self, nodes: Vec<PublicKey>) -> RouteResult<()> {
+        task_exec!(&self.task, async move |s| {
+            s.nodes.clear();
+            s.nodes
+                .extend(nodes.into_iter().map(|node| (node, Entry::default())));
+            Ok(())
+        })
+        .await
+        .map_err(|_| RouteError::SetNodesFailed)
+    }
+
+    async fn update_nodes(&self, nodes: Vec<PublicKey>) -> RouteResult<()> {
+        task_exec!(&self.task, async move |s| {
+            // Remove keys that is excluded in current config
+            s.nodes.retain(|node, _| nodes.contains(node));
+
+            // Add only new nodes, not disrupting current nodes' states
+            for node in nodes {
+                s.nodes.entry(node).or_default();
+            }
+            Ok(())
+        })
+        .await
+        .map_err(|_| RouteError::SetNodesFailed)
+    }
+
+    async fn reset_nodes(&self, nodes: Vec<PublicKey>) -> RouteResult<()> {
+        task_exec!(&self.task, async move |s| {
+            for node in nodes.iter() {
+                s.disconnect(node);
+            }
+            Ok(())
+        })
+        .await
+        .map_err(|_| RouteError::SetNodesFailed)
+    }
+
+    async fn is_reachable(&self, node: PublicKey) -> bool {
+        task_exec!(&self.task, async move |s| Ok(s
+            .nodes
+            .get(&node)
+            .map_or(false, |entry| entry.relay.is_some())))
+        .await
+        .unwrap_or(false)
+    }
+
+    async fn rtt(&self, node: PublicKey) -> RouteResult<Duration> {
+        task_exec!(&self.task, async move |s| Ok(s
+            .nodes
+            .get(&node)
+            .and_then(|entry| entry.relay.and(entry.rtt))))
+        .await
+        .map_err(RouteError::Task)?
+        .ok_or(RouteError::LatencyUnknown)
+    }
+}
+
+impl PeerRelay {
+    /// PeerRelay constructor
+    /// serve - relay traffic on behalf of other nodes, within given bandwidth limits
+    pub fn start(
+        transport: Chan<(PublicKey, PeerRelayMsg)>,
+        data: Chan<(PublicKey, DataMsg)>,
+        events_tx: Tx<(PublicKey, bool)>,
+        serve: Option<FeaturePeerRelay>,
+    ) -> Result<Self> {
+        let mut actions = RepeatedActions::<State, Result<()>>::new();
+        actions.add_action(String::from("Probe peers"), PROBE_INTERVAL, |s| {
+            Box::pin(async move { s.handle_peer_states() })
+        })?;
+
+        Ok(Self {
+            task: Task::start(State {
+                data,
+                transport,
+                events_tx,
+                nodes: HashMap::new(),
+                relays: Vec::new(),
+                serve: serve.map(Serve::new),
+                actions,
+            }),
+        })
+    }
+
+    /// Set nodes, which have consented to relay traffic for us, or through us
+    pub async fn set_relays(&self, relays: HashSet<PublicKey>) -> RouteResult<()> {
+        task_exec!(&self.task, async move |s| {
+            s.set_relays(relays);
+            Ok(())
+        })
+        .await
+        .map_err(RouteError::Task)
+    }
+
+    pub async fn stop(self) {
+        let _ = self.task.stop().await.resume_unwind();
+    }
+}
+
+/// Peer's state in peer relay path
+#[derive(Debug, Default)]
+struct Entry {
+    /// Relaying node, through which peer is reachable (Some() - if connected)
+    relay: Option<PublicKey>,
+    /// Last frame delivered from peer
+    last_rx: Option<Instant>,
+    /// Index of relay candidate to probe next
+    next_candidate: usize,
+    /// Last probe sent to peer, until it gets answered
+    probe_sent: Option<Instant>,
+    /// Round trip time of the last answered probe
+    rtt: Option<Duration>,
+}
+
+/// Bandwidth limiter, allowing bursts of up to one second worth of traffic
+#[derive(Debug)]
+struct TokenBucket {
+    /// Bytes per second
+    rate: u64,
+    tokens: u64,
+    last_refill: Instant,
+}
+
+impl TokenBucket {
+    fn new(rate: u64) -> Self {
+        Self {
+            rate,
+            tokens: rate,
+            last_refill: Instant::now(),
+        }
+    }
+
+    fn refill(&mut self) {
+        let refill = self.last_refill.elapsed().as_micros() * self.rate as u128 / 1_000_000;
+
+        if refill > 0 {
+            self.tokens = (self.tokens as u128 + refill).min(self.rate as u128) as u64;
+            self.last_refill = Instant::now();
+        }
+    }
+
+    fn has(&mut self, len: u64) -> bool {
+        self.refill();
+        self.tokens >= len
+    }
+
+    fn take(&mut self, len: u64) {
+        self.tokens = self.tokens.saturating_sub(len);
+    }
+}
+
+/// Relaying traffic on behalf of other nodes
+#[derive(Debug)]
+struct Serve {
+    total: TokenBucket,
+    peers: HashMap<PublicKey, TokenBucket>,
+    peer_rate: u64,
+}
+
+impl Serve {
+    fn new(features: FeaturePeerRelay) -> Self {
+        Self {
+            total: TokenBucket::new(features.bandwidth_limit),
+            peers: HashMap::new(),
+            peer_rate: features.peer_bandwidth_limit,
+        }
+    }
+
+    /// Check and account relayed frame from `src` against bandwidth limits
+    fn allow(&mut self, src: &PublicKey, len: u64) -> bool {
+        let peer_rate = self.peer_rate;
+        let peer = self
+            .peers
+            .entry(*src)
+            .or_insert_with(|| TokenBucket::new(peer_rate));
+
+        if !self.total.has(len) || !peer.has(len) {
+            return false;
+        }
+
+        self.total.take(len);
+        peer.take(len);
+        true
+    }
+}
+
+struct State {
+    /// Channel, that sends and receives actual WireGuard and IP data
+    data: Chan<(PublicKey, DataMsg)>,
+    /// Relayed frames, exchanged with directly reachable nodes
+    transport: Chan<(PublicKey, PeerRelayMsg)>,
+    /// Events for owner module
+    events_tx: Tx<(PublicKey, bool)>,
+    /// Peer states
+    nodes: HashMap<PublicKey, Entry>,
+    /// Nodes, consenting to relay traffic for us, or through us
+    relays: Vec<PublicKey>,
+    /// Relaying on behalf of other nodes (None - if it is not allowed)
+    serve: Option<Serve>,
+    /// Task list, that occurs every interval
+    actions: RepeatedActions<Self, Result<()>>,
+}
+
+impl State {
+    fn set_relays(&mut self, relays: HashSet<PublicKey>) {
+        let mut relays: Vec<PublicKey> = relays.into_iter().collect();
+        // Keep probing order stable
+        relays.sort_by(|a, b| a.0.cmp(&b.0));
+        self.relays = relays;
+
+        // Drop peers, which are reached through no longer consenting relay
+        let disconnected: Vec<PublicKey> = self
+            .nodes
+            .iter()
+            .filter(|(_, entry)| {
+                entry
+                    .relay
+                    .map_or(false, |relay| !self.relays.contains(&relay))
+            })
+            .map(|(pk, _)| *pk)
+            .collect();
+
+        for pk in disconnected.iter() {
+            self.disconnect(pk);
+        }
+
+        if let Some(serve) = self.serve.as_mut() {
+            let relays = &self.relays;
+            serve.peers.retain(|pk, _| relays.contains(pk));
+        }
+    }
+
+    fn connect(&mut self, pk: &PublicKey, relay: &PublicKey) {
+        if let Some(entry) = self.nodes.get_mut(pk) {
+            entry.last_rx = Some(Instant::now());
+
+            if entry.relay.is_none() {
+                telio_log_debug!(
+                    "({}) Peer {:?} reachable through {:?}",
+                    Self::NAME,
+                    pk,
+                    relay
+                );
+                entry.relay = Some(*relay);
+                let _ = self.events_tx.try_send((*pk, true));
+            }
+        }
+    }
+
+    fn disconnect(&mut self, pk: &PublicKey) {
+        if let Some(entry) = self.nodes.get_mut(pk) {
+            entry.last_rx = None;
+            entry.probe_sent = None;
+            entry.rtt = None;
+
+            if let Some(relay) = entry.relay.take() {
+                telio_log_debug!(
+                    "({}) Peer {:?} no longer reachable through {:?}",
+                    Self::NAME,
+                    pk,
+                    relay
+                );
+                let _ = self.events_tx.try_send((*pk, false));
+            }
+        }
+    }
+
+    /// Probe peers through relaying nodes, drop the ones which are silent for too long
+    fn handle_peer_states(&mut self) -> Result<()> {
+        let timed_out: Vec<PublicKey> = self
+            .nodes
+            .iter()
+            .filter(|(_, entry)| {
+                entry.relay.is_some()
+                    && entry
+                        .last_rx
+                        .map_or(true, |last_rx| last_rx.elapsed() > NO_DATA_TIMEOUT)
+            })
+            .map(|(pk, _)| *pk)
+            .collect();
+
+        for pk in timed_out.iter() {
+            self.disconnect(pk);
+        }
+
+        for (pk, entry) in self.nodes.iter_mut() {
+            let relay = match entry.relay {
+                Some(relay) => relay,
+                None => {
+                    // Rotate through relay candidates, skipping peer itself
+                    let candidates: Vec<&PublicKey> =
+                        self.relays.iter().filter(|relay| *relay != pk).collect();
+
+                    if candidates.is_empty() {
+                        continue;
+                    }
+
+                    entry.next_candidate = (entry.next_candidate + 1) % candidates.len();
+                    *candidates[entry.next_candidate]
+                }
+            };
+
+            telio_log_trace!("({}) Probing {:?} through {:?}", Self::NAME, pk, relay);
+            // Failing to reach one peer should not stop probing of the others
+            match Self::send_probe(&self.transport, pk, &relay, &[]) {
+                Ok(()) => entry.probe_sent = Some(Instant::now()),
+                Err(e) => telio_log_warn!(
+                    "({}) Failed to probe {:?} through {:?}: {}",
+                    Self::NAME,
+                    pk,
+                    relay,
+                    e.to_string()
+                ),
+            }
+        }
+
+        Ok(())
+    }
+
+    fn send_probe(
+        transport: &Chan<(PublicKey, PeerRelayMsg)>,
+        pk: &PublicKey,
+        relay: &PublicKey,
+        payload: &[u8],
+    ) -> Result<()> {
+        transport
+            .tx
+            .try_send((*relay, PeerRelayMsg::forward(pk.0, DataMsg::new(payload))))
+            .map_err(Error::TransportChanTryErr)
+    }
+
+    async fn handle_tx_data_packet(&mut self, msg: DataMsg, pk: &PublicKey) -> Result<()> {
+        let relay = self
+            .nodes
+            .get(pk)
+            .ok_or(Error::NodeNotFound)?
+            .relay
+            .ok_or(Error::NodeNotReachable)?;
+
+        telio_log_trace!(
+            "({}) Tx Data packet: payload: {}, pk: {:?}, relay: {:?}",
+            Self::NAME,
+            msg,
+            pk,
+            relay
+        );
+
+        self.transport
+            .tx
+            .try_send((relay, PeerRelayMsg::forward(pk.0, msg)))
+            .map_err(Error::TransportChanTryErr)
+    }
+
+    async fn handle_rx_packet(&mut self, from: PublicKey, msg: PeerRelayMsg) -> Result<()> {
+        telio_log_trace!("({}) Rx {} from {:?}", Self::NAME, msg, from);
+
+        match msg.get_message_type() {
+            PeerRelayType::Forward => self.handle_forward(from, msg),
+            PeerRelayType::Deliver => self.handle_deliver(from, msg).await,
+            PeerRelayType::Reject => {
+                // Relaying node refused to carry our traffic, try another one
+                let pk = PublicKey(msg.get_peer());
+                if self.nodes.get(&pk).and_then(|entry| entry.relay) == Some(from) {
+                    self.disconnect(&pk);
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// Relay frame on behalf of `src`
+    fn handle_forward(&mut self, src: PublicKey, msg: PeerRelayMsg) -> Result<()> {
+        let dst = PublicKey(msg.get_peer());
+
+        // Both sides have to consent to relaying through us
+        let consent = self.relays.contains(&src)
+            && self.relays.contains(&dst)
+            && self.nodes.contains_key(&dst);
+
+        let serve = match self.serve.as_mut() {
+            Some(serve) if consent => serve,
+            _ => {
+                telio_log_debug!(
+                    "({}) Refusing to relay from {:?} to {:?}",
+                    Self::NAME,
+                    src,
+                    dst
+                );
+                return self
+                    .transport
+                    .tx
+                    .try_send((src, PeerRelayMsg::reject(dst.0)))
+                    .map_err(Error::TransportChanTryErr);
+            }
+        };
+
+        // Frames over the bandwidth limit are dropped, WireGuard will take care of the rest
+        if !serve.allow(&src, msg.payload_len() as u64) {
+            telio_log_trace!(
+                "({}) Bandwidth limit reached, dropping frame from {:?} to {:?}",
+                Self::NAME,
+                src,
+                dst
+            );
+            return Ok(());
+        }
+
+        self.transport
+            .tx
+            .try_send((dst, msg.relay(src.0)?))
+            .map_err(Error::TransportChanTryErr)
+    }
+
+    /// Frame from `src` delivered through `relay`
+    async fn handle_deliver(&mut self, relay: PublicKey, msg: PeerRelayMsg) -> Result<()> {
+        let src = PublicKey(msg.get_peer());
+
+        if !self.relays.contains(&relay) || !self.nodes.contains_key(&src) {
+            return Err(Error::NodeNotFound);
+        }
+
+        let data = msg.get_data()?;
+
+        self.connect(&src, &relay);
+
+        // Probe, answered through the same relay, so the other end knows it is reachable too
+        if data.get_payload().is_empty() {
+            return Self::send_probe(&self.transport, &src, &relay, PROBE_REPLY);
+        }
+
+        if data.get_payload() == PROBE_REPLY {
+            if let Some(entry) = self.nodes.get_mut(&src) {
+                if let Some(sent) = entry.probe_sent.take() {
+                    entry.rtt = Some(sent.elapsed());
+                }
+            }
+            return Ok(());
+        }
+
+        self.data
+            .tx
+            .send((src, data))
+            .await
+            .map_err(Error::DataChanErr)
+    }
+}
+
+#[async_trait]
+impl Runtime for State {
+    const NAME: &'static str = "PeerRelay";
+
+    type Err = ();
+
+    async fn wait_with_update<F>(&mut self, update: F) -> std::result::Result<(), Self::Err>
+    where
+        F: Future<Output = BoxAction<Self, std::result::Result<(), Self::Err>>> + Send,
+    {
+        tokio::select! {
+            // Received relayed frame from another node
+            Some((from, msg)) = self.transport.rx.recv() => {
+                self
+                    .handle_rx_packet(from, msg)
+                    .await
+                    .map_or_else(|e| {
+                        telio_log_warn!("({}) Error handling rx PeerRelay packet: {}", Self::NAME, e.to_string());
+                        Ok(())
+                    }, |_| Ok(()))?;
+            }
+            // Received Data packet on Itf->WG->UDP_proxy->Path_selector -> PeerRelay
+            Some((pk, data_msg)) = self.data.rx.recv() => {
+                self
+                    .handle_tx_data_packet(data_msg, &pk)
+                    .await
+                    .map_or_else(|e| {
+                        telio_log_debug!("({}) Error handling tx Data packet: {}", Self::NAME, e.to_string());
+                        Ok(())
+                    }, |_| Ok(()))?;
+            }
+            // Repeated action
+            Ok((name, action)) = self.actions.select_action() => {
+                action(self)
+                    .await
+                    .map_or_else(|e| {
+                        telio_log_warn!("({}) Error handling repeated action ({}): {}", Self::NAME, name, e.to_string());
+                        Ok(())
+                    }, |_| Ok(()))?;
+            },
+            // Incoming task
+            update = update => {
+                return update(self).await;
+            }
+            else => {
+                return Ok(());
+            },
+        };
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use telio_proto::{Generation, PeerId};
+    use tokio::time::{self, timeout};
+
+    struct Node {
+        pk: PublicKey,
+        route: PeerRelay,
+        data: Chan<(PublicKey, DataMsg)>,
+        events: Rx<(PublicKey, bool)>,
+    }
+
+    impl Node {
+        async fn stop(self) {
+            self.route.stop().await;
+        }
+    }
+
+    /// Start A, B and relaying R nodes, with all of them directly reachable to each other
+    async fn prepare(serve: Option<FeaturePeerRelay>, relay_consents_for_b: bool) -> [Node; 3] {
+        let keys = [
+            PublicKey([1u8; 32]),
+            PublicKey([2u8; 32]),
+            PublicKey([3u8; 32]),
+        ];
+        let (a_pk, b_pk, r_pk) = (keys[0], keys[1], keys[2]);
+
+        let mut nodes = Vec::new();
+        let mut transports = Vec::new();
+
+        for (pk, serve) in [(a_pk, None), (b_pk, None), (r_pk, serve)] {
+            let (transport_us, transport) = Chan::pipe();
+            let (data_us, data) = Chan::pipe();
+            let Chan {
+                tx: events_tx,
+                rx: events,
+            } = Chan::default();
+
+            nodes.push(Node {
+                pk,
+                route: PeerRelay::start(transport_us, data_us, events_tx, serve)
+                    .expect("Cannot start PeerRelay"),
+                data,
+                events,
+            });
+            transports.push((pk, transport));
+        }
+
+        // Acting as a direct path between all nodes
+        let txs: HashMap<PublicKey, Tx<(PublicKey, PeerRelayMsg)>> = transports
+            .iter()
+            .map(|(pk, chan)| (*pk, chan.tx.clone()))
+            .collect();
+
+        for (pk, Chan { tx: _, mut rx }) in transports {
+            let txs = txs.clone();
+            tokio::spawn(async move {
+                while let Some((to, msg)) = rx.recv().await {
+                    if let Some(tx) = txs.get(&to) {
+                        let _ = tx.send((pk, msg)).await;
+                    }
+                }
+            });
+        }
+
+        let mut r_relays: HashSet<PublicKey> = vec![a_pk].into_iter().collect();
+        if relay_consents_for_b {
+            r_relays.insert(b_pk);
+        }
+
+        for (node, nodes, relays) in [
+            (
+                &nodes[0],
+                vec![b_pk, r_pk],
+                vec![r_pk].into_iter().collect(),
+            ),
+            (
+                &nodes[1],
+                vec![a_pk, r_pk],
+                vec![r_pk].into_iter().collect(),
+            ),
+            (&nodes[2], vec![a_pk, b_pk], r_relays),
+        ] {
+            node.route.set_nodes(nodes).await.unwrap();
+            node.route.set_relays(relays).await.unwrap();
+        }
+
+        let mut nodes = nodes.into_iter();
+        [
+            nodes.next().unwrap(),
+            nodes.next().unwrap(),
+            nodes.next().unwrap(),
+        ]
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn relay_data_through_consenting_node() {
+        let [mut a, mut b, r] = prepare(Some(FeaturePeerRelay::default()), true).await;
+
+        // Both ends learn about each other through probes
+        assert_eq!(
+            timeout(NO_DATA_TIMEOUT, a.events.recv()).await.unwrap(),
+            Some((b.pk, true))
+        );
+        assert_eq!(
+            timeout(NO_DATA_TIMEOUT, b.events.recv()).await.unwrap(),
+            Some((a.pk, true))
+        );
+        assert!(a.route.is_reachable(b.pk).await);
+        assert!(b.route.is_reachable(a.pk).await);
+
+        // Answered probes give latency of the path
+        time::sleep(PROBE_INTERVAL * 2).await;
+        assert!(a.route.rtt(b.pk).await.is_ok());
+        assert!(b.route.rtt(a.pk).await.is_ok());
+
+        // A --> R --> B
+        let payload = DataMsg::with_generation(&[1, 2, 3], Generation(1), PeerId(1));
+        a.data.tx.send((b.pk, payload.clone())).await.unwrap();
+        assert_eq!(
+            timeout(NO_DATA_TIMEOUT, b.data.rx.recv()).await.unwrap(),
+            Some((a.pk, payload.clone()))
+        );
+
+        // B --> R --> A
+        b.data.tx.send((a.pk, payload.clone())).await.unwrap();
+        assert_eq!(
+            timeout(NO_DATA_TIMEOUT, a.data.rx.recv()).await.unwrap(),
+            Some((b.pk, payload))
+        );
+
+        // Relaying node withdraws its consent
+        r.route.set_relays(HashSet::new()).await.unwrap();
+        assert_eq!(
+            timeout(NO_DATA_TIMEOUT * 2, a.events.recv()).await.unwrap(),
+            Some((b.pk, false))
+        );
+        assert!(!a.route.is_reachable(b.pk).await);
+
+        a.stop().await;
+        b.stop().await;
+        r.stop().await;
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn no_relay_without_consent() {
+        // Relaying node has not opted in
+        let [mut a, b, r] = prepare(None, true).await;
+
+        assert!(timeout(NO_DATA_TIMEOUT * 2, a.events.recv()).await.is_err());
+        assert!(!a.route.is_reachable(b.pk).await);
+
+        a.stop().await;
+        b.stop().await;
+        r.stop().await;
+
+        // Destination is not allowed by relaying node
+        let [mut a, b, r] = prepare(Some(FeaturePeerRelay::default()), false).await;
+
+        assert!(timeout(NO_DATA_TIMEOUT * 2, a.events.recv()).await.is_err());
+        assert!(!a.route.is_reachable(b.pk).await);
+
+        a.stop().await;
+        b.stop().await;
+        r.stop().await;
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn drop_frames_over_bandwidth_limit() {
+        let [mut a, mut b, r] = prepare(
+            Some(FeaturePeerRelay {
+                bandwidth_limit: 4096,
+                peer_bandwidth_limit: 1024,
+            }),
+            true,
+        )
+        .await;
+
+        assert_eq!(
+            timeout(NO_DATA_TIMEOUT, a.events.recv()).await.unwrap(),
+            Some((b.pk, true))
+        );
+
+        // Peer's budget allows only two such frames within a second
+        let payload = DataMsg::new(&[0u8; 400]);
+        for _ in 0..4 {
+            a.data.tx.send((b.pk, payload.clone())).await.unwrap();
+        }
+
+        let mut received = 0;
+        while let Ok(Some((pk, msg))) = timeout(PROBE_INTERVAL, b.data.rx.recv()).await {
+            assert_eq!(pk, a.pk);
+            assert_eq!(msg, payload);
+            received += 1;
+        }
+        assert_eq!(received, 2);
+
+        // Budget is refilled over time
+        time::sleep(Duration::from_secs(1)).await;
+        a.data.tx.send((b.pk, payload.clone())).await.unwrap();
+        assert_eq!(
+            timeout(NO_DATA_TIMEOUT, b.data.rx.recv()).await.unwrap(),
+            Some((a.pk, payload))
+        );
+
+        a.stop().await;
+        b.stop().await;
+        r.stop().await;
+    }
+}
@@ -1,5 +1,5 @@
 use async_trait::async_trait;
-use futures::Future;
+use futures::{future::pending, Future};
 
 use std::{
     collections::HashSet,
@@ -7,9 +7,11 @@ use std::{
     sync::Arc,
 };
 use telio_crypto::PublicKey;
+use telio_model::{api_config::FeatureTraversal, diagnostics::PeerDiagnostics};
 use telio_proto::{
-    CallMeMaybeDeprecatedType, CallMeMaybeMsgDeprecated, Codec, DataMsg, Generation, Packet,
-    PingerMsgDeprecated,
+    CallMeMaybeDeprecatedType, CallMeMaybeMsg, CallMeMaybeMsgDeprecated, CallMeMaybeType, Codec,
+    DataMsg, Generation, Packet, PeerId, PeerRelayMsg, PingerMsg, PingerMsgDeprecated, Session,
+    WGPort,
 };
 use telio_sockets::External;
 use telio_task::{
@@ -19,7 +21,7 @@ use telio_task::{
 use telio_task::{Runtime, Task};
 use telio_utils::{
     repeated_actions::Error as RAError, telio_log_debug, telio_log_trace, telio_log_warn,
-    RepeatedActions,
+    telio_span, RepeatedActions,
 };
 use tokio::{
     net::UdpSocket,
@@ -28,36 +30,43 @@ use tokio::{
 };
 
 use crate::{
+    endpoint_providers::local::{gather_local_interfaces, GetIfAddrs},
     route::Configure,
-    routes::database::{AbsRouteState, Database, Error as DatabaseError},
+    routes::database::{
+        AbsRouteState, Control, Database, DeprecatedPermit, Error as DatabaseError, Protocol,
+        MTU_PROBE_INTERVAL,
+    },
     routes::stunner::{Error as StunnerError, StunPacket},
     Route, RouteError, RouteResult,
 };
 
 #[cfg(not(test))]
-use crate::routes::stunner::{Config as StunConfig, Stunner};
+use crate::{
+    endpoint_providers::local::SharedGetIfAddrs,
+    routes::stunner::{Config as StunConfig, Stunner},
+};
 
 #[cfg(test)]
 use {
-    crate::routes::{
-        database::CurrentRouteState,
-        stunner::{Config as StunConfig, Result as StunnerResult, Results as StunResponse},
+    crate::{
+        endpoint_providers::local::MockGetIfAddrs,
+        routes::{
+            database::CurrentRouteState,
+            stunner::{Config as StunConfig, Result as StunnerResult, Results as StunResponse},
+        },
     },
     mockall::mock,
     std::net::Ipv4Addr,
-    telio_proto::PeerId,
 };
 
 // Time constants of state machine
 mod constants {
-    use tokio::time::Duration;
-
     pub const MAX_PACKET: usize = u16::MAX as usize;
     pub const USE_PLAINTEXT_STUN: bool = true;
 
     #[cfg(test)]
     pub mod test {
-        use super::*;
+        use tokio::time::Duration;
 
         pub const PING_METRIC_INTERVAL: Duration = Duration::from_secs(150);
         pub const PING_TIMEOUT: Duration = Duration::from_millis(100);
@@ -67,31 +76,33 @@ mod constants {
         pub const CALL_ME_MAYBE_TIMEOUT: Duration = Duration::from_millis(200);
         pub const DISCONNECTED_GRACE_PERIOD: Duration = Duration::from_millis(200);
     }
-
-    #[allow(dead_code)]
-    pub mod prod {
-        use super::*;
-
-        pub const PING_METRIC_INTERVAL: Duration = Duration::from_secs(25);
-        pub const PING_TIMEOUT: Duration = Duration::from_secs(5);
-        pub const NO_DATA_TIMEOUT: Duration = Duration::from_secs(60);
-        pub const CHECK_PEER_STATES_INTERVAL: Duration = Duration::from_secs(5);
-        // TODO: STUN_INTERVAL should be minimized to ~60 seconds, after succesful STUN response
-        pub const STUN_INTERVAL: Duration = Duration::from_secs(60);
-        pub const CALL_ME_MAYBE_TIMEOUT: Duration = Duration::from_secs(5);
-        pub const DISCONNECTED_GRACE_PERIOD: Duration = Duration::from_secs(5);
-    }
 }
 
 #[cfg(test)]
 use constants::test::*;
 
-#[cfg(not(test))]
-use constants::prod::*;
-
 // Include common ones
 use constants::*;
 
+/// Timers of state machine, until configured otherwise
+#[cfg(not(test))]
+fn default_timers() -> FeatureTraversal {
+    FeatureTraversal::default()
+}
+
+#[cfg(test)]
+fn default_timers() -> FeatureTraversal {
+    FeatureTraversal {
+        ping_metric_interval: PING_METRIC_INTERVAL,
+        ping_timeout: PING_TIMEOUT,
+        no_data_timeout: NO_DATA_TIMEOUT,
+        check_peer_states_interval: CHECK_PEER_STATES_INTERVAL,
+        stun_interval: STUN_INTERVAL,
+        call_me_maybe_timeout: CALL_ME_MAYBE_TIMEOUT,
+        disconnected_grace_period: DISCONNECTED_GRACE_PERIOD,
+    }
+}
+
 /// Posible [UdpHolePunch] errors.
 #[derive(thiserror::Error, Debug)]
 pub enum Error {
@@ -121,6 +132,9 @@ pub enum Error {
     /// Event channel is closed (or overflowed)
     #[error(transparent)]
     EventChanTryErr(#[from] ChanTrySendError<(PublicKey, bool)>),
+    /// Peer relay channel is closed (or overflowed)
+    #[error(transparent)]
+    PeerRelayChanTryErr(#[from] ChanTrySendError<(PublicKey, PeerRelayMsg)>),
     /// Stunner tx channel is closed (or overflowed)
     #[error(transparent)]
     StunnerChanErr(#[from] ChanSendError<(StunPacket, SocketAddr)>),
@@ -139,6 +153,9 @@ pub enum Error {
     /// Database error
     #[error(transparent)]
     DbError(#[from] DatabaseError),
+    /// Failed to gather local endpoints
+    #[error(transparent)]
+    EndpointProviderError(#[from] crate::endpoint_providers::Error),
 }
 
 type Result<T> = std::result::Result<T, Error>;
@@ -248,28 +265,43 @@ impl Route for UdpHolePunch {
                 _ => RouteError::LatencyUnknown,
             })
     }
+
+    async fn notify_network_change(&self) -> RouteResult<()> {
+        task_exec!(&self.task, async move |s| {
+            if let Err(e) = s.start_migrations().await {
+                telio_log_warn!(
+                    "({}) Failed to start endpoint migrations: {}",
+                    State::NAME,
+                    e.to_string()
+                );
+            }
+            Ok(())
+        })
+        .await
+        .map_err(RouteError::Task)
+    }
 }
 
 impl UdpHolePunch {
     /// UdpHolePunch constructor
+    /// control_deprecated - CallMeMaybe transport for nodes of older versions
+    /// peer_relay - transport for peer relay frames, exchanged over direct connections
+    #[allow(clippy::too_many_arguments)]
     pub fn start(
         udp_socket: External<UdpSocket>,
         data: Chan<(PublicKey, DataMsg)>,
-        control: Chan<(PublicKey, CallMeMaybeMsgDeprecated)>,
+        control: Chan<(PublicKey, CallMeMaybeMsg)>,
+        control_deprecated: Chan<(PublicKey, CallMeMaybeMsgDeprecated)>,
         events_tx: Tx<(PublicKey, bool)>,
+        peer_relay: Option<Chan<(PublicKey, PeerRelayMsg)>>,
+        get_if_addrs: IfAddrs,
         #[cfg(test)] stunner_fail_cnt: i32,
     ) -> Result<Self> {
         let udp_socket = Arc::new(udp_socket);
 
+        let timers = default_timers();
         let mut actions = RepeatedActions::<State, Result<()>>::new();
-        actions.add_action(String::from("Do Stun"), STUN_INTERVAL, |s| {
-            Box::pin(async move { s.stunner.do_stun().await.map_err(Error::StunnerErr) })
-        })?;
-        actions.add_action(
-            String::from("Check peers' states"),
-            CHECK_PEER_STATES_INTERVAL,
-            |s| Box::pin(async move { s.handle_peer_states().await }),
-        )?;
+        State::add_actions(&mut actions, &timers)?;
 
         #[cfg(not(test))]
         let (stunner, stunner_tx) = Stunner::start(udp_socket.clone(), None);
@@ -310,19 +342,56 @@ impl UdpHolePunch {
         Ok(Self {
             task: Task::start(State {
                 data,
-                control,
+                control: Control {
+                    current: control,
+                    deprecated: control_deprecated,
+                },
                 events_tx,
+                peer_relay,
                 stunner_srv_list: None,
                 db: Database::default(),
                 actions,
+                timers,
                 stunner,
                 stunner_tx,
                 rx_buff: [0u8; MAX_PACKET],
                 udp_socket,
+                get_if_addrs,
             }),
         })
     }
 
+    /// Apply new hole punching timers, without restarting the route
+    pub async fn set_timers(&self, timers: FeatureTraversal) -> Result<()> {
+        task_exec!(&self.task, async move |s| Ok(s.set_timers(timers)))
+            .await
+            .map_err(Error::Task)?
+    }
+
+    /// Tunnel MTU fitting into peer's direct path, [None] if it is not connected or not probed yet
+    pub async fn get_mtu(&self, node: PublicKey) -> Result<Option<u32>> {
+        task_exec!(&self.task, async move |s| {
+            Ok(s.db
+                .get_entry_by_pk(&node)
+                .map(|entry| entry.get_mtu())
+                .map_err(Error::DbError))
+        })
+        .await
+        .map_err(Error::Task)?
+    }
+
+    /// Collect connectivity diagnostics of peer's direct path
+    pub async fn get_diagnostics(&self, node: PublicKey) -> Result<PeerDiagnostics> {
+        task_exec!(&self.task, async move |s| {
+            Ok(s.db
+                .get_entry_by_pk(&node)
+                .map(|entry| entry.get_diagnostics())
+                .map_err(Error::DbError))
+        })
+        .await
+        .map_err(Error::Task)?
+    }
+
     pub async fn stop(self) {
         let _ = self.task.stop().await.resume_unwind();
     }
@@ -346,21 +415,37 @@ impl UdpHolePunch {
         .await
         .map_err(|e| Error::Task(e))?
     }
+
+    #[cfg(test)]
+    async fn get_node_protocol(&self, node: PublicKey) -> Result<Protocol> {
+        task_exec!(&self.task, async move |s| {
+            Ok(s.db
+                .get_entry_by_pk(&node)
+                .map(|entry| entry.get_protocol())
+                .map_err(Error::DbError))
+        })
+        .await
+        .map_err(|e| Error::Task(e))?
+    }
 }
 
 struct State {
     /// Channel, that sends and receives actual WireGuard and IP data
     data: Chan<(PublicKey, DataMsg)>,
-    // Channel, used to send CallMeMaybe Messages between two UDP Hole Punching route instances
-    control: Chan<(PublicKey, CallMeMaybeMsgDeprecated)>,
+    // Channels, used to send CallMeMaybe Messages between two UDP Hole Punching route instances
+    control: Control,
     /// Events for owner module
     events_tx: Tx<(PublicKey, bool)>,
+    /// Channel, that carries peer relay frames over direct connections
+    peer_relay: Option<Chan<(PublicKey, PeerRelayMsg)>>,
     /// Stunner ip list
     stunner_srv_list: Option<HashSet<SocketAddr>>,
     /// Peer states database
     db: Database,
     /// Task list, that occurs every interval
     actions: RepeatedActions<Self, Result<()>>,
+    /// Timers of state machine
+    timers: FeatureTraversal,
     /// Stunner instance
     stunner: Stunner,
     /// Receive bufferfor UDP socket
@@ -369,17 +454,52 @@ struct State {
     stunner_tx: Tx<(StunPacket, SocketAddr)>,
     /// Main socket for UDP communication, shared for all peers and UDP-hole punching
     udp_socket: Arc<External<UdpSocket>>,
+    /// Local interfaces provider, used for endpoint migration
+    get_if_addrs: IfAddrs,
 }
 
 impl State {
+    fn add_actions(
+        actions: &mut RepeatedActions<Self, Result<()>>,
+        timers: &FeatureTraversal,
+    ) -> Result<()> {
+        actions.add_action(String::from("Do Stun"), timers.stun_interval, |s| {
+            Box::pin(async move { s.stunner.do_stun().await.map_err(Error::StunnerErr) })
+        })?;
+        actions.add_action(
+            String::from("Check peers' states"),
+            timers.check_peer_states_interval,
+            |s| Box::pin(async move { s.handle_peer_states().await }),
+        )?;
+        Ok(())
+    }
+
+    /// Apply new timers, without disrupting current peers' states
+    fn set_timers(&mut self, timers: FeatureTraversal) -> Result<()> {
+        if timers.stun_interval != self.timers.stun_interval
+            || timers.check_peer_states_interval != self.timers.check_peer_states_interval
+        {
+            self.actions.remove_action(String::from("Do Stun"))?;
+            self.actions
+                .remove_action(String::from("Check peers' states"))?;
+            Self::add_actions(&mut self.actions, &timers)?;
+        }
+
+        telio_log_debug!("({}) Timers updated: {:?}", Self::NAME, timers);
+        self.timers = timers;
+        Ok(())
+    }
+
     // Check for changes in peers
     async fn handle_peer_states(&mut self) -> Result<()> {
         telio_log_trace!("({}) handle_peer_states()", Self::NAME);
 
+        let timers = self.timers;
+
         for (_, (_, entry)) in self.db.iter_mut() {
             match entry.get_state() {
                 (AbsRouteState::DisconnectedByBreak(_), d) => {
-                    if d >= DISCONNECTED_GRACE_PERIOD {
+                    if d >= timers.disconnected_grace_period {
                         telio_log_debug!("({}) Starting CMM for entry: {}", Self::NAME, entry);
 
                         entry
@@ -403,14 +523,14 @@ impl State {
                         .await?;
                 }
                 (AbsRouteState::SentCallMeMaybeByStart(_), d) => {
-                    if d >= CALL_ME_MAYBE_TIMEOUT {
+                    if d >= timers.call_me_maybe_timeout {
                         telio_log_debug!("({}) Disconnected entry: {}", Self::NAME, entry);
 
                         let _ = entry.disconnect_route(&self.events_tx)?;
                     }
                 }
                 (AbsRouteState::PingingByPing(_), d) => {
-                    if d >= PING_TIMEOUT {
+                    if d >= timers.ping_timeout {
                         if let Err(_e) = entry.choose_route(&self.events_tx, &self.udp_socket).await
                         {
                             telio_log_debug!("({}) Disconnecting entry: {}", Self::NAME, entry);
@@ -421,7 +541,8 @@ impl State {
                 }
                 (AbsRouteState::ConnectedByActivate(_), _) => {
                     if let Some(last_rx_dur) = entry.is_connected() {
-                        if last_rx_dur > NO_DATA_TIMEOUT {
+                        // Migrating route stays connected, until migration either completes or fails
+                        if last_rx_dur > timers.no_data_timeout && entry.is_migrating().is_none() {
                             telio_log_debug!("({}) Disconnecting entry: {}", Self::NAME, entry);
 
                             let _ = entry.disconnect_route(&self.events_tx);
@@ -430,8 +551,33 @@ impl State {
                         telio_log_warn!("({}) Peer's state invalid: {}", Self::NAME, entry,);
                     }
 
+                    if let Some(pinging) = entry.is_migration_pinging() {
+                        if pinging >= timers.ping_timeout {
+                            if let Err(e) = entry.complete_migration(&self.events_tx) {
+                                telio_log_debug!(
+                                    "({}) Endpoint migration failed ({}), keeping old endpoint: {}",
+                                    Self::NAME,
+                                    e.to_string(),
+                                    entry
+                                );
+
+                                let _ = entry.abort_migration(&self.events_tx);
+                            }
+                        }
+                    } else if let Some(migrating) = entry.is_migrating() {
+                        if migrating >= timers.call_me_maybe_timeout {
+                            telio_log_debug!(
+                                "({}) Endpoint migration timeout, keeping old endpoint: {}",
+                                Self::NAME,
+                                entry
+                            );
+
+                            let _ = entry.abort_migration(&self.events_tx);
+                        }
+                    }
+
                     if let Some((measure_start, _)) = entry.is_measuring_metric() {
-                        if measure_start > PING_TIMEOUT {
+                        if measure_start > timers.ping_timeout {
                             telio_log_debug!(
                                 "({}) Metric measure timeout for peer {:?}, restarting ...",
                                 Self::NAME,
@@ -448,7 +594,7 @@ impl State {
                             }
                         }
                     } else if let Some(measure_last) = entry.last_metric_measure() {
-                        if measure_last > PING_METRIC_INTERVAL {
+                        if measure_last > timers.ping_metric_interval {
                             if let Err(e) = entry.start_measuring_metric(&self.udp_socket).await {
                                 telio_log_warn!(
                                     "({}) Error trying to measure peer's {:?} path's metric: {}",
@@ -459,6 +605,28 @@ impl State {
                             }
                         }
                     }
+
+                    // Path MTU is probed only by nodes of current protocol
+                    if entry.get_protocol() != Protocol::Current {
+                        continue;
+                    }
+
+                    if let Some((probe_start, _)) = entry.is_probing_mtu() {
+                        if probe_start > timers.ping_timeout {
+                            let _ = entry.complete_mtu_probe();
+                        }
+                    } else if let Some(probe_last) = entry.last_mtu_probe() {
+                        if probe_last >= MTU_PROBE_INTERVAL {
+                            if let Err(e) = entry.start_probing_mtu(&self.udp_socket).await {
+                                telio_log_warn!(
+                                    "({}) Error trying to probe peer's {:?} path MTU: {}",
+                                    Self::NAME,
+                                    entry.pk,
+                                    e.to_string()
+                                );
+                            }
+                        }
+                    }
                 }
             }
         }
@@ -466,6 +634,58 @@ impl State {
         Ok(())
     }
 
+    /// Start migrating all connected peers to the endpoints of changed local network
+    async fn start_migrations(&mut self) -> Result<()> {
+        // Refresh public endpoint, as the old one is most likely gone
+        if let Err(e) = self.stunner.do_stun().await {
+            telio_log_warn!("({}) Failed to refresh STUN: {}", Self::NAME, e.to_string());
+        }
+
+        let endpoints = self.gather_migration_endpoints().await?;
+
+        for (_, (_, entry)) in self.db.iter_mut() {
+            if entry.is_connected().is_none() {
+                continue;
+            }
+
+            telio_log_debug!(
+                "({}) Starting endpoint migration for entry: {}",
+                Self::NAME,
+                entry
+            );
+
+            if let Err(e) = entry.start_migration(&self.control, endpoints.iter().copied()) {
+                telio_log_warn!(
+                    "({}) Failed to start endpoint migration for peer {:?}: {}",
+                    Self::NAME,
+                    entry.pk,
+                    e.to_string()
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Gather endpoints, which may be reachable after local network change
+    async fn gather_migration_endpoints(&self) -> Result<Vec<SocketAddr>> {
+        let port = self.udp_socket.local_addr()?.port();
+
+        // Local interfaces are fresh, while STUN results may still describe old network
+        let mut endpoints: Vec<SocketAddr> = gather_local_interfaces(&self.get_if_addrs)?
+            .into_iter()
+            .map(|itf| SocketAddr::new(itf.addr.ip(), port))
+            .collect();
+
+        if let Ok(Some(remote)) = self.stunner.fetch_endpoints().await.map(|r| r.remote) {
+            if !endpoints.contains(&remote) {
+                endpoints.push(remote);
+            }
+        }
+
+        Ok(endpoints)
+    }
+
     /// Disconnect all peers
     /// not_conn: bool - reset only not connected states
     fn reset_peers_states(&mut self, not_conn: bool) {
@@ -522,7 +742,7 @@ impl State {
                         |_| Ok(()),
                     )?;
             }
-            Ok(Packet::PingerDeprecated(pinger_msg)) => {
+            Ok(Packet::Pinger(pinger_msg)) => {
                 telio_log_trace!(
                     "handle_rx_pinger_packet(pinger_msg: ({}), src_addr: ({:?}))",
                     pinger_msg,
@@ -542,6 +762,45 @@ impl State {
                         |_| Ok(()),
                     )?;
             }
+            Ok(Packet::PingerDeprecated(pinger_msg)) => {
+                telio_log_trace!(
+                    "handle_rx_pinger_deprecated_packet(pinger_msg: ({}), src_addr: ({:?}))",
+                    pinger_msg,
+                    src_addr
+                );
+                self.handle_rx_pinger_deprecated_packet(pinger_msg, src_addr)
+                    .await
+                    .map_or_else(
+                        |e| {
+                            telio_log_debug!(
+                                "({}) Error handling rx Pinger packet: {}",
+                                Self::NAME,
+                                e.to_string()
+                            );
+                            Err(e)
+                        },
+                        |_| Ok(()),
+                    )?;
+            }
+            Ok(Packet::PeerRelay(peer_relay_msg)) => {
+                telio_log_trace!(
+                    "handle_rx_peer_relay_packet(peer_relay_msg: ({}), src_addr: ({:?}))",
+                    peer_relay_msg,
+                    src_addr
+                );
+                self.handle_rx_peer_relay_packet(peer_relay_msg, src_addr)
+                    .map_or_else(
+                        |e| {
+                            telio_log_debug!(
+                                "({}) Error handling rx PeerRelay packet: {}",
+                                Self::NAME,
+                                e.to_string()
+                            );
+                            Err(e)
+                        },
+                        |_| Ok(()),
+                    )?;
+            }
             Err(e) => {
                 return Err(Error::PacketCodecError(e));
             }
@@ -560,6 +819,41 @@ impl State {
     }
 
     async fn handle_rx_pinger_packet(
+        &mut self,
+        msg: PingerMsg,
+        src_addr: &SocketAddr,
+    ) -> Result<()> {
+        match msg.pong(WGPort(self.udp_socket.local_addr()?.port())) {
+            // Handling Ping message, sending a reply to known node only
+            Some(pong) => {
+                let _ = self.db.get_mut_entry_by_ping(msg.get_session(), src_addr)?;
+
+                telio_log_debug!(
+                    "({}) Rx PingMsg::ping from {:?}, sending reply ...",
+                    Self::NAME,
+                    src_addr
+                );
+
+                self.udp_socket.send_to(&pong.encode()?, src_addr).await?;
+            }
+            // Handle Pong message (update database, if it expecting Pong anytime soons)
+            None => {
+                telio_log_debug!("({}) Rx PingMsg::pong from {:?}", Self::NAME, src_addr);
+
+                let session = msg.get_session();
+                let entry = self.db.get_mut_entry_by_pong(session, src_addr)?;
+                if entry.is_probing_mtu().map(|(_, s)| s) == Some(session) {
+                    entry.handle_probe_pong_rx(src_addr, session, msg.get_probe_size())?;
+                } else {
+                    entry.handle_pong_rx(src_addr, session, msg.get_start_timestamp())?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn handle_rx_pinger_deprecated_packet(
         &mut self,
         msg: PingerMsgDeprecated,
         src_addr: &SocketAddr,
@@ -650,43 +944,112 @@ impl State {
         Ok(())
     }
 
-    // TODO: there are 3 search calls to DB in this func, needs overhaul
+    fn handle_rx_peer_relay_packet(
+        &mut self,
+        msg: PeerRelayMsg,
+        src_addr: &SocketAddr,
+    ) -> Result<()> {
+        let peer_relay = self.peer_relay.as_ref().ok_or(Error::UnexpectedPacket)?;
+        let pk = self.db.get_peer_relay_sender(msg.get_session(), src_addr)?;
+
+        peer_relay.tx.try_send((pk, msg))?;
+        Ok(())
+    }
+
+    async fn handle_tx_peer_relay_packet(
+        &mut self,
+        msg: PeerRelayMsg,
+        pk: &PublicKey,
+    ) -> Result<()> {
+        let (dst_addr, session) = self.db.get_peer_relay_info_tx(pk)?;
+        let msg = msg.with_session(session);
+
+        telio_log_trace!(
+            "Tx PeerRelay packet: {}, dst: {}, pk: {:?}",
+            msg,
+            dst_addr,
+            pk
+        );
+
+        self.udp_socket
+            .send_to(msg.encode()?.as_slice(), &dst_addr)
+            .await?;
+        Ok(())
+    }
+
+    /// Handle already received current CallMeMaybe messages, as they are sent ahead of
+    /// their deprecated copies, which otherwise may be handled first
+    async fn handle_pending_call_me_maybe(&mut self) {
+        while let Ok((pk, cmm)) = self.control.current.rx.try_recv() {
+            telio_log_trace!(
+                "({}) handle_call_me_maybe(pk: ({:?}), cmm: ({}))",
+                Self::NAME,
+                pk,
+                cmm
+            );
+
+            if let Err(e) = self
+                .handle_call_me_maybe(&pk, CallMeMaybe::from(&cmm), None)
+                .await
+            {
+                telio_log_warn!(
+                    "({}) Error handling rx CallMeMaybe packet: {}",
+                    Self::NAME,
+                    e.to_string()
+                );
+            }
+        }
+    }
+
     async fn handle_call_me_maybe(
         &mut self,
         pk: &PublicKey,
-        msg: &CallMeMaybeMsgDeprecated,
-        permit: OwnedPermit<(PublicKey, CallMeMaybeMsgDeprecated)>,
+        cmm: CallMeMaybe,
+        permit: Option<DeprecatedPermit>,
     ) -> Result<()> {
-        // We didn't expect `this` response
-        if msg.get_message_type() == CallMeMaybeDeprecatedType::RESPONDER
-            && Some(msg.get_session()) != self.db.get_entry_by_pk(pk)?.get_traversal_session()
+        // We didn't expect `this` response, or it is a copy in other protocol version
+        if !self
+            .db
+            .get_mut_entry_by_pk(pk)?
+            .accept_cmm(cmm.protocol, cmm.initiator, cmm.session)
         {
-            return Err(Error::DbError(DatabaseError::SessionMismatch));
+            telio_log_debug!(
+                "({}) Ignoring {:?} CallMeMaybe from peer {:?}, session: {}",
+                Self::NAME,
+                cmm.protocol,
+                pk,
+                cmm.session
+            );
+            return Ok(());
         }
 
         // Update tx_peer_id, even though the other end (INITIATOR's case) haven't finished the traversal procedure
-        self.db.update_tx_peer_id(pk, msg.get_peer_id())?;
+        if let Some(peer_id) = cmm.peer_id {
+            self.db.update_tx_peer_id(pk, peer_id)?;
+        }
 
         let entry = self.db.get_mut_entry_by_pk(pk)?;
 
-        match msg.get_message_type() {
-            CallMeMaybeDeprecatedType::INITIATOR => {
-                entry
-                    .handle_cmm_init_rx(
-                        msg.get_addrs().into_iter(),
-                        self.stunner.fetch_endpoints().await?.to_vec()?.into_iter(),
-                        msg.get_session(),
-                        &self.udp_socket,
-                        permit,
-                    )
-                    .await?;
-            }
-            CallMeMaybeDeprecatedType::RESPONDER => {
-                // Check if we're in `SentCallMeMaybe` state, if not - ignore packet
-                entry
-                    .start_pinging(msg.get_addrs().into_iter(), &self.udp_socket)
-                    .await?;
-            }
+        if cmm.initiator {
+            entry
+                .handle_cmm_init_rx(
+                    cmm.addrs.into_iter(),
+                    self.stunner.fetch_endpoints().await?.to_vec()?.into_iter(),
+                    cmm.session,
+                    &self.udp_socket,
+                    &self.control,
+                    permit,
+                )
+                .await?;
+        } else if Some(cmm.session) == entry.get_migration_session() {
+            // Connected route is migrating, ping new endpoints in parallel to the old one
+            entry
+                .start_migration_pinging(cmm.addrs.into_iter(), &self.udp_socket)
+                .await?;
+        } else {
+            entry
+                .start_pinging(cmm.addrs.into_iter(), &self.udp_socket)
+                .await?;
         }
 
         Ok(())
@@ -716,27 +1079,49 @@ impl Runtime for State {
                     }, |_| Ok(()))?;
             }
             // Received CallMeMaybe from another peer
-            Some((permit, Some((pk, cmm)))) = wait_for_tx(&self.control.tx, self.control.rx.recv()) => {
-                telio_log_trace!("({}) handle_call_me_maybe(pk: ({:?}), cmm: ({}), permit)", Self::NAME, pk, cmm);
-                self
-                    .handle_call_me_maybe(&pk, &cmm, permit)
+            Some((pk, cmm)) = self.control.current.rx.recv() => {
+                telio_log_trace!("({}) handle_call_me_maybe(pk: ({:?}), cmm: ({}))", Self::NAME, pk, cmm);
+                telio_span!(peer = pk)
+                    .instrument(self.handle_call_me_maybe(&pk, CallMeMaybe::from(&cmm), None))
                     .await
                     .map_or_else(|e| {
                         telio_log_warn!("({}) Error handling rx CallMeMaybe packet: {}", Self::NAME, e.to_string());
                         Ok(())
                     }, |_| Ok(()))?;
             }
+            // Received deprecated CallMeMaybe from peer of older version
+            Some((permit, Some((pk, cmm)))) = wait_for_tx(&self.control.deprecated.tx, self.control.deprecated.rx.recv()) => {
+                self.handle_pending_call_me_maybe().await;
+
+                telio_log_trace!("({}) handle_call_me_maybe(pk: ({:?}), cmm: ({}))", Self::NAME, pk, cmm);
+                telio_span!(peer = pk)
+                    .instrument(self.handle_call_me_maybe(&pk, CallMeMaybe::from(&cmm), Some(permit)))
+                    .await
+                    .map_or_else(|e| {
+                        telio_log_warn!("({}) Error handling rx CallMeMaybeDeprecated packet: {}", Self::NAME, e.to_string());
+                        Ok(())
+                    }, |_| Ok(()))?;
+            }
             // Received Data packet on Itf->WG->UDP_proxy->Path_selector -> UdpHolePunch
             Some((pk, data_msg)) = self.data.rx.recv() => {
                 telio_log_trace!("({}) handle_tx_data_packet(data_msg: ({}), pk: ({:?}))", Self::NAME, data_msg, pk);
-                self
-                    .handle_tx_data_packet(data_msg, &pk)
+                self.handle_tx_data_packet(data_msg, &pk)
                     .await
                     .map_or_else(|e| {
                         telio_log_warn!("({}) Error handling tx Data packet: {}", Self::NAME, e.to_string());
                         Ok(())
                     }, |_| Ok(()))?;
             }
+            // Received PeerRelay frame to be sent over direct connection
+            Some((pk, msg)) = recv_peer_relay(self.peer_relay.as_mut()) => {
+                telio_log_trace!("({}) handle_tx_peer_relay_packet(msg: ({}), pk: ({:?}))", Self::NAME, msg, pk);
+                self.handle_tx_peer_relay_packet(msg, &pk)
+                    .await
+                    .map_or_else(|e| {
+                        telio_log_debug!("({}) Error handling tx PeerRelay packet: {}", Self::NAME, e.to_string());
+                        Ok(())
+                    }, |_| Ok(()))?;
+            }
             // Repeated action
             Ok((name, action)) = self.actions.select_action() => {
                 telio_log_trace!("({}) name: \"{}\", action = actions.select_action()", Self::NAME, name);
@@ -764,6 +1149,50 @@ impl Runtime for State {
     }
 }
 
+/// CallMeMaybe message, received in any of protocol versions
+struct CallMeMaybe {
+    protocol: Protocol,
+    initiator: bool,
+    addrs: Vec<SocketAddr>,
+    session: Session,
+    /// Only deprecated protocol carries peer id
+    peer_id: Option<PeerId>,
+}
+
+impl From<&CallMeMaybeMsg> for CallMeMaybe {
+    fn from(msg: &CallMeMaybeMsg) -> Self {
+        Self {
+            protocol: Protocol::Current,
+            initiator: msg.get_message_type() == CallMeMaybeType::INITIATOR,
+            addrs: msg.get_addrs(),
+            session: msg.get_session(),
+            peer_id: None,
+        }
+    }
+}
+
+impl From<&CallMeMaybeMsgDeprecated> for CallMeMaybe {
+    fn from(msg: &CallMeMaybeMsgDeprecated) -> Self {
+        Self {
+            protocol: Protocol::Deprecated,
+            initiator: msg.get_message_type() == CallMeMaybeDeprecatedType::INITIATOR,
+            addrs: msg.get_addrs(),
+            session: msg.get_session(),
+            peer_id: Some(msg.get_peer_id()),
+        }
+    }
+}
+
+/// Receive from peer relay channel, if present, otherwise wait forever
+async fn recv_peer_relay(
+    peer_relay: Option<&mut Chan<(PublicKey, PeerRelayMsg)>>,
+) -> Option<(PublicKey, PeerRelayMsg)> {
+    match peer_relay {
+        Some(chan) => chan.rx.recv().await,
+        None => pending().await,
+    }
+}
+
 #[cfg(test)]
 mock! {
     pub Stunner {
@@ -778,10 +1207,17 @@ mock! {
 #[cfg(test)]
 type Stunner = MockStunner;
 
+#[cfg(not(test))]
+type IfAddrs = SharedGetIfAddrs;
+
+#[cfg(test)]
+type IfAddrs = MockGetIfAddrs;
+
 #[cfg(test)]
 #[allow(dead_code)]
 mod tests {
     use super::*;
+    use crate::routes::database::session_peer_id;
     use telio_proto::PingType;
     use telio_sockets::SocketPool;
     use tokio::{sync::mpsc::error, time};
@@ -803,25 +1239,83 @@ mod tests {
         SocketAddr,
         SocketPool,
     ) {
-        let Chan {
-            tx: events_tx,
-            rx: events_rx,
-        } = Chan::default();
-
-        let (data_us, data_them) = Chan::pipe();
-        let (control_us, control_them) = Chan::pipe();
-        let socket_pool = SocketPool::default();
-        let punch_sock = socket_pool
-            .new_external_udp(SocketAddr::from((Ipv4Addr::UNSPECIFIED, 0)), None)
-            .await
-            .expect("Cannot create UdpSocket");
+        prepare_udp_hole_punch_with_if_addrs(stunner_fail_cnt, MockGetIfAddrs::default()).await
+    }
 
-        let punch =
-            UdpHolePunch::start(punch_sock, data_us, control_us, events_tx, stunner_fail_cnt)
-                .expect("Cannot create UdpHolePunch obj: ");
+    /// Same as [`prepare_udp_hole_punch`], with mocked local interfaces
+    async fn prepare_udp_hole_punch_with_if_addrs(
+        stunner_fail_cnt: i32,
+        get_if_addrs: MockGetIfAddrs,
+    ) -> (
+        UdpHolePunch,
+        SocketAddr,
+        Rx<(PublicKey, bool)>,
+        Chan<(PublicKey, DataMsg)>,
+        Chan<(PublicKey, CallMeMaybeMsgDeprecated)>,
+        UdpSocket,
+        SocketAddr,
+        SocketPool,
+    ) {
+        // Older peers have no channel for current CallMeMaybe messages
+        let (punch, punch_addr, events_rx, data, _, control, our_sock, our_addr, socket_pool) =
+            prepare_udp_hole_punch_with_protocols(stunner_fail_cnt, get_if_addrs).await;
 
-        // Getting target address of 'UdpHolePunch' obj
-        let mut punch_addr = punch
+        (
+            punch,
+            punch_addr,
+            events_rx,
+            data,
+            control,
+            our_sock,
+            our_addr,
+            socket_pool,
+        )
+    }
+
+    /// Same as [`prepare_udp_hole_punch_with_if_addrs`], with control channels
+    /// of both current and deprecated protocols
+    async fn prepare_udp_hole_punch_with_protocols(
+        stunner_fail_cnt: i32,
+        get_if_addrs: MockGetIfAddrs,
+    ) -> (
+        UdpHolePunch,
+        SocketAddr,
+        Rx<(PublicKey, bool)>,
+        Chan<(PublicKey, DataMsg)>,
+        Chan<(PublicKey, CallMeMaybeMsg)>,
+        Chan<(PublicKey, CallMeMaybeMsgDeprecated)>,
+        UdpSocket,
+        SocketAddr,
+        SocketPool,
+    ) {
+        let Chan {
+            tx: events_tx,
+            rx: events_rx,
+        } = Chan::default();
+
+        let (data_us, data_them) = Chan::pipe();
+        let (control_us, control_them) = Chan::pipe();
+        let (control_deprecated_us, control_deprecated_them) = Chan::pipe();
+        let socket_pool = SocketPool::default();
+        let punch_sock = socket_pool
+            .new_external_udp(SocketAddr::from((Ipv4Addr::UNSPECIFIED, 0)), None)
+            .await
+            .expect("Cannot create UdpSocket");
+
+        let punch = UdpHolePunch::start(
+            punch_sock,
+            data_us,
+            control_us,
+            control_deprecated_us,
+            events_tx,
+            None,
+            get_if_addrs,
+            stunner_fail_cnt,
+        )
+        .expect("Cannot create UdpHolePunch obj: ");
+
+        // Getting target address of 'UdpHolePunch' obj
+        let mut punch_addr = punch
             .get_sock_addr()
             .await
             .expect("Cannot get UdpHolePunch socket address: ");
@@ -843,6 +1337,7 @@ mod tests {
             events_rx,
             data_them,
             control_them,
+            control_deprecated_them,
             our_sock,
             our_addr,
             socket_pool,
@@ -950,6 +1445,87 @@ mod tests {
         .await;
     }
 
+    #[tokio::test]
+    async fn set_timers_at_runtime() {
+        // Rx packet from peer --> Connect
+        // Prolong no data timeout --> Stay connected
+        // Restore no data timeout --> Disconnect
+
+        let our_rx_peer_id = PeerId(9);
+        let our_tx_peer_id = PeerId(1);
+
+        let (punch, punch_addr, mut events_rx, _data_us, control_us, our_sock, our_addr, _) =
+            prepare_udp_hole_punch(0).await;
+
+        let pubkey_list = vec!["REjdn4zY2TFx2AMujoNGPffo9vDiRDXpGG4jHPtx2AY="
+            .parse::<PublicKey>()
+            .unwrap()];
+
+        punch
+            .set_nodes(pubkey_list.clone())
+            .await
+            .expect("Cannot set nodes: ");
+
+        let payload = DataMsg::with_generation(&[0u8; 16], Generation(1u8), our_tx_peer_id)
+            .encode()
+            .expect("Cannot encode DataMsg: ");
+
+        our_sock
+            .send_to(&payload, punch_addr)
+            .await
+            .expect("Cannot send payload: ");
+
+        control_us
+            .tx
+            .send((
+                pubkey_list[0],
+                CallMeMaybeMsgDeprecated::new(
+                    true,
+                    vec![our_addr].into_iter(),
+                    u64::MAX,
+                    our_rx_peer_id,
+                ),
+            ))
+            .await
+            .expect("Cannot send CallMeMaybeMsgDeprecated request");
+
+        assert_eq!(
+            time::timeout(NO_DATA_TIMEOUT * 4, events_rx.recv())
+                .await
+                .expect("Timeout!"),
+            Some((pubkey_list[0], true))
+        );
+
+        // Intervals of repeated actions are replaced as well
+        punch
+            .set_timers(FeatureTraversal {
+                no_data_timeout: NO_DATA_TIMEOUT * 20,
+                check_peer_states_interval: CHECK_PEER_STATES_INTERVAL / 2,
+                ..default_timers()
+            })
+            .await
+            .expect("Cannot set timers: ");
+
+        assert!(time::timeout(NO_DATA_TIMEOUT * 4, events_rx.recv())
+            .await
+            .is_err());
+        assert!(punch.is_reachable(pubkey_list[0]).await);
+
+        punch
+            .set_timers(default_timers())
+            .await
+            .expect("Cannot set timers: ");
+
+        assert_eq!(
+            time::timeout(NO_DATA_TIMEOUT * 4, events_rx.recv())
+                .await
+                .expect("Timeout!"),
+            Some((pubkey_list[0], false))
+        );
+
+        punch.stop().await;
+    }
+
     #[tokio::test]
     async fn states_happy_path() {
         // Add peer
@@ -1893,4 +2469,689 @@ mod tests {
         })
         .await;
     }
+
+    #[tokio::test]
+    async fn migrate_connected_route_on_network_change() {
+        // Add peer
+        // Receive packet and 'Connect' event
+        // Change local interfaces, notify about network change
+        // Receive - Respond migration CMM with fresh host endpoint
+        // Respond to all Pings
+        // Receive 'Connect' event, without any 'Disconnect' in between
+
+        let our_rx_peer_id = PeerId(9);
+        let our_tx_peer_id = PeerId(1);
+        let mut state = State::Idle;
+
+        // Local interface, which will be changed during the test
+        let local_ip = Arc::new(std::sync::Mutex::new(Ipv4Addr::new(10, 0, 0, 1)));
+
+        let mut get_if_addrs = MockGetIfAddrs::new();
+        get_if_addrs.expect_get().returning({
+            let local_ip = local_ip.clone();
+            move || {
+                Ok(vec![if_addrs::Interface {
+                    name: "eth0".to_owned(),
+                    addr: if_addrs::IfAddr::V4(if_addrs::Ifv4Addr {
+                        ip: *local_ip.lock().expect("Cannot lock local ip"),
+                        netmask: Ipv4Addr::new(255, 255, 255, 0),
+                        broadcast: None,
+                    }),
+                }])
+            }
+        });
+
+        let (punch, punch_addr, mut events_rx, mut data_us, mut control_us, our_sock, our_addr, _) =
+            prepare_udp_hole_punch_with_if_addrs(0, get_if_addrs).await;
+
+        let new_endpoint = SocketAddr::new(
+            IpAddr::V4(Ipv4Addr::new(192, 168, 1, 10)),
+            punch_addr.port(),
+        );
+
+        // This will have `rx_peer_id = 1`
+        let pubkey_list = vec!["REjdn4zY2TFx2AMujoNGPffo9vDiRDXpGG4jHPtx2AY="
+            .parse::<PublicKey>()
+            .unwrap()];
+
+        punch
+            .set_nodes(pubkey_list.clone())
+            .await
+            .expect("Cannot set nodes: ");
+
+        // Sending GenDataMsg to 'UdpHolePunch' obj
+        let payload = DataMsg::with_generation(&[0u8; 16], Generation(1u8), our_tx_peer_id)
+            .encode()
+            .expect("Cannot encode DataMsg: ");
+
+        // Send CMM as initiators
+        control_us
+            .tx
+            .send((
+                pubkey_list[0],
+                CallMeMaybeMsgDeprecated::new(
+                    true,
+                    vec![our_addr].into_iter(),
+                    u64::MAX,
+                    our_rx_peer_id,
+                ),
+            ))
+            .await
+            .expect("Cannot send CallMeMaybeMsgDeprecated request");
+
+        our_sock
+            .send_to(&payload, punch_addr)
+            .await
+            .expect("Cannot send payload: ");
+
+        state = State::WaitingConnect;
+
+        // Runtime
+        let _ = tokio::spawn(async move {
+            let timeout = time::sleep(NO_DATA_TIMEOUT * 4);
+            tokio::pin!(timeout);
+
+            let mut rx_buff = [0; MAX_PACKET];
+
+            loop {
+                tokio::select! {
+                    Some((pk, connect)) = events_rx.recv() => {
+                        assert_eq!(pk, pubkey_list[0]);
+                        assert!(connect, "Route was disconnected during migration!");
+
+                        match state {
+                            State::WaitingConnect => {
+                                assert!(punch.is_reachable(pubkey_list[0]).await);
+
+                                // Simulating network change
+                                *local_ip.lock().expect("Cannot lock local ip") = Ipv4Addr::new(192, 168, 1, 10);
+                                punch.notify_network_change().await.expect("Cannot notify about network change: ");
+
+                                state = State::WaitingCMM;
+                                continue;
+                            }
+                            State::WaitingPings => {
+                                // Migration is complete, route has never been disconnected
+                                assert!(punch.is_reachable(pubkey_list[0]).await);
+                                assert!(matches!(
+                                    punch
+                                        .get_node_state(pubkey_list[0])
+                                        .await
+                                        .expect("Cannot get UdpHolePunch node's state: ")
+                                        .0,
+                                    AbsRouteState::ConnectedByActivate(_)));
+                                break;
+                                // Test is a great success!
+                            }
+                            _ => {
+                                assert!(false, "Invalid state! {:?}", state);
+                            }
+                        }
+                    }
+                    Some((pk, cmm)) = control_us.rx.recv() => {
+                        assert_eq!(pk, pubkey_list[0]);
+
+                        // Ignoring initial traversal CMMs
+                        if state != State::WaitingCMM
+                            || cmm.get_message_type() != CallMeMaybeDeprecatedType::INITIATOR
+                        {
+                            continue;
+                        }
+
+                        // Fresh host candidate goes along with STUN result
+                        assert_eq!(vec![new_endpoint, punch_addr], cmm.get_addrs());
+
+                        control_us.tx.send((
+                            pk,
+                            CallMeMaybeMsgDeprecated::new(
+                                false,
+                                vec![our_addr].into_iter(),
+                                cmm.get_session(),
+                                our_rx_peer_id,
+                            ),
+                        ))
+                        .await
+                        .expect("Cannot send CallMeMaybeMsgDeprecated response");
+
+                        state = State::WaitingPings;
+                    }
+                    Ok((len, addr)) = our_sock.recv_from(&mut rx_buff) => {
+                        // Check, if this packet from where is supposed to come
+                        assert_eq!(addr, punch_addr);
+
+                        // Reply to every ping: hole-punch, metric and migration ones
+                        if let Ok(Packet::PingerDeprecated(pinger_msg)) = Packet::decode(&rx_buff[..len]) {
+                            if pinger_msg.get_message_type() == PingType::PING {
+                                let reply = pinger_msg.pong(our_tx_peer_id)
+                                    .expect("Failed to create PingerMsgDeprecated::Pong: ")
+                                    .encode()
+                                    .expect("Failed to encode PingerMsgDeprecated: ");
+
+                                our_sock.send_to(&reply, punch_addr)
+                                    .await
+                                    .expect("Cannot send payload: ");
+                            }
+                        }
+                    }
+                    _ = data_us.rx.recv() => {}
+                    _ = &mut timeout => {
+                        assert!(false, "Timeout!");
+                    }
+                }
+            }
+
+            let _ = punch.stop().await;
+        })
+        .await;
+    }
+
+    /// Wait for a message, matching the filter, skipping all the others
+    async fn recv_matching<T>(rx: &mut Rx<T>, filter: impl Fn(&T) -> bool) -> T {
+        time::timeout(NO_DATA_TIMEOUT * 4, async {
+            loop {
+                match rx.recv().await {
+                    Some(msg) if filter(&msg) => return msg,
+                    Some(_) => continue,
+                    None => panic!("Channel closed"),
+                }
+            }
+        })
+        .await
+        .expect("Timeout!")
+    }
+
+    /// Wait for a packet, matching the filter, skipping all the others
+    async fn recv_packet_matching(
+        sock: &UdpSocket,
+        filter: impl Fn(&Packet) -> bool,
+    ) -> (Packet, SocketAddr) {
+        let mut rx_buff = [0; MAX_PACKET];
+
+        time::timeout(NO_DATA_TIMEOUT * 4, async {
+            loop {
+                let (len, addr) = sock
+                    .recv_from(&mut rx_buff)
+                    .await
+                    .expect("Cannot receive packet");
+
+                if let Ok(packet) = Packet::decode(&rx_buff[..len]) {
+                    if filter(&packet) {
+                        return (packet, addr);
+                    }
+                }
+            }
+        })
+        .await
+        .expect("Timeout!")
+    }
+
+    /// Relay control messages between two nodes, keeping their order, as relay server does
+    fn relay_control(
+        (pk_a, mut a, mut a_deprecated): (
+            PublicKey,
+            Chan<(PublicKey, CallMeMaybeMsg)>,
+            Chan<(PublicKey, CallMeMaybeMsgDeprecated)>,
+        ),
+        (pk_b, mut b, mut b_deprecated): (
+            PublicKey,
+            Chan<(PublicKey, CallMeMaybeMsg)>,
+            Chan<(PublicKey, CallMeMaybeMsgDeprecated)>,
+        ),
+    ) {
+        tokio::spawn(async move {
+            loop {
+                tokio::select! {
+                    biased;
+                    Some((_, msg)) = a.rx.recv() => { let _ = b.tx.send((pk_a, msg)).await; }
+                    Some((_, msg)) = a_deprecated.rx.recv() => { let _ = b_deprecated.tx.send((pk_a, msg)).await; }
+                    Some((_, msg)) = b.rx.recv() => { let _ = a.tx.send((pk_b, msg)).await; }
+                    Some((_, msg)) = b_deprecated.rx.recv() => { let _ = a_deprecated.tx.send((pk_b, msg)).await; }
+                    else => break,
+                }
+            }
+        });
+    }
+
+    #[tokio::test]
+    async fn protocol_new_initiator_new_responder() {
+        // Add peer
+        // Receive CMM in both versions, respond current one
+        // Receive - Respond current Ping
+        // Receive 'Connect' event, exchange data without peer ids
+
+        let (
+            punch,
+            punch_addr,
+            mut events_rx,
+            mut data_us,
+            mut control_us,
+            mut control_deprecated_us,
+            our_sock,
+            our_addr,
+            _pool,
+        ) = prepare_udp_hole_punch_with_protocols(0, MockGetIfAddrs::default()).await;
+
+        let pk = "REjdn4zY2TFx2AMujoNGPffo9vDiRDXpGG4jHPtx2AY="
+            .parse::<PublicKey>()
+            .unwrap();
+
+        punch.set_nodes(vec![pk]).await.expect("Cannot set nodes: ");
+
+        // Peer's protocol is unknown, so both versions are offered within the same session
+        let (_, cmm) = recv_matching(&mut control_us.rx, |_| true).await;
+        let (_, cmm_deprecated) = recv_matching(&mut control_deprecated_us.rx, |_| true).await;
+
+        assert_eq!(cmm.get_message_type(), CallMeMaybeType::INITIATOR);
+        assert_eq!(cmm.get_session(), cmm_deprecated.get_session());
+        assert_eq!(vec![punch_addr], cmm.get_addrs());
+
+        control_us
+            .tx
+            .send((
+                pk,
+                CallMeMaybeMsg::new(false, vec![our_addr].into_iter(), cmm.get_session()),
+            ))
+            .await
+            .expect("Cannot send CallMeMaybeMsg response");
+
+        let (ping, addr) = recv_packet_matching(&our_sock, |p| {
+            matches!(p, Packet::Pinger(_) | Packet::PingerDeprecated(_))
+        })
+        .await;
+        assert_eq!(addr, punch_addr);
+
+        let ping = match ping {
+            Packet::Pinger(ping) => ping,
+            _ => panic!("Deprecated Ping received"),
+        };
+        assert_eq!(ping.get_message_type(), PingType::PING);
+        assert_eq!(ping.get_session(), cmm.get_session());
+        assert_eq!(ping.get_wg_port(), WGPort(punch_addr.port()));
+
+        let pong = ping
+            .pong(WGPort(our_addr.port()))
+            .expect("Failed to create PingerMsg::Pong: ")
+            .encode()
+            .expect("Failed to encode PingerMsg: ");
+        our_sock
+            .send_to(&pong, punch_addr)
+            .await
+            .expect("Cannot send payload: ");
+
+        assert_eq!(
+            recv_matching(&mut events_rx, |(_, connected)| *connected).await,
+            (pk, true)
+        );
+        assert_eq!(
+            punch
+                .get_node_protocol(pk)
+                .await
+                .expect("Cannot get protocol"),
+            Protocol::Current
+        );
+
+        // Node is recognized by the session of CallMeMaybe exchange
+        let peer_id = session_peer_id(cmm.get_session());
+        let payload = DataMsg::with_generation(&[1u8; 16], Generation(1u8), peer_id);
+        our_sock
+            .send_to(
+                &payload.clone().encode().expect("Cannot encode DataMsg: "),
+                punch_addr,
+            )
+            .await
+            .expect("Cannot send payload: ");
+
+        assert_eq!(
+            recv_matching(&mut data_us.rx, |_| true).await,
+            (pk, payload.clone())
+        );
+
+        data_us
+            .tx
+            .send((pk, payload))
+            .await
+            .expect("Cannot send DataMsg");
+
+        match recv_packet_matching(&our_sock, |p| matches!(p, Packet::Data(_))).await {
+            (Packet::Data(data), _) => assert_eq!(data.get_peer_id(), Some(peer_id)),
+            _ => panic!("Invalid packet received!"),
+        }
+
+        punch.stop().await;
+    }
+
+    #[tokio::test]
+    async fn protocol_new_initiator_old_responder() {
+        // Add peer
+        // Receive CMM in both versions, respond deprecated one (as old peer knows nothing else)
+        // Receive - Respond deprecated Ping
+        // Receive 'Connect' event, exchange data with peer ids
+
+        let our_rx_peer_id = PeerId(9);
+
+        let (
+            punch,
+            punch_addr,
+            mut events_rx,
+            mut data_us,
+            mut control_us,
+            mut control_deprecated_us,
+            our_sock,
+            our_addr,
+            _pool,
+        ) = prepare_udp_hole_punch_with_protocols(0, MockGetIfAddrs::default()).await;
+
+        let pk = "REjdn4zY2TFx2AMujoNGPffo9vDiRDXpGG4jHPtx2AY="
+            .parse::<PublicKey>()
+            .unwrap();
+
+        punch.set_nodes(vec![pk]).await.expect("Cannot set nodes: ");
+
+        let (_, cmm) = recv_matching(&mut control_us.rx, |_| true).await;
+        let (_, cmm_deprecated) = recv_matching(&mut control_deprecated_us.rx, |_| true).await;
+
+        assert_eq!(
+            cmm_deprecated.get_message_type(),
+            CallMeMaybeDeprecatedType::INITIATOR
+        );
+        assert_eq!(cmm.get_session(), cmm_deprecated.get_session());
+
+        control_deprecated_us
+            .tx
+            .send((
+                pk,
+                CallMeMaybeMsgDeprecated::new(
+                    false,
+                    vec![our_addr].into_iter(),
+                    cmm_deprecated.get_session(),
+                    our_rx_peer_id,
+                ),
+            ))
+            .await
+            .expect("Cannot send CallMeMaybeMsgDeprecated response");
+
+        let (ping, addr) = recv_packet_matching(&our_sock, |p| {
+            matches!(p, Packet::Pinger(_) | Packet::PingerDeprecated(_))
+        })
+        .await;
+        assert_eq!(addr, punch_addr);
+
+        let ping = match ping {
+            Packet::PingerDeprecated(ping) => ping,
+            _ => panic!("Current Ping received by old peer"),
+        };
+        assert_eq!(ping.get_message_type(), PingType::PING);
+        assert_eq!(ping.get_session(), cmm_deprecated.get_session());
+        assert_eq!(ping.get_peer_id(), our_rx_peer_id);
+
+        let pong = ping
+            .pong(cmm_deprecated.get_peer_id())
+            .expect("Failed to create PingerMsgDeprecated::Pong: ")
+            .encode()
+            .expect("Failed to encode PingerMsgDeprecated: ");
+        our_sock
+            .send_to(&pong, punch_addr)
+            .await
+            .expect("Cannot send payload: ");
+
+        assert_eq!(
+            recv_matching(&mut events_rx, |(_, connected)| *connected).await,
+            (pk, true)
+        );
+        assert_eq!(
+            punch
+                .get_node_protocol(pk)
+                .await
+                .expect("Cannot get protocol"),
+            Protocol::Deprecated
+        );
+
+        data_us
+            .tx
+            .send((pk, DataMsg::new(&[1u8; 16])))
+            .await
+            .expect("Cannot send DataMsg");
+
+        match recv_packet_matching(&our_sock, |p| matches!(p, Packet::Data(_))).await {
+            (Packet::Data(data), _) => assert_eq!(data.get_peer_id(), Some(our_rx_peer_id)),
+            _ => panic!("Invalid packet received!"),
+        }
+
+        punch.stop().await;
+    }
+
+    #[tokio::test]
+    async fn protocol_new_initiator_to_new_responder() {
+        // Add peer
+        // Send CMM in both versions within the same session, as new peer does
+        // Receive only current CMM response and current hole punch Ping
+        // Send data with peer id of the session, receive 'Connect' event
+
+        let (
+            punch,
+            punch_addr,
+            mut events_rx,
+            mut data_us,
+            mut control_us,
+            mut control_deprecated_us,
+            our_sock,
+            our_addr,
+            _pool,
+        ) = prepare_udp_hole_punch_with_protocols(0, MockGetIfAddrs::default()).await;
+
+        let pk = "REjdn4zY2TFx2AMujoNGPffo9vDiRDXpGG4jHPtx2AY="
+            .parse::<PublicKey>()
+            .unwrap();
+        let session = u64::MAX;
+
+        punch.set_nodes(vec![pk]).await.expect("Cannot set nodes: ");
+
+        control_us
+            .tx
+            .send((
+                pk,
+                CallMeMaybeMsg::new(true, vec![our_addr].into_iter(), session),
+            ))
+            .await
+            .expect("Cannot send CallMeMaybeMsg request");
+        control_deprecated_us
+            .tx
+            .send((
+                pk,
+                CallMeMaybeMsgDeprecated::new(true, vec![our_addr].into_iter(), session, PeerId(9)),
+            ))
+            .await
+            .expect("Cannot send CallMeMaybeMsgDeprecated request");
+
+        let (_, cmm) = recv_matching(&mut control_us.rx, |(_, cmm)| {
+            cmm.get_message_type() == CallMeMaybeType::RESPONDER
+        })
+        .await;
+        assert_eq!(cmm.get_session(), session);
+        assert_eq!(vec![punch_addr], cmm.get_addrs());
+
+        // Hole punch Ping
+        let (ping, _) = recv_packet_matching(&our_sock, |p| {
+            matches!(p, Packet::Pinger(_) | Packet::PingerDeprecated(_))
+        })
+        .await;
+        assert!(
+            matches!(ping, Packet::Pinger(ping) if ping.get_session() == 0),
+            "Invalid Ping received!"
+        );
+
+        // Data of other session is not attributed to the node, even from its endpoint
+        let foreign = DataMsg::with_generation(&[2u8; 16], Generation(1u8), PeerId(0));
+        let payload =
+            DataMsg::with_generation(&[1u8; 16], Generation(1u8), session_peer_id(session));
+        for msg in [&foreign, &payload] {
+            our_sock
+                .send_to(
+                    &msg.clone().encode().expect("Cannot encode DataMsg: "),
+                    punch_addr,
+                )
+                .await
+                .expect("Cannot send payload: ");
+        }
+
+        assert_eq!(
+            recv_matching(&mut events_rx, |(_, connected)| *connected).await,
+            (pk, true)
+        );
+        assert_eq!(
+            recv_matching(&mut data_us.rx, |_| true).await,
+            (pk, payload)
+        );
+
+        // Deprecated copy of request was not answered
+        while let Ok((_, cmm)) = control_deprecated_us.rx.try_recv() {
+            assert_eq!(cmm.get_message_type(), CallMeMaybeDeprecatedType::INITIATOR);
+        }
+
+        punch.stop().await;
+    }
+
+    #[tokio::test]
+    async fn protocol_old_initiator_to_new_responder() {
+        // Add peer
+        // Send deprecated CMM only, as old peer does
+        // Receive only deprecated CMM response and deprecated hole punch Ping
+
+        let our_rx_peer_id = PeerId(9);
+
+        let (
+            punch,
+            _punch_addr,
+            _events_rx,
+            _data_us,
+            mut control_us,
+            mut control_deprecated_us,
+            our_sock,
+            our_addr,
+            _pool,
+        ) = prepare_udp_hole_punch_with_protocols(0, MockGetIfAddrs::default()).await;
+
+        // This will have `rx_peer_id = 1`
+        let pk = "REjdn4zY2TFx2AMujoNGPffo9vDiRDXpGG4jHPtx2AY="
+            .parse::<PublicKey>()
+            .unwrap();
+        let session = u64::MAX;
+
+        punch.set_nodes(vec![pk]).await.expect("Cannot set nodes: ");
+
+        control_deprecated_us
+            .tx
+            .send((
+                pk,
+                CallMeMaybeMsgDeprecated::new(
+                    true,
+                    vec![our_addr].into_iter(),
+                    session,
+                    our_rx_peer_id,
+                ),
+            ))
+            .await
+            .expect("Cannot send CallMeMaybeMsgDeprecated request");
+
+        let (_, cmm) = recv_matching(&mut control_deprecated_us.rx, |(_, cmm)| {
+            cmm.get_message_type() == CallMeMaybeDeprecatedType::RESPONDER
+        })
+        .await;
+        assert_eq!(cmm.get_session(), session);
+        assert_eq!(cmm.get_peer_id(), PeerId(1));
+
+        let (ping, _) = recv_packet_matching(&our_sock, |p| {
+            matches!(p, Packet::Pinger(_) | Packet::PingerDeprecated(_))
+        })
+        .await;
+        assert!(
+            matches!(ping, Packet::PingerDeprecated(ping) if ping.get_peer_id() == our_rx_peer_id),
+            "Invalid Ping received!"
+        );
+
+        assert_eq!(
+            punch
+                .get_node_protocol(pk)
+                .await
+                .expect("Cannot get protocol"),
+            Protocol::Deprecated
+        );
+
+        // Current CMMs are never answered by old peer
+        while let Ok((_, cmm)) = control_us.rx.try_recv() {
+            assert_eq!(cmm.get_message_type(), CallMeMaybeType::INITIATOR);
+        }
+
+        punch.stop().await;
+    }
+
+    #[tokio::test]
+    async fn protocol_new_peers_connect() {
+        // Two route instances, connected through relay
+        // Both of them receive 'Connect' event, speaking current protocol
+        // Data flows between them
+
+        let (punch_a, _, mut events_a, mut data_a, control_a, control_deprecated_a, _, _, _pool_a) =
+            prepare_udp_hole_punch_with_protocols(0, MockGetIfAddrs::default()).await;
+        let (punch_b, _, mut events_b, mut data_b, control_b, control_deprecated_b, _, _, _pool_b) =
+            prepare_udp_hole_punch_with_protocols(0, MockGetIfAddrs::default()).await;
+
+        let pk_a = "REjdn4zY2TFx2AMujoNGPffo9vDiRDXpGG4jHPtx2AY="
+            .parse::<PublicKey>()
+            .unwrap();
+        let pk_b = "GB2q5nJA3PUm/U5u3PAtLLmsLQ7nwCYo7YzfyYcjWzY="
+            .parse::<PublicKey>()
+            .unwrap();
+
+        relay_control(
+            (pk_a, control_a, control_deprecated_a),
+            (pk_b, control_b, control_deprecated_b),
+        );
+
+        punch_a
+            .set_nodes(vec![pk_b])
+            .await
+            .expect("Cannot set nodes: ");
+        punch_b
+            .set_nodes(vec![pk_a])
+            .await
+            .expect("Cannot set nodes: ");
+
+        assert_eq!(
+            recv_matching(&mut events_a, |(_, c)| *c).await,
+            (pk_b, true)
+        );
+        assert_eq!(
+            recv_matching(&mut events_b, |(_, c)| *c).await,
+            (pk_a, true)
+        );
+
+        assert_eq!(
+            punch_a
+                .get_node_protocol(pk_b)
+                .await
+                .expect("Cannot get protocol"),
+            Protocol::Current
+        );
+        assert_eq!(
+            punch_b
+                .get_node_protocol(pk_a)
+                .await
+                .expect("Cannot get protocol"),
+            Protocol::Current
+        );
+
+        data_a
+            .tx
+            .send((pk_b, DataMsg::new(&[1u8; 16])))
+            .await
+            .expect("Cannot send DataMsg");
+
+        let (pk, data) = recv_matching(&mut data_b.rx, |_| true).await;
+        assert_eq!(pk, pk_a);
+        assert_eq!(data.get_payload(), &[1u8; 16]);
+
+        punch_a.stop().await;
+        punch_b.stop().await;
+    }
 }
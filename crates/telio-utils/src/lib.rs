@@ -4,6 +4,12 @@
 /// export utils
 pub mod utils;
 
+/// Structured logging: spans, per module filters and JSON lines
+pub mod logging;
+
+/// Redaction of keys, addresses and hostnames in log messages
+pub mod redact;
+
 /// Utils for rust std map types
 pub mod map;
 pub use map::*;
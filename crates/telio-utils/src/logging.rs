@@ -0,0 +1,361 @@
+use std::{
+    cell::RefCell,
+    fmt::Write as _,
+    fs::{self, File, OpenOptions},
+    future::Future,
+    io::{self, Write},
+    marker::PhantomData,
+    path::{Path, PathBuf},
+    pin::Pin,
+    str::FromStr,
+    sync::Arc,
+    task::{Context, Poll},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use log::{Level, LevelFilter, Record};
+
+use crate::redact::{privacy, redact};
+use serde_json::{Map, Value};
+use thiserror::Error as ThisError;
+
+/// Key/value field attached to log records
+pub type Field = (&'static str, String);
+
+thread_local! {
+    static SPANS: RefCell<Vec<Span>> = RefCell::new(Vec::new());
+}
+
+/// Set of fields attached to every record logged while the span is entered
+#[derive(Clone, Debug, Default)]
+pub struct Span(Arc<Vec<Field>>);
+
+impl Span {
+    /// Create span with given fields, prefer [telio_span!](crate::telio_span) macro
+    pub fn new(fields: Vec<Field>) -> Self {
+        Self(Arc::new(fields))
+    }
+
+    /// Enter span on the current thread until the guard is dropped.
+    ///
+    /// The guard must not be held across `.await`, use [Span::instrument] for async code.
+    pub fn enter(&self) -> SpanGuard {
+        SPANS.with(|spans| spans.borrow_mut().push(self.clone()));
+        SpanGuard(PhantomData)
+    }
+
+    /// Enter span each time the future is polled
+    pub fn instrument<F: Future>(&self, future: F) -> Instrumented<F> {
+        Instrumented {
+            span: self.clone(),
+            future: Box::pin(future),
+        }
+    }
+}
+
+/// Leaves the span when dropped
+pub struct SpanGuard(PhantomData<*const ()>);
+
+impl Drop for SpanGuard {
+    fn drop(&mut self) {
+        SPANS.with(|spans| spans.borrow_mut().pop());
+    }
+}
+
+/// Future running within a span, created by [Span::instrument]
+pub struct Instrumented<F> {
+    span: Span,
+    future: Pin<Box<F>>,
+}
+
+impl<F: Future> Future for Instrumented<F> {
+    type Output = F::Output;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let _guard = self.span.enter();
+        self.future.as_mut().poll(cx)
+    }
+}
+
+/// Fields of all spans entered on the current thread, outermost first
+pub fn current_fields() -> Vec<Field> {
+    SPANS.with(|spans| {
+        spans
+            .borrow()
+            .iter()
+            .flat_map(|span| span.0.iter().cloned())
+            .collect()
+    })
+}
+
+/// Create a [Span](crate::logging::Span) with given fields
+///
+/// ```
+/// # use telio_utils::telio_span;
+/// let peer = "peer-key";
+/// let span = telio_span!(peer = peer, attempt = 3);
+/// let _guard = span.enter();
+/// ```
+#[macro_export]
+macro_rules! telio_span {
+    ($($key:ident = $value:expr),* $(,)?) => {
+        $crate::logging::Span::new(vec![$((stringify!($key), $value.to_string())),*])
+    };
+}
+
+/// Error returned for malformed log filter
+#[derive(Debug, ThisError, PartialEq, Eq)]
+#[error("Invalid log filter directive: {0}")]
+pub struct InvalidFilter(String);
+
+/// Per module log level filter.
+///
+/// Parsed from comma separated directives, like `info,telio_relay=trace,telio_traversal=debug`.
+/// Directive without target sets the default level. Target matches the module path and all its
+/// submodules, the most specific directive wins.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LogFilter {
+    default: LevelFilter,
+    targets: Vec<(String, LevelFilter)>,
+}
+
+impl LogFilter {
+    /// Create filter with only the default level
+    pub fn new(default: LevelFilter) -> Self {
+        Self {
+            default,
+            targets: Vec::new(),
+        }
+    }
+
+    /// Parse directives, overriding the `default` level if given one without target
+    pub fn parse(default: LevelFilter, directives: &str) -> Result<Self, InvalidFilter> {
+        let mut filter = Self::new(default);
+        for directive in directives
+            .split(',')
+            .map(str::trim)
+            .filter(|d| !d.is_empty())
+        {
+            match directive.split_once('=') {
+                Some((target, level)) => {
+                    let level = LevelFilter::from_str(level.trim())
+                        .map_err(|_| InvalidFilter(directive.to_owned()))?;
+                    let target = target.trim().to_owned();
+                    filter.targets.retain(|(t, _)| *t != target);
+                    filter.targets.push((target, level));
+                }
+                None => {
+                    filter.default = LevelFilter::from_str(directive)
+                        .map_err(|_| InvalidFilter(directive.to_owned()))?;
+                }
+            }
+        }
+        Ok(filter)
+    }
+
+    /// Check if record of given target and level passes the filter
+    pub fn enabled(&self, target: &str, level: Level) -> bool {
+        level <= self.level(target)
+    }
+
+    /// Most verbose level enabled for any target
+    pub fn max_level(&self) -> LevelFilter {
+        self.targets
+            .iter()
+            .map(|(_, level)| *level)
+            .fold(self.default, LevelFilter::max)
+    }
+
+    fn level(&self, target: &str) -> LevelFilter {
+        self.targets
+            .iter()
+            .filter(|(prefix, _)| {
+                target == prefix
+                    || (target.starts_with(prefix.as_str())
+                        && target[prefix.len()..].starts_with("::"))
+            })
+            .max_by_key(|(prefix, _)| prefix.len())
+            .map_or(self.default, |(_, level)| *level)
+    }
+}
+
+/// Format record as `file:line message key=value...`, with redacted fields of entered spans
+pub fn format_text(record: &Record) -> String {
+    let mut line = format!(
+        "{:#?}:{:#?} {}",
+        record.file().unwrap_or("unknown file"),
+        record.line().unwrap_or(0),
+        record.args()
+    );
+    for (key, value) in current_fields() {
+        let _ = write!(line, " {}={}", key, redact(&value, privacy()));
+    }
+    line
+}
+
+/// Format record as single line JSON object, with redacted fields of entered spans
+pub fn format_json(record: &Record) -> String {
+    let fields: Map<String, Value> = current_fields()
+        .into_iter()
+        .map(|(key, value)| (key.to_owned(), Value::String(redact(&value, privacy()))))
+        .collect();
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or_default();
+
+    serde_json::json!({
+        "timestamp": timestamp,
+        "level": record.level().as_str(),
+        "target": record.target(),
+        "file": record.file(),
+        "line": record.line(),
+        "message": record.args().to_string(),
+        "fields": fields,
+    })
+    .to_string()
+}
+
+/// Log file rotated by size.
+///
+/// When the file would exceed `max_size`, it is renamed to `<path>.1`, older files are shifted
+/// up to `<path>.<max_files>` and the oldest one is removed.
+pub struct RotatingFile {
+    path: PathBuf,
+    max_size: u64,
+    max_files: u32,
+    file: File,
+    size: u64,
+}
+
+impl RotatingFile {
+    /// Open file for appending
+    pub fn open(path: impl AsRef<Path>, max_size: u64, max_files: u32) -> io::Result<Self> {
+        let path = path.as_ref().to_owned();
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let size = file.metadata()?.len();
+        Ok(Self {
+            path,
+            max_size,
+            max_files,
+            file,
+            size,
+        })
+    }
+
+    /// Append line to the file, rotating it first if needed
+    pub fn write_line(&mut self, line: &str) -> io::Result<()> {
+        let len = line.len() as u64 + 1;
+        if self.size > 0 && self.size + len > self.max_size {
+            self.rotate()?;
+        }
+        self.file.write_all(line.as_bytes())?;
+        self.file.write_all(b"\n")?;
+        self.size += len;
+        Ok(())
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        for i in (1..self.max_files).rev() {
+            let from = self.rotated(i);
+            if from.exists() {
+                fs::rename(from, self.rotated(i + 1))?;
+            }
+        }
+        if self.max_files > 0 {
+            fs::rename(&self.path, self.rotated(1))?;
+        }
+        self.file = File::create(&self.path)?;
+        self.size = 0;
+        Ok(())
+    }
+
+    fn rotated(&self, index: u32) -> PathBuf {
+        let mut path = self.path.clone().into_os_string();
+        path.push(format!(".{}", index));
+        path.into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn filter_picks_most_specific_target() {
+        let filter = LogFilter::parse(
+            LevelFilter::Info,
+            "telio_relay=trace, telio_relay::http=warn,telio_traversal=debug",
+        )
+        .unwrap();
+
+        assert!(filter.enabled("telio_relay", Level::Trace));
+        assert!(filter.enabled("telio_relay::derp", Level::Trace));
+        assert!(!filter.enabled("telio_relay::http", Level::Info));
+        assert!(filter.enabled("telio_traversal::cross_ping_check", Level::Debug));
+        assert!(!filter.enabled("telio_relay_extra", Level::Debug));
+        assert!(filter.enabled("telio_wg", Level::Info));
+        assert!(!filter.enabled("telio_wg", Level::Debug));
+        assert_eq!(filter.max_level(), LevelFilter::Trace);
+    }
+
+    #[test]
+    fn filter_default_level_can_be_overridden() {
+        let filter = LogFilter::parse(LevelFilter::Info, "error,telio_nurse=debug").unwrap();
+        assert!(!filter.enabled("telio_wg", Level::Warn));
+        assert!(filter.enabled("telio_nurse", Level::Debug));
+
+        assert_eq!(
+            LogFilter::parse(LevelFilter::Info, "telio_relay=loud"),
+            Err(InvalidFilter("telio_relay=loud".to_owned()))
+        );
+    }
+
+    #[test]
+    fn json_line_contains_span_fields() {
+        let outer = telio_span!(peer = "abc");
+        let _outer = outer.enter();
+        let line = {
+            let _inner = telio_span!(attempt = 2).enter();
+            format_json(
+                &Record::builder()
+                    .args(format_args!("hello"))
+                    .level(Level::Debug)
+                    .target("telio_nurse::heartbeat")
+                    .file(Some("heartbeat.rs"))
+                    .line(Some(7))
+                    .build(),
+            )
+        };
+
+        let value: Value = serde_json::from_str(&line).unwrap();
+        assert_eq!(value["level"], "DEBUG");
+        assert_eq!(value["target"], "telio_nurse::heartbeat");
+        assert_eq!(value["message"], "hello");
+        assert_eq!(value["fields"]["peer"], "abc");
+        assert_eq!(value["fields"]["attempt"], "2");
+        assert_eq!(current_fields(), vec![("peer", "abc".to_owned())]);
+    }
+
+    #[test]
+    fn file_is_rotated_by_size() {
+        let path = std::env::temp_dir().join(format!("telio-log-{}.log", std::process::id()));
+        let mut file = RotatingFile::open(&path, 10, 2).unwrap();
+        for line in ["first", "second", "third", "fourth"] {
+            file.write_line(line).unwrap();
+        }
+
+        let read = |index: u32| fs::read_to_string(file.rotated(index)).unwrap_or_default();
+        let (rotated1, rotated2, rotated3) = (read(1), read(2), read(3));
+        let current = fs::read_to_string(&path).unwrap();
+        for index in 1..=2 {
+            let _ = fs::remove_file(file.rotated(index));
+        }
+        let _ = fs::remove_file(&path);
+
+        assert_eq!(current, "fourth\n");
+        assert_eq!(rotated1, "third\n");
+        assert_eq!(rotated2, "second\n");
+        assert_eq!(rotated3, "");
+    }
+}
@@ -0,0 +1,281 @@
+use std::{
+    collections::hash_map::RandomState,
+    fmt,
+    hash::{BuildHasher, Hash, Hasher},
+    net::{IpAddr, Ipv4Addr},
+    sync::atomic::{AtomicU8, Ordering},
+};
+
+/// How much of sensitive data is left in log messages
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub enum PrivacyLevel {
+    /// Messages are logged as they are
+    Off = 0,
+    /// Keys are hashed, IPs truncated to their network part, hostnames hashed except for TLD
+    Standard = 1,
+    /// Keys, IPs and hostnames are all replaced by their hashes
+    Strict = 2,
+}
+
+static PRIVACY: AtomicU8 = AtomicU8::new(PrivacyLevel::Off as u8);
+
+lazy_static::lazy_static! {
+    // Random per process, so hashes can be correlated within a session only
+    static ref SALT: RandomState = RandomState::new();
+}
+
+/// Set privacy level of all subsequent log messages
+pub fn set_privacy(level: PrivacyLevel) {
+    PRIVACY.store(level as u8, Ordering::Relaxed);
+}
+
+/// Current privacy level of log messages
+pub fn privacy() -> PrivacyLevel {
+    match PRIVACY.load(Ordering::Relaxed) {
+        1 => PrivacyLevel::Standard,
+        2 => PrivacyLevel::Strict,
+        _ => PrivacyLevel::Off,
+    }
+}
+
+/// Log message, redacted according to the current privacy level when displayed.
+///
+/// Used by `telio_log_*` macros, so call sites do not need to care about redaction.
+pub struct Redacted<'a>(pub fmt::Arguments<'a>);
+
+impl fmt::Display for Redacted<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match privacy() {
+            PrivacyLevel::Off => f.write_fmt(self.0),
+            level => f.write_str(&redact(&self.0.to_string(), level)),
+        }
+    }
+}
+
+/// Replace keys, IPs and hostnames in the message, leaving everything else untouched
+pub fn redact(message: &str, level: PrivacyLevel) -> String {
+    if level == PrivacyLevel::Off {
+        return message.to_owned();
+    }
+
+    let mut redacted = String::with_capacity(message.len());
+    let mut rest = message;
+    while let Some(start) = rest.find(is_token_char) {
+        redacted.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let end = rest.find(|c| !is_token_char(c)).unwrap_or(rest.len());
+        redact_token(&rest[..end], level, &mut redacted);
+        rest = &rest[end..];
+    }
+    redacted.push_str(rest);
+    redacted
+}
+
+fn is_token_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || "+/=.:-_%".contains(c)
+}
+
+fn redact_token(token: &str, level: PrivacyLevel, out: &mut String) {
+    // Key ends with the only `=` it contains, so splitting after `=` keeps keys whole
+    for part in token.split_inclusive('=') {
+        if is_key(part) {
+            out.push_str(&format!("key#{}", hash(part)));
+            continue;
+        }
+
+        for part in part.split_inclusive(is_separator) {
+            let (part, separator) = match part.char_indices().last() {
+                Some((i, c)) if is_separator(c) => part.split_at(i),
+                _ => (part, ""),
+            };
+            // Trailing punctuation is part of the sentence, not of the address
+            let trimmed = part.trim_end_matches(|c| c == '.' || c == ':');
+            out.push_str(&redact_address(trimmed, level).unwrap_or_else(|| trimmed.to_owned()));
+            out.push_str(&part[trimmed.len()..]);
+            out.push_str(separator);
+        }
+    }
+}
+
+fn is_separator(c: char) -> bool {
+    c == '/' || c == '+' || c == '='
+}
+
+/// Base64 encoded key, or its shortened form as printed by `Debug`, like `AbCd...wXy=`,
+/// or hex encoded key, as written in UAPI commands
+fn is_key(token: &str) -> bool {
+    let is_base64 = |s: &str| {
+        s.chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '+' || c == '/' || c == '=')
+    };
+    (token.len() == 44 && token.ends_with('=') && is_base64(token))
+        || (token.len() == 11
+            && &token[4..7] == "..."
+            && is_base64(&token[..4])
+            && is_base64(&token[7..]))
+        || (token.len() == 64 && token.chars().all(|c| c.is_ascii_hexdigit()))
+}
+
+fn redact_address(token: &str, level: PrivacyLevel) -> Option<String> {
+    if let Ok(ip) = token.parse::<IpAddr>() {
+        return Some(redact_ip(ip, level));
+    }
+    if let Some((host, port)) = token.rsplit_once(':') {
+        if !port.is_empty() && port.chars().all(|c| c.is_ascii_digit()) {
+            if let Some(host) = redact_address(host, level) {
+                return Some(format!("{}:{}", host, port));
+            }
+        }
+    }
+    if is_hostname(token) {
+        return Some(match (level, token.rsplit_once('.')) {
+            (PrivacyLevel::Standard, Some((_, tld))) => format!("host#{}.{}", hash(token), tld),
+            _ => format!("host#{}", hash(token)),
+        });
+    }
+    None
+}
+
+fn redact_ip(ip: IpAddr, level: PrivacyLevel) -> String {
+    match (level, ip) {
+        (PrivacyLevel::Standard, IpAddr::V4(ip)) if !is_local(ip.into()) => {
+            let [a, b, _, _] = ip.octets();
+            format!("{}.{}.x.x", a, b)
+        }
+        (PrivacyLevel::Standard, IpAddr::V6(ip)) if !is_local(ip.into()) => {
+            let [a, b, c, ..] = ip.segments();
+            format!("{:x}:{:x}:{:x}:x", a, b, c)
+        }
+        (PrivacyLevel::Standard, ip) => ip.to_string(),
+        _ => format!("ip#{}", hash(&ip)),
+    }
+}
+
+// Addresses identical on all machines, nothing to hide in those
+fn is_local(ip: IpAddr) -> bool {
+    ip.is_loopback() || ip.is_unspecified() || ip == IpAddr::V4(Ipv4Addr::BROADCAST)
+}
+
+fn is_hostname(token: &str) -> bool {
+    // Dotted identifiers like `features.logging` or `Config.toml` are much more common in the
+    // messages than hosts, so only country code and well known generic TLDs are considered
+    const GENERIC_TLDS: &[&str] = &[
+        "com", "net", "org", "info", "biz", "edu", "gov", "mil", "int", "app", "dev", "cloud",
+        "vpn", "arpa", "local", "lan", "home", "internal",
+    ];
+    // Extensions of files, which show up in the messages much more often than hosts would
+    const FILE_EXTENSIONS: &[&str] = &["rs", "so", "sh", "md", "py", "js", "ts", "gz"];
+
+    let labels: Vec<&str> = token.split('.').collect();
+    let tld = labels.last().copied().unwrap_or_default();
+    labels.len() >= 2
+        && labels.iter().all(|label| {
+            !label.is_empty()
+                && !label.starts_with('-')
+                && label
+                    .chars()
+                    .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-')
+        })
+        && ((tld.len() == 2 && tld.chars().all(|c| c.is_ascii_lowercase()))
+            || GENERIC_TLDS.contains(&tld))
+        && !FILE_EXTENSIONS.contains(&tld)
+}
+
+fn hash<T: Hash + ?Sized>(value: &T) -> String {
+    let mut hasher = SALT.build_hasher();
+    value.hash(&mut hasher);
+    format!("{:016x}", hasher.finish())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY: &str = "REjdn4zY2TFx2AMujoNGPffo9vDiRDXpGG4jHPtx2AY=";
+
+    #[test]
+    fn off_leaves_message_untouched() {
+        let message = format!("Peer {} at 10.5.1.2:51820 via de1.example.com", KEY);
+        assert_eq!(redact(&message, PrivacyLevel::Off), message);
+    }
+
+    #[test]
+    fn standard_hashes_keys_and_truncates_addresses() {
+        let message = format!(
+            "Peer public_key={} ({:?}) endpoint=10.5.1.2:51820, [2001:db8:aa:bb::1]:80 via de1.example.com. Ping to 127.0.0.1",
+            KEY, "REjd...2AY="
+        );
+        let redacted = redact(&message, PrivacyLevel::Standard);
+        let key = hash(KEY);
+        let short_key = hash("REjd...2AY=");
+
+        assert_eq!(
+            redacted,
+            format!(
+                "Peer public_key=key#{} (\"key#{}\") endpoint=10.5.x.x:51820, [2001:db8:aa:x]:80 via host#{}.com. Ping to 127.0.0.1",
+                key,
+                short_key,
+                hash("de1.example.com")
+            )
+        );
+        // Same value gets the same hash within session
+        assert_eq!(redact(KEY, PrivacyLevel::Standard), format!("key#{}", key));
+    }
+
+    #[test]
+    fn strict_hashes_everything() {
+        let redacted = redact(
+            "connecting to https://derp.example.com:8765/derp from 100.64.0.2",
+            PrivacyLevel::Strict,
+        );
+        assert_eq!(
+            redacted,
+            format!(
+                "connecting to https://host#{}:8765/derp from ip#{}",
+                hash("derp.example.com"),
+                hash(&"100.64.0.2".parse::<IpAddr>().unwrap())
+            )
+        );
+    }
+
+    #[test]
+    fn non_sensitive_tokens_are_kept() {
+        let message = "src/device/mod.rs:42 telio_relay::derp version 1.2.3 took 0.5s";
+        assert_eq!(redact(message, PrivacyLevel::Strict), message);
+    }
+
+    #[test]
+    fn hex_keys_of_uapi_are_hashed() {
+        let hex = "a8dd4d5a37e2c4a61c10f1fd9e4e5ab3b3f4bdd6fe2bcd0c5b8e4f1f2a1e9d70";
+        let message = format!("set=1\nprivate_key={}\npreshared_key={}\n", hex, hex);
+        assert_eq!(
+            redact(&message, PrivacyLevel::Standard),
+            format!(
+                "set=1\nprivate_key=key#{0}\npreshared_key=key#{0}\n",
+                hash(hex)
+            )
+        );
+    }
+
+    #[test]
+    fn dotted_identifiers_are_not_hosts() {
+        let message = "features.logging self.config peer.endpoint Config.toml journal.jsonl \
+            telio.Node event.type v1.2.3-beta e.g. meshnet.enabled";
+        assert_eq!(redact(message, PrivacyLevel::Strict), message);
+
+        for host in [
+            "de1.example.com",
+            "nordvpn.com",
+            "api.example.io",
+            "router.lan",
+        ] {
+            assert!(is_hostname(host), "{}", host);
+        }
+    }
+
+    #[test]
+    fn hash_keeps_full_width() {
+        assert_eq!(hash(KEY).len(), 16);
+    }
+}
@@ -4,36 +4,56 @@
 /// wrapping log::trace and adding more information
 #[macro_export]
 macro_rules! telio_log_trace {
-       ( $msg: expr) => {log::trace!($msg)};
-       ( $format: expr, $($arg:tt)+) => {log::trace!($format,  $($arg)+)};
+       ( $($key: ident = $value: expr),+ ; $($rest: tt)+ ) => {{
+           let _fields = $crate::telio_span!($($key = $value),+).enter();
+           $crate::telio_log_trace!($($rest)+)
+       }};
+       ( $msg: expr) => {log::trace!("{}", $crate::redact::Redacted(format_args!($msg)))};
+       ( $format: expr, $($arg:tt)+) => {log::trace!("{}", $crate::redact::Redacted(format_args!($format, $($arg)+)))};
 }
 
 /// wrapping log::debug and adding more information
 #[macro_export]
 macro_rules! telio_log_debug {
-       ( $msg: expr) => {log::debug!($msg)};
-       ( $format: expr, $($arg:tt)+) => { log::debug!( $format,  $($arg)+) };
+       ( $($key: ident = $value: expr),+ ; $($rest: tt)+ ) => {{
+           let _fields = $crate::telio_span!($($key = $value),+).enter();
+           $crate::telio_log_debug!($($rest)+)
+       }};
+       ( $msg: expr) => {log::debug!("{}", $crate::redact::Redacted(format_args!($msg)))};
+       ( $format: expr, $($arg:tt)+) => { log::debug!("{}", $crate::redact::Redacted(format_args!($format, $($arg)+))) };
 }
 
 /// wrapping log::info and adding more information
 #[macro_export]
 macro_rules! telio_log_info {
-       ( $msg: expr) => {log::info!($msg)};
-       ( $format: expr, $($arg:tt)+) => { log::info!( $format,  $($arg)+) };
+       ( $($key: ident = $value: expr),+ ; $($rest: tt)+ ) => {{
+           let _fields = $crate::telio_span!($($key = $value),+).enter();
+           $crate::telio_log_info!($($rest)+)
+       }};
+       ( $msg: expr) => {log::info!("{}", $crate::redact::Redacted(format_args!($msg)))};
+       ( $format: expr, $($arg:tt)+) => { log::info!("{}", $crate::redact::Redacted(format_args!($format, $($arg)+))) };
 }
 
 /// wrapping log::warn and adding more information
 #[macro_export]
 macro_rules! telio_log_warn {
-       ( $msg: expr) => {log::warn!($msg)};
-       ( $format: expr, $($arg:tt)+) => { log::warn!( $format,  $($arg)+) };
+       ( $($key: ident = $value: expr),+ ; $($rest: tt)+ ) => {{
+           let _fields = $crate::telio_span!($($key = $value),+).enter();
+           $crate::telio_log_warn!($($rest)+)
+       }};
+       ( $msg: expr) => {log::warn!("{}", $crate::redact::Redacted(format_args!($msg)))};
+       ( $format: expr, $($arg:tt)+) => { log::warn!("{}", $crate::redact::Redacted(format_args!($format, $($arg)+))) };
 }
 
 /// wrapping log::error and adding more information
 #[macro_export]
 macro_rules! telio_log_error {
-       ( $msg: expr) => {log::error!($msg)};
-       ( $format: expr, $($arg:tt)+) => { log::error!( $format,  $($arg)+) };
+       ( $($key: ident = $value: expr),+ ; $($rest: tt)+ ) => {{
+           let _fields = $crate::telio_span!($($key = $value),+).enter();
+           $crate::telio_log_error!($($rest)+)
+       }};
+       ( $msg: expr) => {log::error!("{}", $crate::redact::Redacted(format_args!($msg)))};
+       ( $format: expr, $($arg:tt)+) => { log::error!("{}", $crate::redact::Redacted(format_args!($format, $($arg)+))) };
 }
 
 /// Error with log is used to log something
@@ -49,11 +69,14 @@ macro_rules! telio_err_with_log {
         match $error {
             tmp => {
                 log::debug!(
-                    "{:?} - {:?} at {:?}:{:?}",
-                    std::module_path!(),
-                    &tmp,
-                    file!(),
-                    line!()
+                    "{}",
+                    $crate::redact::Redacted(format_args!(
+                        "{:?} - {:?} at {:?}:{:?}",
+                        std::module_path!(),
+                        &tmp,
+                        file!(),
+                        line!()
+                    ))
                 );
                 Err(tmp)
             }
@@ -63,7 +86,31 @@ macro_rules! telio_err_with_log {
 
 #[cfg(test)]
 mod tests {
-    use std::sync::atomic::{AtomicUsize, Ordering};
+    use crate::logging::{current_fields, Field};
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    };
+
+    lazy_static::lazy_static! {
+        static ref LOGGED: Mutex<Vec<(String, Vec<Field>)>> = Mutex::new(Vec::new());
+    }
+
+    struct FieldsLogger;
+
+    impl log::Log for FieldsLogger {
+        fn enabled(&self, _: &log::Metadata) -> bool {
+            true
+        }
+
+        fn log(&self, record: &log::Record) {
+            if let Ok(mut logged) = LOGGED.lock() {
+                logged.push((record.args().to_string(), current_fields()));
+            }
+        }
+
+        fn flush(&self) {}
+    }
 
     #[test]
     fn telio_err_with_log_evaluates_once() {
@@ -73,4 +120,32 @@ mod tests {
             telio_err_with_log!(counter.fetch_add(1, Ordering::Relaxed));
         assert_eq!(1, counter.load(Ordering::Relaxed));
     }
+
+    #[test]
+    fn log_fields_are_attached_to_single_record() {
+        let _ = log::set_boxed_logger(Box::new(FieldsLogger));
+        log::set_max_level(log::LevelFilter::Debug);
+
+        telio_log_info!(peer = "abc", attempt = 2; "connecting {}", 1);
+        telio_log_debug!("connected");
+
+        // Other tests may log concurrently
+        let logged: Vec<_> = LOGGED
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(msg, _)| msg.starts_with("connect"))
+            .cloned()
+            .collect();
+        assert_eq!(
+            logged,
+            vec![
+                (
+                    "connecting 1".to_owned(),
+                    vec![("peer", "abc".to_owned()), ("attempt", "2".to_owned())]
+                ),
+                ("connected".to_owned(), vec![]),
+            ]
+        );
+    }
 }
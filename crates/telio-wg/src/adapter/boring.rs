@@ -7,17 +7,24 @@ use std::ops::Deref;
 use std::sync::Arc;
 use tokio::sync::RwLock;
 
+#[cfg(target_os = "linux")]
+use super::nft::MssClamp;
 use super::{Adapter, Error as AdapterError, Tun as NativeTun};
 use crate::uapi::{self, Cmd, Response};
 
 pub use boringtun::device::Error;
 use libc::socket;
+#[cfg(target_os = "linux")]
+use telio_crypto::PublicKey;
 use telio_sockets::SocketPool;
 
 pub type FirewallCb = Option<Arc<dyn Fn(&[u8; 32], &[u8]) -> bool + Send + Sync>>;
 
 pub struct BoringTun {
     device: RwLock<DeviceHandle>,
+    /// Packets are handled inside of boringtun, so MSS is clamped by kernel
+    #[cfg(target_os = "linux")]
+    mss_clamp: MssClamp,
 }
 
 impl BoringTun {
@@ -48,6 +55,8 @@ impl BoringTun {
 
         Ok(BoringTun {
             device: RwLock::new(device),
+            #[cfg(target_os = "linux")]
+            mss_clamp: MssClamp::new(name),
         })
     }
 
@@ -60,7 +69,12 @@ impl BoringTun {
 impl Adapter for BoringTun {
     async fn send_uapi_cmd(&self, cmd: &Cmd) -> Response {
         let res = self.send_uapi_cmd_str(&cmd.to_string()).await;
-        uapi::response_from_str(&res)
+        let res = uapi::response_from_str(&res);
+        #[cfg(target_os = "linux")]
+        if let (Cmd::Get, Some(interface)) = (cmd, &res.interface) {
+            self.mss_clamp.update(interface).await;
+        }
+        res
     }
 
     fn get_adapter_luid(&self) -> u64 {
@@ -71,7 +85,19 @@ impl Adapter for BoringTun {
         self.device.read().await.drop_connected_sockets();
     }
 
+    fn pushes_peer_changes(&self) -> bool {
+        // Peer activity is reported by the inbound packet callback
+        true
+    }
+
+    #[cfg(target_os = "linux")]
+    fn set_peer_mtu(&self, public_key: &PublicKey, mtu: u32) {
+        self.mss_clamp.set_peer_mtu(public_key, mtu);
+    }
+
     async fn stop(&self) {
+        #[cfg(target_os = "linux")]
+        self.mss_clamp.clear().await;
         self.device.read().await.trigger_exit();
         self.device.write().await.wait();
     }
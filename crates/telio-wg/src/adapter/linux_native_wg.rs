@@ -2,16 +2,28 @@
 
 #![warn(clippy::unwrap_used)]
 
-use super::{Adapter, Error as AdapterError, Tun as NativeTun};
+use super::{
+    nft::{nft, nft_detached, nft_table, MssClamp},
+    Adapter, Error as AdapterError, FirewallRulesCb, Tun as NativeTun, Watcher,
+};
 use crate::uapi::{Cmd, Cmd::Get, Cmd::Set, Interface, Peer, Response};
 use boringtun::device::Sock;
 use futures::executor::block_on;
 use futures::future::{BoxFuture, FutureExt};
 use ipnetwork::{IpNetwork, IpNetworkError};
 use std::collections::BTreeMap;
+use std::ffi::CString;
+use std::io;
+use std::mem;
 use std::net::IpAddr;
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+use std::thread::{self, JoinHandle};
 use telio_crypto::{PublicKey, SecretKey};
-use telio_utils::{telio_log_error, telio_log_info};
+use telio_sockets::SocketPool;
+use telio_utils::{telio_log_error, telio_log_info, telio_log_warn};
 use tokio::sync::Mutex;
 use wireguard_uapi::{
     err, get, linux::set::WgDeviceF, set, xplatform, DeviceInterface, RouteSocket, WgSocket,
@@ -22,6 +34,14 @@ pub struct LinuxNativeWg {
     ifname: String,
     rtsocket: Mutex<RouteSocket>,
     wgsocket: Mutex<WgSocket>,
+    link_monitor: Option<LinkMonitor>,
+    socket_pool: Arc<SocketPool>,
+    firewall_rules: FirewallRulesCb,
+    // Last applied firewall rules, so nftables are touched only on change
+    applied_rules: Mutex<Option<Vec<String>>>,
+    // Set when `nft` is not installed, firewall policy is not enforced then
+    nft_missing: AtomicBool,
+    mss_clamp: MssClamp,
 }
 
 #[derive(thiserror::Error, Debug)]
@@ -41,21 +61,233 @@ pub enum Error {
         #[from]
         source: err::LinkDeviceError,
     },
+    #[error("Firewall: {0}")]
+    Firewall(#[from] io::Error),
 }
 
 impl LinuxNativeWg {
-    pub fn start(name: &str, _tun: Option<NativeTun>) -> Result<Self, AdapterError> {
+    pub fn start(
+        name: &str,
+        _tun: Option<NativeTun>,
+        socket_pool: Arc<SocketPool>,
+        firewall_rules: FirewallRulesCb,
+        watcher: Watcher,
+    ) -> Result<Self, AdapterError> {
         let mut rtsocket = RouteSocket::connect().map_err(Error::from)?;
 
         rtsocket.add_device(name).map_err(Error::from)?;
-        let wgsocket = WgSocket::connect().map_err(Error::from)?;
+        let wgsocket = match WgSocket::connect() {
+            Ok(wgsocket) => wgsocket,
+            Err(e) => {
+                let _ = rtsocket.del_device(name);
+                return Err(Error::from(e).into());
+            }
+        };
+
+        // Kernel sockets cannot be passed to protect callback, only fwmark applies to them
+        if socket_pool.get_fwmark().is_none() {
+            telio_log_warn!(
+                "LinuxNativeWg: custom socket protection is not applied to kernel WireGuard socket, use fwmark instead"
+            );
+        }
+
+        // Kernel does not multicast peers' changes, so only link changes are pushed,
+        // while peers are still polled
+        let link_monitor = match LinkMonitor::start(name, watcher) {
+            Ok(monitor) => Some(monitor),
+            Err(e) => {
+                telio_log_warn!("LinuxNativeWg: failed to monitor link changes: {}", e);
+                None
+            }
+        };
 
         Ok(Self {
             ifname: name.to_owned(),
             rtsocket: Mutex::new(rtsocket),
             wgsocket: Mutex::new(wgsocket),
+            link_monitor,
+            socket_pool,
+            firewall_rules,
+            applied_rules: Mutex::new(None),
+            nft_missing: AtomicBool::new(false),
+            mss_clamp: MssClamp::new(name),
+        })
+    }
+}
+
+/// Ruleset, replacing interface's table atomically. Table is declared before its deletion,
+/// so the deletion succeeds, even if table does not exist yet
+fn nft_ruleset(ifname: &str, rules: &[String]) -> String {
+    let table = nft_table("telio", ifname);
+    let mut ruleset = format!(
+        "table inet {table}\ndelete table inet {table}\n",
+        table = table
+    );
+    ruleset.push_str(&format!("table inet {} {{\n", table));
+    for hook in &["input", "forward"] {
+        ruleset.push_str(&format!(
+            "    chain {hook} {{\n        type filter hook {hook} priority 0; policy accept;\n        iifname \"{ifname}\" jump inbound\n    }}\n",
+            hook = hook,
+            ifname = ifname
+        ));
+    }
+    ruleset.push_str("    chain inbound {\n");
+    for rule in rules {
+        ruleset.push_str(&format!("        {}\n", rule));
+    }
+    ruleset.push_str("    }\n}\n");
+    ruleset
+}
+
+/// Listens to rtnetlink multicast notifications about WireGuard interface's link
+struct LinkMonitor {
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+/// Size of `nlmsghdr`
+const NLMSG_HDR_LEN: usize = 16;
+/// Offset of `ifi_index` in `ifinfomsg`, following `nlmsghdr`
+const IFI_INDEX_OFFSET: usize = NLMSG_HDR_LEN + 4;
+
+impl LinkMonitor {
+    fn start(ifname: &str, watcher: Watcher) -> io::Result<Self> {
+        let name =
+            CString::new(ifname).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        let ifindex = unsafe { libc::if_nametoindex(name.as_ptr()) };
+        if ifindex == 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        let fd = unsafe {
+            libc::socket(
+                libc::AF_NETLINK,
+                libc::SOCK_RAW | libc::SOCK_CLOEXEC,
+                libc::NETLINK_ROUTE,
+            )
+        };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        if let Err(e) = Self::subscribe(fd) {
+            unsafe { libc::close(fd) };
+            return Err(e);
+        }
+
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread = {
+            let stop = stop.clone();
+            thread::Builder::new()
+                .name("wg-link-monitor".to_owned())
+                .spawn(move || Self::run(fd, ifindex as i32, &stop, &watcher))?
+        };
+
+        Ok(Self {
+            stop,
+            thread: Some(thread),
         })
     }
+
+    /// Join link multicast group, with receive timeout for checking the stop flag
+    fn subscribe(fd: libc::c_int) -> io::Result<()> {
+        let mut addr: libc::sockaddr_nl = unsafe { mem::zeroed() };
+        addr.nl_family = libc::AF_NETLINK as libc::sa_family_t;
+        addr.nl_groups = libc::RTMGRP_LINK as u32;
+
+        let res = unsafe {
+            libc::bind(
+                fd,
+                &addr as *const libc::sockaddr_nl as *const libc::sockaddr,
+                mem::size_of::<libc::sockaddr_nl>() as libc::socklen_t,
+            )
+        };
+        if res < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        let timeout = libc::timeval {
+            tv_sec: 1,
+            tv_usec: 0,
+        };
+        let res = unsafe {
+            libc::setsockopt(
+                fd,
+                libc::SOL_SOCKET,
+                libc::SO_RCVTIMEO,
+                &timeout as *const libc::timeval as *const libc::c_void,
+                mem::size_of::<libc::timeval>() as libc::socklen_t,
+            )
+        };
+        if res < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(())
+    }
+
+    fn run(fd: libc::c_int, ifindex: i32, stop: &AtomicBool, watcher: &Watcher) {
+        let mut buf = [0u8; 8192];
+
+        while !stop.load(Ordering::Relaxed) {
+            let len =
+                unsafe { libc::recv(fd, buf.as_mut_ptr() as *mut libc::c_void, buf.len(), 0) };
+            if len < 0 {
+                let e = io::Error::last_os_error();
+                match e.kind() {
+                    io::ErrorKind::WouldBlock
+                    | io::ErrorKind::TimedOut
+                    | io::ErrorKind::Interrupted => continue,
+                    _ => {
+                        telio_log_warn!("LinuxNativeWg: link monitor stopped: {}", e);
+                        break;
+                    }
+                }
+            }
+
+            if Self::concerns_link(&buf[..len as usize], ifindex) {
+                watcher.interface_changed();
+            }
+        }
+
+        unsafe { libc::close(fd) };
+    }
+
+    /// Check, if any of netlink messages is a change of the link
+    fn concerns_link(mut msgs: &[u8], ifindex: i32) -> bool {
+        let read_u32 = |b: &[u8], at: usize| {
+            b.get(at..at + 4)
+                .map(|v| u32::from_ne_bytes([v[0], v[1], v[2], v[3]]))
+        };
+
+        while let Some(len) = read_u32(msgs, 0).map(|l| l as usize) {
+            if len < NLMSG_HDR_LEN || len > msgs.len() {
+                break;
+            }
+
+            let msg_type = u16::from_ne_bytes([msgs[4], msgs[5]]);
+            if (msg_type == libc::RTM_NEWLINK || msg_type == libc::RTM_DELLINK)
+                && read_u32(msgs, IFI_INDEX_OFFSET) == Some(ifindex as u32)
+            {
+                return true;
+            }
+
+            // Messages are aligned to 4 bytes
+            let aligned = (len + 3) & !3;
+            msgs = msgs.get(aligned..).unwrap_or_default();
+        }
+
+        false
+    }
+}
+
+impl Drop for LinkMonitor {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
 }
 
 #[async_trait::async_trait]
@@ -63,16 +295,41 @@ impl Adapter for LinuxNativeWg {
     async fn send_uapi_cmd(&self, cmd: &Cmd) -> Response {
         match cmd {
             Get => {
-                let dev = self.get_wg_data(&self.ifname).await;
+                let interface = self.get_wg_data(&self.ifname).await.map(Interface::from);
+                if let Some(interface) = &interface {
+                    self.update_firewall(interface).await;
+                    self.mss_clamp.update(interface).await;
+                }
+                Response {
+                    errno: interface.as_ref().map_or(1, |_| 0),
+                    interface,
+                }
+            }
+            Set(device) => {
+                // Packets of kernel interface do not pass through firewall callbacks, so its
+                // policy is enforced by nftables, before any peer is added
+                if let Err(e) = self.install_firewall().await {
+                    telio_log_error!("LinuxNativeWg: failed to apply firewall rules: {}", e);
+                    return Response {
+                        errno: 1,
+                        interface: None,
+                    };
+                }
+
+                let errno = self.set_wg_data(&self.ifname, device).await;
+                // Whitelisted peers are let through right away, not on the next poll
+                if errno == 0 {
+                    if let Some(interface) =
+                        self.get_wg_data(&self.ifname).await.map(Interface::from)
+                    {
+                        self.update_firewall(&interface).await;
+                    }
+                }
                 Response {
-                    errno: dev.as_ref().map_or(1, |_| 0),
-                    interface: dev.map(Interface::from),
+                    errno,
+                    interface: None,
                 }
             }
-            Set(device) => Response {
-                errno: self.set_wg_data(&self.ifname, device).await,
-                interface: None,
-            },
         }
     }
 
@@ -80,8 +337,26 @@ impl Adapter for LinuxNativeWg {
         0
     }
 
+    fn set_peer_mtu(&self, public_key: &PublicKey, mtu: u32) {
+        self.mss_clamp.set_peer_mtu(public_key, mtu);
+    }
+
+    async fn refresh_firewall(&self) {
+        if let Some(interface) = self.get_wg_data(&self.ifname).await.map(Interface::from) {
+            self.update_firewall(&interface).await;
+        }
+    }
+
     async fn stop(&self) {
         let _ = self.rtsocket.lock().await.del_device(&self.ifname);
+        self.mss_clamp.clear().await;
+        if self.applied_rules.lock().await.take().is_some() {
+            let _ = nft(&format!(
+                "delete table inet {}\n",
+                nft_table("telio", &self.ifname)
+            ))
+            .await;
+        }
     }
 }
 
@@ -90,10 +365,67 @@ impl Drop for LinuxNativeWg {
         if let Ok(mut rs) = RouteSocket::connect() {
             let _ = rs.del_device(&self.ifname);
         }
+        // Adapter is normally stopped before, so tables are removed just in case
+        self.mss_clamp.clear_detached();
+        if self.applied_rules.get_mut().take().is_some() {
+            nft_detached(format!(
+                "delete table inet {}\n",
+                nft_table("telio", &self.ifname)
+            ));
+        }
     }
 }
 
 impl LinuxNativeWg {
+    /// Apply firewall rules without any peer, unless rules were already applied
+    async fn install_firewall(&self) -> io::Result<()> {
+        let rules_cb = match &self.firewall_rules {
+            Some(rules_cb) => rules_cb,
+            None => return Ok(()),
+        };
+
+        let mut applied_rules = self.applied_rules.lock().await;
+        if applied_rules.is_none() && !self.nft_missing.load(Ordering::Relaxed) {
+            let rules = rules_cb(&[]);
+            match nft(&nft_ruleset(&self.ifname, &rules)).await {
+                Ok(()) => *applied_rules = Some(rules),
+                // Hosts without nftables still get a working tunnel, only unfiltered
+                Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                    telio_log_warn!(
+                        "LinuxNativeWg: nft is not installed, firewall policy is not enforced"
+                    );
+                    self.nft_missing.store(true, Ordering::Relaxed);
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    }
+
+    /// Regenerate firewall rules for current peers, applying them if changed
+    async fn update_firewall(&self, interface: &Interface) {
+        let rules_cb = match &self.firewall_rules {
+            Some(rules_cb) if !self.nft_missing.load(Ordering::Relaxed) => rules_cb,
+            _ => return,
+        };
+
+        let peers: Vec<_> = interface
+            .peers
+            .iter()
+            .map(|(public_key, peer)| (*public_key, peer.allowed_ips.clone()))
+            .collect();
+        let rules = rules_cb(&peers);
+
+        let mut applied_rules = self.applied_rules.lock().await;
+        if applied_rules.as_ref() == Some(&rules) {
+            return;
+        }
+        match nft(&nft_ruleset(&self.ifname, &rules)).await {
+            Ok(()) => *applied_rules = Some(rules),
+            Err(e) => telio_log_error!("LinuxNativeWg: failed to apply firewall rules: {}", e),
+        }
+    }
+
     async fn get_wg_data(&self, ifname: &str) -> Option<get::Device> {
         let dev_ifc = DeviceInterface::from_name(ifname);
         let dev = self.wgsocket.lock().await.get_device(dev_ifc);
@@ -150,7 +482,10 @@ impl LinuxNativeWg {
 
         dev.private_key = device.private_key.as_ref();
         dev.listen_port = device.listen_port;
-        dev.fwmark = device.fwmark;
+        // Kernel socket is protected the same way as sockets of the pool
+        dev.fwmark = device
+            .fwmark
+            .or_else(|| self.socket_pool.get_fwmark().filter(|fwmark| *fwmark != 0));
 
         match self.wgsocket.lock().await.set_device(dev) {
             Ok(_) => 0,
@@ -163,3 +498,276 @@ impl LinuxNativeWg {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adapter::FirewallCb;
+
+    /// Kernel firewall tests need root, WireGuard module and given tools, they are skipped
+    /// on hosts without them
+    fn kernel_firewall_available(tools: &[&str]) -> bool {
+        let available = unsafe { libc::geteuid() } == 0
+            && std::path::Path::new("/sys/module/wireguard").exists()
+            && tools.iter().all(|tool| {
+                std::process::Command::new(tool)
+                    .arg("-V")
+                    .output()
+                    .map_or(false, |output| output.status.success())
+            });
+        if !available {
+            eprintln!(
+                "Skipped: needs root, WireGuard kernel module and {:?}",
+                tools
+            );
+        }
+        available
+    }
+
+    fn link_msg(msg_type: u16, ifindex: i32) -> Vec<u8> {
+        let mut msg = Vec::new();
+        msg.extend_from_slice(&32u32.to_ne_bytes());
+        msg.extend_from_slice(&msg_type.to_ne_bytes());
+        msg.extend_from_slice(&[0u8; 10]);
+        msg.extend_from_slice(&[0u8; 4]);
+        msg.extend_from_slice(&ifindex.to_ne_bytes());
+        msg.extend_from_slice(&[0u8; 8]);
+        msg
+    }
+
+    #[test]
+    fn link_monitor_filters_by_link() {
+        let mut msgs = link_msg(libc::RTM_NEWLINK, 3);
+        assert!(!LinkMonitor::concerns_link(&msgs, 7));
+
+        msgs.extend(link_msg(libc::RTM_DELLINK, 7));
+        assert!(LinkMonitor::concerns_link(&msgs, 7));
+
+        assert!(!LinkMonitor::concerns_link(
+            &link_msg(libc::RTM_NEWADDR, 7),
+            7
+        ));
+        assert!(!LinkMonitor::concerns_link(&msgs[..20], 7));
+    }
+
+    #[test]
+    fn nft_ruleset_replaces_interface_table() {
+        let ruleset = nft_ruleset("nlx-0", &["ip saddr 100.64.0.2 accept".to_owned()]);
+        let lines: Vec<_> = ruleset.lines().map(str::trim).collect();
+
+        assert_eq!(
+            &lines[..3],
+            &[
+                "table inet telio_nlx_0",
+                "delete table inet telio_nlx_0",
+                "table inet telio_nlx_0 {"
+            ]
+        );
+        assert_eq!(
+            lines
+                .iter()
+                .filter(|l| **l == "iifname \"nlx-0\" jump inbound")
+                .count(),
+            2
+        );
+        assert!(lines.ends_with(&["chain inbound {", "ip saddr 100.64.0.2 accept", "}", "}"]));
+    }
+
+    /// Same firewall policy, as BoringTun enforces in userspace, is installed for kernel interface
+    #[tokio::test]
+    async fn native_adapter_enforces_firewall_policy() {
+        use std::process::Command;
+        use telio_firewall::firewall::Firewall;
+
+        if !kernel_firewall_available(&["nft"]) {
+            return;
+        }
+
+        let ifname = "tlfwtest0";
+        let list_table = || {
+            Command::new("nft")
+                .args(&["list", "table", "inet", &nft_table("telio", ifname)])
+                .output()
+                .expect("nft is not installed")
+        };
+
+        let firewall = Arc::new(Firewall::new());
+        let rules_cb: FirewallRulesCb = {
+            let firewall = firewall.clone();
+            Some(Arc::new(move |peers| firewall.nft_inbound_rules(peers)))
+        };
+        let adapter = LinuxNativeWg::start(
+            ifname,
+            None,
+            Arc::new(SocketPool::default()),
+            rules_cb,
+            Watcher::new().0,
+        )
+        .expect("failed to start adapter");
+
+        let peer = SecretKey::gen().public();
+        let device = xplatform::set::Device {
+            private_key: Some(SecretKey::gen().0),
+            listen_port: None,
+            fwmark: None,
+            replace_peers: None,
+            peers: vec![xplatform::set::Peer::from(&Peer {
+                public_key: peer,
+                allowed_ips: vec!["100.64.0.2/32".parse().unwrap()],
+                ..Default::default()
+            })],
+        };
+        assert_eq!(adapter.send_uapi_cmd(&Set(device)).await.errno, 0);
+
+        // Peer is not whitelisted, its traffic passes only through conntrack
+        let listed = String::from_utf8_lossy(&list_table().stdout).into_owned();
+        assert!(listed.contains("icmp type != { echo-request"));
+        assert!(listed.contains("drop"));
+        assert!(!listed.contains("ip saddr 100.64.0.2 accept"));
+
+        firewall.add_to_peer_whitelist(peer);
+        adapter.refresh_firewall().await;
+        let listed = String::from_utf8_lossy(&list_table().stdout).into_owned();
+        assert!(listed.contains("ip saddr 100.64.0.2 accept"));
+
+        adapter.stop().await;
+        assert!(!list_table().status.success());
+    }
+
+    /// Kernel interface lets through the same traffic, as BoringTun filtering in userspace.
+    /// Both adapters are peers of each other over loopback, with BoringTun's interface moved
+    /// to its own namespace, so pings between them pass through both tunnels
+    #[tokio::test(flavor = "multi_thread")]
+    async fn native_adapter_filters_like_boringtun() {
+        use super::super::boring::BoringTun;
+        use std::process::Command;
+        use telio_firewall::firewall::Firewall;
+
+        if !kernel_firewall_available(&["nft", "ip"]) {
+            return;
+        }
+
+        let (native_if, boring_if, netns) = ("tlfwtest0", "tlfwtest1", "tlfwtest");
+        let run = |args: &[&str]| {
+            Command::new(args[0])
+                .args(&args[1..])
+                .stdout(std::process::Stdio::null())
+                .status()
+                .expect("command is not installed")
+                .success()
+        };
+
+        let firewall = Arc::new(Firewall::new());
+        let rules_cb: FirewallRulesCb = {
+            let firewall = firewall.clone();
+            Some(Arc::new(move |peers| firewall.nft_inbound_rules(peers)))
+        };
+        let inbound_cb: FirewallCb = {
+            let firewall = firewall.clone();
+            Some(Arc::new(move |peer, packet| {
+                firewall.process_inbound_packet(peer, packet)
+            }))
+        };
+        let outbound_cb: FirewallCb = {
+            let firewall = firewall.clone();
+            Some(Arc::new(move |peer, packet| {
+                firewall.process_outbound_packet(peer, packet)
+            }))
+        };
+
+        let native = LinuxNativeWg::start(
+            native_if,
+            None,
+            Arc::new(SocketPool::default()),
+            rules_cb,
+            Watcher::new().0,
+        )
+        .expect("failed to start native adapter");
+        let boring = BoringTun::start(
+            boring_if,
+            None,
+            Arc::new(SocketPool::default()),
+            inbound_cb,
+            outbound_cb,
+        )
+        .expect("failed to start BoringTun adapter");
+
+        let (native_key, boring_key) = (SecretKey::gen(), SecretKey::gen());
+        let set = |key: &SecretKey, port: u16, peer: PublicKey, peer_port: u16, peer_ip: &str| {
+            Set(xplatform::set::Device {
+                private_key: Some(key.0),
+                listen_port: Some(port),
+                fwmark: None,
+                replace_peers: None,
+                peers: vec![xplatform::set::Peer::from(&Peer {
+                    public_key: peer,
+                    endpoint: Some(([127, 0, 0, 1], peer_port).into()),
+                    allowed_ips: vec![peer_ip.parse().unwrap()],
+                    ..Default::default()
+                })],
+            })
+        };
+        let set_native = set(
+            &native_key,
+            51821,
+            boring_key.public(),
+            51822,
+            "100.64.0.2/32",
+        );
+        assert_eq!(native.send_uapi_cmd(&set_native).await.errno, 0);
+        let set_boring = set(
+            &boring_key,
+            51822,
+            native_key.public(),
+            51821,
+            "100.64.0.1/32",
+        );
+        assert_eq!(boring.send_uapi_cmd(&set_boring).await.errno, 0);
+
+        for cmd in [
+            vec!["ip", "addr", "add", "100.64.0.1/10", "dev", native_if],
+            vec!["ip", "link", "set", native_if, "up"],
+            vec!["ip", "netns", "add", netns],
+            vec!["ip", "link", "set", boring_if, "netns", netns],
+            vec![
+                "ip",
+                "-n",
+                netns,
+                "addr",
+                "add",
+                "100.64.0.2/10",
+                "dev",
+                boring_if,
+            ],
+            vec!["ip", "-n", netns, "link", "set", boring_if, "up"],
+        ] {
+            assert!(run(&cmd), "{:?} failed", cmd);
+        }
+        let ping = |netns: Option<&str>, ip: &str| {
+            let mut cmd = vec!["ip", "netns", "exec", netns.unwrap_or_default()];
+            if netns.is_none() {
+                cmd.clear();
+            }
+            cmd.extend(["ping", "-c", "3", "-i", "0.2", "-W", "1", ip]);
+            run(&cmd)
+        };
+        let ping_native = || ping(Some(netns), "100.64.0.1");
+        let ping_boring = || ping(None, "100.64.0.2");
+
+        // Pings from peers, which are not whitelisted, are dropped by both
+        assert!(!ping_native());
+        assert!(!ping_boring());
+
+        firewall.add_to_peer_whitelist(native_key.public());
+        firewall.add_to_peer_whitelist(boring_key.public());
+        native.refresh_firewall().await;
+
+        // And let through by both, once they are
+        assert!(ping_native());
+        assert!(ping_boring());
+
+        native.stop().await;
+        boring.stop().await;
+        run(&["ip", "netns", "del", netns]);
+    }
+}
@@ -7,6 +7,14 @@ mod boring;
 #[cfg_attr(docsrs, doc(cfg(target_os = "linux")))]
 mod linux_native_wg;
 
+#[cfg(any(target_os = "linux", doc))]
+#[cfg_attr(docsrs, doc(cfg(target_os = "linux")))]
+mod nft;
+
+#[cfg(any(not(windows), doc))]
+#[cfg_attr(docsrs, doc(cfg(not(windows))))]
+mod userspace;
+
 #[cfg(any(windows, doc))]
 #[cfg_attr(docsrs, doc(cfg(windows)))]
 mod wireguard_go;
@@ -15,18 +23,30 @@ mod wireguard_go;
 #[cfg_attr(docsrs, doc(cfg(windows)))]
 mod windows_native_wg;
 
+use arc_swap::ArcSwap;
 use async_trait::async_trait;
+use ipnetwork::IpNetwork;
 #[cfg(test)]
 use mockall::automock;
-use std::{io, sync::Arc};
+use std::{collections::HashSet, io, sync::Arc};
+use telio_crypto::PublicKey;
 use telio_sockets::{Protect, SocketPool};
 use thiserror::Error as TError;
+use tokio::sync::mpsc;
 
-use crate::uapi::{self, Cmd, Response};
+use crate::{
+    packet_io::PacketIo,
+    uapi::{self, Cmd, Response},
+};
 
 /// Function pointer to Firewall Callback
 pub type FirewallCb = Option<Arc<dyn Fn(&[u8; 32], &[u8]) -> bool + Send + Sync>>;
 
+/// Function pointer generating nftables rules of firewall's inbound chain from peers and their
+/// allowed IPs, used by adapters whose packets do not pass through [FirewallCb]
+pub type FirewallRulesCb =
+    Option<Arc<dyn Fn(&[(PublicKey, Vec<IpNetwork>)]) -> Vec<String> + Send + Sync>>;
+
 /// Tunnel file descriptor
 #[cfg(not(target_os = "windows"))]
 #[cfg_attr(docsrs, doc(cfg(not(windows))))]
@@ -57,6 +77,83 @@ pub(crate) trait Adapter: Send + Sync {
 
     /// Disconnect all connected peer sockets
     async fn drop_connected_sockets(&self) {}
+
+    /// Does adapter push peers' changes through [Watcher], so they need to be polled only
+    /// as a fallback. Overridable
+    fn pushes_peer_changes(&self) -> bool {
+        false
+    }
+
+    /// Set MTU of path towards peer, to which MSS of TCP connections is clamped.
+    /// Adapters, which neither own the packet path nor can clamp it in kernel, ignore it.
+    /// Overridable
+    fn set_peer_mtu(&self, _public_key: &PublicKey, _mtu: u32) {}
+
+    /// Apply changes of firewall policy right away, for adapters enforcing it outside of
+    /// firewall callbacks. Overridable
+    async fn refresh_firewall(&self) {}
+}
+
+/// Change of WireGuard state, pushed by adapter
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Change {
+    /// Watched peer became active, most likely by completing a handshake
+    Peer(PublicKey),
+    /// Interface's link has changed or was removed
+    Interface,
+}
+
+/// Capacity of pushed changes queue, changes that do not fit are coalesced
+const CHANGES_CAPACITY: usize = 64;
+
+/// Handle, through which adapter pushes its changes
+#[derive(Clone)]
+pub(crate) struct Watcher {
+    /// Peers, whose activity is pushed (the ones, which are not connected yet). Read on
+    /// every inbound packet, so it is swapped instead of locked
+    watched: Arc<ArcSwap<HashSet<PublicKey>>>,
+    changes: mpsc::Sender<Change>,
+}
+
+impl Watcher {
+    pub fn new() -> (Self, mpsc::Receiver<Change>) {
+        let (changes, rx) = mpsc::channel(CHANGES_CAPACITY);
+        (
+            Self {
+                watched: Default::default(),
+                changes,
+            },
+            rx,
+        )
+    }
+
+    /// Replace the set of watched peers
+    pub fn watch(&self, peers: HashSet<PublicKey>) {
+        self.watched.store(Arc::new(peers));
+    }
+
+    /// Report activity of a peer, called from adapter's packet path
+    pub fn peer_active(&self, public_key: &[u8; 32]) {
+        let public_key = PublicKey(*public_key);
+        if self.watched.load().contains(&public_key) {
+            let _ = self.changes.try_send(Change::Peer(public_key));
+        }
+    }
+
+    /// Report change of interface's link
+    pub fn interface_changed(&self) {
+        let _ = self.changes.try_send(Change::Interface);
+    }
+
+    /// Wrap firewall callback, so every inbound packet reports peer's activity
+    #[cfg(unix)]
+    fn wrap_inbound(&self, callback: FirewallCb) -> FirewallCb {
+        let watcher = self.clone();
+        Some(Arc::new(move |public_key, packet| {
+            watcher.peer_active(public_key);
+            callback.as_ref().map_or(true, |cb| cb(public_key, packet))
+        }))
+    }
 }
 
 /// Enumeration of `Error` types for `Adapter` struct
@@ -73,6 +170,12 @@ pub enum Error {
     #[error("LinuxNativeWg adapter error {0}")]
     LinuxNativeWg(#[from] linux_native_wg::Error),
 
+    /// Error types from userspace implementation
+    #[cfg(any(not(windows), doc))]
+    #[cfg_attr(docsrs, doc(cfg(not(windows))))]
+    #[error("UserspaceWg adapter error {0}")]
+    UserspaceWg(#[from] userspace::Error),
+
     /// Error types from WireGuard Go implementation
     #[cfg(any(windows, doc))]
     #[cfg_attr(docsrs, doc(cfg(windows)))]
@@ -107,6 +210,10 @@ pub enum Error {
     /// Failed to restart adapter error
     #[error("Failed to restart internal adapter")]
     RestartFailed,
+
+    /// Both TUN device and packet I/O were given, while adapter uses only one of them
+    #[error("TUN device and packet I/O cannot be used together")]
+    TunWithPacketIo,
 }
 
 /// Enumeration of types for `Adapter` struct
@@ -120,6 +227,8 @@ pub enum AdapterType {
     WireguardGo,
     /// Windows Native
     WindowsNativeWg,
+    /// Userspace, over pluggable packet I/O instead of TUN device
+    UserspaceWg,
 }
 
 impl Default for AdapterType {
@@ -141,9 +250,12 @@ pub(crate) fn start(
     adapter: AdapterType,
     name: &str,
     tun: Option<Tun>,
+    packet_io: Option<Arc<dyn PacketIo>>,
     socket_pool: Arc<SocketPool>,
     firewall_process_inbound_callback: FirewallCb,
     firewall_process_outbound_callback: FirewallCb,
+    firewall_rules_callback: FirewallRulesCb,
+    watcher: &Watcher,
 ) -> Result<Box<dyn Adapter>, Error> {
     #![allow(unused_variables)]
 
@@ -157,7 +269,7 @@ pub(crate) fn start(
                 name,
                 tun,
                 socket_pool,
-                firewall_process_inbound_callback,
+                watcher.wrap_inbound(firewall_process_inbound_callback),
                 firewall_process_outbound_callback,
             )?))
         }
@@ -166,7 +278,13 @@ pub(crate) fn start(
             return Err(Error::UnsupportedAdapter);
 
             #[cfg(target_os = "linux")]
-            Ok(Box::new(linux_native_wg::LinuxNativeWg::start(name, tun)?))
+            Ok(Box::new(linux_native_wg::LinuxNativeWg::start(
+                name,
+                tun,
+                socket_pool,
+                firewall_rules_callback,
+                watcher.clone(),
+            )?))
         }
         AdapterType::WireguardGo => {
             #[cfg(not(windows))]
@@ -184,5 +302,46 @@ pub(crate) fn start(
                 name, tun,
             )?))
         }
+        AdapterType::UserspaceWg => {
+            #[cfg(windows)]
+            return Err(Error::UnsupportedAdapter);
+
+            #[cfg(unix)]
+            {
+                let packet_io: Arc<dyn PacketIo> = match (packet_io, tun) {
+                    // Either of them would be left unused, so ambiguous config is rejected
+                    (Some(_), Some(_)) => return Err(Error::TunWithPacketIo),
+                    (Some(packet_io), None) => packet_io,
+                    (None, Some(tun)) => {
+                        // Descriptor given in config is closed once interface stops
+                        let fd = unsafe { libc::dup(tun) };
+                        if fd < 0 {
+                            return Err(io::Error::last_os_error().into());
+                        }
+                        Arc::new(crate::packet_io::TunIo::new(fd)?)
+                    }
+                    (None, None) => {
+                        use boringtun::device::tun::TunSocket;
+                        use std::os::unix::io::AsRawFd;
+
+                        // TUN device lives as long as any of its descriptors is open
+                        let tun = TunSocket::new(name)?;
+                        let fd = unsafe { libc::dup(tun.as_raw_fd()) };
+                        if fd < 0 {
+                            return Err(io::Error::last_os_error().into());
+                        }
+                        Arc::new(crate::packet_io::TunIo::new(fd)?)
+                    }
+                };
+
+                Ok(Box::new(userspace::UserspaceWg::start(
+                    packet_io,
+                    socket_pool,
+                    firewall_process_inbound_callback,
+                    firewall_process_outbound_callback,
+                    watcher.clone(),
+                )?))
+            }
+        }
     }
 }
@@ -0,0 +1,234 @@
+//! nftables of kernel interfaces, whose packets do not pass through userspace
+
+use ipnetwork::IpNetwork;
+use std::collections::HashMap;
+use std::io;
+use std::process::Stdio;
+use std::sync::Mutex as StdMutex;
+use telio_crypto::PublicKey;
+use telio_utils::{telio_log_error, telio_log_warn};
+use tokio::{io::AsyncWriteExt, process::Command, sync::Mutex};
+
+use crate::uapi::Interface;
+
+/// Run nftables script
+pub(super) async fn nft(script: &str) -> io::Result<()> {
+    let mut child = Command::new("nft")
+        .args(&["-f", "-"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(script.as_bytes()).await?;
+    }
+
+    let output = child.wait_with_output().await?;
+    if output.status.success() {
+        Ok(())
+    } else {
+        Err(io::Error::new(
+            io::ErrorKind::Other,
+            String::from_utf8_lossy(&output.stderr).trim().to_owned(),
+        ))
+    }
+}
+
+/// Run nftables script from [Drop], in background of current runtime, if there is one
+pub(super) fn nft_detached(script: String) {
+    match tokio::runtime::Handle::try_current() {
+        Ok(runtime) => {
+            runtime.spawn(async move {
+                if let Err(e) = nft(&script).await {
+                    telio_log_warn!("Failed to run nftables script: {}", e);
+                }
+            });
+        }
+        Err(_) => telio_log_warn!(
+            "No runtime to run nftables script, it is not applied: {}",
+            script.trim()
+        ),
+    }
+}
+
+/// Name of nftables table of interface, prefixed by its purpose
+pub(super) fn nft_table(prefix: &str, ifname: &str) -> String {
+    let ifname: String = ifname
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    format!("{}_{}", prefix, ifname)
+}
+
+/// Clamps MSS of TCP connections through interface to MTU of each peer's path, as kernel
+/// interface's MTU does not follow paths of its peers
+pub(super) struct MssClamp {
+    ifname: String,
+    /// Path MTUs set by [`super::Adapter::set_peer_mtu`], applied on next interface update
+    mtus: StdMutex<HashMap<PublicKey, u32>>,
+    /// Last applied ruleset, so nftables are touched only on change
+    applied: Mutex<Option<String>>,
+}
+
+impl MssClamp {
+    pub fn new(ifname: &str) -> Self {
+        Self {
+            ifname: ifname.to_owned(),
+            mtus: Default::default(),
+            applied: Mutex::new(None),
+        }
+    }
+
+    pub fn set_peer_mtu(&self, public_key: &PublicKey, mtu: u32) {
+        if let Ok(mut mtus) = self.mtus.lock() {
+            mtus.insert(*public_key, mtu);
+        }
+    }
+
+    /// Regenerate clamping rules for current peers, applying them if changed
+    pub async fn update(&self, interface: &Interface) {
+        let peers: Vec<_> = match self.mtus.lock() {
+            Ok(mut mtus) => {
+                mtus.retain(|public_key, _| interface.peers.contains_key(public_key));
+                interface
+                    .peers
+                    .iter()
+                    .filter_map(|(public_key, peer)| {
+                        Some((peer.allowed_ips.clone(), *mtus.get(public_key)?))
+                    })
+                    .collect()
+            }
+            Err(_) => return,
+        };
+
+        let mut applied = self.applied.lock().await;
+        let ruleset = if peers.is_empty() {
+            match applied.as_ref() {
+                Some(_) => self.delete(),
+                None => return,
+            }
+        } else {
+            mss_ruleset(&self.ifname, &peers)
+        };
+        if applied.as_ref() == Some(&ruleset) {
+            return;
+        }
+        match nft(&ruleset).await {
+            Ok(()) => *applied = Some(ruleset).filter(|_| !peers.is_empty()),
+            Err(e) => telio_log_error!("Failed to apply MSS clamping rules: {}", e),
+        }
+    }
+
+    /// Remove clamping rules
+    pub async fn clear(&self) {
+        if self.applied.lock().await.take().is_some() {
+            let _ = nft(&self.delete()).await;
+        }
+    }
+
+    /// Remove clamping rules in background, from [Drop] of adapter
+    pub fn clear_detached(&mut self) {
+        if self.applied.get_mut().take().is_some() {
+            nft_detached(self.delete());
+        }
+    }
+
+    fn delete(&self) -> String {
+        format!(
+            "delete table inet {}\n",
+            nft_table("telio_mss", &self.ifname)
+        )
+    }
+}
+
+/// Ruleset, replacing interface's MSS clamping table atomically. SYNs towards peer are
+/// clamped to MTU of its path, as well as SYNs from it, advertising MSS of peer's own link
+fn mss_ruleset(ifname: &str, peers: &[(Vec<IpNetwork>, u32)]) -> String {
+    let table = nft_table("telio_mss", ifname);
+    let mut ruleset = format!(
+        "table inet {table}\ndelete table inet {table}\ntable inet {table} {{\n",
+        table = table
+    );
+    for (hook, jumps) in &[
+        ("output", vec![("oifname", "outbound")]),
+        (
+            "forward",
+            vec![("oifname", "outbound"), ("iifname", "inbound")],
+        ),
+        ("input", vec![("iifname", "inbound")]),
+    ] {
+        ruleset.push_str(&format!(
+            "    chain {hook} {{\n        type filter hook {hook} priority mangle; policy accept;\n",
+            hook = hook
+        ));
+        for (dir, chain) in jumps {
+            ruleset.push_str(&format!("        {} \"{}\" jump {}\n", dir, ifname, chain));
+        }
+        ruleset.push_str("    }\n");
+    }
+
+    for (chain, addr) in &[("outbound", "daddr"), ("inbound", "saddr")] {
+        ruleset.push_str(&format!("    chain {} {{\n", chain));
+        for (allowed_ips, mtu) in peers {
+            for net in allowed_ips {
+                let (family, overhead) = match net {
+                    IpNetwork::V4(_) => ("ip", 40),
+                    IpNetwork::V6(_) => ("ip6", 60),
+                };
+                let mss = mtu.saturating_sub(overhead);
+                ruleset.push_str(&format!(
+                    "        {family} {addr} {net} tcp flags syn tcp option maxseg size > {mss} tcp option maxseg size set {mss}\n",
+                    family = family,
+                    addr = addr,
+                    net = net,
+                    mss = mss
+                ));
+            }
+        }
+        ruleset.push_str("    }\n");
+    }
+    ruleset.push_str("}\n");
+    ruleset
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mss_ruleset_clamps_both_directions() {
+        let peers = vec![(
+            vec![
+                "100.64.0.2/32".parse().unwrap(),
+                "fd74:656c:696f::2/128".parse().unwrap(),
+            ],
+            1380,
+        )];
+        let ruleset = mss_ruleset("nlx-0", &peers);
+        let lines: Vec<_> = ruleset.lines().map(str::trim).collect();
+
+        assert_eq!(
+            &lines[..3],
+            &[
+                "table inet telio_mss_nlx_0",
+                "delete table inet telio_mss_nlx_0",
+                "table inet telio_mss_nlx_0 {"
+            ]
+        );
+        for rule in &[
+            "ip daddr 100.64.0.2/32 tcp flags syn tcp option maxseg size > 1340 tcp option maxseg size set 1340",
+            "ip saddr 100.64.0.2/32 tcp flags syn tcp option maxseg size > 1340 tcp option maxseg size set 1340",
+            "ip6 daddr fd74:656c:696f::2/128 tcp flags syn tcp option maxseg size > 1320 tcp option maxseg size set 1320",
+        ] {
+            assert!(lines.contains(rule), "{} missing in {}", rule, ruleset);
+        }
+        assert_eq!(
+            lines
+                .iter()
+                .filter(|l| **l == "oifname \"nlx-0\" jump outbound")
+                .count(),
+            2
+        );
+    }
+}
@@ -0,0 +1,969 @@
+//! Userspace WireGuard, driving boringtun's noise protocol directly over pluggable packet I/O
+//!
+//! Unlike [BoringTun](super::boring::BoringTun) adapter, it neither creates nor needs a TUN
+//! device, plaintext packets are exchanged through [PacketIo]. This allows running inside a
+//! netstack, over in-memory pipes in tests or in unprivileged containers.
+
+#![warn(clippy::unwrap_used)]
+
+use super::{Adapter, Error as AdapterError, FirewallCb, Watcher};
+use crate::{
+    mtu::{clamp_mss, needs_mss_clamp, TUNNEL_MTU},
+    packet_io::PacketIo,
+    uapi::{Cmd, Interface, Peer as UapiPeer, Response},
+};
+use async_trait::async_trait;
+use boringtun::{
+    crypto::x25519::{X25519PublicKey, X25519SecretKey},
+    noise::{errors::WireGuardError, handshake::parse_handshake_anon, Packet, Tunn, TunnResult},
+};
+use ipnetwork::IpNetwork;
+use std::{
+    cmp::Reverse,
+    collections::{BTreeMap, HashMap},
+    convert::TryInto,
+    io,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, UdpSocket as StdUdpSocket},
+    os::unix::io::AsRawFd,
+    sync::{
+        atomic::{AtomicU32, AtomicU64, Ordering},
+        Arc, Mutex, RwLock, RwLockReadGuard, RwLockWriteGuard,
+    },
+    time::Duration,
+};
+use telio_crypto::{PresharedKey, PublicKey, SecretKey};
+use telio_sockets::SocketPool;
+use telio_utils::{telio_log_debug, telio_log_error, telio_log_warn};
+use tokio::{net::UdpSocket, runtime::Handle, sync::watch, task::JoinHandle, time};
+use wireguard_uapi::xplatform::set;
+
+/// Largest packet, that can be exchanged with peers or packet I/O
+const MAX_PACKET: usize = 65536;
+/// Interval of WireGuard timers (handshake retries, keepalives, session expiry)
+const TIMERS_TICK_MILLIS: u64 = 250;
+/// Peer indices are 24 bits, lowest 8 bits of receiver index are used by sessions
+const INDEX_MASK: u32 = 0x00ff_ffff;
+
+// WireGuard message types
+const HANDSHAKE_INIT: u8 = 1;
+const HANDSHAKE_RESPONSE: u8 = 2;
+const COOKIE_REPLY: u8 = 3;
+const DATA: u8 = 4;
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("Userspace adapter must be started from within tokio runtime")]
+    NoRuntime,
+    #[error("Private key must be set before adding peers")]
+    NoPrivateKey,
+    #[error("Invalid key")]
+    InvalidKey,
+    #[error("Failed to create tunnel: {0}")]
+    Tunnel(&'static str),
+    #[error("Failed to bind socket: {0}")]
+    Bind(io::Error),
+}
+
+/// Userspace WireGuard adapter implementation
+pub struct UserspaceWg {
+    inner: Arc<Inner>,
+    tasks: Vec<JoinHandle<()>>,
+}
+
+struct Inner {
+    /// Socket is replaced, when listen port changes
+    socket: watch::Sender<Arc<UdpSocket>>,
+    socket_pool: Arc<SocketPool>,
+    io: Arc<dyn PacketIo>,
+    state: RwLock<State>,
+    firewall_process_inbound_callback: FirewallCb,
+    firewall_process_outbound_callback: FirewallCb,
+    watcher: Watcher,
+}
+
+#[derive(Default)]
+struct State {
+    private_key: Option<SecretKey>,
+    fwmark: u32,
+    peers: HashMap<PublicKey, Arc<Peer>>,
+    by_index: HashMap<u32, Arc<Peer>>,
+    next_index: u32,
+    /// Allowed IPs of all peers by prefix length, the most specific first
+    routes: BTreeMap<Reverse<u8>, HashMap<IpNetwork, Arc<Peer>>>,
+}
+
+struct Peer {
+    public_key: PublicKey,
+    index: u32,
+    tunn: Box<Tunn>,
+    config: Mutex<PeerConfig>,
+    rx_bytes: AtomicU64,
+    tx_bytes: AtomicU64,
+    /// MTU of path towards peer, MSS of TCP connections is clamped to it
+    mtu: AtomicU32,
+}
+
+#[derive(Clone, Default)]
+struct PeerConfig {
+    endpoint: Option<SocketAddr>,
+    allowed_ips: Vec<IpNetwork>,
+    persistent_keepalive_interval: Option<u16>,
+    preshared_key: Option<[u8; 32]>,
+}
+
+impl UserspaceWg {
+    pub fn start(
+        io: Arc<dyn PacketIo>,
+        socket_pool: Arc<SocketPool>,
+        firewall_process_inbound_callback: FirewallCb,
+        firewall_process_outbound_callback: FirewallCb,
+        watcher: Watcher,
+    ) -> Result<Self, AdapterError> {
+        let runtime = Handle::try_current().map_err(|_| Error::NoRuntime)?;
+        let _guard = runtime.enter();
+
+        let socket = bind(&socket_pool, 0)?;
+
+        let inner = Arc::new(Inner {
+            socket: watch::channel(Arc::new(socket)).0,
+            socket_pool,
+            io,
+            state: Default::default(),
+            firewall_process_inbound_callback,
+            firewall_process_outbound_callback,
+            watcher,
+        });
+
+        let tasks = vec![
+            runtime.spawn(inner.clone().outbound()),
+            runtime.spawn(inner.clone().inbound()),
+            runtime.spawn(inner.clone().timers()),
+        ];
+
+        Ok(Self { inner, tasks })
+    }
+}
+
+#[async_trait]
+impl Adapter for UserspaceWg {
+    async fn send_uapi_cmd(&self, cmd: &Cmd) -> Response {
+        match cmd {
+            Cmd::Get => Response {
+                errno: 0,
+                interface: Some(self.inner.get()),
+            },
+            Cmd::Set(device) => Response {
+                errno: match self.inner.set(device) {
+                    Ok(()) => 0,
+                    Err(e) => {
+                        telio_log_error!("UserspaceWg: [SET01] {}", e);
+                        1
+                    }
+                },
+                interface: None,
+            },
+        }
+    }
+
+    fn get_adapter_luid(&self) -> u64 {
+        0
+    }
+
+    fn pushes_peer_changes(&self) -> bool {
+        // Peer activity is reported from the inbound packet path
+        true
+    }
+
+    fn set_peer_mtu(&self, public_key: &PublicKey, mtu: u32) {
+        if let Some(peer) = self.inner.state().peers.get(public_key) {
+            peer.mtu.store(mtu, Ordering::Relaxed);
+        }
+    }
+
+    async fn stop(&self) {
+        for task in &self.tasks {
+            task.abort();
+        }
+    }
+}
+
+impl Drop for UserspaceWg {
+    fn drop(&mut self) {
+        for task in &self.tasks {
+            task.abort();
+        }
+    }
+}
+
+impl Inner {
+    fn state(&self) -> RwLockReadGuard<State> {
+        self.state.read().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn state_mut(&self) -> RwLockWriteGuard<State> {
+        self.state.write().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn socket(&self) -> Arc<UdpSocket> {
+        self.socket.borrow().clone()
+    }
+
+    fn get(&self) -> Interface {
+        let state = self.state();
+        Interface {
+            private_key: state.private_key,
+            listen_port: self.socket().local_addr().ok().map(|addr| addr.port()),
+            fwmark: state.fwmark,
+            peers: state
+                .peers
+                .values()
+                .map(|peer| (peer.public_key, peer.to_uapi()))
+                .collect::<BTreeMap<_, _>>(),
+        }
+    }
+
+    fn set(&self, device: &set::Device) -> Result<(), Error> {
+        let mut state = self.state_mut();
+        let res = self.update(&mut state, device);
+        // Routes follow peers, even if update was applied only partially
+        state.rebuild_routes();
+        res
+    }
+
+    fn update(&self, state: &mut State, device: &set::Device) -> Result<(), Error> {
+        if let Some(fwmark) = device.fwmark {
+            self.set_fwmark(fwmark);
+            state.fwmark = fwmark;
+        }
+
+        if let Some(port) = device.listen_port {
+            self.set_listen_port(port, state.fwmark)?;
+        }
+
+        if let Some(private_key) = device.private_key {
+            let private_key = SecretKey(private_key);
+            if state.private_key != Some(private_key) {
+                state.private_key = Some(private_key);
+                // Sessions are bound to our static key, thus all of them are restarted
+                let peers: Vec<_> = state.peers.values().cloned().collect();
+                for peer in peers {
+                    state.insert_peer(peer.public_key, peer.config(), Some(&peer))?;
+                }
+            }
+        }
+
+        if matches!(device.replace_peers, Some(true)) {
+            state.peers.clear();
+            state.by_index.clear();
+        }
+
+        for update in &device.peers {
+            let public_key = PublicKey(update.public_key);
+
+            if matches!(update.remove, Some(true)) {
+                state.remove_peer(&public_key);
+                continue;
+            }
+
+            let current = state.peers.get(&public_key).cloned();
+            if current.is_none() && matches!(update.update_only, Some(true)) {
+                continue;
+            }
+
+            let old_config = current
+                .as_ref()
+                .map(|peer| peer.config())
+                .unwrap_or_default();
+            let mut config = old_config.clone();
+            if update.endpoint.is_some() {
+                config.endpoint = update.endpoint;
+            }
+            if let Some(interval) = update.persistent_keepalive_interval {
+                config.persistent_keepalive_interval = Some(interval).filter(|i| *i > 0);
+            }
+            if update.preshared_key.is_some() {
+                config.preshared_key = update.preshared_key;
+            }
+            if matches!(update.replace_allowed_ips, Some(true)) {
+                config.allowed_ips.clear();
+            }
+            for ip in &update.allowed_ips {
+                if let Ok(ip) = IpNetwork::new(ip.ipaddr, ip.cidr_mask) {
+                    if !config.allowed_ips.contains(&ip) {
+                        config.allowed_ips.push(ip);
+                    }
+                }
+            }
+
+            match current {
+                Some(peer)
+                    if config.persistent_keepalive_interval
+                        == old_config.persistent_keepalive_interval
+                        && config.preshared_key == old_config.preshared_key =>
+                {
+                    peer.set_config(config);
+                }
+                current => state.insert_peer(public_key, config, current.as_deref())?,
+            }
+        }
+
+        Ok(())
+    }
+
+    fn set_listen_port(&self, port: u16, fwmark: u32) -> Result<(), Error> {
+        // Port `0` leaves the choice to OS, so any bound port satisfies it
+        let current = self.socket().local_addr().ok().map(|addr| addr.port());
+        if port == 0 || current == Some(port) {
+            return Ok(());
+        }
+
+        let socket = bind(&self.socket_pool, port).map_err(Error::Bind)?;
+        self.socket.send_replace(Arc::new(socket));
+        if fwmark != 0 {
+            self.set_fwmark(fwmark);
+        }
+        telio_log_debug!("UserspaceWg: listening on port {}", port);
+        Ok(())
+    }
+
+    #[cfg(target_os = "linux")]
+    fn set_fwmark(&self, fwmark: u32) {
+        let res = unsafe {
+            libc::setsockopt(
+                self.socket().as_raw_fd(),
+                libc::SOL_SOCKET,
+                libc::SO_MARK,
+                &fwmark as *const u32 as *const libc::c_void,
+                std::mem::size_of::<u32>() as libc::socklen_t,
+            )
+        };
+        if res < 0 {
+            telio_log_warn!(
+                "UserspaceWg: failed to set fwmark: {}",
+                io::Error::last_os_error()
+            );
+        }
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn set_fwmark(&self, _fwmark: u32) {}
+
+    /// Find peer, whose allowed IPs most specifically match the destination
+    fn route(&self, dst: IpAddr) -> Option<Arc<Peer>> {
+        self.state().route(dst)
+    }
+
+    /// Find peer, which has sent the datagram
+    fn sender(&self, datagram: &[u8]) -> Option<Arc<Peer>> {
+        let state = self.state();
+        match receiver_index(datagram) {
+            Some(index) => state.by_index.get(&(index >> 8)).cloned(),
+            None if datagram.first() == Some(&HANDSHAKE_INIT) => state.initiator(datagram),
+            None => None,
+        }
+    }
+
+    async fn send_to_peer(&self, peer: &Peer, datagram: &[u8]) {
+        let endpoint = match peer.endpoint() {
+            Some(endpoint) => endpoint,
+            None => return,
+        };
+        match self.socket().send_to(datagram, endpoint).await {
+            Ok(len) => {
+                peer.tx_bytes.fetch_add(len as u64, Ordering::Relaxed);
+            }
+            Err(e) => telio_log_debug!("UserspaceWg: failed to send to {}: {}", endpoint, e),
+        }
+    }
+
+    /// Encrypt packets, coming from packet I/O, and send them to peers
+    async fn outbound(self: Arc<Self>) {
+        let mut packet = vec![0u8; MAX_PACKET];
+        let mut dst = vec![0u8; MAX_PACKET];
+        loop {
+            let len = match self.io.recv(&mut packet).await {
+                Ok(len) => len,
+                Err(e) if e.kind() == io::ErrorKind::BrokenPipe => {
+                    telio_log_warn!("UserspaceWg: packet I/O was closed");
+                    return;
+                }
+                Err(e) => {
+                    telio_log_debug!("UserspaceWg: failed to receive packet: {}", e);
+                    continue;
+                }
+            };
+            let packet = &mut packet[..len];
+
+            let peer = match destination(packet).and_then(|dst| self.route(dst)) {
+                Some(peer) => peer,
+                None => continue,
+            };
+
+            if let Some(callback) = &self.firewall_process_outbound_callback {
+                if !callback(&peer.public_key.0, packet) {
+                    continue;
+                }
+            }
+
+            clamp_mss(packet, peer.mtu.load(Ordering::Relaxed));
+
+            match peer.tunn.encapsulate(packet, &mut dst) {
+                TunnResult::WriteToNetwork(datagram) => self.send_to_peer(&peer, datagram).await,
+                TunnResult::Err(e) => telio_log_debug!("UserspaceWg: encapsulate failed: {:?}", e),
+                _ => (),
+            }
+        }
+    }
+
+    /// Decrypt datagrams, coming from peers, and pass them to packet I/O
+    async fn inbound(self: Arc<Self>) {
+        let mut datagram = vec![0u8; MAX_PACKET];
+        let mut dst = vec![0u8; MAX_PACKET];
+        let mut sockets = self.socket.subscribe();
+        let mut socket = sockets.borrow_and_update().clone();
+        loop {
+            let (len, from) = tokio::select! {
+                res = socket.recv_from(&mut datagram) => match res {
+                    Ok(res) => res,
+                    Err(e) => {
+                        telio_log_debug!("UserspaceWg: failed to receive datagram: {}", e);
+                        continue;
+                    }
+                },
+                // Listen port has changed
+                Ok(()) = sockets.changed() => {
+                    socket = sockets.borrow_and_update().clone();
+                    continue;
+                }
+            };
+            let datagram = &datagram[..len];
+
+            let peer = match self.sender(datagram) {
+                Some(peer) => peer,
+                None => continue,
+            };
+
+            let res = peer.tunn.decapsulate(Some(from.ip()), datagram, &mut dst);
+            if let TunnResult::Err(e) = res {
+                telio_log_debug!("UserspaceWg: decapsulate failed: {:?}", e);
+                continue;
+            }
+
+            peer.rx_bytes.fetch_add(len as u64, Ordering::Relaxed);
+            peer.set_endpoint(from);
+            self.watcher.peer_active(&peer.public_key.0);
+
+            match res {
+                TunnResult::WriteToNetwork(reply) => {
+                    self.send_to_peer(&peer, reply).await;
+                    // Handshake is complete, flush packets queued while it was ongoing
+                    let mut queued = vec![0u8; MAX_PACKET];
+                    while let TunnResult::WriteToNetwork(packet) =
+                        peer.tunn.decapsulate(None, &[], &mut queued)
+                    {
+                        self.send_to_peer(&peer, packet).await;
+                    }
+                }
+                TunnResult::WriteToTunnelV4(packet, src) => {
+                    self.deliver(&peer, packet, IpAddr::V4(src)).await
+                }
+                TunnResult::WriteToTunnelV6(packet, src) => {
+                    self.deliver(&peer, packet, IpAddr::V6(src)).await
+                }
+                _ => (),
+            }
+        }
+    }
+
+    async fn deliver(&self, peer: &Peer, packet: &[u8], src: IpAddr) {
+        // Source must be routed back to the same peer
+        if self
+            .route(src)
+            .map_or(true, |owner| owner.public_key != peer.public_key)
+        {
+            telio_log_debug!("UserspaceWg: dropping packet from disallowed {}", src);
+            return;
+        }
+
+        if let Some(callback) = &self.firewall_process_inbound_callback {
+            if !callback(&peer.public_key.0, packet) {
+                return;
+            }
+        }
+
+        // Peer's SYN advertises MSS of its own link, which may not fit into the path
+        let mtu = peer.mtu.load(Ordering::Relaxed);
+        let mut clamped = Vec::new();
+        let packet = if needs_mss_clamp(packet, mtu) {
+            clamped.extend_from_slice(packet);
+            clamp_mss(&mut clamped, mtu);
+            &clamped[..]
+        } else {
+            packet
+        };
+
+        if let Err(e) = self.io.send(packet).await {
+            telio_log_debug!("UserspaceWg: failed to deliver packet: {}", e);
+        }
+    }
+
+    /// Drive handshake retries, keepalives and session expiry
+    async fn timers(self: Arc<Self>) {
+        let mut interval = time::interval(Duration::from_millis(TIMERS_TICK_MILLIS));
+        let mut dst = vec![0u8; MAX_PACKET];
+        loop {
+            interval.tick().await;
+            let peers: Vec<_> = self.state().peers.values().cloned().collect();
+            for peer in peers {
+                match peer.tunn.update_timers(&mut dst) {
+                    TunnResult::WriteToNetwork(datagram) => {
+                        self.send_to_peer(&peer, datagram).await
+                    }
+                    // Expired sessions are re-established by the next outbound packet
+                    TunnResult::Err(WireGuardError::ConnectionExpired) => {
+                        telio_log_debug!("UserspaceWg: session of {:?} expired", peer.public_key)
+                    }
+                    TunnResult::Err(e) => telio_log_warn!(
+                        "UserspaceWg: timers of {:?} failed: {:?}",
+                        peer.public_key,
+                        e
+                    ),
+                    _ => (),
+                }
+            }
+        }
+    }
+}
+
+impl State {
+    /// Create (or recreate, keeping counters of `previous`) peer's session
+    fn insert_peer(
+        &mut self,
+        public_key: PublicKey,
+        config: PeerConfig,
+        previous: Option<&Peer>,
+    ) -> Result<(), Error> {
+        let private_key = self.private_key.as_ref().ok_or(Error::NoPrivateKey)?;
+        let private_key: X25519SecretKey = private_key
+            .to_string()
+            .parse()
+            .map_err(|_| Error::InvalidKey)?;
+
+        let index = self.allocate_index();
+        let tunn = Tunn::new(
+            Arc::new(private_key),
+            Arc::new(X25519PublicKey::from(&public_key.0[..])),
+            config.preshared_key,
+            config.persistent_keepalive_interval,
+            index,
+            None,
+        )
+        .map_err(Error::Tunnel)?;
+
+        let peer = Arc::new(Peer {
+            public_key,
+            index,
+            tunn,
+            config: Mutex::new(config),
+            rx_bytes: AtomicU64::new(previous.map_or(0, |p| p.rx_bytes.load(Ordering::Relaxed))),
+            tx_bytes: AtomicU64::new(previous.map_or(0, |p| p.tx_bytes.load(Ordering::Relaxed))),
+            mtu: AtomicU32::new(previous.map_or(TUNNEL_MTU, |p| p.mtu.load(Ordering::Relaxed))),
+        });
+
+        self.remove_peer(&public_key);
+        self.by_index.insert(index, peer.clone());
+        self.peers.insert(public_key, peer);
+        Ok(())
+    }
+
+    /// Find initiator of handshake by decrypting its static key, instead of trying
+    /// the handshake against every peer
+    fn initiator(&self, datagram: &[u8]) -> Option<Arc<Peer>> {
+        let private_key: X25519SecretKey = self.private_key.as_ref()?.to_string().parse().ok()?;
+        let public_key = private_key.public_key();
+
+        match Tunn::parse_incoming_packet(datagram) {
+            Ok(Packet::HandshakeInit(init)) => {
+                let half = parse_handshake_anon(&private_key, &public_key, &init).ok()?;
+                self.peers.get(&PublicKey(half.peer_static_public)).cloned()
+            }
+            _ => None,
+        }
+    }
+
+    fn remove_peer(&mut self, public_key: &PublicKey) {
+        if let Some(peer) = self.peers.remove(public_key) {
+            self.by_index.remove(&peer.index);
+        }
+    }
+
+    fn route(&self, dst: IpAddr) -> Option<Arc<Peer>> {
+        self.routes.iter().find_map(|(Reverse(prefix), peers)| {
+            // Prefix may be too long for address family of destination
+            let network = IpNetwork::new(dst, *prefix).ok()?;
+            peers
+                .get(&IpNetwork::new(network.network(), *prefix).ok()?)
+                .cloned()
+        })
+    }
+
+    fn rebuild_routes(&mut self) {
+        self.routes.clear();
+        for peer in self.peers.values() {
+            for net in peer.config().allowed_ips {
+                if let Ok(key) = IpNetwork::new(net.network(), net.prefix()) {
+                    self.routes
+                        .entry(Reverse(net.prefix()))
+                        .or_default()
+                        .insert(key, peer.clone());
+                }
+            }
+        }
+    }
+
+    fn allocate_index(&mut self) -> u32 {
+        loop {
+            self.next_index = (self.next_index + 1) & INDEX_MASK;
+            if !self.by_index.contains_key(&self.next_index) {
+                return self.next_index;
+            }
+        }
+    }
+}
+
+impl Peer {
+    fn config(&self) -> PeerConfig {
+        self.config
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    fn set_config(&self, config: PeerConfig) {
+        *self.config.lock().unwrap_or_else(|e| e.into_inner()) = config;
+    }
+
+    fn endpoint(&self) -> Option<SocketAddr> {
+        self.config
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .endpoint
+    }
+
+    /// Peer has roamed, or its endpoint was learned from the handshake
+    fn set_endpoint(&self, endpoint: SocketAddr) {
+        self.config
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .endpoint = Some(endpoint);
+    }
+
+    fn to_uapi(&self) -> UapiPeer {
+        let config = self.config();
+        UapiPeer {
+            public_key: self.public_key,
+            endpoint: config.endpoint,
+            persistent_keepalive_interval: config.persistent_keepalive_interval.map(u32::from),
+            allowed_ips: config.allowed_ips,
+            rx_bytes: Some(self.rx_bytes.load(Ordering::Relaxed)),
+            tx_bytes: Some(self.tx_bytes.load(Ordering::Relaxed)),
+            time_since_last_handshake: self.tunn.time_since_last_handshake(),
+            preshared_key: config.preshared_key.map(PresharedKey),
+        }
+    }
+}
+
+/// Bind socket for exchanging datagrams with peers, port `0` picks any free one
+fn bind(socket_pool: &SocketPool, port: u16) -> io::Result<UdpSocket> {
+    let socket = StdUdpSocket::bind((Ipv4Addr::UNSPECIFIED, port))?;
+    socket.set_nonblocking(true)?;
+    socket_pool.make_external(socket.as_raw_fd());
+    UdpSocket::from_std(socket)
+}
+
+/// Destination address of plaintext IP packet
+fn destination(packet: &[u8]) -> Option<IpAddr> {
+    match packet.first()? >> 4 {
+        4 => {
+            let dst: [u8; 4] = packet.get(16..20)?.try_into().ok()?;
+            Some(Ipv4Addr::from(dst).into())
+        }
+        6 => {
+            let dst: [u8; 16] = packet.get(24..40)?.try_into().ok()?;
+            Some(Ipv6Addr::from(dst).into())
+        }
+        _ => None,
+    }
+}
+
+/// Receiver index of WireGuard message, `None` for handshake initiations
+fn receiver_index(datagram: &[u8]) -> Option<u32> {
+    let range = match *datagram.first()? {
+        HANDSHAKE_RESPONSE => 8..12,
+        COOKIE_REPLY | DATA => 4..8,
+        _ => return None,
+    };
+    Some(u32::from_le_bytes(datagram.get(range)?.try_into().ok()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::packet_io::{packet_pipe, PacketPipe};
+
+    fn ipv4_packet(src: Ipv4Addr, dst: Ipv4Addr, payload: &[u8]) -> Vec<u8> {
+        let mut packet = vec![0u8; 20];
+        packet[0] = 0x45;
+        packet[2..4].copy_from_slice(&((20 + payload.len()) as u16).to_be_bytes());
+        packet[8] = 64;
+        packet[9] = 17;
+        packet[12..16].copy_from_slice(&src.octets());
+        packet[16..20].copy_from_slice(&dst.octets());
+        packet.extend_from_slice(payload);
+        packet
+    }
+
+    fn peer_update(
+        public_key: PublicKey,
+        endpoint: Option<SocketAddr>,
+        allowed_ip: Ipv4Addr,
+    ) -> set::Peer {
+        set::Peer {
+            public_key: public_key.0,
+            endpoint,
+            allowed_ips: vec![set::AllowedIp {
+                ipaddr: allowed_ip.into(),
+                cidr_mask: 32,
+            }],
+            ..Default::default()
+        }
+    }
+
+    fn start(key: SecretKey) -> (UserspaceWg, PacketPipe) {
+        let (ours, theirs) = packet_pipe();
+        let adapter = UserspaceWg::start(
+            Arc::new(theirs),
+            Arc::new(SocketPool::default()),
+            None,
+            None,
+            Watcher::new().0,
+        )
+        .expect("adapter starts");
+        adapter
+            .inner
+            .set(&set::Device {
+                private_key: Some(key.0),
+                ..Default::default()
+            })
+            .expect("private key is set");
+        (adapter, ours)
+    }
+
+    #[test]
+    fn parses_packet_destination() {
+        let packet = ipv4_packet(Ipv4Addr::new(10, 0, 0, 1), Ipv4Addr::new(10, 0, 0, 2), &[]);
+        assert_eq!(
+            Some(IpAddr::V4(Ipv4Addr::new(10, 0, 0, 2))),
+            destination(&packet)
+        );
+
+        let mut packet = vec![0u8; 40];
+        packet[0] = 0x60;
+        packet[39] = 1;
+        assert_eq!(Some(IpAddr::V6(Ipv6Addr::LOCALHOST)), destination(&packet));
+
+        assert_eq!(None, destination(&[0x45, 0, 0]));
+        assert_eq!(None, destination(&[]));
+    }
+
+    #[test]
+    fn parses_receiver_index() {
+        let mut response = vec![HANDSHAKE_RESPONSE, 0, 0, 0];
+        response.extend_from_slice(&7u32.to_le_bytes());
+        response.extend_from_slice(&0x0102u32.to_le_bytes());
+        assert_eq!(Some(0x0102), receiver_index(&response));
+
+        let mut data = vec![DATA, 0, 0, 0];
+        data.extend_from_slice(&0x0304u32.to_le_bytes());
+        assert_eq!(Some(0x0304), receiver_index(&data));
+
+        assert_eq!(None, receiver_index(&[HANDSHAKE_INIT, 0, 0, 0, 1, 2, 3, 4]));
+        assert_eq!(None, receiver_index(&[DATA, 0, 0]));
+    }
+
+    #[tokio::test]
+    async fn exchanges_packets_over_packet_io() {
+        let (alpha_key, beta_key) = (SecretKey::gen(), SecretKey::gen());
+        let (alpha_ip, beta_ip) = (Ipv4Addr::new(100, 64, 0, 1), Ipv4Addr::new(100, 64, 0, 2));
+        let (alpha, alpha_io) = start(alpha_key);
+        let (beta, beta_io) = start(beta_key);
+
+        let beta_endpoint = SocketAddr::new(
+            Ipv4Addr::LOCALHOST.into(),
+            beta.inner.get().listen_port.expect("beta is bound"),
+        );
+        alpha
+            .inner
+            .set(&set::Device {
+                peers: vec![peer_update(beta_key.public(), Some(beta_endpoint), beta_ip)],
+                ..Default::default()
+            })
+            .expect("beta is added");
+        beta.inner
+            .set(&set::Device {
+                peers: vec![peer_update(alpha_key.public(), None, alpha_ip)],
+                ..Default::default()
+            })
+            .expect("alpha is added");
+
+        let packet = ipv4_packet(alpha_ip, beta_ip, b"ping");
+        alpha_io.send(&packet).await.expect("packet is sent");
+
+        let mut buf = vec![0u8; MAX_PACKET];
+        let len = time::timeout(Duration::from_secs(5), beta_io.recv(&mut buf))
+            .await
+            .expect("packet arrives in time")
+            .expect("packet is received");
+        assert_eq!(packet, &buf[..len]);
+
+        let alpha_peer = &beta.inner.get().peers[&alpha_key.public()];
+        assert!(alpha_peer.endpoint.is_some());
+        assert!(alpha_peer.rx_bytes.unwrap_or_default() > 0);
+
+        alpha.stop().await;
+        beta.stop().await;
+    }
+
+    #[tokio::test]
+    async fn finds_initiator_among_peers() {
+        let (alpha_key, beta_key) = (SecretKey::gen(), SecretKey::gen());
+        let (alpha, _alpha_io) = start(alpha_key);
+        let (beta, _beta_io) = start(beta_key);
+
+        let mut peers: Vec<_> = (1..=8)
+            .map(|i| {
+                peer_update(
+                    SecretKey::gen().public(),
+                    None,
+                    Ipv4Addr::new(100, 64, 1, i),
+                )
+            })
+            .collect();
+        peers.push(peer_update(
+            alpha_key.public(),
+            None,
+            Ipv4Addr::new(100, 64, 0, 1),
+        ));
+        beta.inner
+            .set(&set::Device {
+                peers,
+                ..Default::default()
+            })
+            .expect("peers are added");
+
+        alpha
+            .inner
+            .set(&set::Device {
+                peers: vec![peer_update(
+                    beta_key.public(),
+                    None,
+                    Ipv4Addr::new(100, 64, 0, 2),
+                )],
+                ..Default::default()
+            })
+            .expect("beta is added");
+        let mut init = vec![0u8; MAX_PACKET];
+        let init = match alpha.inner.state().peers[&beta_key.public()]
+            .tunn
+            .format_handshake_initiation(&mut init, false)
+        {
+            TunnResult::WriteToNetwork(init) => init.to_vec(),
+            _ => panic!("Handshake initiation is not formatted"),
+        };
+
+        let initiator = beta.inner.sender(&init).expect("initiator is found");
+        assert_eq!(alpha_key.public(), initiator.public_key);
+        assert!(beta.inner.sender(&init[..init.len() - 1]).is_none());
+
+        alpha.stop().await;
+        beta.stop().await;
+    }
+
+    #[tokio::test]
+    async fn rebinds_to_listen_port() {
+        let (adapter, _io) = start(SecretKey::gen());
+        let port = StdUdpSocket::bind((Ipv4Addr::LOCALHOST, 0))
+            .and_then(|socket| socket.local_addr())
+            .expect("free port is found")
+            .port();
+
+        adapter
+            .inner
+            .set(&set::Device {
+                listen_port: Some(port),
+                ..Default::default()
+            })
+            .expect("listen port is set");
+        assert_eq!(Some(port), adapter.inner.get().listen_port);
+
+        // Any port satisfies `0`, so socket is kept
+        adapter
+            .inner
+            .set(&set::Device {
+                listen_port: Some(0),
+                ..Default::default()
+            })
+            .expect("listen port is set");
+        assert_eq!(Some(port), adapter.inner.get().listen_port);
+
+        adapter.stop().await;
+    }
+
+    #[tokio::test]
+    async fn routes_to_most_specific_prefix() {
+        let (adapter, _io) = start(SecretKey::gen());
+        let (exit, peer) = (SecretKey::gen().public(), SecretKey::gen().public());
+        let peer_ip = Ipv4Addr::new(100, 64, 0, 2);
+        let route = |ip: Ipv4Addr| adapter.inner.route(ip.into()).map(|peer| peer.public_key);
+
+        let mut exit_update = peer_update(exit, None, Ipv4Addr::UNSPECIFIED);
+        exit_update.allowed_ips[0].cidr_mask = 0;
+        adapter
+            .inner
+            .set(&set::Device {
+                peers: vec![exit_update, peer_update(peer, None, peer_ip)],
+                ..Default::default()
+            })
+            .expect("peers are added");
+        assert_eq!(Some(peer), route(peer_ip));
+        assert_eq!(Some(exit), route(Ipv4Addr::new(1, 1, 1, 1)));
+
+        // Routes follow allowed IPs, changed in place
+        adapter
+            .inner
+            .set(&set::Device {
+                peers: vec![set::Peer {
+                    replace_allowed_ips: Some(true),
+                    ..peer_update(peer, None, Ipv4Addr::new(100, 64, 0, 3))
+                }],
+                ..Default::default()
+            })
+            .expect("allowed IPs are replaced");
+        assert_eq!(Some(exit), route(peer_ip));
+        assert_eq!(Some(peer), route(Ipv4Addr::new(100, 64, 0, 3)));
+
+        adapter
+            .inner
+            .set(&set::Device {
+                peers: vec![set::Peer {
+                    remove: Some(true),
+                    ..peer_update(exit, None, Ipv4Addr::UNSPECIFIED)
+                }],
+                ..Default::default()
+            })
+            .expect("peer is removed");
+        assert_eq!(None, route(peer_ip));
+
+        adapter.stop().await;
+    }
+}
@@ -5,9 +5,13 @@ pub(crate) mod adapter;
 pub(crate) mod wg;
 pub(crate) mod windows;
 
+pub mod mtu;
+pub mod packet_io;
+pub mod stats;
 pub mod uapi;
 
 pub use crate::{
-    adapter::{AdapterType, Error, FirewallCb, Tun},
+    adapter::{AdapterType, Error, FirewallCb, FirewallRulesCb, Tun},
+    packet_io::PacketIo,
     wg::*,
 };
@@ -0,0 +1,240 @@
+//! Tunnel MTU and TCP MSS clamping, so TCP segments sent over tunnel fit into the path
+//! towards peer, even if ICMP "fragmentation needed" messages are dropped on the way
+
+use std::io;
+
+/// MTU of tunnel interface, fitting into 1500 byte links with WireGuard's IPv6 overhead
+pub const TUNNEL_MTU: u32 = 1420;
+
+const TCP: u8 = 6;
+const SYN: u8 = 0x02;
+const END_OPTION: u8 = 0;
+const NOP_OPTION: u8 = 1;
+const MSS_OPTION: u8 = 2;
+
+/// MSS option of TCP SYN, which exceeds MSS fitting into MTU
+struct Clamp {
+    /// Offset of MSS value within packet
+    offset: usize,
+    /// Offset of TCP checksum within packet
+    checksum: usize,
+    old: u16,
+    new: u16,
+}
+
+/// Does packet carry a TCP SYN, whose MSS option exceeds `mtu`
+pub fn needs_mss_clamp(packet: &[u8], mtu: u32) -> bool {
+    find_clamp(packet, mtu).is_some()
+}
+
+/// Lower MSS option of TCP SYN packet, so segments of the connection fit into `mtu`.
+/// Returns [`true`] if packet was modified
+pub fn clamp_mss(packet: &mut [u8], mtu: u32) -> bool {
+    let clamp = match find_clamp(packet, mtu) {
+        Some(clamp) => clamp,
+        None => return false,
+    };
+
+    packet[clamp.offset..clamp.offset + 2].copy_from_slice(&clamp.new.to_be_bytes());
+    update_checksum(
+        &mut packet[clamp.checksum..clamp.checksum + 2],
+        clamp.old,
+        clamp.new,
+    );
+    true
+}
+
+fn find_clamp(packet: &[u8], mtu: u32) -> Option<Clamp> {
+    let (header_len, overhead) = match packet.first()? >> 4 {
+        4 => {
+            // Only the first fragment carries TCP header
+            let fragment_offset = u16::from_be_bytes([*packet.get(6)?, *packet.get(7)?]) & 0x1fff;
+            if *packet.get(9)? != TCP || fragment_offset != 0 {
+                return None;
+            }
+            (((packet[0] & 0x0f) as usize) * 4, 40)
+        }
+        // Extension headers are not walked, SYNs carrying them are left as they are
+        6 if *packet.get(6)? == TCP => (40, 60),
+        _ => return None,
+    };
+    let max_mss = mtu.saturating_sub(overhead).min(u16::MAX as u32) as u16;
+
+    let tcp = packet.get(header_len..)?;
+    if tcp.len() < 20 || tcp[13] & SYN == 0 {
+        return None;
+    }
+
+    let options_end = (((tcp[12] >> 4) as usize) * 4).min(tcp.len());
+    let mut i = 20;
+    while i < options_end {
+        match tcp[i] {
+            END_OPTION => return None,
+            NOP_OPTION => i += 1,
+            kind => {
+                let len = *tcp.get(i + 1)? as usize;
+                if len < 2 {
+                    return None;
+                }
+                if kind == MSS_OPTION && len == 4 && i + 4 <= options_end {
+                    let old = u16::from_be_bytes([tcp[i + 2], tcp[i + 3]]);
+                    return Some(Clamp {
+                        offset: header_len + i + 2,
+                        checksum: header_len + 16,
+                        old,
+                        new: max_mss,
+                    })
+                    .filter(|_| old > max_mss);
+                }
+                i += len;
+            }
+        }
+    }
+
+    None
+}
+
+/// Update ones' complement checksum for a changed 16 bit word, as in RFC 1624
+fn update_checksum(checksum: &mut [u8], old: u16, new: u16) {
+    let sum = !u16::from_be_bytes([checksum[0], checksum[1]]) as u32 + !old as u32 + new as u32;
+    let sum = (sum & 0xffff) + (sum >> 16);
+    let sum = (sum & 0xffff) + (sum >> 16);
+    checksum.copy_from_slice(&(!(sum as u16)).to_be_bytes());
+}
+
+/// Set MTU of network link
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub fn set_link_mtu(name: &str, mtu: u32) -> io::Result<()> {
+    /// `ifreq` with `ifr_mtu` member of its union
+    #[repr(C)]
+    struct IfReqMtu {
+        name: [libc::c_char; libc::IFNAMSIZ],
+        mtu: libc::c_int,
+        _pad: [u8; 20],
+    }
+
+    if name.len() >= libc::IFNAMSIZ {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Link name is too long",
+        ));
+    }
+    let mut req = IfReqMtu {
+        name: [0; libc::IFNAMSIZ],
+        mtu: mtu as libc::c_int,
+        _pad: [0; 20],
+    };
+    for (dst, src) in req.name.iter_mut().zip(name.bytes()) {
+        *dst = src as libc::c_char;
+    }
+
+    let fd = unsafe { libc::socket(libc::AF_INET, libc::SOCK_DGRAM | libc::SOCK_CLOEXEC, 0) };
+    if fd < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let res = unsafe { libc::ioctl(fd, libc::SIOCSIFMTU as _, &mut req as *mut IfReqMtu) };
+    let err = io::Error::last_os_error();
+    unsafe { libc::close(fd) };
+
+    if res < 0 {
+        return Err(err);
+    }
+    Ok(())
+}
+
+/// Set MTU of network link
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+pub fn set_link_mtu(_name: &str, _mtu: u32) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Other,
+        "Setting link MTU is not supported on this platform",
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ones_complement_sum(data: &[u8]) -> u32 {
+        let mut sum = data
+            .chunks(2)
+            .map(|c| u16::from_be_bytes([c[0], *c.get(1).unwrap_or(&0)]) as u32)
+            .sum::<u32>();
+        while sum > 0xffff {
+            sum = (sum & 0xffff) + (sum >> 16);
+        }
+        sum
+    }
+
+    fn tcp_checksum_valid(packet: &[u8]) -> bool {
+        let tcp = &packet[20..];
+        let mut pseudo = packet[12..20].to_vec();
+        pseudo.extend_from_slice(&[0, TCP]);
+        pseudo.extend_from_slice(&(tcp.len() as u16).to_be_bytes());
+        ones_complement_sum(&[pseudo, tcp.to_vec()].concat()) == 0xffff
+    }
+
+    fn ipv4_syn(flags: u8, mss: u16) -> Vec<u8> {
+        let mut packet = vec![0u8; 20];
+        packet[0] = 0x45;
+        packet[2..4].copy_from_slice(&48u16.to_be_bytes());
+        packet[8] = 64;
+        packet[9] = TCP;
+        packet[12..16].copy_from_slice(&[100, 64, 0, 1]);
+        packet[16..20].copy_from_slice(&[100, 64, 0, 2]);
+
+        let mut tcp = vec![0u8; 20];
+        tcp[0..2].copy_from_slice(&40000u16.to_be_bytes());
+        tcp[2..4].copy_from_slice(&443u16.to_be_bytes());
+        tcp[12] = 7 << 4;
+        tcp[13] = flags;
+        // NOP and window scale options, followed by MSS
+        tcp.extend_from_slice(&[NOP_OPTION, 3, 3, 7]);
+        tcp.extend_from_slice(&[MSS_OPTION, 4]);
+        tcp.extend_from_slice(&mss.to_be_bytes());
+        packet.extend_from_slice(&tcp);
+
+        let sum = {
+            let mut pseudo = packet[12..20].to_vec();
+            pseudo.extend_from_slice(&[0, TCP]);
+            pseudo.extend_from_slice(&(tcp.len() as u16).to_be_bytes());
+            ones_complement_sum(&[pseudo, tcp].concat())
+        };
+        packet[36..38].copy_from_slice(&(!(sum as u16)).to_be_bytes());
+        assert!(tcp_checksum_valid(&packet));
+        packet
+    }
+
+    #[test]
+    fn clamps_mss_of_syn() {
+        let mut packet = ipv4_syn(SYN, 1460);
+        assert!(needs_mss_clamp(&packet, TUNNEL_MTU));
+        assert!(clamp_mss(&mut packet, TUNNEL_MTU));
+        assert_eq!(&packet[46..48], &1380u16.to_be_bytes());
+        assert!(tcp_checksum_valid(&packet));
+
+        // Already fits
+        assert!(!clamp_mss(&mut packet, TUNNEL_MTU));
+    }
+
+    #[test]
+    fn leaves_other_packets_intact() {
+        // Not a SYN
+        let mut packet = ipv4_syn(0x10, 1460);
+        assert!(!clamp_mss(&mut packet, TUNNEL_MTU));
+
+        // Small MSS
+        let mut packet = ipv4_syn(SYN, 1200);
+        assert!(!clamp_mss(&mut packet, TUNNEL_MTU));
+
+        // Not TCP
+        let mut packet = ipv4_syn(SYN, 1460);
+        packet[9] = 17;
+        assert!(!clamp_mss(&mut packet, TUNNEL_MTU));
+
+        // Truncated
+        let mut packet = ipv4_syn(SYN, 1460);
+        assert!(!clamp_mss(&mut packet[..30], TUNNEL_MTU));
+        assert!(!clamp_mss(&mut [], TUNNEL_MTU));
+    }
+}
@@ -0,0 +1,223 @@
+//! Pluggable source and sink of plaintext IP packets for userspace WireGuard adapter
+
+use async_trait::async_trait;
+use std::io;
+use tokio::sync::{mpsc, Mutex};
+
+#[cfg(unix)]
+use {
+    std::os::unix::io::{AsRawFd, RawFd},
+    tokio::io::unix::AsyncFd,
+};
+
+/// Capacity of in-memory packet queue, per direction
+const PIPE_CAPACITY: usize = 1024;
+
+/// Source and sink of plaintext IP packets, replacing TUN device
+#[async_trait]
+pub trait PacketIo: Send + Sync + 'static {
+    /// Receive next IP packet to be sent through the tunnel, returns its length
+    async fn recv(&self, buf: &mut [u8]) -> io::Result<usize>;
+    /// Deliver IP packet, received through the tunnel
+    async fn send(&self, packet: &[u8]) -> io::Result<()>;
+}
+
+/// One end of in-memory packet pipe, e.g. for connecting adapter with netstack or tests
+pub struct PacketPipe {
+    tx: mpsc::Sender<Vec<u8>>,
+    rx: Mutex<mpsc::Receiver<Vec<u8>>>,
+}
+
+/// Create in-memory packet pipe, packets sent through one end are received on the other
+pub fn packet_pipe() -> (PacketPipe, PacketPipe) {
+    let (a_tx, a_rx) = mpsc::channel(PIPE_CAPACITY);
+    let (b_tx, b_rx) = mpsc::channel(PIPE_CAPACITY);
+
+    (
+        PacketPipe {
+            tx: a_tx,
+            rx: Mutex::new(b_rx),
+        },
+        PacketPipe {
+            tx: b_tx,
+            rx: Mutex::new(a_rx),
+        },
+    )
+}
+
+#[async_trait]
+impl PacketIo for PacketPipe {
+    async fn recv(&self, buf: &mut [u8]) -> io::Result<usize> {
+        let packet = self
+            .rx
+            .lock()
+            .await
+            .recv()
+            .await
+            .ok_or_else(|| io::Error::from(io::ErrorKind::BrokenPipe))?;
+
+        let len = packet.len().min(buf.len());
+        buf[..len].copy_from_slice(&packet[..len]);
+        Ok(len)
+    }
+
+    async fn send(&self, packet: &[u8]) -> io::Result<()> {
+        self.tx
+            .send(packet.to_vec())
+            .await
+            .map_err(|_| io::Error::from(io::ErrorKind::BrokenPipe))
+    }
+}
+
+/// Packet I/O over already opened TUN device
+#[cfg(unix)]
+pub struct TunIo {
+    fd: AsyncFd<OwnedFd>,
+}
+
+#[cfg(unix)]
+struct OwnedFd(RawFd);
+
+#[cfg(unix)]
+impl AsRawFd for OwnedFd {
+    fn as_raw_fd(&self) -> RawFd {
+        self.0
+    }
+}
+
+#[cfg(unix)]
+impl Drop for OwnedFd {
+    fn drop(&mut self) {
+        unsafe { libc::close(self.0) };
+    }
+}
+
+/// Apple's utun prefixes packets with address family
+#[cfg(any(target_os = "macos", target_os = "ios"))]
+const AF_HEADER_LEN: usize = 4;
+#[cfg(not(any(target_os = "macos", target_os = "ios")))]
+const AF_HEADER_LEN: usize = 0;
+
+#[cfg(unix)]
+impl TunIo {
+    /// Take ownership of TUN file descriptor, it is closed once [TunIo] is dropped
+    pub fn new(fd: RawFd) -> io::Result<Self> {
+        let fd = OwnedFd(fd);
+
+        let flags = unsafe { libc::fcntl(fd.0, libc::F_GETFL) };
+        if flags < 0 || unsafe { libc::fcntl(fd.0, libc::F_SETFL, flags | libc::O_NONBLOCK) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(Self {
+            fd: AsyncFd::new(fd)?,
+        })
+    }
+}
+
+#[cfg(unix)]
+#[async_trait]
+impl PacketIo for TunIo {
+    async fn recv(&self, buf: &mut [u8]) -> io::Result<usize> {
+        // Address family is read aside, so packet lands right in the caller's buffer
+        let mut header = [0u8; AF_HEADER_LEN];
+        loop {
+            let mut guard = self.fd.readable().await?;
+            let res = guard.try_io(|fd| {
+                let iov = [
+                    libc::iovec {
+                        iov_base: header.as_mut_ptr() as *mut libc::c_void,
+                        iov_len: header.len(),
+                    },
+                    libc::iovec {
+                        iov_base: buf.as_mut_ptr() as *mut libc::c_void,
+                        iov_len: buf.len(),
+                    },
+                ];
+                let len = unsafe { libc::readv(fd.as_raw_fd(), iov.as_ptr(), iov.len() as _) };
+                if len < 0 {
+                    return Err(io::Error::last_os_error());
+                }
+                Ok(len as usize)
+            });
+
+            if let Ok(len) = res {
+                return Ok(len?.saturating_sub(AF_HEADER_LEN));
+            }
+        }
+    }
+
+    async fn send(&self, packet: &[u8]) -> io::Result<()> {
+        #[cfg(any(target_os = "macos", target_os = "ios"))]
+        let header = match packet.first().map(|b| b >> 4) {
+            Some(6) => libc::AF_INET6 as u32,
+            _ => libc::AF_INET as u32,
+        }
+        .to_be_bytes();
+        #[cfg(not(any(target_os = "macos", target_os = "ios")))]
+        let header = [0u8; AF_HEADER_LEN];
+
+        loop {
+            let mut guard = self.fd.writable().await?;
+            let res = guard.try_io(|fd| {
+                let iov = [
+                    libc::iovec {
+                        iov_base: header.as_ptr() as *mut libc::c_void,
+                        iov_len: header.len(),
+                    },
+                    libc::iovec {
+                        iov_base: packet.as_ptr() as *mut libc::c_void,
+                        iov_len: packet.len(),
+                    },
+                ];
+                let len = unsafe { libc::writev(fd.as_raw_fd(), iov.as_ptr(), iov.len() as _) };
+                if len < 0 {
+                    return Err(io::Error::last_os_error());
+                }
+                Ok(())
+            });
+
+            if let Ok(res) = res {
+                return res;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn pipe_delivers_packets_to_other_end() {
+        let (a, b) = packet_pipe();
+        let mut buf = [0u8; 16];
+
+        a.send(&[1, 2, 3]).await.unwrap();
+        assert_eq!(3, b.recv(&mut buf).await.unwrap());
+        assert_eq!(&[1, 2, 3], &buf[..3]);
+
+        b.send(&[4, 5]).await.unwrap();
+        assert_eq!(2, a.recv(&mut buf).await.unwrap());
+        assert_eq!(&[4, 5], &buf[..2]);
+
+        drop(a);
+        assert!(b.recv(&mut buf).await.is_err());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn tun_io_delivers_packets_through_fd() {
+        let mut fds = [0; 2];
+        let res = unsafe { libc::socketpair(libc::AF_UNIX, libc::SOCK_DGRAM, 0, fds.as_mut_ptr()) };
+        assert_eq!(0, res);
+        let (a, b) = (TunIo::new(fds[0]).unwrap(), TunIo::new(fds[1]).unwrap());
+        let mut buf = [0u8; 16];
+
+        for packet in [&[0x45, 1, 2, 3][..], &[0x60, 4, 5]] {
+            a.send(packet).await.unwrap();
+            let len = b.recv(&mut buf).await.unwrap();
+            assert_eq!(packet, &buf[..len]);
+        }
+    }
+}
@@ -0,0 +1,265 @@
+//! Per-peer traffic statistics, sampled from adapter's counters on every sync
+
+use serde::Serialize;
+use std::{
+    collections::{BTreeMap, VecDeque},
+    time::Duration,
+};
+use telio_crypto::PublicKey;
+use tokio::time::Instant;
+
+use crate::uapi::Interface;
+
+/// Windows, over which traffic rates are reported
+pub const RATE_WINDOWS: [Duration; 3] = [
+    Duration::from_secs(10),
+    Duration::from_secs(60),
+    Duration::from_secs(300),
+];
+
+// Handshake age reported by adapters is rounded, so small shifts are not new handshakes
+const HANDSHAKE_TOLERANCE: Duration = Duration::from_secs(1);
+
+/// Traffic rate over a window ending now
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Rate {
+    /// Length of the window
+    pub window_secs: u64,
+    /// Received bytes per second
+    pub rx_bps: u64,
+    /// Transmitted bytes per second
+    pub tx_bps: u64,
+}
+
+/// Statistics of a single peer
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct PeerStats {
+    /// Public key of the peer
+    pub public_key: PublicKey,
+    /// Total bytes received from the peer
+    pub rx_bytes: u64,
+    /// Total bytes sent to the peer
+    pub tx_bytes: u64,
+    /// Rates over each of [RATE_WINDOWS], shorter windows first
+    pub rates: Vec<Rate>,
+    /// Handshakes observed since the peer was added
+    pub handshakes: u64,
+    /// Seconds since the last handshake, `None` if peer has never handshaked
+    pub last_handshake_secs: Option<u64>,
+}
+
+#[derive(Clone, Copy)]
+struct Sample {
+    at: Instant,
+    rx_bytes: u64,
+    tx_bytes: u64,
+}
+
+#[derive(Default)]
+struct History {
+    samples: VecDeque<Sample>,
+    handshakes: u64,
+    last_handshake: Option<Instant>,
+}
+
+/// Sliding window history of all peers of an interface
+#[derive(Default)]
+pub struct Stats {
+    peers: BTreeMap<PublicKey, History>,
+}
+
+impl Stats {
+    /// Record counters of interface's peers, forgetting peers which are gone
+    pub fn record(&mut self, interface: &Interface, now: Instant) {
+        self.peers
+            .retain(|public_key, _| interface.peers.contains_key(public_key));
+
+        let oldest = now.checked_sub(RATE_WINDOWS[RATE_WINDOWS.len() - 1]);
+        for (public_key, peer) in &interface.peers {
+            let history = self.peers.entry(*public_key).or_default();
+            let sample = Sample {
+                at: now,
+                rx_bytes: peer.rx_bytes.unwrap_or_default(),
+                tx_bytes: peer.tx_bytes.unwrap_or_default(),
+            };
+
+            // Counters are reset when peer is recreated by the adapter
+            if let Some(last) = history.samples.back() {
+                if sample.rx_bytes < last.rx_bytes || sample.tx_bytes < last.tx_bytes {
+                    history.samples.clear();
+                }
+            }
+            history.samples.push_back(sample);
+
+            // Keep a single sample older than the longest window, as its start point
+            while let (Some(oldest), Some(second)) = (oldest, history.samples.get(1)) {
+                if second.at > oldest {
+                    break;
+                }
+                history.samples.pop_front();
+            }
+
+            if let Some(handshake) = peer
+                .time_since_last_handshake
+                .and_then(|age| now.checked_sub(age))
+            {
+                let is_new = history
+                    .last_handshake
+                    .map_or(true, |last| handshake > last + HANDSHAKE_TOLERANCE);
+                if is_new {
+                    history.handshakes += 1;
+                    history.last_handshake = Some(handshake);
+                }
+            }
+        }
+    }
+
+    /// Statistics of all known peers
+    pub fn peers(&self, now: Instant) -> Vec<PeerStats> {
+        self.peers
+            .iter()
+            .map(|(public_key, history)| history.stats(*public_key, now))
+            .collect()
+    }
+}
+
+impl History {
+    fn stats(&self, public_key: PublicKey, now: Instant) -> PeerStats {
+        let last = self.samples.back().copied();
+        PeerStats {
+            public_key,
+            rx_bytes: last.map_or(0, |s| s.rx_bytes),
+            tx_bytes: last.map_or(0, |s| s.tx_bytes),
+            rates: RATE_WINDOWS
+                .iter()
+                .map(|window| self.rate(*window, now))
+                .collect(),
+            handshakes: self.handshakes,
+            last_handshake_secs: self
+                .last_handshake
+                .map(|at| now.saturating_duration_since(at).as_secs()),
+        }
+    }
+
+    fn rate(&self, window: Duration, now: Instant) -> Rate {
+        let mut rate = Rate {
+            window_secs: window.as_secs(),
+            rx_bps: 0,
+            tx_bps: 0,
+        };
+
+        let last = match self.samples.back() {
+            Some(last) => last,
+            None => return rate,
+        };
+        // Latest sample, which is not newer than the window's start, or the oldest one
+        let start = now.checked_sub(window);
+        let first = self
+            .samples
+            .iter()
+            .rev()
+            .find(|s| start.map_or(false, |start| s.at <= start))
+            .or_else(|| self.samples.front())
+            .unwrap_or(last);
+
+        let elapsed = last.at.saturating_duration_since(first.at).as_secs_f64();
+        if elapsed > 0.0 {
+            rate.rx_bps = ((last.rx_bytes - first.rx_bytes) as f64 / elapsed) as u64;
+            rate.tx_bps = ((last.tx_bytes - first.tx_bytes) as f64 / elapsed) as u64;
+        }
+        rate
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::uapi::Peer;
+
+    fn interface(public_key: PublicKey, rx: u64, tx: u64, handshake: Option<u64>) -> Interface {
+        let mut interface = Interface::default();
+        interface.peers.insert(
+            public_key,
+            Peer {
+                public_key,
+                rx_bytes: Some(rx),
+                tx_bytes: Some(tx),
+                time_since_last_handshake: handshake.map(Duration::from_secs),
+                ..Default::default()
+            },
+        );
+        interface
+    }
+
+    #[test]
+    fn computes_rates_over_windows() {
+        let public_key = PublicKey([1u8; 32]);
+        let mut stats = Stats::default();
+        let start = Instant::now() + Duration::from_secs(600);
+
+        for i in 0..=120u64 {
+            let now = start + Duration::from_secs(i);
+            // 1000 B/s received over the whole time, sending only in the last 10 seconds
+            let tx = i.saturating_sub(110) * 500;
+            stats.record(&interface(public_key, i * 1000, tx, None), now);
+        }
+
+        let now = start + Duration::from_secs(120);
+        let peer = &stats.peers(now)[0];
+        assert_eq!(120_000, peer.rx_bytes);
+        assert_eq!(
+            vec![
+                Rate {
+                    window_secs: 10,
+                    rx_bps: 1000,
+                    tx_bps: 500
+                },
+                Rate {
+                    window_secs: 60,
+                    rx_bps: 1000,
+                    tx_bps: 83
+                },
+                Rate {
+                    window_secs: 300,
+                    rx_bps: 1000,
+                    tx_bps: 41
+                },
+            ],
+            peer.rates
+        );
+    }
+
+    #[test]
+    fn counts_handshakes() {
+        let public_key = PublicKey([1u8; 32]);
+        let mut stats = Stats::default();
+        let start = Instant::now() + Duration::from_secs(600);
+
+        stats.record(&interface(public_key, 0, 0, None), start);
+        assert_eq!(0, stats.peers(start)[0].handshakes);
+        assert_eq!(None, stats.peers(start)[0].last_handshake_secs);
+
+        // Handshake 2s ago, then aging without a new one
+        for i in 0..5 {
+            let now = start + Duration::from_secs(i);
+            stats.record(&interface(public_key, 0, 0, Some(i + 2)), now);
+        }
+        // Rekey
+        let now = start + Duration::from_secs(130);
+        stats.record(&interface(public_key, 0, 0, Some(1)), now);
+
+        let peer = &stats.peers(now + Duration::from_secs(4))[0];
+        assert_eq!(2, peer.handshakes);
+        assert_eq!(Some(5), peer.last_handshake_secs);
+    }
+
+    #[test]
+    fn forgets_removed_peers() {
+        let mut stats = Stats::default();
+        let now = Instant::now();
+
+        stats.record(&interface(PublicKey([1u8; 32]), 10, 10, None), now);
+        stats.record(&Interface::default(), now);
+        assert!(stats.peers(now).is_empty());
+    }
+}
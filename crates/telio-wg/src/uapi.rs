@@ -1,6 +1,6 @@
 use ipnetwork::IpNetwork;
 use serde::{Deserialize, Serialize};
-use telio_crypto::{PublicKey, SecretKey};
+use telio_crypto::{PresharedKey, PublicKey, SecretKey};
 use telio_utils::telio_log_warn;
 use wireguard_uapi::{get, xplatform::set};
 
@@ -32,6 +32,8 @@ pub struct Peer {
     pub tx_bytes: Option<u64>,
     /// Time since last handshakeor `None`, differs from WireGuard field meaning
     pub time_since_last_handshake: Option<Duration>,
+    /// Preshared key or `None`
+    pub preshared_key: Option<PresharedKey>,
 }
 
 impl From<get::Peer> for Peer {
@@ -52,6 +54,8 @@ impl From<get::Peer> for Peer {
             time_since_last_handshake: Peer::calculate_time_since_last_handshake(Some(
                 item.last_handshake_time,
             )),
+            // Not read back, configured key is kept by the driver
+            preshared_key: None,
         }
     }
 }
@@ -69,6 +73,7 @@ impl From<set::Peer> for Peer {
                 .map(|ip| IpNetwork::new(ip.ipaddr, ip.cidr_mask))
                 .collect::<Result<Vec<IpNetwork>, _>>()
                 .unwrap_or_default(),
+            preshared_key: item.preshared_key.map(PresharedKey),
             ..Default::default()
         }
     }
@@ -89,6 +94,7 @@ impl From<&Peer> for set::Peer {
                     cidr_mask: ip.prefix(),
                 })
                 .collect(),
+            preshared_key: item.preshared_key.map(|key| key.0),
             ..Default::default()
         }
     }
@@ -184,10 +190,20 @@ pub struct Event {
     pub peer: Peer,
 }
 
+/// Restart of failed adapter, after which its last known state was replayed
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RecoveryEvent {
+    /// Number of successive failed adapter calls, which triggered the restart
+    pub failed_calls: u32,
+    /// Error of restart, `None` if adapter was restarted and its state replayed
+    pub error: Option<String>,
+}
+
 #[derive(Clone, Debug)]
 pub struct AnalyticsEvent {
     pub public_key: PublicKey,
     pub endpoint: SocketAddr,
+    pub allowed_ips: Vec<IpNetwork>,
     pub tx_bytes: u64,
     pub rx_bytes: u64,
     pub peer_state: PeerState,
@@ -406,6 +422,13 @@ fn parse_peer<R: Read>(
                 peer.persistent_keepalive_interval = Some(val.parse().unwrap())
             }
             "allowed_ip" => peer.allowed_ips.push(val.parse().unwrap()),
+            "preshared_key" => {
+                // All zeroes stand for no preshared key
+                peer.preshared_key = val
+                    .parse()
+                    .ok()
+                    .filter(|key: &PresharedKey| key.0 != [0u8; 32])
+            }
             "rx_bytes" => peer.rx_bytes = Some(val.parse().unwrap()),
             "tx_bytes" => peer.tx_bytes = Some(val.parse().unwrap()),
             "last_handshake_time_nsec" => {
@@ -507,4 +530,40 @@ errno=0
         };
         assert_eq!(response_from_str(&resp_str), resp);
     }
+
+    #[test]
+    fn zero_preshared_key_becomes_none() {
+        let resp_str = "\
+public_key=b85996fecc9c7f1fc6d2572a76eda11d59bcd20be8e543b15ce4bd85a8e75a33
+preshared_key=babababababababababababababababababababababababababababababababa
+public_key=58402e695ba1772b1cc9309755f043251ea77fdcf10fbe63989ceb7e19321376
+preshared_key=0000000000000000000000000000000000000000000000000000000000000000
+errno=0
+";
+        let interface = response_from_str(&resp_str).interface.unwrap();
+        let keys: Vec<_> = interface
+            .peers
+            .values()
+            .map(|peer| peer.preshared_key)
+            .collect();
+        assert!(keys.contains(&Some(PresharedKey([0xBAu8; 32]))));
+        assert!(keys.contains(&None));
+    }
+
+    #[test]
+    fn zero_keepalive_is_set_explicitly() {
+        let peer = Peer {
+            public_key: SecretKey::gen().public(),
+            persistent_keepalive_interval: Some(0),
+            ..Default::default()
+        };
+        let cmd = Cmd::Set(set::Device {
+            peers: vec![set::Peer::from(&peer)],
+            ..Default::default()
+        });
+        assert!(cmd
+            .to_string()
+            .lines()
+            .any(|line| line == "persistent_keepalive_interval=0"));
+    }
 }
@@ -2,9 +2,12 @@ use async_trait::async_trait;
 use futures::{future::pending, FutureExt};
 use slog::{o, Drain, Logger, Never};
 use telio_sockets::SocketPool;
-use telio_utils::telio_log_debug;
+use telio_utils::{telio_log_debug, telio_log_error, telio_log_warn, telio_span};
 use thiserror::Error as TError;
-use tokio::time::{self, Interval};
+use tokio::{
+    sync::mpsc,
+    time::{self, Instant, Interval},
+};
 use wireguard_uapi::xplatform::set;
 
 use telio_crypto::{PublicKey, SecretKey};
@@ -18,9 +21,10 @@ use telio_task::{
 use mockall::automock;
 
 use crate::{
-    adapter::{self, Adapter, AdapterType, Error, Tun},
-    uapi::{self, AnalyticsEvent, Cmd, Event, Interface, Peer, PeerState, Response},
-    FirewallCb,
+    adapter::{self, Adapter, AdapterType, Change, Error, Tun, Watcher},
+    stats::{PeerStats, Stats},
+    uapi::{self, AnalyticsEvent, Cmd, Event, Interface, Peer, PeerState, RecoveryEvent, Response},
+    FirewallCb, FirewallRulesCb, PacketIo,
 };
 
 use std::{collections::HashSet, future::Future, io, sync::Arc, time::Duration};
@@ -59,32 +63,58 @@ pub struct Config {
     pub adapter: AdapterType,
     pub name: Option<String>,
     pub tun: Option<Tun>,
+    /// Packet source and sink, used by [AdapterType::UserspaceWg] instead of `tun`, not with it
+    pub packet_io: Option<Arc<dyn PacketIo>>,
     pub socket_pool: Arc<SocketPool>,
     pub firewall_process_inbound_callback: FirewallCb,
     pub firewall_process_outbound_callback: FirewallCb,
+    /// Firewall policy for [AdapterType::LinuxNativeWg], which filters packets in kernel
+    pub firewall_rules_callback: FirewallRulesCb,
+    /// MTU of tunnel link, adapter's default if not set
+    pub mtu: Option<u32>,
 }
 
 pub struct Io {
     pub events: Tx<Box<Event>>,
     pub analytics_tx: Option<mc_chan::Tx<Box<AnalyticsEvent>>>,
+    /// Restarts of failed adapter
+    pub recovery_tx: Option<mc_chan::Tx<Box<RecoveryEvent>>>,
 }
 
 struct State {
-    #[cfg(unix)]
+    // Configuration, from which adapter is restarted after failure
     cfg: Config,
     adapter: Box<dyn Adapter>,
     interval: Interval,
+    poll_millis: u64,
     interface: Interface,
+    // Changes pushed by adapter, synced without waiting for the next poll
+    watcher: Watcher,
+    changes: mpsc::Receiver<Change>,
+    last_sync: Instant,
     event: Tx<Box<Event>>,
     analytics_tx: Option<mc_chan::Tx<Box<AnalyticsEvent>>>,
+    recovery_tx: Option<mc_chan::Tx<Box<RecoveryEvent>>>,
+    stats: Stats,
 
     // Detecting unexpected driver failures, such as a malicious removal
     // We won't be notified of any errors, but a periodic call to get_config_uapi() will return a Win32 error code != 0.
     uapi_failed_last_call: bool,
-    uapi_fail_counter: i32,
+    uapi_fail_counter: u32,
+    // Successive failed restarts, each doubling the failed calls needed for the next one
+    restart_failures: u32,
 }
 
 const POLL_MILLIS: u64 = 1000;
+// Polling interval of adapters, which push peers' changes themselves, while no peer is
+// connected (disconnects and endpoint changes of connected peers are not pushed)
+const FALLBACK_POLL_MILLIS: u64 = 5000;
+// Pushed peers' changes are not synced more often than this
+const MIN_PUSHED_SYNC_MILLIS: u64 = 100;
+// Successive failed adapter calls, after which adapter is considered dead and restarted
+const MAX_UAPI_FAILS: u32 = 3;
+// Limit of failed restarts backoff, as an exponent of two
+const MAX_RESTART_BACKOFF: u32 = 5;
 
 #[cfg(windows)]
 const DEFAULT_NAME: &str = "NordLynx";
@@ -95,50 +125,90 @@ const DEFAULT_NAME: &str = "utun10";
 #[cfg(any(target_os = "linux", target_os = "android"))]
 const DEFAULT_NAME: &str = "nlx0";
 
+/// Name of tunnel, if none is configured. Interfaces next to the first one are numbered,
+/// so their names do not collide with it
+pub fn default_name(interface: u32) -> String {
+    if interface == 0 {
+        return DEFAULT_NAME.to_owned();
+    }
+
+    #[cfg(windows)]
+    return format!("{}{}", DEFAULT_NAME, interface);
+    #[cfg(any(target_os = "macos", target_os = "ios"))]
+    return format!("utun{}", 10 + interface);
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    return format!("nlx{}", interface);
+}
+
 impl DynamicWg {
     pub fn start(io: Io, cfg: Config) -> Result<Self, Error>
     where
         Self: Sized,
     {
-        let adapter = Self::start_adapter(cfg.try_clone()?)?;
-        #[cfg(unix)]
-        return Ok(Self::start_with(io, adapter, cfg));
-        #[cfg(windows)]
-        return Ok(Self::start_with(io, adapter));
+        let watcher = Watcher::new();
+        let adapter = Self::start_adapter(cfg.try_clone()?, &watcher.0)?;
+        Ok(Self::start_with(io, adapter, watcher, cfg))
     }
 
-    fn start_with(io: Io, adapter: Box<dyn Adapter>, #[cfg(unix)] cfg: Config) -> Self {
-        let interval = time::interval(Duration::from_millis(POLL_MILLIS));
+    fn start_with(
+        io: Io,
+        adapter: Box<dyn Adapter>,
+        (watcher, changes): (Watcher, mpsc::Receiver<Change>),
+        cfg: Config,
+    ) -> Self {
+        let poll_millis = poll_millis(adapter.pushes_peer_changes(), &Interface::default());
+        let interval = time::interval(Duration::from_millis(poll_millis));
 
         Self {
             task: Task::start(State {
-                #[cfg(unix)]
                 cfg,
                 adapter,
                 interval,
+                poll_millis,
                 interface: Default::default(),
+                watcher,
+                changes,
+                last_sync: Instant::now(),
                 event: io.events,
                 analytics_tx: io.analytics_tx,
+                recovery_tx: io.recovery_tx,
+                stats: Stats::default(),
                 uapi_failed_last_call: false,
                 uapi_fail_counter: 0,
+                restart_failures: 0,
             }),
         }
     }
 
     #[cfg(not(test))]
-    fn start_adapter(cfg: Config) -> Result<Box<dyn Adapter>, Error> {
-        adapter::start(
+    fn start_adapter(cfg: Config, watcher: &Watcher) -> Result<Box<dyn Adapter>, Error> {
+        let name = cfg.name.unwrap_or_else(|| DEFAULT_NAME.to_owned());
+        let (adapter_type, mtu) = (cfg.adapter, cfg.mtu);
+
+        let adapter = adapter::start(
             cfg.adapter,
-            &cfg.name.unwrap_or_else(|| DEFAULT_NAME.to_owned()),
+            &name,
             cfg.tun,
+            cfg.packet_io,
             cfg.socket_pool,
             cfg.firewall_process_inbound_callback,
             cfg.firewall_process_outbound_callback,
-        )
+            cfg.firewall_rules_callback,
+            watcher,
+        )?;
+
+        // Userspace adapter has no link
+        if let (Some(mtu), false) = (mtu, matches!(adapter_type, AdapterType::UserspaceWg)) {
+            if let Err(e) = crate::mtu::set_link_mtu(&name, mtu) {
+                telio_log_warn!("Failed to set MTU {} of {}: {}", mtu, name, e);
+            }
+        }
+
+        Ok(adapter)
     }
 
     #[cfg(test)]
-    fn start_adapter(_cfg: Config) -> Result<Box<dyn Adapter>, Error> {
+    fn start_adapter(_cfg: Config, _watcher: &Watcher) -> Result<Box<dyn Adapter>, Error> {
         use std::sync::Mutex;
 
         if let Some(adapter) = tests::RUNTIME_ADAPTER.lock().unwrap().take() {
@@ -192,27 +262,35 @@ impl WireGuard for DynamicWg {
 
     async fn add_peer(&self, mut peer: Peer) {
         let _ = task_exec!(&self.task, async move |s| {
-            let mut to = s.interface.clone();
-
-            if let Some(old_peer) = to.peers.get(&peer.public_key) {
-                peer.time_since_last_handshake = old_peer.time_since_last_handshake;
-                peer.rx_bytes = old_peer.rx_bytes;
-                peer.tx_bytes = old_peer.tx_bytes;
-            }
+            let span = telio_span!(peer = peer.public_key);
+            span.instrument(async move {
+                let mut to = s.interface.clone();
+
+                if let Some(old_peer) = to.peers.get(&peer.public_key) {
+                    peer.time_since_last_handshake = old_peer.time_since_last_handshake;
+                    peer.rx_bytes = old_peer.rx_bytes;
+                    peer.tx_bytes = old_peer.tx_bytes;
+                }
 
-            to.peers.insert(peer.public_key, peer);
-            s.update(&to, true).await;
-            Ok(())
+                to.peers.insert(peer.public_key, peer);
+                s.update(&to, true).await;
+                Ok(())
+            })
+            .await
         })
         .await;
     }
 
     async fn del_peer(&self, key: PublicKey) {
         let _ = task_exec!(&self.task, async move |s| {
-            let mut to = s.interface.clone();
-            to.peers.remove(&key);
-            s.update(&to, true).await;
-            Ok(())
+            telio_span!(peer = key)
+                .instrument(async move {
+                    let mut to = s.interface.clone();
+                    to.peers.remove(&key);
+                    s.update(&to, true).await;
+                    Ok(())
+                })
+                .await
         })
         .await;
     }
@@ -230,6 +308,33 @@ impl WireGuard for DynamicWg {
     }
 }
 
+impl DynamicWg {
+    /// Traffic statistics of adapter's peers
+    pub async fn get_peer_stats(&self) -> Vec<PeerStats> {
+        task_exec!(&self.task, async move |s| Ok(s.stats.peers(Instant::now())))
+            .await
+            .unwrap_or_default()
+    }
+
+    /// Set MTU of path towards peer, for adapters clamping MSS of TCP connections
+    pub async fn set_peer_mtu(&self, public_key: PublicKey, mtu: u32) {
+        let _ = task_exec!(&self.task, async move |s| {
+            s.adapter.set_peer_mtu(&public_key, mtu);
+            Ok(())
+        })
+        .await;
+    }
+
+    /// Apply changed firewall policy, for adapters filtering packets outside of callbacks
+    pub async fn refresh_firewall(&self) {
+        let _ = task_exec!(&self.task, async move |s| {
+            s.adapter.refresh_firewall().await;
+            Ok(())
+        })
+        .await;
+    }
+}
+
 impl Config {
     fn try_clone(&self) -> Result<Self, io::Error> {
         #[cfg(unix)]
@@ -250,9 +355,12 @@ impl Config {
             adapter: self.adapter,
             name: self.name.clone(),
             tun,
+            packet_io: self.packet_io.clone(),
             socket_pool: self.socket_pool.clone(),
             firewall_process_inbound_callback: self.firewall_process_inbound_callback.clone(),
             firewall_process_outbound_callback: self.firewall_process_outbound_callback.clone(),
+            firewall_rules_callback: self.firewall_rules_callback.clone(),
+            mtu: self.mtu,
         })
     }
 }
@@ -266,14 +374,108 @@ struct DiffKeys {
 
 impl State {
     async fn sync(&mut self) {
-        if let Some(to) = self.uapi_request(&uapi::Cmd::Get).await.interface {
+        self.last_sync = Instant::now();
+        if let Some(mut to) = self.uapi_request(&uapi::Cmd::Get).await.interface {
+            // Not every adapter reports preshared keys, configured ones are kept
+            for (pubkey, peer) in to.peers.iter_mut() {
+                if peer.preshared_key.is_none() {
+                    peer.preshared_key = self
+                        .interface
+                        .peers
+                        .get(pubkey)
+                        .and_then(|p| p.preshared_key);
+                }
+            }
+            self.stats.record(&to, self.last_sync);
             let _ = self.update(&to, false).await;
         }
+
+        let threshold = MAX_UAPI_FAILS << self.restart_failures.min(MAX_RESTART_BACKOFF);
+        if self.uapi_fail_counter >= threshold {
+            self.restart().await;
+        }
+    }
+
+    /// Replace failed adapter with a new one, replaying the last known interface to it
+    async fn restart(&mut self) {
+        let failed_calls = self.uapi_fail_counter;
+        telio_log_warn!(
+            "Adapter failed {} successive calls, restarting it",
+            failed_calls
+        );
+
+        self.adapter.stop().await;
+        let adapter = self
+            .cfg
+            .try_clone()
+            .map_err(Error::from)
+            .and_then(|cfg| DynamicWg::start_adapter(cfg, &self.watcher));
+
+        let error = match adapter {
+            Ok(adapter) => {
+                self.adapter = adapter;
+
+                // New adapter is empty, so the whole interface is pushed as new,
+                // keeping peers' states, which are updated by the next sync
+                let to = self.interface.clone();
+                self.interface = Interface::default();
+                let diff_keys = self.update_calculate_changes(&to);
+                let dev = self.update_construct_set_device(&to, &diff_keys);
+                self.interface = to;
+
+                if self.uapi_request(&Cmd::Set(dev)).await.errno == 0 {
+                    None
+                } else {
+                    Some("failed to replay interface to restarted adapter".to_owned())
+                }
+            }
+            Err(e) => Some(e.to_string()),
+        };
+
+        match &error {
+            None => {
+                telio_log_warn!("Adapter restarted, interface replayed");
+                self.restart_failures = 0;
+            }
+            Some(e) => {
+                telio_log_error!("Adapter restart failed: {}", e);
+                self.restart_failures += 1;
+            }
+        }
+
+        if let Some(recovery_tx) = &self.recovery_tx {
+            let event = RecoveryEvent {
+                failed_calls,
+                error,
+            };
+            if recovery_tx.send(Box::new(event)).is_err() {
+                telio_log_debug!("Failed to send adapter recovery event");
+            }
+        }
+    }
+
+    async fn handle_change(&mut self, change: Change) {
+        telio_log_debug!("Adapter pushed change: {:?}", change);
+
+        // Coalesce changes, which were pushed in the meantime
+        let mut interface_changed = change == Change::Interface;
+        while let Ok(change) = self.changes.try_recv() {
+            interface_changed |= change == Change::Interface;
+        }
+
+        // Peer keeps pushing its changes until it is synced as connected, so it is safe to skip
+        if !interface_changed
+            && self.last_sync.elapsed() < Duration::from_millis(MIN_PUSHED_SYNC_MILLIS)
+        {
+            return;
+        }
+
+        self.sync().await;
     }
 
     async fn uapi_request(&mut self, cmd: &Cmd) -> Response {
         let ret = self.adapter.send_uapi_cmd(cmd).await;
-        telio_log_debug!("UAPI request: {}, response: {}", &cmd.to_string(), &ret);
+        telio_log_debug!("UAPI request: {}, response: {}", loggable(cmd), &ret);
 
         // Count continuous adapter failures.
         // As observed on Windows, a vNIC driver might fail a call right after wake-up,
@@ -286,7 +488,7 @@ impl State {
         } else {
             self.uapi_failed_last_call = true;
             self.uapi_fail_counter += 1;
-            // TODO: check failure count threshold, cleanup network config, destroy adapter, notify app
+            // Adapter is restarted by sync(), once failures reach the threshold
         }
 
         ret
@@ -382,14 +584,23 @@ impl State {
             .filter(|(pubkey, _)| diff_keys.delete_keys.contains(pubkey))
             .map(|(pubkey, _)| set::Peer::from_public_key(pubkey.0).remove(true));
         let peers = to.peers.iter().map(|(pubkey, peer)| {
-            set::Peer::from(peer)
+            let mut set_peer = set::Peer::from(peer)
                 .update_only(diff_keys.update_keys.contains(pubkey))
                 .replace_allowed_ips(
                     from.peers
                         .get(pubkey)
                         .map(|p| p.allowed_ips != peer.allowed_ips)
                         .unwrap_or(false),
-                )
+                );
+            // Missing key leaves the old one in place, so removal is done by zeroing it
+            let had_preshared_key = from
+                .peers
+                .get(pubkey)
+                .map_or(false, |p| p.preshared_key.is_some());
+            if peer.preshared_key.is_none() && had_preshared_key {
+                set_peer.preshared_key = Some([0u8; 32]);
+            }
+            set_peer
         });
 
         set::Device {
@@ -450,13 +661,14 @@ impl State {
                     let event = AnalyticsEvent {
                         public_key: *pubkey,
                         endpoint,
+                        allowed_ips: peer.allowed_ips.clone(),
                         tx_bytes,
                         rx_bytes,
                         peer_state,
                         timestamp: std::time::Instant::now(),
                     };
                     if analytics_tx.send(Box::new(event)).is_err() {
-                        telio_log_debug!("Failed to send analytics info for {:?}", pubkey);
+                        telio_log_debug!(peer = pubkey; "Failed to send analytics info");
                     }
                 }
             }
@@ -464,8 +676,39 @@ impl State {
 
         self.interface = to.clone();
 
+        // Only the activity of not yet connected peers is worth pushing
+        self.watcher.watch(
+            self.interface
+                .peers
+                .values()
+                .filter(|peer| !peer.connected())
+                .map(|peer| peer.public_key)
+                .collect(),
+        );
+        self.update_poll_interval();
+
         success
     }
+
+    fn update_poll_interval(&mut self) {
+        let poll_millis = poll_millis(self.adapter.pushes_peer_changes(), &self.interface);
+        if poll_millis != self.poll_millis {
+            telio_log_debug!("Polling adapter every {}ms", poll_millis);
+            let period = Duration::from_millis(poll_millis);
+            self.poll_millis = poll_millis;
+            self.interval = time::interval_at(Instant::now() + period, period);
+        }
+    }
+}
+
+/// Polling interval of adapter, connected peers are polled fast, as only the activity of
+/// not yet connected ones is pushed
+fn poll_millis(pushes_peer_changes: bool, interface: &Interface) -> u64 {
+    if pushes_peer_changes && !interface.peers.values().any(|peer| peer.connected()) {
+        FALLBACK_POLL_MILLIS
+    } else {
+        POLL_MILLIS
+    }
 }
 
 #[async_trait]
@@ -475,11 +718,17 @@ impl Runtime for State {
     type Err = Error;
 
     async fn wait(&mut self) -> WaitResponse<'_, Self::Err> {
-        let _ = self.interval.tick().await;
-        Self::guard(async move {
-            self.sync().await;
-            Ok(())
-        })
+        tokio::select! {
+            _ = self.interval.tick() => Self::guard(async move {
+                self.sync().await;
+                Ok(())
+            }),
+            // Watcher is owned by state, so channel is never closed
+            Some(change) = self.changes.recv() => Self::guard(async move {
+                self.handle_change(change).await;
+                Ok(())
+            }),
+        }
     }
 
     async fn stop(self) {
@@ -491,6 +740,18 @@ impl Runtime for State {
     }
 }
 
+/// UAPI command as logged, secret keys are left out regardless of log privacy
+fn loggable(cmd: &Cmd) -> String {
+    cmd.to_string()
+        .lines()
+        .map(|line| match line.split_once('=') {
+            Some((key @ ("private_key" | "preshared_key"), _)) => format!("{}=(hidden)", key),
+            _ => line.to_owned(),
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
 #[cfg(test)]
 mod tests {
     use std::{
@@ -502,7 +763,7 @@ mod tests {
     use mockall::predicate;
     use tokio::{runtime::Handle, sync::Mutex, task, time::sleep};
 
-    use telio_task::io::Chan;
+    use telio_task::io::{Chan, McChan};
 
     use super::*;
     use crate::adapter::MockAdapter;
@@ -537,13 +798,16 @@ mod tests {
 
     struct Env {
         event: Rx<Box<Event>>,
+        recovery: mc_chan::Rx<Box<RecoveryEvent>>,
         adapter: Arc<Mutex<MockAdapter>>,
         wg: DynamicWg,
         cfg: Config,
+        watcher: Watcher,
     }
 
     async fn setup() -> Env {
         let chan = Chan::default();
+        let recovery = McChan::default();
 
         let adapter = Arc::new(Mutex::new(MockAdapter::new()));
 
@@ -557,13 +821,15 @@ mod tests {
                 errno: 0,
                 interface: Some(Interface::default()),
             });
+        let (watcher, changes) = Watcher::new();
         let wg = DynamicWg::start_with(
             Io {
                 events: chan.tx,
                 analytics_tx: None,
+                recovery_tx: Some(recovery.tx),
             },
             Box::new(adapter.clone()),
-            #[cfg(unix)]
+            (watcher.clone(), changes),
             Config::default(),
         );
         time::advance(Duration::from_millis(0)).await;
@@ -571,9 +837,11 @@ mod tests {
 
         Env {
             event: chan.rx,
+            recovery: recovery.rx,
             adapter,
             wg,
             cfg: Config::default(),
+            watcher,
         }
     }
 
@@ -763,6 +1031,118 @@ mod tests {
         wg.stop().await;
     }
 
+    #[test]
+    fn secret_keys_are_not_logged() {
+        let secret_key = SecretKey::gen();
+        let peer = Peer {
+            public_key: SecretKey::gen().public(),
+            preshared_key: Some(telio_crypto::PresharedKey([0xBAu8; 32])),
+            ..Default::default()
+        };
+        let cmd = Cmd::Set(set::Device {
+            private_key: Some(secret_key.0),
+            peers: vec![set::Peer::from(&peer)],
+            ..Default::default()
+        });
+
+        let logged = loggable(&cmd);
+        assert!(logged.contains("private_key=(hidden)"));
+        assert!(logged.contains("preshared_key=(hidden)"));
+        assert!(!logged.contains(&hex::encode(secret_key.0)));
+        assert!(!logged.contains(&hex::encode([0xBAu8; 32])));
+        assert!(logged.contains("public_key="));
+    }
+
+    #[test]
+    fn default_names_do_not_collide() {
+        assert_eq!(DEFAULT_NAME, default_name(0));
+        assert_ne!(default_name(0), default_name(1));
+        assert_ne!(default_name(1), default_name(2));
+    }
+
+    #[test]
+    fn wg_polls_connected_peers_fast() {
+        let mut ifa = Interface::default();
+        assert_eq!(poll_millis(false, &ifa), POLL_MILLIS);
+        assert_eq!(poll_millis(true, &ifa), FALLBACK_POLL_MILLIS);
+
+        let mut peer = Peer {
+            public_key: SecretKey::gen().public(),
+            ..Default::default()
+        };
+        ifa.peers.insert(peer.public_key, peer.clone());
+        assert_eq!(poll_millis(true, &ifa), FALLBACK_POLL_MILLIS);
+
+        // Disconnect of connected peer is noticed by polling only
+        peer.time_since_last_handshake = Some(Duration::from_secs(0));
+        ifa.peers.insert(peer.public_key, peer);
+        assert_eq!(poll_millis(true, &ifa), POLL_MILLIS);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn wg_syncs_pushed_peer_change() {
+        let Env {
+            adapter,
+            wg,
+            mut event,
+            watcher,
+            ..
+        } = setup().await;
+        let mut ifa = Interface::default();
+
+        let pkc = SecretKey::gen().public();
+        let mut peer = Peer {
+            public_key: pkc,
+            endpoint: Some(([1, 1, 1, 1], 123).into()),
+            persistent_keepalive_interval: Some(25),
+            ..Default::default()
+        };
+
+        adapter
+            .lock()
+            .await
+            .expect_send_uapi_cmd()
+            .times(1)
+            .return_const(Response {
+                errno: 0,
+                interface: None,
+            });
+        wg.add_peer(peer.clone()).await;
+        assert_eq!(PeerState::Connecting, event.recv().await.unwrap().state);
+        adapter.lock().await.checkpoint();
+
+        // Adapter pushes handshake long before the next poll
+        time::advance(Duration::from_millis(MIN_PUSHED_SYNC_MILLIS)).await;
+        peer.time_since_last_handshake = Some(Duration::from_secs(0));
+        ifa.peers.insert(pkc, peer.clone());
+        adapter
+            .lock()
+            .await
+            .expect_send_uapi_cmd()
+            .with(predicate::eq(Cmd::Get))
+            .times(1)
+            .return_const(Response {
+                errno: 0,
+                interface: Some(ifa.clone()),
+            });
+        watcher.peer_active(&pkc.0);
+
+        let connected = time::timeout(Duration::from_millis(POLL_MILLIS / 2), event.recv())
+            .await
+            .expect("Pushed change was not synced before the next poll");
+        assert_eq!(
+            Some(Box::new(Event {
+                state: PeerState::Connected,
+                peer: peer.clone()
+            })),
+            connected
+        );
+        adapter.lock().await.checkpoint();
+
+        adapter.lock().await.expect_stop().return_once(|| ());
+        wg.stop().await;
+    }
+
     #[tokio::test(start_paused = true)]
     async fn wg_peer_disconnects() {
         let Env {
@@ -973,4 +1353,100 @@ mod tests {
         adapter.lock().await.expect_stop().return_once(|| ());
         wg.stop().await;
     }
+
+    #[tokio::test(start_paused = true)]
+    async fn wg_restarts_failed_adapter() {
+        let Env {
+            adapter,
+            wg,
+            mut recovery,
+            ..
+        } = setup().await;
+
+        let sks = SecretKey::gen();
+        let pubkey = SecretKey::gen().public();
+        let peer = Peer {
+            public_key: pubkey,
+            endpoint: Some(([1, 1, 1, 1], 123).into()),
+            ..Default::default()
+        };
+        let mut iface = Interface {
+            private_key: Some(sks),
+            listen_port: Some(12345),
+            ..Default::default()
+        };
+        iface.peers.insert(pubkey, peer.clone());
+
+        adapter
+            .lock()
+            .await
+            .expect_send_uapi_cmd()
+            .times(2)
+            .return_const(Response {
+                errno: 0,
+                interface: None,
+            });
+        wg.set_secret_key(sks).await;
+        wg.add_peer(peer).await;
+        adapter.lock().await.checkpoint();
+
+        // Adapter state is polled, so listen port is learned from it
+        adapter
+            .lock()
+            .await
+            .expect_send_uapi_cmd()
+            .with(predicate::eq(Cmd::Get))
+            .times(1)
+            .return_const(Response {
+                errno: 0,
+                interface: Some(iface.clone()),
+            });
+        time::advance(Duration::from_millis(POLL_MILLIS)).await;
+        adapter.lock().await.checkpoint();
+        assert_eq!(Some(iface.clone()), wg.get_interface().await);
+
+        // Adapter dies
+        adapter
+            .lock()
+            .await
+            .expect_send_uapi_cmd()
+            .with(predicate::eq(Cmd::Get))
+            .times(MAX_UAPI_FAILS as usize)
+            .return_const(Response {
+                errno: 1,
+                interface: None,
+            });
+        adapter.lock().await.expect_stop().return_once(|| ());
+
+        let restarted = Arc::new(Mutex::new(MockAdapter::new()));
+        restarted
+            .lock()
+            .await
+            .expect_send_uapi_cmd()
+            .with(predicate::eq(Cmd::Set(set::Device::from(iface.clone()))))
+            .times(1)
+            .return_const(Response {
+                errno: 0,
+                interface: None,
+            });
+        *RUNTIME_ADAPTER.lock().unwrap() = Some(Box::new(restarted.clone()));
+
+        for _ in 0..MAX_UAPI_FAILS {
+            time::advance(Duration::from_millis(POLL_MILLIS)).await;
+        }
+        adapter.lock().await.checkpoint();
+        restarted.lock().await.checkpoint();
+
+        assert_eq!(
+            RecoveryEvent {
+                failed_calls: MAX_UAPI_FAILS,
+                error: None,
+            },
+            *recovery.recv().await.unwrap()
+        );
+        assert_eq!(Some(iface), wg.get_interface().await);
+
+        restarted.lock().await.expect_stop().return_once(|| ());
+        wg.stop().await;
+    }
 }
@@ -30,8 +30,30 @@ typedef enum telio_adapter_type {
    * WindowsNativeWireguardNt implementation
    */
   TELIO_ADAPTER_WINDOWS_NATIVE_TUN,
+  /**
+   * Userland rust implementation, without kernel TUN device when used with packet I/O
+   */
+  TELIO_ADAPTER_USERSPACE_WG,
 } telio_adapter_type;
 
+/**
+ * Redaction of sensitive data in log messages.
+ */
+typedef enum telio_log_privacy {
+  /**
+   * Keys, IPs and hostnames are logged as they are.
+   */
+  TELIO_LOG_PRIVACY_OFF,
+  /**
+   * Keys and hostnames are hashed, IPs truncated to their network part.
+   */
+  TELIO_LOG_PRIVACY_STANDARD,
+  /**
+   * Keys, IPs and hostnames are hashed.
+   */
+  TELIO_LOG_PRIVACY_STRICT,
+} telio_log_privacy;
+
 /**
  * Possible log levels.
  */
@@ -179,6 +201,34 @@ enum telio_result telio_destroy_hard(struct telio *dev);
  */
 enum telio_adapter_type telio_get_default_adapter(void);
 
+/**
+ * Set callback receiving analytics events as JSON strings, used when `callback` sink
+ * is selected in `lana` features. Must be called before `telio_new`.
+ *
+ * # Parameters
+ * - `analytics`: Analytics callback
+ */
+enum telio_result telio_set_analytics_cb(struct telio_event_cb analytics);
+
+/**
+ * Change per module log levels at runtime, applies to all instances.
+ *
+ * # Parameters
+ * - `filter`: Comma separated directives, e.g. `telio_relay=trace,telio_traversal=debug`.
+ *             Directive without module sets the level for all other modules,
+ *             otherwise the level passed to `telio_new` is used.
+ */
+enum telio_result telio_set_log_filter(const char *filter);
+
+/**
+ * Change redaction of keys, IPs and hostnames in log messages at runtime,
+ * applies to all instances.
+ *
+ * # Parameters
+ * - `privacy`: Privacy level of subsequent log messages
+ */
+enum telio_result telio_set_log_privacy(enum telio_log_privacy privacy);
+
 /**
  * Start telio with specified adapter.
  *
@@ -221,6 +271,32 @@ enum telio_result telio_start_with_tun(const struct telio *dev,
  */
 enum telio_result telio_stop(const struct telio *dev);
 
+/**
+ * Start additional WireGuard interface next to the one started by `telio_start*`.
+ *
+ * Interface has its own private key, fwmark, meshnet and exit node, and is addressed by
+ * returned handle in `telio_interface_*` calls. Default interface has handle 0.
+ *
+ * # Parameters
+ * - `interface`: Handle of started interface is written here.
+ * - `private_key`: Base64 encoded WireGuard private key of the interface.
+ * - `adapter`: Adapter type.
+ * - `name`: Name of the tunnel, may be NULL to name it after the returned handle.
+ * - `mtu`: MTU of the tunnel, 0 to use the default one.
+ *
+ */
+enum telio_result telio_add_interface(const struct telio *dev,
+                                      unsigned int *interface,
+                                      const char *private_key,
+                                      enum telio_adapter_type adapter,
+                                      const char *name,
+                                      unsigned int mtu);
+
+/**
+ * Stop interface started by `telio_add_interface`.
+ */
+enum telio_result telio_remove_interface(const struct telio *dev, unsigned int interface);
+
 /**
  * get device luid.
  */
@@ -231,14 +307,30 @@ uint64_t telio_get_adapter_luid(const struct telio *dev);
  *
  * If private_key is not set, device will never connect.
  *
+ * Key can be rotated on running device: meshnet peers, preshared keys, traversal
+ * and magic DNS records are kept, relay reconnects to the same server with the
+ * new key and peers handshake again once they learn it from meshnet config.
+ *
  * # Parameters
  * - `private_key`: Base64 encoded WireGuard private key, must not be NULL.
  *
  */
 enum telio_result telio_set_private_key(const struct telio *dev, const char *private_key);
 
+/**
+ * Sets private key for interface started by `telio_add_interface`.
+ */
+enum telio_result telio_interface_set_private_key(const struct telio *dev,
+                                                  unsigned int interface,
+                                                  const char *private_key);
+
 char *telio_get_private_key(const struct telio *dev);
 
+/**
+ * Gets private key of interface started by `telio_add_interface`.
+ */
+char *telio_interface_get_private_key(const struct telio *dev, unsigned int interface);
+
 #if defined(__linux__)
 /**
  * Sets fmark for started device.
@@ -250,6 +342,15 @@ char *telio_get_private_key(const struct telio *dev);
 enum telio_result telio_set_fwmark(const struct telio *dev, unsigned int fwmark);
 #endif
 
+#if defined(__linux__)
+/**
+ * Sets fwmark for interface started by `telio_add_interface`.
+ */
+enum telio_result telio_interface_set_fwmark(const struct telio *dev,
+                                             unsigned int interface,
+                                             unsigned int fwmark);
+#endif
+
 /**
  * Notify telio with network state changes.
  *
@@ -259,6 +360,15 @@ enum telio_result telio_set_fwmark(const struct telio *dev, unsigned int fwmark)
  */
 enum telio_result telio_notify_network_change(const struct telio *dev, const char *network_info);
 
+/**
+ * Tune hole punching timers, without restarting meshnet.
+ *
+ * # Parameters
+ * - `traversal`: JSON string of traversal features, same as `traversal` field of features
+ *                passed to `telio_new`. Omitted timers are reset to their defaults.
+ */
+enum telio_result telio_set_traversal(const struct telio *dev, const char *traversal);
+
 /**
  * Connects to an exit node. (VPN if endpoint is not NULL, Peer if endpoint is NULL)
  *
@@ -301,6 +411,17 @@ enum telio_result telio_connect_to_exit_node(const struct telio *dev,
                                              const char *allowed_ips,
                                              const char *endpoint);
 
+/**
+ * Connects interface started by `telio_add_interface` to exit node.
+ *
+ * Parameters are the same as of `telio_connect_to_exit_node`.
+ */
+enum telio_result telio_interface_connect_to_exit_node(const struct telio *dev,
+                                                       unsigned int interface,
+                                                       const char *public_key,
+                                                       const char *allowed_ips,
+                                                       const char *endpoint);
+
 /**
  * Enables magic DNS if it was not enabled yet,
  *
@@ -335,11 +456,24 @@ enum telio_result telio_disable_magic_dns(const struct telio *dev);
  */
 enum telio_result telio_disconnect_from_exit_node(const struct telio *dev, const char *public_key);
 
+/**
+ * Disconnects interface started by `telio_add_interface` from specified exit node.
+ */
+enum telio_result telio_interface_disconnect_from_exit_node(const struct telio *dev,
+                                                            unsigned int interface,
+                                                            const char *public_key);
+
 /**
  * Disconnects from all exit nodes with no parameters required.
  */
 enum telio_result telio_disconnect_from_exit_nodes(const struct telio *dev);
 
+/**
+ * Disconnects interface started by `telio_add_interface` from all exit nodes.
+ */
+enum telio_result telio_interface_disconnect_from_exit_nodes(const struct telio *dev,
+                                                             unsigned int interface);
+
 /**
  * Enables meshnet if it is not enabled yet.
  * In case meshnet is enabled, this updates the peer map with the specified one.
@@ -350,6 +484,13 @@ enum telio_result telio_disconnect_from_exit_nodes(const struct telio *dev);
  */
 enum telio_result telio_set_meshnet(const struct telio *dev, const char *cfg);
 
+/**
+ * Sets meshnet config of interface started by `telio_add_interface`, NULL disables it.
+ */
+enum telio_result telio_interface_set_meshnet(const struct telio *dev,
+                                              unsigned int interface,
+                                              const char *cfg);
+
 /**
  * Disables the meshnet functionality by closing all the connections.
  */
@@ -363,8 +504,78 @@ char *telio_get_version_tag(void);
 
 char *telio_get_commit_sha(void);
 
+/**
+ * Get nodes of all interfaces as JSON array. Nodes of interfaces started by
+ * `telio_add_interface` carry handle of their interface in `interface` field.
+ */
 char *telio_get_status_map(const struct telio *dev);
 
+/**
+ * Get connectivity diagnostics of peer's direct path, for attaching to support tickets.
+ *
+ * # Parameters
+ * - `public_key`: Base64 encoded WireGuard public key of the peer.
+ *
+ * Returns JSON report or NULL on failure, including NULL or invalid `public_key`.
+ */
+char *telio_get_peer_diagnostics(const struct telio *dev, const char *public_key);
+
+/**
+ * Get traffic statistics of peers: total bytes, rates over 10s, 60s and 300s windows,
+ * handshake count and seconds since the last handshake.
+ *
+ * Returns JSON array or NULL on failure.
+ */
+char *telio_get_peer_stats(const struct telio *dev);
+
+/**
+ * Get traffic statistics of peers of interface started by `telio_add_interface`.
+ */
+char *telio_get_interface_peer_stats(const struct telio *dev, unsigned int interface);
+
+/**
+ * Get metrics of peers, relay and nurse in OpenMetrics text format, for Prometheus scraping.
+ * Samples of peers are labeled by their public key and handle of their interface.
+ *
+ * Returns exposition text or NULL on failure.
+ */
+char *telio_get_metrics(const struct telio *dev);
+
+/**
+ * Get meshnet connectivity matrix, aggregated by nurse during its last heartbeat collection.
+ * Lists internal nodes of the meshnet and the state of the link between each pair of them.
+ *
+ * Returns JSON string, `null` if nurse has not collected the matrix yet, or NULL on failure.
+ */
+char *telio_get_connectivity_matrix(const struct telio *dev);
+
+/**
+ * Get events and state transitions recorded in the journal, enabled by `journal` feature.
+ *
+ * # Parameters
+ * - `since`: Only entries recorded at or after this time, in milliseconds since UNIX epoch.
+ * - `filter`: Comma separated kinds of entries (`relay`, `node`, `error`, `state`), NULL or empty for all.
+ *
+ * Returns JSON array of entries or NULL on failure.
+ */
+char *telio_get_event_history(const struct telio *dev, uint64_t since, const char *filter);
+
+/**
+ * Start JSON-RPC control server on a Unix domain socket, so the device can be controlled
+ * from other processes, e.g. by `tcli --control <path>`. Socket is accessible only to the
+ * owner of the process. Replaces server started earlier, stopped by `telio_destroy`.
+ * Not supported on Windows.
+ *
+ * # Parameters
+ * - `path`: Path of the socket, socket left at the path by a previous run is replaced.
+ */
+enum telio_result telio_start_control_server(const struct telio *dev, const char *path);
+
+/**
+ * Stop control server started by `telio_start_control_server`, closing its connections.
+ */
+enum telio_result telio_stop_control_server(const struct telio *dev);
+
 /**
  * Get last error's message length, including trailing null
  */
@@ -382,6 +593,7 @@ enum telio_result __telio_generate_thread_panic(const struct telio *dev);
 
 void __telio_force_export(enum telio_result,
                           enum telio_adapter_type,
+                          enum telio_log_privacy,
                           struct telio_event_cb,
                           struct telio_logger_cb,
                           struct telio_protect_cb);
@@ -45,6 +45,12 @@ struct telio {};
 %extend telio {
     static enum telio_adapter_type get_default_adapter();
 
+    static enum telio_result set_analytics_cb(telio_event_cb analytics);
+
+    static enum telio_result set_log_filter(const char* filter);
+
+    static enum telio_result set_log_privacy(enum telio_log_privacy privacy);
+
 
 #if defined(__ANDROID__)
     telio(const char* features, telio_event_cb events, enum telio_log_level level, telio_logger_cb logger, telio_protect_cb protect) {
@@ -88,18 +94,41 @@ struct telio {};
 
     enum telio_result stop();
 
+    /* Returns handle of started interface, or negated telio_result on failure,
+     * as handle 0 is the default interface. */
+    long long add_interface(const char *private_key,
+                            enum telio_adapter_type adapter,
+                            const char *name,
+                            unsigned int mtu) {
+        unsigned int interface = 0;
+        enum telio_result res = telio_add_interface($self, &interface, private_key, adapter, name, mtu);
+        if (TELIO_RES_OK != res) {
+            return -(long long)res;
+        }
+        return interface;
+    }
+
+    enum telio_result remove_interface(unsigned int interface);
+
     unsigned long long get_adapter_luid();
 
     enum telio_result set_private_key(const char *private_key);
 
+    enum telio_result interface_set_private_key(unsigned int interface,
+                                                const char *private_key);
+
     const char* get_private_key();
 
 #if defined(__linux__)
     enum telio_result set_fwmark(unsigned int fwmark);
+
+    enum telio_result interface_set_fwmark(unsigned int interface, unsigned int fwmark);
 #endif
 
     enum telio_result notify_network_change(const char *notify_info);
 
+    enum telio_result set_traversal(const char *traversal);
+
     enum telio_result connect_to_exit_node(const char *public_key,
                                            const char *allowed_ips,
                                            const char *endpoint);
@@ -108,8 +137,17 @@ struct telio {};
 
     enum telio_result disconnect_from_exit_nodes();
 
+    enum telio_result interface_connect_to_exit_node(unsigned int interface,
+                                                     const char *public_key,
+                                                     const char *allowed_ips,
+                                                     const char *endpoint);
+
+    enum telio_result interface_disconnect_from_exit_nodes(unsigned int interface);
+
     enum telio_result set_meshnet(const char *cfg);
 
+    enum telio_result interface_set_meshnet(unsigned int interface, const char *cfg);
+
     enum telio_result set_meshnet_off();
 
     %newobject generate_secret_key;
@@ -121,6 +159,28 @@ struct telio {};
     %newobject get_status_map;
     const char* get_status_map();
 
+    %newobject get_peer_diagnostics;
+    const char* get_peer_diagnostics(const char *public_key);
+
+    %newobject get_peer_stats;
+    const char* get_peer_stats();
+
+    %newobject get_interface_peer_stats;
+    const char* get_interface_peer_stats(unsigned int interface);
+
+    %newobject get_metrics;
+    const char* get_metrics();
+
+    %newobject get_connectivity_matrix;
+    const char* get_connectivity_matrix();
+
+    %newobject get_event_history;
+    const char* get_event_history(unsigned long long since, const char* filter);
+
+    enum telio_result start_control_server(const char* path);
+
+    enum telio_result stop_control_server();
+
     %newobject get_last_error;
     const char* get_last_error();
 
@@ -0,0 +1,165 @@
+use std::{
+    collections::VecDeque,
+    io::{self, BufRead, BufReader, ErrorKind, Write},
+    net::IpAddr,
+    os::unix::net::UnixStream,
+    path::Path,
+};
+
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::{json, Value};
+use telio_crypto::PublicKey;
+use telio_model::{event::EventType, mesh::ExitNode};
+use thiserror::Error as TError;
+
+use super::protocol::{
+    RpcError, RpcRequest, RpcResponse, Version, EVENT_METHOD, JSONRPC_VERSION, SCHEMA_VERSION,
+};
+
+#[derive(Debug, TError)]
+pub enum ClientError {
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    #[error(transparent)]
+    Serde(#[from] serde_json::Error),
+    #[error(transparent)]
+    Rpc(#[from] RpcError),
+    #[error("Server uses schema version {0}, expected {}", SCHEMA_VERSION)]
+    SchemaVersion(u32),
+    #[error("Connection closed by server")]
+    Closed,
+}
+
+pub type Result<T> = std::result::Result<T, ClientError>;
+
+/// Blocking client of [ControlServer](super::ControlServer)
+pub struct Client {
+    stream: BufReader<UnixStream>,
+    next_id: u64,
+    // Bytes of a message, which was not yet received whole
+    partial: Vec<u8>,
+    events: VecDeque<Value>,
+}
+
+impl Client {
+    /// Connect to server, checking it speaks the same schema version
+    pub fn connect(path: impl AsRef<Path>) -> Result<Self> {
+        let mut client = Self {
+            stream: BufReader::new(UnixStream::connect(path)?),
+            next_id: 0,
+            partial: Vec::new(),
+            events: VecDeque::new(),
+        };
+
+        let version = client.get_version()?;
+        if version.schema != SCHEMA_VERSION {
+            return Err(ClientError::SchemaVersion(version.schema));
+        }
+        Ok(client)
+    }
+
+    pub fn get_version(&mut self) -> Result<Version> {
+        self.call("get_version", Value::Null)
+    }
+
+    /// Set meshnet config, given as JSON string, or turn meshnet off with `None`
+    pub fn set_meshnet(&mut self, config: Option<&str>) -> Result<()> {
+        let config: Value = match config {
+            Some(config) => serde_json::from_str(config)?,
+            None => Value::Null,
+        };
+        self.call("set_meshnet", config)
+    }
+
+    pub fn connect_to_exit_node(&mut self, node: &ExitNode) -> Result<()> {
+        self.call("connect_to_exit_node", node)
+    }
+
+    pub fn enable_magic_dns(&mut self, forward_servers: &[IpAddr]) -> Result<()> {
+        self.call(
+            "enable_magic_dns",
+            json!({ "forward_servers": forward_servers }),
+        )
+    }
+
+    /// Nodes of the running device, as JSON
+    pub fn get_status_map(&mut self) -> Result<Value> {
+        self.call("get_status_map", Value::Null)
+    }
+
+    /// Subscribe to events of given types about given peers, empty lists pass all events
+    pub fn subscribe(&mut self, types: &[EventType], peers: &[PublicKey]) -> Result<()> {
+        self.call("subscribe", json!({ "types": types, "peers": peers }))
+    }
+
+    /// Events received since the last call, without waiting for more
+    pub fn poll_events(&mut self) -> Result<Vec<Value>> {
+        self.stream.get_ref().set_nonblocking(true)?;
+        let received = loop {
+            match self.receive() {
+                Ok(message) => self.queue_event(message),
+                Err(ClientError::Io(e)) if e.kind() == ErrorKind::WouldBlock => break Ok(()),
+                Err(e) => break Err(e),
+            }
+        };
+        self.stream.get_ref().set_nonblocking(false)?;
+        received?;
+        Ok(self.events.drain(..).collect())
+    }
+
+    /// Call a method and wait for its result, queueing events received meanwhile
+    pub fn call<P: Serialize, R: DeserializeOwned>(
+        &mut self,
+        method: &str,
+        params: P,
+    ) -> Result<R> {
+        self.next_id += 1;
+        let id = Value::from(self.next_id);
+        let request = RpcRequest {
+            jsonrpc: JSONRPC_VERSION.to_owned(),
+            id: Some(id.clone()),
+            method: method.to_owned(),
+            params: serde_json::to_value(params)?,
+        };
+        let mut line = serde_json::to_vec(&request)?;
+        line.push(b'\n');
+        self.stream.get_mut().write_all(&line)?;
+
+        loop {
+            let message = self.receive()?;
+            if message.get("method").is_some() {
+                self.queue_event(message);
+                continue;
+            }
+
+            let response: RpcResponse = serde_json::from_value(message)?;
+            // Response to a request abandoned by an earlier failed call
+            if response.id != id {
+                continue;
+            }
+            return match response.error {
+                Some(error) => Err(error.into()),
+                None => Ok(serde_json::from_value(response.result.unwrap_or_default())?),
+            };
+        }
+    }
+
+    fn queue_event(&mut self, message: Value) {
+        if message["method"] == EVENT_METHOD {
+            self.events.push_back(message["params"].clone());
+        }
+    }
+
+    fn receive(&mut self) -> Result<Value> {
+        // On timeout or interruption, bytes read so far are kept in `partial`
+        if self.stream.read_until(b'\n', &mut self.partial)? == 0 {
+            return Err(ClientError::Closed);
+        }
+        if self.partial.last() != Some(&b'\n') {
+            return Err(ClientError::Closed);
+        }
+        let message = serde_json::from_slice(&self.partial);
+        self.partial.clear();
+        Ok(message?)
+    }
+}
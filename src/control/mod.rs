@@ -0,0 +1,107 @@
+//! Optional JSON-RPC 2.0 control interface over a Unix domain socket, for controlling
+//! a running device from other processes. See [protocol::Request] for available methods.
+
+mod client;
+pub mod protocol;
+mod server;
+
+pub use client::{Client, ClientError};
+pub use server::{ControlServer, Extension};
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{BufRead, BufReader, Write},
+        os::unix::net::UnixStream,
+        sync::{Arc, Mutex},
+    };
+
+    use serde_json::Value;
+    use telio_model::api_config::Features;
+
+    use super::{protocol::code, *};
+    use crate::device::Device;
+
+    fn server(name: &str) -> (ControlServer, std::path::PathBuf) {
+        let path = std::env::temp_dir().join(format!("{}-{}.sock", name, std::process::id()));
+        let device = Device::new_without_callback(Features::default(), None).unwrap();
+        let server = ControlServer::start(&path, Arc::new(Mutex::new(device))).unwrap();
+        (server, path)
+    }
+
+    #[test]
+    fn client_calls_device_methods() {
+        let (server, path) = server("control-calls");
+        let mut client = Client::connect(&path).unwrap();
+
+        client.subscribe(&[], &[]).unwrap();
+        match client.subscribe(&[], &[]) {
+            Err(ClientError::Rpc(error)) => assert_eq!(error.code, code::INVALID_REQUEST),
+            other => panic!("Connection is subscribed already, got {:?}", other),
+        }
+        match client.get_status_map() {
+            Err(ClientError::Rpc(error)) => assert_eq!(error.code, code::DEVICE_ERROR),
+            other => panic!("Device is not started, got {:?}", other),
+        }
+        match client.call::<_, Value>("reboot", Value::Null) {
+            Err(ClientError::Rpc(error)) => assert_eq!(error.code, code::METHOD_NOT_FOUND),
+            other => panic!("Method does not exist, got {:?}", other),
+        }
+        assert!(client.poll_events().unwrap().is_empty());
+
+        drop(server);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn socket_is_private_and_serves_extension() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let path = std::env::temp_dir().join(format!("control-ext-{}.sock", std::process::id()));
+        let device = Device::new_without_callback(Features::default(), None).unwrap();
+        let extension: Extension =
+            Arc::new(|method: &str, params: Value| (method == "echo").then(|| Ok(params)));
+        let _server = ControlServer::start_with_extension(
+            &path,
+            Arc::new(Mutex::new(device)),
+            Some(extension),
+        )
+        .unwrap();
+
+        let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+
+        let mut client = Client::connect(&path).unwrap();
+        assert_eq!(
+            client.call::<_, Value>("echo", "hi").unwrap(),
+            Value::from("hi")
+        );
+        match client.call::<_, Value>("reboot", Value::Null) {
+            Err(ClientError::Rpc(error)) => assert_eq!(error.code, code::METHOD_NOT_FOUND),
+            other => panic!("Method does not exist, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn malformed_messages_get_errors() {
+        let (_server, path) = server("control-malformed");
+        let mut stream = UnixStream::connect(&path).unwrap();
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+        let mut response = |line: &str| {
+            stream.write_all(line.as_bytes()).unwrap();
+            let mut response = String::new();
+            reader.read_line(&mut response).unwrap();
+            serde_json::from_str::<Value>(&response).unwrap()
+        };
+
+        assert_eq!(response("not json\n")["error"]["code"], code::PARSE_ERROR);
+        assert_eq!(
+            response("{\"jsonrpc\": \"1.0\", \"id\": 3, \"method\": \"get_version\"}\n")["error"]
+                ["code"],
+            code::INVALID_REQUEST
+        );
+        let version = response("{\"jsonrpc\": \"2.0\", \"id\": 4, \"method\": \"get_version\"}\n");
+        assert_eq!(version["id"], 4);
+        assert_eq!(version["result"]["schema"], protocol::SCHEMA_VERSION);
+    }
+}
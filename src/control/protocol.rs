@@ -0,0 +1,183 @@
+use std::net::IpAddr;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use telio_crypto::PublicKey;
+use telio_model::{config::Config, event::EventType, mesh::ExitNode};
+use thiserror::Error as TError;
+
+/// Version of the schema of methods, their params and results.
+///
+/// Bumped on every incompatible change, clients should refuse to talk to other versions.
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// Version of JSON-RPC used
+pub const JSONRPC_VERSION: &str = "2.0";
+
+/// Method of notifications carrying events of subscribed connection
+pub const EVENT_METHOD: &str = "event";
+
+/// Error codes, as defined by JSON-RPC 2.0
+pub mod code {
+    pub const PARSE_ERROR: i64 = -32700;
+    pub const INVALID_REQUEST: i64 = -32600;
+    pub const METHOD_NOT_FOUND: i64 = -32601;
+    pub const INVALID_PARAMS: i64 = -32602;
+    /// Device refused the request, message holds the reason
+    pub const DEVICE_ERROR: i64 = -32000;
+}
+
+/// Methods accepted by the control server, with their params
+#[derive(Debug, Deserialize)]
+#[serde(tag = "method", content = "params", rename_all = "snake_case")]
+pub enum Request {
+    /// Result: [Version]
+    GetVersion,
+    /// Params: meshnet config, or `null` to turn meshnet off. Result: `null`
+    SetMeshnet(Option<Config>),
+    /// Params: exit node. Result: `null`
+    ConnectToExitNode(ExitNode),
+    /// Params: `{"forward_servers": [ip, ...]}`. Result: `null`
+    EnableMagicDns { forward_servers: Vec<IpAddr> },
+    /// Result: array of nodes, as returned by `telio_get_status_map`
+    GetStatusMap,
+    /// Params: `{"types": [type, ...], "peers": [public key, ...]}`, both optional, empty passes
+    /// all events. Result: `null`, followed by [EVENT_METHOD] notifications with the events
+    Subscribe {
+        #[serde(default)]
+        types: Vec<EventType>,
+        #[serde(default)]
+        peers: Vec<PublicKey>,
+    },
+}
+
+impl Request {
+    const METHODS: &'static [&'static str] = &[
+        "get_version",
+        "set_meshnet",
+        "connect_to_exit_node",
+        "enable_magic_dns",
+        "get_status_map",
+        "subscribe",
+    ];
+
+    /// Parse method call, telling unknown methods apart from bad params
+    pub fn parse(method: &str, params: Value) -> Result<Self, RpcError> {
+        if !Self::METHODS.contains(&method) {
+            return Err(RpcError::new(
+                code::METHOD_NOT_FOUND,
+                format!("Unknown method: {}", method),
+            ));
+        }
+        serde_json::from_value(serde_json::json!({ "method": method, "params": params }))
+            .map_err(|e| RpcError::new(code::INVALID_PARAMS, e.to_string()))
+    }
+}
+
+/// Result of [Request::GetVersion]
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Version {
+    /// [SCHEMA_VERSION] of the server
+    pub schema: u32,
+    /// Version of libtelio
+    pub libtelio: String,
+}
+
+/// JSON-RPC request envelope, request without `id` is a notification and gets no response
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RpcRequest {
+    pub jsonrpc: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<Value>,
+    pub method: String,
+    #[serde(default)]
+    pub params: Value,
+}
+
+/// JSON-RPC response envelope
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RpcResponse {
+    pub jsonrpc: String,
+    pub id: Value,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub result: Option<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<RpcError>,
+}
+
+impl RpcResponse {
+    pub fn new(id: Value, result: Result<Value, RpcError>) -> Self {
+        let (result, error) = match result {
+            Ok(result) => (Some(result), None),
+            Err(error) => (None, Some(error)),
+        };
+        Self {
+            jsonrpc: JSONRPC_VERSION.to_owned(),
+            id,
+            result,
+            error,
+        }
+    }
+}
+
+/// JSON-RPC error object
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, TError)]
+#[error("{message} ({code})")]
+pub struct RpcError {
+    pub code: i64,
+    pub message: String,
+}
+
+impl RpcError {
+    pub fn new(code: i64, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn requests_are_parsed_by_method() {
+        assert!(matches!(
+            Request::parse("get_status_map", Value::Null),
+            Ok(Request::GetStatusMap)
+        ));
+        assert!(matches!(
+            Request::parse("set_meshnet", Value::Null),
+            Ok(Request::SetMeshnet(None))
+        ));
+        assert!(matches!(
+            Request::parse(
+                "enable_magic_dns",
+                serde_json::json!({ "forward_servers": ["1.1.1.1"] })
+            ),
+            Ok(Request::EnableMagicDns { forward_servers }) if forward_servers == ["1.1.1.1".parse::<IpAddr>().unwrap()]
+        ));
+        assert!(matches!(
+            Request::parse("subscribe", serde_json::json!({ "types": ["node"] })),
+            Ok(Request::Subscribe { types, peers }) if types == [EventType::Node] && peers.is_empty()
+        ));
+    }
+
+    #[test]
+    fn unknown_methods_and_bad_params_are_told_apart() {
+        assert_eq!(
+            Request::parse("reboot", Value::Null).unwrap_err().code,
+            code::METHOD_NOT_FOUND
+        );
+        assert_eq!(
+            Request::parse(
+                "connect_to_exit_node",
+                serde_json::json!({ "public_key": 7 })
+            )
+            .unwrap_err()
+            .code,
+            code::INVALID_PARAMS
+        );
+    }
+}
@@ -0,0 +1,304 @@
+use std::{
+    fs::{self, DirBuilder, Permissions},
+    io::{self, BufRead, BufReader, Write},
+    net::Shutdown,
+    os::unix::{
+        fs::{DirBuilderExt, FileTypeExt, PermissionsExt},
+        net::{UnixListener, UnixStream},
+    },
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex, PoisonError,
+    },
+    thread::{self, JoinHandle},
+};
+
+use futures::stream::{abortable, AbortHandle};
+use serde::Serialize;
+use serde_json::Value;
+use telio_utils::{telio_log_debug, telio_log_warn};
+
+use super::protocol::{
+    code, Request, RpcError, RpcRequest, RpcResponse, Version, EVENT_METHOD, JSONRPC_VERSION,
+    SCHEMA_VERSION,
+};
+use crate::device::{Device, EventFilter};
+
+/// Methods of the application embedding the device, called with method name and params
+/// for methods unknown to the server. Returns `None` for methods unknown to it too.
+pub type Extension = Arc<dyn Fn(&str, Value) -> Option<Result<Value, RpcError>> + Send + Sync>;
+
+/// JSON-RPC control server on a Unix domain socket.
+///
+/// Messages are JSON objects, one per line. Each connection is served by its own thread,
+/// as device methods block on the device runtime. Server closes all connections and stops
+/// when dropped.
+pub struct ControlServer {
+    path: PathBuf,
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+    connections: Connections,
+}
+
+impl ControlServer {
+    /// Start listening on the socket at `path`, accessible only to the owner of the process
+    pub fn start(path: impl AsRef<Path>, device: Arc<Mutex<Device>>) -> io::Result<Self> {
+        Self::start_with_extension(path, device, None)
+    }
+
+    /// Same as [ControlServer::start], also serving methods of the `extension`
+    pub fn start_with_extension(
+        path: impl AsRef<Path>,
+        device: Arc<Mutex<Device>>,
+        extension: Option<Extension>,
+    ) -> io::Result<Self> {
+        let path = path.as_ref().to_owned();
+
+        // Socket left behind by a previous run would fail the bind, anything else is kept
+        if let Ok(metadata) = fs::symlink_metadata(&path) {
+            if metadata.file_type().is_socket() {
+                fs::remove_file(&path)?;
+            }
+        }
+        let listener = bind_private(&path)?;
+
+        let stop = Arc::new(AtomicBool::new(false));
+        let connections = Connections::default();
+        let thread = {
+            let stop = stop.clone();
+            let connections = connections.clone();
+            thread::spawn(move || {
+                for stream in listener.incoming() {
+                    if stop.load(Ordering::Relaxed) {
+                        break;
+                    }
+                    let accepted = stream.and_then(|stream| Ok((stream.try_clone()?, stream)));
+                    match accepted {
+                        Ok((handle, stream)) => {
+                            let device = device.clone();
+                            let extension = extension.clone();
+                            let thread = thread::spawn(move || {
+                                if let Err(e) = serve(stream, device, extension) {
+                                    telio_log_debug!("Control connection closed: {}", e);
+                                }
+                            });
+                            let mut connections =
+                                connections.lock().unwrap_or_else(PoisonError::into_inner);
+                            // Forget connections closed by their clients
+                            connections.retain(|(_, thread)| !thread.is_finished());
+                            connections.push((handle, thread));
+                        }
+                        Err(e) => telio_log_warn!("Failed to accept control connection: {}", e),
+                    }
+                }
+            })
+        };
+
+        Ok(Self {
+            path,
+            stop,
+            thread: Some(thread),
+            connections,
+        })
+    }
+}
+
+impl Drop for ControlServer {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        // Wake up the listener, so it notices it should stop
+        let _ = UnixStream::connect(&self.path);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+
+        let connections = std::mem::take(
+            &mut *self
+                .connections
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner),
+        );
+        for (stream, thread) in connections {
+            // Ends the reading loop of the connection, which stops it
+            let _ = stream.shutdown(Shutdown::Both);
+            let _ = thread.join();
+        }
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// Bind the socket inside a directory accessible only to the owner, so nobody can connect
+/// before its permissions are restricted, then link it to `path`. Unlike renaming, linking
+/// fails when something else is at `path`.
+fn bind_private(path: &Path) -> io::Result<UnixListener> {
+    let dir = path.with_file_name(format!(
+        ".{}.{}",
+        path.file_name()
+            .map(|name| name.to_string_lossy())
+            .unwrap_or_default(),
+        std::process::id()
+    ));
+    DirBuilder::new().mode(0o700).create(&dir)?;
+
+    let bound = dir.join("socket");
+    let listener = UnixListener::bind(&bound).and_then(|listener| {
+        fs::set_permissions(&bound, Permissions::from_mode(0o600))?;
+        fs::hard_link(&bound, path)?;
+        Ok(listener)
+    });
+    let _ = fs::remove_file(&bound);
+    let _ = fs::remove_dir(&dir);
+    listener
+}
+
+/// Events forwarded to the connection, stopped when dropped
+struct Subscription {
+    abort: AbortHandle,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Drop for Subscription {
+    fn drop(&mut self) {
+        self.abort.abort();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+type Writer = Arc<Mutex<UnixStream>>;
+
+/// Accepted connections, with their streams kept for shutting them down
+type Connections = Arc<Mutex<Vec<(UnixStream, JoinHandle<()>)>>>;
+
+/// Per connection state
+struct Connection {
+    device: Arc<Mutex<Device>>,
+    extension: Option<Extension>,
+    writer: Writer,
+    subscription: Option<Subscription>,
+}
+
+fn serve(
+    stream: UnixStream,
+    device: Arc<Mutex<Device>>,
+    extension: Option<Extension>,
+) -> io::Result<()> {
+    let mut connection = Connection {
+        device,
+        extension,
+        writer: Arc::new(Mutex::new(stream.try_clone()?)),
+        subscription: None,
+    };
+    for line in BufReader::new(stream).lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        if let Some(response) = handle(&line, &mut connection) {
+            send(&connection.writer, &response)?;
+        }
+    }
+    Ok(())
+}
+
+fn handle(line: &str, connection: &mut Connection) -> Option<RpcResponse> {
+    let request: RpcRequest = match serde_json::from_str(line) {
+        Ok(request) => request,
+        Err(e) => {
+            let error = RpcError::new(code::PARSE_ERROR, e.to_string());
+            return Some(RpcResponse::new(Value::Null, Err(error)));
+        }
+    };
+
+    let result = if request.jsonrpc != JSONRPC_VERSION {
+        Err(RpcError::new(
+            code::INVALID_REQUEST,
+            format!("Unsupported jsonrpc version: {}", request.jsonrpc),
+        ))
+    } else {
+        match Request::parse(&request.method, request.params.clone()) {
+            Err(error) if error.code == code::METHOD_NOT_FOUND => match &connection.extension {
+                Some(extension) => extension(&request.method, request.params).unwrap_or(Err(error)),
+                None => Err(error),
+            },
+            call => call.and_then(|call| execute(call, connection)),
+        }
+    };
+
+    // Requests without id are notifications, which get no response
+    request.id.map(|id| RpcResponse::new(id, result))
+}
+
+fn execute(request: Request, connection: &mut Connection) -> Result<Value, RpcError> {
+    let device = connection
+        .device
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner);
+    let device_error = |e: crate::device::Error| RpcError::new(code::DEVICE_ERROR, e.to_string());
+
+    match request {
+        Request::GetVersion => to_value(Version {
+            schema: SCHEMA_VERSION,
+            libtelio: option_env!("CI_COMMIT_TAG").unwrap_or("dev").to_owned(),
+        }),
+        Request::SetMeshnet(config) => device
+            .set_config(&config)
+            .map(|_| Value::Null)
+            .map_err(device_error),
+        Request::ConnectToExitNode(node) => device
+            .connect_exit_node(&node)
+            .map(|_| Value::Null)
+            .map_err(device_error),
+        Request::EnableMagicDns { forward_servers } => device
+            .enable_magic_dns(&forward_servers)
+            .map(|_| Value::Null)
+            .map_err(device_error),
+        Request::GetStatusMap => to_value(device.external_nodes().map_err(device_error)?),
+        Request::Subscribe { .. } if connection.subscription.is_some() => Err(RpcError::new(
+            code::INVALID_REQUEST,
+            "Connection is already subscribed",
+        )),
+        Request::Subscribe { types, peers } => {
+            let filter = types
+                .into_iter()
+                .fold(EventFilter::new(), EventFilter::event_type);
+            let filter = peers.into_iter().fold(filter, EventFilter::peer);
+            let (events, abort) = abortable(device.subscribe(filter));
+            let events = futures::executor::block_on_stream(Box::pin(events));
+            let writer = connection.writer.clone();
+            let thread = thread::spawn(move || {
+                for event in events {
+                    let notification = serde_json::json!({
+                        "jsonrpc": JSONRPC_VERSION,
+                        "method": EVENT_METHOD,
+                        "params": event,
+                    });
+                    // Connection is gone, stop forwarding
+                    if send(&writer, &notification).is_err() {
+                        break;
+                    }
+                }
+            });
+            connection.subscription = Some(Subscription {
+                abort,
+                thread: Some(thread),
+            });
+            Ok(Value::Null)
+        }
+    }
+}
+
+fn to_value<T: Serialize>(value: T) -> Result<Value, RpcError> {
+    serde_json::to_value(value).map_err(|e| RpcError::new(code::DEVICE_ERROR, e.to_string()))
+}
+
+fn send<T: Serialize>(writer: &Writer, message: &T) -> io::Result<()> {
+    let mut line = serde_json::to_vec(message)?;
+    line.push(b'\n');
+    writer
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .write_all(&line)
+}
@@ -0,0 +1,268 @@
+use std::{
+    collections::VecDeque,
+    fs::{self, File, OpenOptions},
+    io::{self, BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+    sync::mpsc::{self, Receiver, Sender},
+    thread::{self, JoinHandle},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use telio_model::{api_config::FeatureJournal, event::Event};
+use telio_utils::telio_log_warn;
+
+/// Kind of entries recording state transitions of device, as opposed to reported events
+pub const STATE_KIND: &str = "state";
+
+/// Single record of the journal
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct JournalEntry {
+    /// Milliseconds since UNIX epoch
+    pub timestamp: u64,
+    /// Type of the event (`relay`, `node` or `error`), or `state` for state transitions
+    pub kind: String,
+    /// Event as it was reported to the app, or description of the state transition
+    pub body: Value,
+}
+
+/// Bounded journal of events and state transitions, persisted as JSON lines.
+///
+/// Entries are kept in memory for queries, while a dedicated thread appends them to the file,
+/// so recording never waits for the disk. The file is rewritten with only the last
+/// `max_entries` entries once it grows to twice that size.
+pub struct Journal {
+    max_entries: usize,
+    entries: VecDeque<JournalEntry>,
+    writer: Option<(Sender<JournalEntry>, JoinHandle<()>)>,
+}
+
+/// Owner of the journal file, running on its own thread
+struct Writer {
+    path: PathBuf,
+    max_entries: usize,
+    entries: VecDeque<JournalEntry>,
+    file: File,
+    lines_in_file: usize,
+}
+
+impl Journal {
+    /// Open journal, keeping entries left in the file by previous runs
+    pub fn open(config: &FeatureJournal) -> io::Result<Self> {
+        let path = PathBuf::from(&config.path);
+        let max_entries = (config.max_entries as usize).max(1);
+
+        let mut entries = VecDeque::with_capacity(max_entries);
+        if let Ok(file) = File::open(&path) {
+            for line in BufReader::new(file).lines() {
+                // Last line may be cut short, if process was killed while writing it
+                if let Ok(entry) = serde_json::from_str(&line?) {
+                    push_bounded(&mut entries, max_entries, entry);
+                }
+            }
+        }
+
+        let writer = Writer {
+            file: Writer::rewrite(&path, &entries)?,
+            lines_in_file: entries.len(),
+            path,
+            max_entries,
+            entries: entries.clone(),
+        };
+        let (tx, rx) = mpsc::channel();
+        let handle = thread::Builder::new()
+            .name("telio-journal".to_owned())
+            .spawn(move || writer.run(rx))?;
+
+        let mut journal = Self {
+            max_entries,
+            entries,
+            writer: Some((tx, handle)),
+        };
+        journal.record(STATE_KIND, serde_json::json!({ "transition": "opened" }));
+        Ok(journal)
+    }
+
+    /// Record event reported to the app
+    pub fn record_event(&mut self, event: &Event) {
+        match serde_json::to_value(event) {
+            Ok(body) => {
+                let kind = body["type"].as_str().unwrap_or_default().to_owned();
+                self.record(&kind, body);
+            }
+            Err(e) => telio_log_warn!("Failed to serialize event for journal: {}", e),
+        }
+    }
+
+    /// Record state transition of device
+    pub fn record_state(&mut self, body: Value) {
+        self.record(STATE_KIND, body);
+    }
+
+    /// Entries recorded at or after `since`, of given kinds, or of all kinds if `kinds` is empty
+    ///
+    /// # Arguments
+    ///
+    /// * `since` - Milliseconds since UNIX epoch.
+    /// * `kinds` - Kinds of entries to return.
+    pub fn history(&self, since: u64, kinds: &[&str]) -> Vec<JournalEntry> {
+        self.entries
+            .iter()
+            .filter(|entry| entry.timestamp >= since)
+            .filter(|entry| kinds.is_empty() || kinds.contains(&entry.kind.as_str()))
+            .cloned()
+            .collect()
+    }
+
+    fn record(&mut self, kind: &str, body: Value) {
+        let entry = JournalEntry {
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_millis() as u64)
+                .unwrap_or_default(),
+            kind: kind.to_owned(),
+            body,
+        };
+
+        if let Some((writer, _)) = &self.writer {
+            if writer.send(entry.clone()).is_err() {
+                telio_log_warn!("Journal writer has stopped, entry is kept in memory only");
+            }
+        }
+        push_bounded(&mut self.entries, self.max_entries, entry);
+    }
+}
+
+impl Drop for Journal {
+    fn drop(&mut self) {
+        // Let the writer finish entries recorded so far
+        if let Some((writer, handle)) = self.writer.take() {
+            drop(writer);
+            let _ = handle.join();
+        }
+    }
+}
+
+impl Writer {
+    fn run(mut self, entries: Receiver<JournalEntry>) {
+        for entry in entries {
+            if let Err(e) = self.append(&entry) {
+                telio_log_warn!("Failed to write journal entry: {}", e);
+            }
+            push_bounded(&mut self.entries, self.max_entries, entry);
+        }
+    }
+
+    fn append(&mut self, entry: &JournalEntry) -> io::Result<()> {
+        if self.lines_in_file >= 2 * self.max_entries {
+            self.file = Self::rewrite(&self.path, &self.entries)?;
+            self.lines_in_file = self.entries.len();
+        }
+
+        let mut line = serde_json::to_string(entry)?;
+        line.push('\n');
+        self.file.write_all(line.as_bytes())?;
+        self.lines_in_file += 1;
+        Ok(())
+    }
+
+    /// Replace the file with given entries, returning it opened for appending
+    fn rewrite(path: &Path, entries: &VecDeque<JournalEntry>) -> io::Result<File> {
+        let mut tmp = path.as_os_str().to_owned();
+        tmp.push(".tmp");
+        {
+            let mut file = File::create(&tmp)?;
+            for entry in entries {
+                let mut line = serde_json::to_string(entry)?;
+                line.push('\n');
+                file.write_all(line.as_bytes())?;
+            }
+            file.sync_all()?;
+        }
+        fs::rename(&tmp, path)?;
+        OpenOptions::new().append(true).open(path)
+    }
+}
+
+fn push_bounded(entries: &mut VecDeque<JournalEntry>, max_entries: usize, entry: JournalEntry) {
+    if entries.len() == max_entries {
+        entries.pop_front();
+    }
+    entries.push_back(entry);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use telio_model::event::{Error as ErrorEvent, EventMsg, Set};
+
+    fn config(name: &str, max_entries: u32) -> FeatureJournal {
+        let path = std::env::temp_dir().join(format!("{}-{}.journal", name, std::process::id()));
+        let _ = fs::remove_file(&path);
+        FeatureJournal {
+            path: path.to_string_lossy().into_owned(),
+            max_entries,
+        }
+    }
+
+    #[test]
+    fn journal_is_bounded_and_survives_reopen() {
+        let config = config("bounded", 3);
+
+        let mut journal = Journal::open(&config).unwrap();
+        for i in 0..10 {
+            journal.record_state(serde_json::json!({ "transition": "test", "i": i }));
+        }
+        drop(journal);
+
+        let journal = Journal::open(&config).unwrap();
+        let history = journal.history(0, &[]);
+        let _ = fs::remove_file(&config.path);
+
+        // Reopening records its own transition, pushing out the oldest entry
+        assert_eq!(history.len(), 3);
+        assert_eq!(history[0].body["i"], 8);
+        assert_eq!(history[1].body["i"], 9);
+        assert_eq!(history[2].body["transition"], "opened");
+    }
+
+    #[test]
+    fn history_is_filtered_by_kind_and_time() {
+        let config = config("filtered", 100);
+
+        let mut journal = Journal::open(&config).unwrap();
+        journal.record_event(&Event::new::<ErrorEvent>().set(EventMsg::from("oops")));
+        let errors = journal.history(0, &["error"]);
+        let later = journal.history(errors[0].timestamp + 60_000, &[]);
+        let all = journal.history(0, &[]);
+        let _ = fs::remove_file(&config.path);
+
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].body["body"]["msg"], "oops");
+        assert!(later.is_empty());
+        assert_eq!(all.len(), 2);
+    }
+
+    #[test]
+    fn rewrite_keeps_files_named_like_journal() {
+        let config = config("sibling", 2);
+        let sibling = Path::new(&config.path).with_extension("tmp");
+        fs::write(&sibling, "not a journal").unwrap();
+
+        let mut journal = Journal::open(&config).unwrap();
+        for i in 0..10 {
+            journal.record_state(serde_json::json!({ "transition": "test", "i": i }));
+        }
+        drop(journal);
+
+        let kept = fs::read_to_string(&sibling);
+        let lines = fs::read_to_string(&config.path).unwrap().lines().count();
+        let _ = fs::remove_file(&sibling);
+        let _ = fs::remove_file(&config.path);
+
+        assert_eq!(kept.unwrap(), "not a journal");
+        // File is compacted once it reaches twice the number of kept entries
+        assert!(lines <= 4, "{} lines in journal", lines);
+    }
+}
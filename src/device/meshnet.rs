@@ -14,17 +14,20 @@ use std::{
 };
 
 //debug tools
-use telio_utils::{telio_err_with_log, telio_log_debug, telio_log_trace, telio_log_warn};
+use telio_utils::{
+    telio_err_with_log, telio_log_debug, telio_log_trace, telio_log_warn, telio_span,
+};
 
 use super::{Error, Result};
 use telio_model::{
-    api_config::PathType,
+    api_config::{FeatureKeepalive, PathType},
     mesh::{Node, NodeState},
 };
 
 /// Mapping from meshnet domain into WG domain
 pub(super) struct Meshnet {
     pub driver: Arc<wg::DynamicWg>,
+    keepalive: Option<FeatureKeepalive>,
     node_events: JoinHandle<()>,
     state: Arc<Mutex<State>>,
 }
@@ -43,6 +46,7 @@ impl Meshnet {
         mut path_changes: mpsc::Receiver<(PublicKey, PathType)>,
         driver: Arc<DynamicWg>,
         chan_rx: chan::Rx<Box<PeerEvent>>,
+        keepalive: Option<FeatureKeepalive>,
     ) -> Result<(Self, broadcast::Receiver<Box<Node>>)> {
         let (tx, rx) = broadcast::channel(256);
         let state = Arc::new(Mutex::new(State {
@@ -55,6 +59,7 @@ impl Meshnet {
         }));
 
         let s = state.clone();
+        let wg = driver.clone();
         let mut event_rx = chan_rx;
         let node_events = tokio::spawn(async move {
             loop {
@@ -63,7 +68,12 @@ impl Meshnet {
                         s.lock().await.upsert_peer_event(*event).await;
                     },
                     Some(event) = path_changes.recv()  =>{
-                        s.lock().await.peer_pathchange_event(event).await;
+                        let node = s.lock().await.peer_pathchange_event(event).await;
+                        if let (Some(node), Some(keepalive)) = (node, keepalive) {
+                            telio_span!(peer = node.public_key)
+                                .instrument(update_keepalive(&wg, &keepalive, &node))
+                                .await;
+                        }
                     },
                 }
             }
@@ -72,6 +82,7 @@ impl Meshnet {
         Ok((
             Self {
                 driver,
+                keepalive,
                 node_events,
                 state,
             },
@@ -137,10 +148,6 @@ impl Meshnet {
         self.driver.get_adapter_luid().await
     }
 
-    pub(super) async fn drop_connected_sockets(&self) {
-        self.driver.drop_connected_sockets().await
-    }
-
     #[cfg(target_os = "linux")]
     pub(super) async fn set_fwmark(&self, fwmark: u32) -> bool {
         if !self.driver.set_fwmark(fwmark).await {
@@ -179,7 +186,22 @@ impl Meshnet {
 
         if let Some(node) = state.upsert_node(node.clone()).await {
             telio_log_debug!("upsert_node : {:?}", node);
-            self.driver.add_peer({ &node }.into()).await;
+            let mut peer: wg::uapi::Peer = (&node).into();
+            if let Some(keepalive) = &self.keepalive {
+                // Endpoint of direct path is set by the path itself, not by config
+                let endpoint = match peer.endpoint {
+                    Some(endpoint) => Some(endpoint),
+                    None => self
+                        .driver
+                        .get_interface()
+                        .await
+                        .and_then(|interface| interface.peers.get(&node.public_key)?.endpoint),
+                };
+                // Zero turns off keepalive, set for peer's previous class
+                peer.persistent_keepalive_interval =
+                    Some(keepalive.interval(node.is_exit, node.path, endpoint.map(|e| e.ip())));
+            }
+            self.driver.add_peer(peer).await;
         }
 
         telio_log_debug!("Upsert_node_ignored succesfull: {:?}", node);
@@ -235,14 +257,42 @@ impl Meshnet {
     }
 }
 
+/// Apply keepalive policy to peer, whose path has changed. Only keepalive is updated,
+/// as peer's endpoint is managed by the path itself
+async fn update_keepalive(driver: &DynamicWg, keepalive: &FeatureKeepalive, node: &Node) {
+    let peer = driver
+        .get_interface()
+        .await
+        .and_then(|interface| interface.peers.get(&node.public_key).cloned());
+
+    if let Some(mut peer) = peer {
+        let interval =
+            Some(keepalive.interval(node.is_exit, node.path, peer.endpoint.map(|e| e.ip())));
+        if peer.persistent_keepalive_interval != interval {
+            telio_log_debug!(
+                "Keepalive of {:?} on {:?} path: {:?}",
+                node.public_key,
+                node.path,
+                interval
+            );
+            peer.persistent_keepalive_interval = interval;
+            driver.add_peer(peer).await;
+        }
+    }
+}
+
 impl State {
-    async fn peer_pathchange_event(&mut self, event: (PublicKey, PathType)) {
+    async fn peer_pathchange_event(&mut self, event: (PublicKey, PathType)) -> Option<Node> {
         let (pk, pt) = event;
-        if let Some(mut node) = self.nodes.get(&pk).cloned() {
-            node.path = pt;
-            telio_log_debug!("node at peer_pathchange_event:{:?}", node);
-            self.upsert_node(node).await;
+        let node = self.nodes.get_mut(&pk)?;
+        if node.path == pt {
+            return None;
         }
+        node.path = pt;
+        let node = node.clone();
+        telio_log_debug!("node at peer_pathchange_event:{:?}", node);
+        self.report(node.clone()).await;
+        Some(node)
     }
 
     /// Sync WG -> telio
@@ -275,6 +325,8 @@ impl State {
                 if node.hostname.is_none() {
                     node.hostname = old_node.hostname.clone();
                 }
+                // Path is tracked from path changes, not from config
+                node.path = old_node.path;
 
                 // Check for changes
                 if old_node != &node {
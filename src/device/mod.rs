@@ -1,19 +1,27 @@
+mod journal;
 mod meshnet;
 mod relay;
 
-use log::debug;
 use telio_crypto::{PublicKey, SecretKey};
 use telio_lana::*;
 use telio_nat_detect::nat_detection::{retrieve_single_nat, NatData};
+use telio_netstack::Netstack;
 use telio_sockets::{NativeProtector, Protect, SocketPool};
-use telio_task::io::{mc_chan::Tx, Chan, McChan};
+use telio_task::io::{
+    mc_chan::{into_stream, Tx},
+    Chan, McChan,
+};
 
 #[cfg(any(target_os = "macos", target_os = "ios"))]
 use telio_sockets::native;
 
 use telio_firewall::firewall::Firewall;
-use telio_nurse::data::MeshConfigUpdateEvent;
+use telio_nurse::{
+    data::MeshConfigUpdateEvent,
+    metrics::{MetricType, Metrics},
+};
 use telio_relay::derp::{Config as DerpConfig, Server as DerpServer};
+use telio_traversal::endpoint_providers::local::SharedGetIfAddrs;
 use telio_wg as wg;
 use thiserror::Error as TError;
 use tokio::{
@@ -28,28 +36,34 @@ use telio_dns::bind_tun;
 
 use ipnetwork::IpNetwork;
 
+use futures::{Stream, StreamExt};
+use serde_json::{json, Value};
+
 use std::{
-    collections::{HashMap, HashSet},
+    collections::{BTreeMap, HashMap, HashSet},
     io::{Error as IoError, ErrorKind},
     net::{IpAddr, Ipv4Addr},
-    sync::Arc,
+    sync::{Arc, Mutex as StdMutex, PoisonError},
     time::Duration,
 };
 
 use telio_utils::{telio_log_debug, telio_log_info};
 
 use telio_model::{
-    api_config::Features,
+    api_config::{FeatureTraversal, Features, LanaSink, PathType},
     config::Config,
-    event::{Event, Set},
+    diagnostics::{NatReport, PeerDiagnostics},
+    event::{Error as ErrorEvent, ErrorCode, ErrorLevel, Event, EventType, Set},
     mesh::Map as MeshMap,
-    mesh::{ExitNode, Node},
+    mesh::{ExitNode, Node, NodeState},
     report_event,
 };
 
-use self::{meshnet::Meshnet, relay::Config as RelayConfig, relay::Relay};
+use self::{journal::Journal, meshnet::Meshnet, relay::Config as RelayConfig, relay::Relay};
 
-pub use wg::{AdapterType, DynamicWg, Error as AdapterError, FirewallCb, Tun, WireGuard};
+use wg::{mtu::TUNNEL_MTU, stats::PeerStats};
+
+pub use wg::{AdapterType, DynamicWg, Error as AdapterError, FirewallCb, PacketIo, Tun, WireGuard};
 
 #[derive(Debug, TError)]
 pub enum Error {
@@ -85,8 +99,24 @@ pub enum Error {
     RelayError(#[from] relay::Error),
     #[error("Failed to recover information about NAT")]
     FailedNatInfoRecover(std::io::Error),
-    #[error("Failed to initialize libmoose: {0}")]
-    LibmooseError(#[from] telio_lana::moose::Error),
+    #[error("Failed to initialize analytics: {0}")]
+    LanaError(#[from] telio_lana::SinkError),
+    #[error("Invalid traversal features: {0}")]
+    BadTraversalFeatures(String),
+    #[error("Invalid paths features: {0}")]
+    BadPathsFeatures(String),
+    #[error("Direct connections are disabled")]
+    DirectDisabled,
+    #[error("Failed to serialize diagnostics: {0}")]
+    DiagnosticsSerialization(#[from] serde_json::Error),
+    #[error(transparent)]
+    Netstack(#[from] telio_netstack::Error),
+    #[error("Unknown interface {0}")]
+    InvalidInterface(InterfaceHandle),
+    #[error("Event journal is not enabled")]
+    JournalDisabled,
+    #[error("Failed to open event journal: {0}")]
+    JournalOpen(IoError),
 }
 
 pub type Result<T = ()> = std::result::Result<T, Error>;
@@ -94,20 +124,77 @@ pub type Result<T = ()> = std::result::Result<T, Error>;
 pub trait EventCb: Fn(Box<Event>) + Send + 'static {}
 impl<T> EventCb for T where T: Fn(Box<Event>) + Send + 'static {}
 
+/// Selects events delivered by [Device::subscribe]. Empty filter passes all events.
+#[derive(Clone, Debug, Default)]
+pub struct EventFilter {
+    types: HashSet<EventType>,
+    peers: HashSet<PublicKey>,
+}
+
+impl EventFilter {
+    /// Filter passing all events, narrowed down by [EventFilter::event_type] and [EventFilter::peer]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Pass events of given type, can be called multiple times to pass more types
+    pub fn event_type(mut self, event_type: EventType) -> Self {
+        self.types.insert(event_type);
+        self
+    }
+
+    /// Pass only events about given node, can be called multiple times to pass more nodes.
+    /// Events not related to any node (relay and error events) are not passed then.
+    pub fn peer(mut self, public_key: PublicKey) -> Self {
+        self.peers.insert(public_key);
+        self
+    }
+
+    /// Whether `event` is of one of selected types and about one of selected nodes
+    pub fn matches(&self, event: &Event) -> bool {
+        (self.types.is_empty() || self.types.contains(&event.event_type()))
+            && (self.peers.is_empty()
+                || event
+                    .peer()
+                    .map_or(false, |peer| self.peers.contains(&peer)))
+    }
+}
+
 #[derive(Clone, Default)]
 pub struct DeviceConfig {
     pub private_key: SecretKey,
     pub adapter: AdapterType,
     pub name: Option<String>,
     pub tun: Option<Tun>,
+    /// Packet source and sink for [AdapterType::UserspaceWg], replacing `tun`, which must not be set
+    pub packet_io: Option<Arc<dyn PacketIo>>,
+    /// MTU of tunnel, [TUNNEL_MTU] if not set. Path MTUs of peers are capped to it
+    pub mtu: Option<u32>,
+    /// Local interfaces, offered to peers when direct paths migrate, system ones by default
+    pub local_interfaces: SharedGetIfAddrs,
 }
 
+/// Handle, addressing one of device's WireGuard interfaces
+pub type InterfaceHandle = u32;
+
+/// Interface created by [Device::start], used by calls which do not take a handle
+pub const DEFAULT_INTERFACE: InterfaceHandle = 0;
+
+/// How often probed path MTUs are pushed to WireGuard adapter
+const PATH_MTU_SYNC_INTERVAL: Duration = Duration::from_secs(30);
+
 pub struct Device {
     art: Option<Arc<AsyncRuntime>>,
     event: Tx<Box<Event>>,
     rt: Option<Mutex<Runtime>>,
+    // Interfaces added next to the default one, each with its own keys, meshnet and relay
+    interfaces: BTreeMap<InterfaceHandle, Mutex<Runtime>>,
+    last_interface: InterfaceHandle,
     protect: Option<Protect>,
-    features: Features,
+    // Traversal features may be tuned on a running device
+    features: StdMutex<Features>,
+    // Shared with the task forwarding events to the app, which records them
+    journal: Option<Arc<StdMutex<Journal>>>,
 }
 
 enum ExitNodeType {
@@ -139,11 +226,14 @@ pub struct RequestedState {
 }
 
 struct Runtime {
+    interface: InterfaceHandle,
     stop: watch::Sender<bool>,
     meshnet: Meshnet,
     wireguard_interface: Arc<DynamicWg>,
     dns: Option<LocalDnsResolver>,
     tun_for_dns: Option<i32>,
+    // Userspace TCP/IP stack, terminating meshnet traffic instead of kernel interface
+    netstack: Option<Netstack>,
     // save fwmark to use it again when derp conn is recreated
     #[cfg(target_os = "linux")]
     fwmark: u32,
@@ -183,6 +273,17 @@ impl From<&Config> for RelayConfig {
                 .unwrap_or_else(|| vec![IpAddr::V4(Ipv4Addr::UNSPECIFIED)])
                 .get(0)
                 .map_or(IpAddr::V4(Ipv4Addr::UNSPECIFIED), |ip| *ip),
+            peer_relays: c
+                .peers
+                .as_ref()
+                .map(|peers| {
+                    peers
+                        .iter()
+                        .filter(|p| p.allow_peer_relay)
+                        .map(|p| p.public_key)
+                        .collect()
+                })
+                .unwrap_or_default(),
         }
     }
 }
@@ -194,13 +295,39 @@ impl Device {
         features: Features,
         event_cb: F,
         protect: Option<Protect>,
+    ) -> Result<Self> {
+        Self::with_callback(features, Some(Box::new(event_cb)), protect)
+    }
+
+    /// Create device, whose events are only delivered through [Device::subscribe]
+    pub fn new_without_callback(features: Features, protect: Option<Protect>) -> Result<Self> {
+        Self::with_callback(features, None, protect)
+    }
+
+    fn with_callback(
+        features: Features,
+        event_cb: Option<Box<dyn EventCb>>,
+        protect: Option<Protect>,
     ) -> Result<Self> {
         let version_tag = option_env!("CI_COMMIT_TAG").unwrap_or("dev");
         let commit_sha = option_env!("CI_COMMIT_SHA").unwrap_or("dev");
         telio_log_info!("Created libtelio instance {}, {}", version_tag, commit_sha);
 
+        if let Some(traversal) = &features.traversal {
+            traversal.validate().map_err(Error::BadTraversalFeatures)?;
+        }
+
+        if let Some(paths) = &features.paths {
+            paths.validate().map_err(Error::BadPathsFeatures)?;
+        }
+
         if let Some(lana) = &features.lana {
-            init_lana(lana.event_path.clone(), version_tag.to_string(), lana.prod)?;
+            let sink: Arc<dyn EventSink> = match lana.sink {
+                LanaSink::Moose => Arc::new(MooseSink::new(lana.event_path.clone(), lana.prod)),
+                LanaSink::File => Arc::new(FileSink::new(&lana.event_path)),
+                LanaSink::Callback => app_sink().ok_or(telio_lana::SinkError::NoCallback)?,
+            };
+            init_lana(sink, version_tag)?;
         }
 
         let art = Builder::new_multi_thread()
@@ -209,19 +336,54 @@ impl Device {
             .enable_time()
             .build()?;
 
-        let (event_tx, mut event_rx) = tokio::sync::broadcast::channel(256);
-        art.spawn(async move {
-            while let Ok(event) = event_rx.recv().await {
-                event_cb(event);
-            }
-        });
+        let journal = match &features.journal {
+            Some(config) => Some(Arc::new(StdMutex::new(
+                Journal::open(config).map_err(Error::JournalOpen)?,
+            ))),
+            None => None,
+        };
+
+        let (event_tx, event_rx) = tokio::sync::broadcast::channel(256);
+        // Subscribers have their own receivers, nothing to forward to otherwise
+        if event_cb.is_some() || journal.is_some() {
+            let event_journal = journal.clone();
+            art.spawn(async move {
+                // Forwarder which falls behind skips the oldest events, but keeps going
+                let mut events = Box::pin(into_stream(event_rx));
+                while let Some(event) = events.next().await {
+                    if let Some(journal) = event_journal.as_ref() {
+                        journal
+                            .lock()
+                            .unwrap_or_else(PoisonError::into_inner)
+                            .record_event(&event);
+                    }
+                    if let Some(event_cb) = event_cb.as_ref() {
+                        event_cb(event);
+                    }
+                }
+            });
+        }
 
         Ok(Device {
-            features,
+            features: StdMutex::new(features),
             art: Some(Arc::new(art)),
             event: event_tx,
             rt: None,
+            interfaces: BTreeMap::new(),
+            last_interface: DEFAULT_INTERFACE,
             protect,
+            journal,
+        })
+    }
+
+    /// Stream of events reported from now on, passing the `filter`.
+    ///
+    /// Each call creates an independent subscriber. Stream ends when device is dropped.
+    /// Subscriber which does not keep up with events misses the oldest of them.
+    pub fn subscribe(&self, filter: EventFilter) -> impl Stream<Item = Event> + Send + 'static {
+        into_stream(self.event.subscribe()).filter_map(move |event| {
+            let event = filter.matches(&event).then(|| *event);
+            async move { event }
         })
     }
 
@@ -232,14 +394,25 @@ impl Device {
     pub fn nodes(&self) -> Result<Vec<Node>> {
         self.art()?.block_on(async {
             let rt = self.rt()?.lock().await;
-            Ok(rt.meshnet.nodes().await)
+            Ok(rt.nodes().await)
         })
     }
 
+    /// External nodes of all interfaces, each tagged with handle of its interface
     pub fn external_nodes(&self) -> Result<Vec<Node>> {
         self.art()?.block_on(async {
-            let rt = self.rt()?.lock().await;
-            Ok(rt.meshnet.external_nodes().await)
+            let mut nodes = self.rt()?.lock().await.external_nodes().await;
+            for rt in self.interfaces.values() {
+                nodes.extend(rt.lock().await.external_nodes().await);
+            }
+            Ok(nodes)
+        })
+    }
+
+    pub fn interface_external_nodes(&self, interface: InterfaceHandle) -> Result<Vec<Node>> {
+        self.art()?.block_on(async {
+            let rt = self.iface(interface)?.lock().await;
+            Ok(rt.external_nodes().await)
         })
     }
 
@@ -248,23 +421,97 @@ impl Device {
             return Err(Error::AlreadyStarted);
         }
 
-        self.rt = Some(Mutex::new(self.art()?.block_on(Runtime::start(
+        let rt = self.art()?.block_on(Runtime::start(
+            DEFAULT_INTERFACE,
             self.event.clone(),
             config,
-            self.features.clone(),
+            self.features(),
             self.protect.clone(),
-        ))?));
+        ));
+        self.record_state(
+            json!({ "transition": "started", "interface": DEFAULT_INTERFACE }),
+            &rt,
+        );
+        self.rt = Some(Mutex::new(rt?));
         Ok(())
     }
 
     pub fn stop(&mut self) {
+        let interfaces = std::mem::take(&mut self.interfaces);
+        if let Some(art) = &self.art {
+            for rt in interfaces.into_values() {
+                art.block_on(rt.into_inner().stop())
+            }
+        }
+
         if let Some(rt) = self.rt.take() {
             if let Some(art) = &self.art {
                 art.block_on(rt.into_inner().stop())
             }
+            self.record_state(json!({ "transition": "stopped" }), &Ok(()));
         }
     }
 
+    /// Start additional WireGuard interface next to the default one, e.g. for a VPN exit
+    /// which should not share keys, fwmark or MTU with meshnet. Interface gets its own
+    /// meshnet and relay, but netstack and magic DNS are only available on the default one.
+    /// Unnamed interface gets a name, numbered by its handle.
+    pub fn add_interface(&mut self, config: &DeviceConfig) -> Result<InterfaceHandle> {
+        self.rt()?;
+
+        let interface = self.last_interface + 1;
+        let features = Features {
+            netstack: None,
+            ..self.features()
+        };
+        let config = DeviceConfig {
+            name: config
+                .name
+                .clone()
+                .or_else(|| Some(wg::default_name(interface))),
+            ..config.clone()
+        };
+        let rt = self.art()?.block_on(Runtime::start(
+            interface,
+            self.event.clone(),
+            &config,
+            features,
+            self.protect.clone(),
+        ));
+        self.record_state(
+            json!({ "transition": "interface_added", "interface": interface }),
+            &rt,
+        );
+        let rt = rt?;
+
+        self.last_interface = interface;
+        self.interfaces.insert(interface, Mutex::new(rt));
+        Ok(interface)
+    }
+
+    /// Stop interface added by [Device::add_interface]
+    pub fn remove_interface(&mut self, interface: InterfaceHandle) -> Result {
+        let rt = self
+            .interfaces
+            .remove(&interface)
+            .ok_or(Error::InvalidInterface(interface))?;
+        self.art()?.block_on(rt.into_inner().stop());
+        self.record_state(
+            json!({ "transition": "interface_removed", "interface": interface }),
+            &Ok(()),
+        );
+        Ok(())
+    }
+
+    /// Handles of running interfaces, starting with the default one
+    pub fn interfaces(&self) -> Vec<InterfaceHandle> {
+        self.rt
+            .iter()
+            .map(|_| DEFAULT_INTERFACE)
+            .chain(self.interfaces.keys().copied())
+            .collect()
+    }
+
     pub fn get_adapter_luid(&mut self) -> u64 {
         if let Some(art) = &self.art {
             art.block_on(async {
@@ -300,16 +547,34 @@ impl Device {
         }
     }
 
+    /// Rotate device's private key, keeping meshnet peers, relay and magic DNS running
     pub fn set_private_key(&self, private_key: &SecretKey) -> Result {
-        self.art()?.block_on(async {
-            let mut rt = self.rt()?.lock().await;
+        self.set_interface_private_key(DEFAULT_INTERFACE, private_key)
+    }
+
+    pub fn set_interface_private_key(
+        &self,
+        interface: InterfaceHandle,
+        private_key: &SecretKey,
+    ) -> Result {
+        let ret = self.art()?.block_on(async {
+            let mut rt = self.iface(interface)?.lock().await;
             rt.set_private_key(private_key).await
-        })
+        });
+        self.record_state(
+            json!({ "transition": "private_key_set", "interface": interface }),
+            &ret,
+        );
+        ret
     }
 
     pub fn get_private_key(&self) -> Result<SecretKey> {
+        self.get_interface_private_key(DEFAULT_INTERFACE)
+    }
+
+    pub fn get_interface_private_key(&self, interface: InterfaceHandle) -> Result<SecretKey> {
         self.art()?.block_on(async {
-            let rt = self.rt()?.lock().await;
+            let rt = self.iface(interface)?.lock().await;
             rt.get_private_key().await
         })
     }
@@ -317,8 +582,14 @@ impl Device {
     #[cfg(any(target_os = "linux", doc))]
     #[cfg_attr(docsrs, doc(cfg(target_os = "linux")))]
     pub fn set_fwmark(&self, fwmark: u32) -> Result {
+        self.set_interface_fwmark(DEFAULT_INTERFACE, fwmark)
+    }
+
+    #[cfg(any(target_os = "linux", doc))]
+    #[cfg_attr(docsrs, doc(cfg(target_os = "linux")))]
+    pub fn set_interface_fwmark(&self, interface: InterfaceHandle, fwmark: u32) -> Result {
         self.art()?.block_on(async {
-            let mut rt = self.rt()?.lock().await;
+            let mut rt = self.iface(interface)?.lock().await;
             rt.set_fwmark(fwmark).await
         })
     }
@@ -337,48 +608,159 @@ impl Device {
     }
 
     pub fn set_config(&self, config: &Option<Config>) -> Result {
-        self.art()?.block_on(async {
-            let mut rt = self.rt()?.lock().await;
+        self.set_interface_config(DEFAULT_INTERFACE, config)
+    }
+
+    pub fn set_interface_config(
+        &self,
+        interface: InterfaceHandle,
+        config: &Option<Config>,
+    ) -> Result {
+        let ret = self.art()?.block_on(async {
+            let mut rt = self.iface(interface)?.lock().await;
             rt.set_config(config).await
-        })
+        });
+        let peers = config
+            .as_ref()
+            .map(|config| config.peers.as_ref().map_or(0, |peers| peers.len()));
+        self.record_state(
+            json!({ "transition": "config_set", "interface": interface, "peers": peers }),
+            &ret,
+        );
+        ret
     }
 
     pub fn notify_network_change(&self) -> Result {
-        self.art()?.block_on(async {
-            let mut rt = self.rt()?.lock().await;
-            rt.notify_network_change().await
-        })
+        let ret = self.art()?.block_on(async {
+            self.rt()?.lock().await.notify_network_change().await?;
+            for rt in self.interfaces.values() {
+                rt.lock().await.notify_network_change().await?;
+            }
+            Ok(())
+        });
+        self.record_state(json!({ "transition": "network_changed" }), &ret);
+        ret
+    }
+
+    /// Tune hole punching timers, applied to the running meshnet without restarting it
+    pub fn set_traversal(&self, traversal: FeatureTraversal) -> Result {
+        traversal.validate().map_err(Error::BadTraversalFeatures)?;
+        self.features
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .traversal = Some(traversal);
+
+        for rt in self.rt.iter().chain(self.interfaces.values()) {
+            self.art()?.block_on(async {
+                let mut rt = rt.lock().await;
+                rt.set_traversal(traversal).await
+            })?;
+        }
+        Ok(())
     }
 
     pub fn connect_exit_node(&self, node: &ExitNode) -> Result {
-        self.art()?.block_on(async {
-            let mut rt = self.rt()?.lock().await;
+        self.connect_interface_exit_node(DEFAULT_INTERFACE, node)
+    }
+
+    pub fn connect_interface_exit_node(
+        &self,
+        interface: InterfaceHandle,
+        node: &ExitNode,
+    ) -> Result {
+        let ret = self.art()?.block_on(async {
+            let mut rt = self.iface(interface)?.lock().await;
             let ret = rt.connect_exit_node(node).await;
             // todo: delete this as sockets are protected from within boringtun itself
             #[cfg(not(windows))]
             self.protect_from_vpn(&*rt.wireguard_interface).await?;
             ret
-        })
+        });
+        self.record_state(
+            json!({
+                "transition": "exit_node_connected",
+                "interface": interface,
+                "public_key": node.public_key.to_string(),
+            }),
+            &ret,
+        );
+        ret
     }
 
     pub fn disconnect_exit_node(&self, node_key: &PublicKey) -> Result {
-        self.art()?.block_on(async {
-            let mut rt = self.rt()?.lock().await;
+        self.disconnect_interface_exit_node(DEFAULT_INTERFACE, node_key)
+    }
+
+    pub fn disconnect_interface_exit_node(
+        &self,
+        interface: InterfaceHandle,
+        node_key: &PublicKey,
+    ) -> Result {
+        let ret = self.art()?.block_on(async {
+            let mut rt = self.iface(interface)?.lock().await;
             rt.disconnect_exit_node(node_key).await
-        })
+        });
+        self.record_state(
+            json!({
+                "transition": "exit_node_disconnected",
+                "interface": interface,
+                "public_key": node_key.to_string(),
+            }),
+            &ret,
+        );
+        ret
     }
 
     pub fn disconnect_exit_nodes(&self) -> Result {
-        self.art()?.block_on(async {
-            let mut rt = self.rt()?.lock().await;
+        self.disconnect_interface_exit_nodes(DEFAULT_INTERFACE)
+    }
+
+    pub fn disconnect_interface_exit_nodes(&self, interface: InterfaceHandle) -> Result {
+        let ret = self.art()?.block_on(async {
+            let mut rt = self.iface(interface)?.lock().await;
             rt.disconnect_exit_nodes().await
-        })
+        });
+        self.record_state(
+            json!({ "transition": "exit_nodes_disconnected", "interface": interface }),
+            &ret,
+        );
+        ret
+    }
+
+    /// Record state transition in the journal, with the error if it failed
+    fn record_state<T>(&self, mut body: Value, result: &Result<T>) {
+        if let Some(journal) = self.journal.as_ref() {
+            if let Err(e) = result {
+                body["error"] = e.to_string().into();
+            }
+            journal
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .record_state(body);
+        }
+    }
+
+    fn features(&self) -> Features {
+        self.features
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
     }
 
     fn rt(&self) -> Result<&Mutex<Runtime>> {
         self.rt.as_ref().ok_or(Error::NotStarted)
     }
 
+    fn iface(&self, interface: InterfaceHandle) -> Result<&Mutex<Runtime>> {
+        match interface {
+            DEFAULT_INTERFACE => self.rt(),
+            _ => self
+                .interfaces
+                .get(&interface)
+                .ok_or(Error::InvalidInterface(interface)),
+        }
+    }
+
     fn art(&self) -> Result<&Arc<AsyncRuntime>> {
         self.art.as_ref().ok_or(Error::NotStarted)
     }
@@ -415,6 +797,90 @@ impl Device {
         })
     }
 
+    /// Connectivity diagnostics of peer's direct path, serialized as JSON
+    pub fn get_peer_diagnostics(&self, public_key: &PublicKey) -> Result<String> {
+        self.art()?.block_on(async {
+            let (mut diagnostics, nat_server) = {
+                let rt = self.rt()?.lock().await;
+                (
+                    rt.get_peer_diagnostics(*public_key).await?,
+                    rt.get_nat_server().await,
+                )
+            };
+
+            // NAT detection waits for STUN responses, so it runs with runtime unlocked
+            if let Some(server) = nat_server {
+                match retrieve_single_nat(server).await {
+                    Ok(data) => {
+                        diagnostics.nat = Some(NatReport {
+                            public_ip: data.public_ip,
+                            nat_type: format!("{:?}", data.nat_type),
+                        })
+                    }
+                    Err(e) => telio_log_debug!("Failed to detect NAT for diagnostics: {}", e),
+                }
+            }
+
+            Ok(serde_json::to_string(&diagnostics)?)
+        })
+    }
+
+    /// Traffic statistics of default interface's peers, serialized as JSON
+    pub fn get_peer_stats(&self) -> Result<String> {
+        self.get_interface_peer_stats(DEFAULT_INTERFACE)
+    }
+
+    pub fn get_interface_peer_stats(&self, interface: InterfaceHandle) -> Result<String> {
+        let stats = self.art()?.block_on(async {
+            let rt = self.iface(interface)?.lock().await;
+            Ok::<_, Error>(rt.wireguard_interface.get_peer_stats().await)
+        })?;
+        Ok(serde_json::to_string(&stats)?)
+    }
+
+    /// Metrics of peers of all interfaces, relay and nurse, in OpenMetrics text format
+    pub fn get_metrics(&self) -> Result<String> {
+        self.art()?.block_on(async {
+            let rt = self.rt()?.lock().await;
+            let mut peers = rt.peer_metrics().await;
+            for iface in self.interfaces.values() {
+                peers.extend(iface.lock().await.peer_metrics().await);
+            }
+
+            let mut metrics = Metrics::default();
+            write_peer_metrics(&mut metrics, &peers);
+            metrics.append(rt.relay.lock().await.get_nurse_metrics().await);
+            Ok(metrics.finish())
+        })
+    }
+
+    /// Meshnet connectivity matrix from the last heartbeat collection of nurse, serialized as JSON
+    ///
+    /// Serializes to `null` if nurse is not running or has not finished a collection yet.
+    pub fn get_connectivity_matrix(&self) -> Result<String> {
+        let matrix = self.art()?.block_on(async {
+            let rt = self.rt()?.lock().await;
+            let matrix = rt.relay.lock().await.get_nurse_connectivity_matrix().await;
+            Ok::<_, Error>(matrix)
+        })?;
+        Ok(serde_json::to_string(&matrix)?)
+    }
+
+    /// Journaled events and state transitions, serialized as JSON array
+    ///
+    /// # Arguments
+    ///
+    /// * `since` - Only entries recorded at or after this time, in milliseconds since UNIX epoch.
+    /// * `kinds` - Only entries of these kinds (`relay`, `node`, `error`, `state`), all if empty.
+    pub fn get_event_history(&self, since: u64, kinds: &[&str]) -> Result<String> {
+        let journal = self.journal.as_ref().ok_or(Error::JournalDisabled)?;
+        let history = journal
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .history(since, kinds);
+        Ok(serde_json::to_string(&history)?)
+    }
+
     pub fn get_nat(&self, ip: String) -> Result<NatData> {
         match self.art()?.block_on(retrieve_single_nat(ip)) {
             Ok(data) => Ok(data),
@@ -425,7 +891,7 @@ impl Device {
 
 impl Drop for Device {
     fn drop(&mut self) {
-        if self.features.lana.is_some() {
+        if self.features().lana.is_some() {
             let _ = telio_lana::deinit_lana();
         }
     }
@@ -485,6 +951,7 @@ impl RequestedState {
 
 impl Runtime {
     async fn start(
+        interface: InterfaceHandle,
         event: Tx<Box<Event>>,
         config: &DeviceConfig,
         features: Features,
@@ -504,6 +971,10 @@ impl Runtime {
             let fw = firewall.clone();
             move |peer: &[u8; 32], packet: &[u8]| fw.process_outbound_packet(peer, packet)
         };
+        let firewall_rules = {
+            let fw = firewall.clone();
+            move |peers: &[(PublicKey, Vec<IpNetwork>)]| fw.nft_inbound_rules(peers)
+        };
 
         let socket_pool = Arc::new({
             if let Some(protect) = protect.clone() {
@@ -519,26 +990,56 @@ impl Runtime {
             (None, None)
         };
 
+        // Netstack exchanges packets with userspace WireGuard, no kernel interface is needed
+        let (adapter, tun, packet_io, netstack_io) = match features.netstack {
+            Some(_) => {
+                let (wg_io, netstack_io) = wg::packet_io::packet_pipe();
+                let wg_io: Arc<dyn PacketIo> = Arc::new(wg_io);
+                (
+                    AdapterType::UserspaceWg,
+                    None,
+                    Some(wg_io),
+                    Some(netstack_io),
+                )
+            }
+            None => (config.adapter, config.tun, config.packet_io.clone(), None),
+        };
+
         let chan = Chan::default();
+        let recovery_ch = McChan::default();
+        let mut recoveries = recovery_ch.rx;
         let wireguard_interface = Arc::new(DynamicWg::start(
             wg::Io {
                 events: chan.tx,
                 analytics_tx: analytics_ch.clone(),
+                recovery_tx: Some(recovery_ch.tx),
             },
             wg::Config {
-                adapter: config.adapter,
+                adapter,
                 name: config.name.clone(),
-                tun: config.tun,
+                tun,
+                packet_io,
                 socket_pool: socket_pool.clone(),
                 firewall_process_inbound_callback: Some(Arc::new(firewall_filter_inbound_packets)),
                 firewall_process_outbound_callback: Some(Arc::new(
                     firewall_filter_outbound_packets,
                 )),
+                firewall_rules_callback: Some(Arc::new(firewall_rules)),
+                mtu: config.mtu,
             },
         )?);
 
-        let (meshnet, mut mesh_events) =
-            Meshnet::new(path_change_rx, wireguard_interface.clone(), chan.rx)?;
+        let netstack = match (netstack_io, &features.netstack) {
+            (Some(io), Some(netstack)) => Some(Netstack::start(Arc::new(io), netstack).await?),
+            _ => None,
+        };
+
+        let (meshnet, mut mesh_events) = Meshnet::new(
+            path_change_rx,
+            wireguard_interface.clone(),
+            chan.rx,
+            features.keepalive,
+        )?;
 
         meshnet.set_private_key(&config.private_key).await;
 
@@ -550,6 +1051,7 @@ impl Runtime {
         #[cfg(any(target_os = "macos", target_os = "ios"))]
         set_tunnel_interface(&socket_pool, config);
 
+        let tunnel_mtu = config.mtu.unwrap_or(TUNNEL_MTU);
         let relay = Arc::new(Mutex::new(
             Relay::start(
                 &config.private_key,
@@ -559,16 +1061,54 @@ impl Runtime {
                 path_change_tx,
                 analytics_ch.clone(),
                 config_update_ch.clone(),
+                config.local_interfaces.clone(),
+                tunnel_mtu,
             )
             .await?,
         ));
 
+        let (mtu_relay, mtu_wg) = (relay.clone(), wireguard_interface.clone());
+        let mut sync_mtu = tokio::time::interval(PATH_MTU_SYNC_INTERVAL);
         let jmesh = tokio::spawn(async move {
             loop {
                 tokio::select! {
-                    Ok(mesh_event) = mesh_events.recv() => {
+                    Ok(mut mesh_event) = mesh_events.recv() => {
+                        mesh_event.interface = interface;
                         report_event!(event, Event::new::<Node>().set(*mesh_event));
                     }
+                    Ok(recovery) = recoveries.recv() => {
+                        let (level, msg) = match &recovery.error {
+                            None => (
+                                ErrorLevel::Severe,
+                                format!(
+                                    "WireGuard adapter failed {} successive calls, it was restarted and its configuration restored",
+                                    recovery.failed_calls
+                                ),
+                            ),
+                            Some(e) => (
+                                ErrorLevel::Critical,
+                                format!(
+                                    "WireGuard adapter failed {} successive calls, restarting it failed: {}",
+                                    recovery.failed_calls, e
+                                ),
+                            ),
+                        };
+                        report_event!(
+                            event,
+                            Event::new::<ErrorEvent>()
+                                .set(ErrorCode::Unknown)
+                                .set(level)
+                                .set(msg)
+                        );
+                    }
+                    _ = sync_mtu.tick() => {
+                        // Adapter clamps MSS of tunneled TCP to path MTU
+                        let mtus = mtu_relay.lock().await.get_path_mtus().await;
+                        for (pk, mtu) in mtus.unwrap_or_default() {
+                            let mtu = mtu.map_or(tunnel_mtu, |mtu| mtu.min(tunnel_mtu));
+                            mtu_wg.set_peer_mtu(pk, mtu).await;
+                        }
+                    }
                     _ = stopped.changed() => {
                         return;
                     }
@@ -577,6 +1117,7 @@ impl Runtime {
         });
 
         Ok(Runtime {
+            interface,
             stop,
             meshnet,
             wireguard_interface,
@@ -585,6 +1126,7 @@ impl Runtime {
             tun_for_dns: config.tun.map(|fd| fd as i32),
             #[cfg(windows)]
             tun_for_dns: None,
+            netstack,
             relay,
             #[cfg(target_os = "linux")]
             fwmark: 0,
@@ -601,16 +1143,61 @@ impl Runtime {
     }
 
     async fn set_private_key(&mut self, private_key: &SecretKey) -> Result {
-        if self.dns.is_some() {
-            return Err(Error::DnsNotDisabled);
+        if self.requested_state.device_config.private_key == *private_key {
+            return Ok(());
         }
 
+        // Peers, relay and traversal sessions are kept, peers handshake again
+        // once they learn the new public key from meshnet config
         self.requested_state.device_config.private_key = *private_key;
         self.meshnet.set_private_key(private_key).await;
         let _ = self.relay.lock().await.set_private_key(private_key).await;
+
+        // Magic DNS tunnel is bound to device's public key, records and the
+        // DNS peer on the adapter stay as they are
+        if let Some(dns) = &mut self.dns {
+            dns.set_telio_public_key(&private_key.public())
+                .await
+                .map_err(Error::DnsResolverError)?;
+        }
         Ok(())
     }
 
+    async fn nodes(&self) -> Vec<Node> {
+        self.tag_nodes(self.meshnet.nodes().await)
+    }
+
+    async fn external_nodes(&self) -> Vec<Node> {
+        self.tag_nodes(self.meshnet.external_nodes().await)
+    }
+
+    /// External nodes paired with traffic statistics of their peers
+    async fn peer_metrics(&self) -> Vec<(Node, Option<PeerStats>)> {
+        let mut stats: HashMap<PublicKey, PeerStats> = self
+            .wireguard_interface
+            .get_peer_stats()
+            .await
+            .into_iter()
+            .map(|stats| (stats.public_key, stats))
+            .collect();
+
+        self.external_nodes()
+            .await
+            .into_iter()
+            .map(|node| {
+                let stats = stats.remove(&node.public_key);
+                (node, stats)
+            })
+            .collect()
+    }
+
+    fn tag_nodes(&self, mut nodes: Vec<Node>) -> Vec<Node> {
+        for node in &mut nodes {
+            node.interface = self.interface;
+        }
+        nodes
+    }
+
     async fn get_private_key(&self) -> Result<SecretKey> {
         Ok(self.requested_state.device_config.private_key)
     }
@@ -631,8 +1218,7 @@ impl Runtime {
     }
 
     async fn notify_network_change(&mut self) -> Result {
-        self.meshnet.drop_connected_sockets().await;
-
+        // Connected sockets are kept, so direct paths can migrate without interruption
         let mut relay = self.relay.lock().await;
         if relay.is_runtime_started() {
             match relay.reconnect().await {
@@ -648,6 +1234,12 @@ impl Runtime {
         }
     }
 
+    async fn set_traversal(&mut self, traversal: FeatureTraversal) -> Result {
+        self.features.traversal = Some(traversal);
+        self.relay.lock().await.set_traversal(traversal).await?;
+        Ok(())
+    }
+
     async fn start_dns(&mut self, public_key: &PublicKey, dns_servers: &[IpAddr]) -> Result {
         if let Some(dns) = &self.dns {
             dns.forward(dns_servers)
@@ -783,6 +1375,20 @@ impl Runtime {
         self.log_nat().await;
         self.update_map(map, old_node_map).await?;
 
+        if let Some(netstack) = &self.netstack {
+            let addresses = config
+                .as_ref()
+                .and_then(|config| config.this.ip_addresses.clone())
+                .unwrap_or_default();
+            let hosts = self
+                .requested_state
+                .collect_dns_records()
+                .into_iter()
+                .map(|(name, ip)| (name, IpAddr::V4(ip)))
+                .collect();
+            netstack.configure(addresses, hosts).await?;
+        }
+
         Ok(())
     }
 
@@ -869,6 +1475,7 @@ impl Runtime {
                     self.meshnet.del_node(&exit_node.public_key).await?;
                     self.firewall
                         .remove_from_peer_whitelist(exit_node.public_key);
+                    self.wireguard_interface.refresh_firewall().await;
                 }
             }
 
@@ -917,6 +1524,10 @@ impl Runtime {
         let _ = self.stop_dns().await;
         let _ = self.join.await;
 
+        if let Some(netstack) = self.netstack.take() {
+            netstack.stop().await;
+        }
+
         // A background task holds a strong reference on Windows, so we await
         // its end and then consume the contained mutex.
         #[cfg(windows)]
@@ -946,13 +1557,13 @@ impl Runtime {
         if let Ok(task) = Arc::try_unwrap(self.wireguard_interface)
             .map(|m| m.stop())
             .map_err(|arc| {
-                debug!(
+                telio_log_debug!(
                     "Oops, smething went wrong! Something (current strong count {}) is holding a strong reference to the wireguard_interface instance.",
                     Arc::strong_count(&arc));
             }) {
             task.await;
         } else {
-            debug!("Oops, something went wrong while stopping wireguard_interface tasks.");
+            telio_log_debug!("Oops, something went wrong while stopping wireguard_interface tasks.");
         }
     }
 
@@ -970,6 +1581,7 @@ impl Runtime {
             for ip in &node.allowed_ips {
                 self.firewall.add_to_network_whitelist(*ip);
             }
+            self.wireguard_interface.refresh_firewall().await;
         } else {
             self.firewall_remove_node(node).await;
         }
@@ -979,6 +1591,8 @@ impl Runtime {
         for ip in &node.allowed_ips {
             self.firewall.remove_from_network_whitelist(*ip);
         }
+        // Kernel interface filters packets by nftables, which follow whitelist only on refresh
+        self.wireguard_interface.refresh_firewall().await;
     }
 
     async fn get_derp_server(&self) -> Result<Option<DerpServer>> {
@@ -994,6 +1608,107 @@ impl Runtime {
             Err(e) => Err(e.into()),
         }
     }
+
+    async fn get_peer_diagnostics(&self, public_key: PublicKey) -> Result<PeerDiagnostics> {
+        self.relay
+            .lock()
+            .await
+            .get_peer_diagnostics(public_key)
+            .await?
+            .ok_or(Error::DirectDisabled)
+    }
+
+    /// Server to detect NAT against, the same one is used for logging
+    async fn get_nat_server(&self) -> Option<String> {
+        let config = self.get_derp_config().await.ok()?;
+        config
+            .servers
+            .iter()
+            .min_by_key(|server| server.weight)
+            .map(|server| server.ipv4.to_string())
+    }
+}
+
+fn write_peer_metrics(metrics: &mut Metrics, peers: &[(Node, Option<PeerStats>)]) {
+    let labels = |node: &Node| (node.public_key.to_string(), node.interface.to_string());
+
+    metrics.family(
+        "telio_peer_connected",
+        MetricType::Gauge,
+        "Whether WireGuard connection with the peer is established",
+    );
+    for (node, _) in peers {
+        let (peer, interface) = labels(node);
+        metrics.sample(
+            "telio_peer_connected",
+            &[("peer", &peer), ("interface", &interface)],
+            (node.state == Some(NodeState::Connected)) as u8,
+        );
+    }
+
+    metrics.family(
+        "telio_peer_path",
+        MetricType::Gauge,
+        "Path currently carrying traffic of the peer",
+    );
+    for (node, _) in peers {
+        let (peer, interface) = labels(node);
+        let path = match node.path {
+            PathType::Relay => "relay",
+            PathType::UdpHolePunch => "udp-hole-punch",
+            PathType::PeerRelay => "peer-relay",
+        };
+        metrics.sample(
+            "telio_peer_path",
+            &[("peer", &peer), ("interface", &interface), ("path", path)],
+            1,
+        );
+    }
+
+    let counters: [(&str, &str, fn(&PeerStats) -> u64); 3] = [
+        (
+            "telio_peer_rx_bytes",
+            "Bytes received from the peer",
+            |stats| stats.rx_bytes,
+        ),
+        ("telio_peer_tx_bytes", "Bytes sent to the peer", |stats| {
+            stats.tx_bytes
+        }),
+        (
+            "telio_peer_handshakes",
+            "WireGuard handshakes with the peer",
+            |stats| stats.handshakes,
+        ),
+    ];
+    for (name, help, value) in counters.iter() {
+        metrics.family(name, MetricType::Counter, help);
+        for (node, stats) in peers {
+            if let Some(stats) = stats {
+                let (peer, interface) = labels(node);
+                metrics.sample(
+                    &format!("{}_total", name),
+                    &[("peer", &peer), ("interface", &interface)],
+                    value(stats),
+                );
+            }
+        }
+    }
+
+    metrics.family(
+        "telio_peer_last_handshake_seconds",
+        MetricType::Gauge,
+        "Seconds since the last WireGuard handshake with the peer",
+    );
+    for (node, stats) in peers {
+        if let Some(secs) = stats.as_ref().and_then(|stats| stats.last_handshake_secs) {
+            let (peer, interface) = labels(node);
+            metrics.sample(
+                "telio_peer_last_handshake_seconds",
+                &[("peer", &peer), ("interface", &interface)],
+                secs,
+            );
+        }
+    }
 }
 
 #[cfg(any(target_os = "macos", target_os = "ios"))]
@@ -1033,7 +1748,376 @@ fn set_tunnel_interface(socket_pool: &Arc<SocketPool>, config: &DeviceConfig) {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use futures::FutureExt;
+    use telio_model::api_config::{FeatureJournal, FeaturePaths};
     use telio_model::config::{Peer, PeerBase};
+    use telio_model::event::EventMsg;
+
+    #[test]
+    fn subscribers_get_filtered_events() {
+        let device = Device::new_without_callback(Features::default(), None).unwrap();
+        let peer = PublicKey([1; 32]);
+        let nodes = device.subscribe(EventFilter::new().peer(peer));
+        let errors = device.subscribe(EventFilter::new().event_type(EventType::Error));
+
+        for public_key in [PublicKey([2; 32]), peer] {
+            let node = Node {
+                public_key,
+                ..Default::default()
+            };
+            device
+                .event
+                .send(Box::new(Event::new::<Node>().set(node)))
+                .unwrap();
+        }
+        device
+            .event
+            .send(Box::new(
+                Event::new::<ErrorEvent>().set(EventMsg::from("oops")),
+            ))
+            .unwrap();
+
+        device.art().unwrap().block_on(async {
+            futures::pin_mut!(nodes, errors);
+            assert_eq!(nodes.next().await.and_then(|e| e.peer()), Some(peer));
+            assert_eq!(
+                errors.next().await.map(|e| e.event_type()),
+                Some(EventType::Error)
+            );
+        });
+    }
+
+    #[test]
+    fn device_journals_events_and_transitions() {
+        let path = std::env::temp_dir().join(format!("device-{}.journal", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let features = Features {
+            journal: Some(FeatureJournal {
+                path: path.to_string_lossy().into_owned(),
+                max_entries: 100,
+            }),
+            ..Default::default()
+        };
+        let device = Device::new_without_callback(features, None).unwrap();
+
+        // Failed transitions are journaled with their error
+        assert!(device.notify_network_change().is_err());
+        device
+            .event
+            .send(Box::new(
+                Event::new::<ErrorEvent>().set(EventMsg::from("oops")),
+            ))
+            .unwrap();
+
+        // Events are journaled by the task forwarding them to the app
+        let mut history = Vec::new();
+        for _ in 0..100 {
+            history =
+                serde_json::from_str::<Vec<Value>>(&device.get_event_history(0, &[]).unwrap())
+                    .unwrap();
+            if history.len() == 3 {
+                break;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        drop(device);
+        let persisted = std::fs::read_to_string(&path).unwrap_or_default();
+        let _ = std::fs::remove_file(&path);
+
+        assert_eq!(history.len(), 3, "{:?}", history);
+        assert_eq!(history[0]["body"]["transition"], "opened");
+        assert_eq!(history[1]["body"]["transition"], "network_changed");
+        assert!(history[1]["body"]["error"].is_string());
+        assert_eq!(history[2]["kind"], "error");
+        assert_eq!(persisted.lines().count(), 3);
+    }
+
+    #[test]
+    fn test_peer_metrics() {
+        let connected = Node {
+            public_key: PublicKey([1; 32]),
+            state: Some(NodeState::Connected),
+            path: PathType::UdpHolePunch,
+            ..Default::default()
+        };
+        let connecting = Node {
+            public_key: PublicKey([2; 32]),
+            state: Some(NodeState::Connecting),
+            interface: 1,
+            ..Default::default()
+        };
+        let stats = PeerStats {
+            public_key: connected.public_key,
+            rx_bytes: 1024,
+            tx_bytes: 2048,
+            rates: Vec::new(),
+            handshakes: 3,
+            last_handshake_secs: Some(7),
+        };
+
+        let mut metrics = Metrics::default();
+        write_peer_metrics(
+            &mut metrics,
+            &[(connected.clone(), Some(stats)), (connecting.clone(), None)],
+        );
+        let text = metrics.finish();
+
+        let a = format!("peer=\"{}\",interface=\"0\"", connected.public_key);
+        let b = format!("peer=\"{}\",interface=\"1\"", connecting.public_key);
+        for sample in &[
+            format!("telio_peer_connected{{{}}} 1\n", a),
+            format!("telio_peer_connected{{{}}} 0\n", b),
+            format!("telio_peer_path{{{},path=\"udp-hole-punch\"}} 1\n", a),
+            format!("telio_peer_path{{{},path=\"relay\"}} 1\n", b),
+            format!("telio_peer_rx_bytes_total{{{}}} 1024\n", a),
+            format!("telio_peer_tx_bytes_total{{{}}} 2048\n", a),
+            format!("telio_peer_handshakes_total{{{}}} 3\n", a),
+            format!("telio_peer_last_handshake_seconds{{{}}} 7\n", a),
+        ] {
+            assert!(
+                text.contains(sample.as_str()),
+                "{} missing in {}",
+                sample,
+                text
+            );
+        }
+        assert!(!text.contains(&format!("telio_peer_rx_bytes_total{{{}}}", b)));
+        assert!(text.ends_with("# EOF\n"));
+    }
+
+    #[test]
+    fn test_additional_interfaces_need_started_device() {
+        let mut device = Device::new(Default::default(), |_: Box<Event>| {}, None).unwrap();
+
+        assert!(matches!(
+            device.add_interface(&DeviceConfig::default()),
+            Err(Error::NotStarted)
+        ));
+        assert!(matches!(
+            device.remove_interface(1),
+            Err(Error::InvalidInterface(1))
+        ));
+        assert!(device.interfaces().is_empty());
+    }
+
+    #[test]
+    fn test_peer_diagnostics_need_started_direct_path() {
+        let peer = SecretKey::gen().public();
+        let mut device = Device::new_without_callback(Features::default(), None).unwrap();
+        assert!(matches!(
+            device.get_peer_diagnostics(&peer),
+            Err(Error::NotStarted)
+        ));
+
+        device
+            .start(&DeviceConfig {
+                private_key: SecretKey::gen(),
+                adapter: AdapterType::BoringTun,
+                ..Default::default()
+            })
+            .unwrap();
+
+        // Relay is not connected, so there is no direct path to diagnose
+        assert!(device.get_peer_diagnostics(&peer).is_err());
+        // Runtime is not left locked
+        assert!(device.external_nodes().is_ok());
+
+        device.stop();
+    }
+
+    #[test]
+    fn test_network_change_keeps_peers_connected() {
+        let mut device = Device::new_without_callback(Features::default(), None).unwrap();
+        device
+            .start(&DeviceConfig {
+                private_key: SecretKey::gen(),
+                adapter: AdapterType::BoringTun,
+                ..Default::default()
+            })
+            .unwrap();
+
+        let peer_base = PeerBase {
+            identifier: "identifier".to_owned(),
+            public_key: SecretKey::gen().public(),
+            hostname: "hostname".to_owned(),
+            ip_addresses: None,
+        };
+        let config = Config {
+            this: peer_base.clone(),
+            peers: Some(vec![Peer {
+                base: peer_base,
+                ..Default::default()
+            }]),
+            derp_servers: None,
+            dns: None,
+        };
+        device.set_config(&Some(config)).unwrap();
+
+        let states = |device: &Device| -> Vec<_> {
+            device
+                .external_nodes()
+                .unwrap()
+                .into_iter()
+                .map(|node| (node.public_key, node.state))
+                .collect()
+        };
+        let before = states(&device);
+        let events = device.subscribe(EventFilter::new().event_type(EventType::Node));
+
+        device.notify_network_change().unwrap();
+
+        // Peers are neither dropped nor reported disconnected, while their paths migrate
+        assert_eq!(states(&device), before);
+        futures::pin_mut!(events);
+        while let Some(Some(event)) = events.next().now_or_never() {
+            if let Event::Node { body: Some(node) } = event {
+                assert_ne!(node.state, Some(NodeState::Disconnected), "{:?}", node);
+            }
+        }
+
+        device.stop();
+    }
+
+    #[test]
+    fn test_interface_change_migrates_direct_paths() {
+        // Local interface, which will be changed during the test
+        let local_ip = Arc::new(StdMutex::new(Ipv4Addr::new(10, 0, 0, 1)));
+        // Interfaces, which were offered to peers
+        let offered = Arc::new(StdMutex::new(Vec::new()));
+        let local_interfaces = SharedGetIfAddrs::new({
+            let (local_ip, offered) = (local_ip.clone(), offered.clone());
+            move || {
+                let ip = *local_ip.lock().unwrap();
+                offered.lock().unwrap().push(ip);
+                Ok(vec![if_addrs::Interface {
+                    name: "eth0".to_owned(),
+                    addr: if_addrs::IfAddr::V4(if_addrs::Ifv4Addr {
+                        ip,
+                        netmask: Ipv4Addr::new(255, 255, 255, 0),
+                        broadcast: None,
+                    }),
+                }])
+            }
+        });
+
+        let features = Features {
+            paths: Some(FeaturePaths {
+                priority: vec![PathType::UdpHolePunch],
+                force: None,
+            }),
+            ..Default::default()
+        };
+        let mut device = Device::new_without_callback(features, None).unwrap();
+        device
+            .start(&DeviceConfig {
+                private_key: SecretKey::gen(),
+                adapter: AdapterType::BoringTun,
+                local_interfaces,
+                ..Default::default()
+            })
+            .unwrap();
+
+        let peer_base = PeerBase {
+            identifier: "identifier".to_owned(),
+            public_key: SecretKey::gen().public(),
+            hostname: "hostname".to_owned(),
+            ip_addresses: None,
+        };
+        let config = Config {
+            this: peer_base.clone(),
+            peers: Some(vec![Peer {
+                base: peer_base,
+                ..Default::default()
+            }]),
+            derp_servers: None,
+            dns: None,
+        };
+        device.set_config(&Some(config)).unwrap();
+
+        let states = |device: &Device| -> Vec<_> {
+            device
+                .external_nodes()
+                .unwrap()
+                .into_iter()
+                .map(|node| (node.public_key, node.state))
+                .collect()
+        };
+        let before = states(&device);
+        let events = device.subscribe(EventFilter::new().event_type(EventType::Node));
+
+        // Simulating network change
+        *local_ip.lock().unwrap() = Ipv4Addr::new(192, 168, 1, 10);
+        device.notify_network_change().unwrap();
+
+        // Direct path migrates to the fresh interface, not the one gone with the old network
+        assert_eq!(
+            *offered.lock().unwrap(),
+            vec![Ipv4Addr::new(192, 168, 1, 10)]
+        );
+        assert_eq!(states(&device), before);
+        futures::pin_mut!(events);
+        while let Some(Some(event)) = events.next().now_or_never() {
+            if let Event::Node { body: Some(node) } = event {
+                assert_ne!(node.state, Some(NodeState::Disconnected), "{:?}", node);
+            }
+        }
+
+        device.stop();
+    }
+
+    #[test]
+    fn test_private_key_rotation_keeps_peers() {
+        let mut device = Device::new_without_callback(Features::default(), None).unwrap();
+        device
+            .start(&DeviceConfig {
+                private_key: SecretKey::gen(),
+                adapter: AdapterType::BoringTun,
+                ..Default::default()
+            })
+            .unwrap();
+        device.enable_magic_dns(&[]).unwrap();
+
+        let peer_base = PeerBase {
+            identifier: "identifier".to_owned(),
+            public_key: SecretKey::gen().public(),
+            hostname: "hostname".to_owned(),
+            ip_addresses: None,
+        };
+        let preshared_key = telio_crypto::PresharedKey([7; 32]);
+        let config = Config {
+            this: peer_base.clone(),
+            peers: Some(vec![Peer {
+                base: peer_base.clone(),
+                preshared_key: Some(preshared_key),
+                ..Default::default()
+            }]),
+            derp_servers: None,
+            dns: None,
+        };
+        device.set_config(&Some(config)).unwrap();
+
+        let private_key = SecretKey::gen();
+        device.set_private_key(&private_key).unwrap();
+
+        device.art().unwrap().block_on(async {
+            let rt = device.rt().unwrap().lock().await;
+            let interface = rt.wireguard_interface.get_interface().await.unwrap();
+            assert_eq!(interface.private_key, Some(private_key));
+            assert_eq!(
+                interface
+                    .peers
+                    .get(&peer_base.public_key)
+                    .and_then(|peer| peer.preshared_key),
+                Some(preshared_key)
+            );
+
+            // Magic DNS keeps its peer on the adapter
+            let dns = rt.dns.as_ref().unwrap().public_key();
+            assert!(interface.peers.contains_key(&dns));
+        });
+
+        device.stop();
+    }
 
     #[tokio::test(flavor = "multi_thread")]
     async fn test_disconnect_exit_nodes() {
@@ -1042,6 +2126,7 @@ mod tests {
         let features = Default::default();
 
         let mut rt = Runtime::start(
+            DEFAULT_INTERFACE,
             sender,
             &DeviceConfig {
                 private_key: SecretKey::gen(),
@@ -1091,12 +2176,12 @@ mod tests {
         let features = Features::default();
 
         let mut rt = Runtime::start(
+            DEFAULT_INTERFACE,
             sender,
             &DeviceConfig {
                 private_key: SecretKey::gen(),
                 adapter: AdapterType::BoringTun,
-                name: None,
-                tun: None,
+                ..Default::default()
             },
             features,
             None,
@@ -1140,6 +2225,7 @@ mod tests {
         let features = Default::default();
 
         let mut rt = Runtime::start(
+            DEFAULT_INTERFACE,
             sender,
             &DeviceConfig {
                 private_key: SecretKey::gen(),
@@ -1,5 +1,5 @@
 use std::{
-    collections::HashSet,
+    collections::{HashMap, HashSet},
     net::{IpAddr, Ipv4Addr, SocketAddr},
     sync::Arc,
 };
@@ -9,11 +9,17 @@ use tokio::task::JoinHandle;
 
 use telio_crypto::{PublicKey, SecretKey};
 use telio_model::{
-    api_config::{Features, PathType},
+    api_config::{FeatureTraversal, Features, PathType},
+    diagnostics::PeerDiagnostics,
     event::{Event, Set},
     report_event, EndpointMap,
 };
-use telio_nurse::{config::Config as NurseConfig, data::MeshConfigUpdateEvent, Nurse};
+use telio_nurse::{
+    config::Config as NurseConfig,
+    data::{ConnectivityMatrix, MeshConfigUpdateEvent},
+    metrics::Metrics,
+    Nurse,
+};
 use telio_proxy::Error as ProxyError;
 use telio_relay::{
     derp::{Config as DerpConfig, DerpRelay, Server as DerpServer},
@@ -22,10 +28,11 @@ use telio_relay::{
 use telio_task::io::{chan, mc_chan::Tx, Chan, McChan};
 use telio_task::{task_exec, Task};
 use telio_traversal::{
-    ConfigBuilder as RouterConfigBuilder, Error as RouterError, PathSetIo, Router,
+    endpoint_providers::local::SharedGetIfAddrs, ConfigBuilder as RouterConfigBuilder,
+    Error as RouterError, PathSetIo, Router,
 };
 
-use telio_utils::{telio_err_with_log, telio_log_debug, telio_log_trace};
+use telio_utils::{telio_err_with_log, telio_log_debug, telio_log_trace, telio_log_warn};
 
 pub type Result<T = ()> = std::result::Result<T, Error>;
 
@@ -36,6 +43,7 @@ pub struct Config {
     pub mesh_ip: IpAddr,
     pub nodes: HashSet<PublicKey>,
     pub servers: Vec<DerpServer>,
+    pub peer_relays: HashSet<PublicKey>,
 }
 
 pub struct Relay {
@@ -47,6 +55,8 @@ pub struct Relay {
     path_change_ch: chan::Tx<(PublicKey, PathType)>,
     analytics_ch: Option<Tx<Box<AnalyticsEvent>>>,
     config_update_ch: Option<Tx<Box<MeshConfigUpdateEvent>>>,
+    local_interfaces: SharedGetIfAddrs,
+    tunnel_mtu: u32,
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -74,6 +84,7 @@ struct Runtime {
 }
 
 impl Relay {
+    #[allow(clippy::too_many_arguments)]
     pub async fn start(
         private_key: &SecretKey,
         wg_port: Option<u16>,
@@ -82,6 +93,8 @@ impl Relay {
         path_change_ch: chan::Tx<(PublicKey, PathType)>,
         analytics_ch: Option<Tx<Box<AnalyticsEvent>>>,
         config_update_ch: Option<Tx<Box<MeshConfigUpdateEvent>>>,
+        local_interfaces: SharedGetIfAddrs,
+        tunnel_mtu: u32,
     ) -> Result<Self> {
         Ok(Self {
             rt: None,
@@ -92,6 +105,8 @@ impl Relay {
             path_change_ch,
             analytics_ch,
             config_update_ch,
+            local_interfaces,
+            tunnel_mtu,
         })
     }
 
@@ -111,6 +126,15 @@ impl Relay {
                     ..rt.derp.get_config().await
                 })
                 .await;
+            // Heartbeats are authenticated with the node key, so nurse must follow the change
+            if let Some(nurse) = rt.nurse.as_ref() {
+                let private_key = *private_key;
+                let _ = task_exec!(nurse, async move |state| {
+                    state.set_private_key(private_key).await;
+                    Ok(())
+                })
+                .await;
+            }
             return Ok(());
         }
         telio_err_with_log!(Error::RuntimeNotStarted)
@@ -118,6 +142,12 @@ impl Relay {
 
     pub async fn reconnect(&mut self) -> Result<()> {
         if let Some(rt) = self.rt.as_ref() {
+            // Migration is started first, so direct paths do not wait for relay to come back,
+            // migration requests are queued until then
+            if let Err(e) = rt.router.notify_network_change().await {
+                telio_log_warn!("Failed to migrate direct paths: {}", e);
+            }
+
             rt.derp.reconnect().await;
             telio_log_trace!("Derp Reconnected");
             return Ok(());
@@ -156,6 +186,8 @@ impl Relay {
                     self.path_change_ch.clone(),
                     self.analytics_ch.clone(),
                     self.config_update_ch.clone(),
+                    self.local_interfaces.clone(),
+                    self.tunnel_mtu,
                 )
                 .await?,
             );
@@ -163,6 +195,9 @@ impl Relay {
 
         if let Some(rt) = self.rt.as_mut() {
             let nodes = config.as_ref().map_or(HashSet::new(), |c| c.nodes.clone());
+            let peer_relays = config
+                .as_ref()
+                .map_or(HashSet::new(), |c| c.peer_relays.clone());
             let from = rt
                 .router
                 .proxy
@@ -192,6 +227,7 @@ impl Relay {
                         .wg_port(wg_port)
                         .peers(nodes)
                         .derp(derp_config)
+                        .peer_relays(peer_relays)
                         .build()
                         .map_err(|err| Error::Router(err.into()))?,
                 )
@@ -220,6 +256,53 @@ impl Relay {
         telio_err_with_log!(Error::RuntimeNotStarted)
     }
 
+    /// Apply hole punching timers to running runtime, otherwise they are applied on start
+    pub async fn set_traversal(&self, config: FeatureTraversal) -> Result<()> {
+        if let Some(rt) = self.rt.as_ref() {
+            rt.router.set_traversal(config).await?;
+        }
+        Ok(())
+    }
+
+    /// Diagnostics of peer's direct path, [None] if hole punching is disabled
+    pub async fn get_peer_diagnostics(&self, pk: PublicKey) -> Result<Option<PeerDiagnostics>> {
+        if let Some(rt) = self.rt.as_ref() {
+            return Ok(rt.router.get_diagnostics(pk).await?);
+        }
+        telio_err_with_log!(Error::RuntimeNotStarted)
+    }
+
+    /// Tunnel MTU fitting into each peer's active path, [None] if it was not probed.
+    /// Empty if relay is not started
+    pub async fn get_path_mtus(&self) -> Result<HashMap<PublicKey, Option<u32>>> {
+        if let Some(rt) = self.rt.as_ref() {
+            return Ok(rt.router.get_path_mtus().await?);
+        }
+        Ok(HashMap::new())
+    }
+
+    /// Metrics collected by nurse, empty if nurse is not running
+    pub async fn get_nurse_metrics(&self) -> Metrics {
+        if let Some(nurse) = self.rt.as_ref().and_then(|rt| rt.nurse.as_ref()) {
+            return task_exec!(nurse, async move |state| Ok(state.get_metrics().await))
+                .await
+                .unwrap_or_default();
+        }
+        Metrics::default()
+    }
+
+    /// Connectivity matrix aggregated by nurse, `None` if nurse is not running or has not collected it yet
+    pub async fn get_nurse_connectivity_matrix(&self) -> Option<ConnectivityMatrix> {
+        if let Some(nurse) = self.rt.as_ref().and_then(|rt| rt.nurse.as_ref()) {
+            return task_exec!(nurse, async move |state| Ok(state
+                .get_connectivity_matrix()
+                .await))
+            .await
+            .unwrap_or_default();
+        }
+        None
+    }
+
     pub async fn get_relay_config(&self) -> Result<DerpConfig> {
         if let Some(rt) = self.rt.as_ref() {
             telio_log_trace!("get_relay_config() - OK");
@@ -244,6 +327,8 @@ impl Runtime {
         path_change_ch: chan::Tx<(PublicKey, PathType)>,
         analytics_ch: Option<Tx<Box<AnalyticsEvent>>>,
         config_update_ch: Option<Tx<Box<MeshConfigUpdateEvent>>>,
+        local_interfaces: SharedGetIfAddrs,
+        tunnel_mtu: u32,
     ) -> Result<Runtime> {
         telio_log_trace!("starting relay runtime...");
         // Pipe for multiplexer -> derp communication
@@ -277,10 +362,15 @@ impl Runtime {
 
         let nurse = if telio_lana::is_lana_initialized() {
             if let Some(nurse_features) = &features.nurse {
+                let mut nurse_config = NurseConfig::new(nurse_features);
+                if !config.mesh_ip.is_unspecified() {
+                    nurse_config.qos_config.mesh_ip = Some(config.mesh_ip);
+                }
+
                 Some(Task::start(
                     Nurse::new(
-                        private_key.public(),
-                        NurseConfig::new(nurse_features),
+                        private_key,
+                        nurse_config,
                         &devent_tx,
                         &event_ch,
                         &multiplexer,
@@ -313,9 +403,17 @@ impl Runtime {
 
         let router = Router::start(
             feature_paths,
+            features.peer_relay.clone(),
+            tunnel_mtu,
             PathSetIo {
                 relay: multiplexer.get_channel().await?,
-                udp_hole_punch: (sock, multiplexer.get_channel().await?),
+                relay_pinger: Some(multiplexer.get_channel().await?),
+                udp_hole_punch: (
+                    sock,
+                    multiplexer.get_channel().await?,
+                    multiplexer.get_channel().await?,
+                ),
+                local_interfaces,
             },
             path_change_ch,
         )?;
@@ -329,6 +427,10 @@ impl Runtime {
             )
             .await?;
 
+        if let Some(traversal) = features.traversal {
+            router.set_traversal(traversal).await?;
+        }
+
         Ok(Runtime {
             router,
             multiplexer,
@@ -367,6 +469,7 @@ mod tests {
         multiplexer::Multiplexer,
     };
     use telio_task::io::McChan;
+    use telio_wg::mtu::TUNNEL_MTU;
     use tokio::net::UdpSocket;
 
     struct RelayTest {
@@ -431,6 +534,8 @@ mod tests {
                 path_event_tx,
                 None,
                 None,
+                SharedGetIfAddrs::default(),
+                TUNNEL_MTU,
             )
             .await
             .unwrap();
@@ -449,6 +554,7 @@ mod tests {
                     nodes,
                     servers: vec![fake_server],
                     mesh_ip: IpAddr::V4(Ipv4Addr::LOCALHOST),
+                    peer_relays: HashSet::new(),
                 };
 
                 let features = Default::default();
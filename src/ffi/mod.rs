@@ -5,10 +5,10 @@ use crypto_box::SecretKey;
 use ffi_helpers::{error_handling, panic as panic_handling};
 use ipnetwork::IpNetwork;
 use libc::c_char;
-use log::{error, Level, Metadata, Record};
+use log::{Level, LevelFilter, Metadata, Record};
+use telio_lana::CallbackSink;
 use telio_wg::AdapterType;
 
-#[cfg(target_os = "linux")]
 use libc::c_uint;
 
 #[cfg(not(target_os = "windows"))]
@@ -20,16 +20,29 @@ use std::{
     ffi::{CStr, CString},
     net::{IpAddr, SocketAddr},
     panic,
-    sync::{Mutex, Once},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex, Once, PoisonError, RwLock,
+    },
     time::Duration,
 };
 
 use self::types::*;
-use crate::device::{Device, DeviceConfig, Result as DevResult};
-use telio_model::{config::Config, event::*, mesh::ExitNode};
+#[cfg(unix)]
+use crate::control::ControlServer;
+use crate::device::{Device, DeviceConfig, Result as DevResult, DEFAULT_INTERFACE};
+use telio_model::{
+    api_config::{FeatureLogging, FeatureTraversal},
+    config::Config,
+    event::*,
+    mesh::ExitNode,
+};
 
 // debug tools
-use telio_utils::{telio_log_debug, telio_log_error, telio_log_trace, telio_log_warn};
+use telio_utils::{
+    logging::{format_json, format_text, LogFilter, RotatingFile},
+    redact, telio_log_debug, telio_log_error, telio_log_trace, telio_log_warn,
+};
 
 const DEFAULT_PANIC_MSG: &str = "libtelio panicked";
 
@@ -70,14 +83,59 @@ struct LogStatus {
     counter: u32,
 }
 
+/// Where and how log records are passed, shared by all instances.
+/// Every `telio_new` replaces it with its own `logging` features, or the defaults when absent.
+struct LogOutput {
+    /// Level passed to `telio_new`, used when filter has no default directive
+    level: RwLock<LevelFilter>,
+    filter: RwLock<LogFilter>,
+    json: AtomicBool,
+    file: Mutex<Option<RotatingFile>>,
+}
+
 lazy_static::lazy_static! {
     static ref LAST_LOG_STATUS: Mutex<LogStatus> = {
         Mutex::new(LogStatus{string: String::default(), counter: 0})
     };
+    static ref LOG_OUTPUT: LogOutput = LogOutput {
+        level: RwLock::new(LevelFilter::Info),
+        filter: RwLock::new(LogFilter::new(LevelFilter::Info)),
+        json: AtomicBool::new(false),
+        file: Mutex::new(None),
+    };
 }
 
 #[allow(non_camel_case_types)]
-pub struct telio(Mutex<Device>);
+pub struct telio {
+    device: Arc<Mutex<Device>>,
+    /// Started by `telio_start_control_server`, its connections use the device
+    #[cfg(unix)]
+    control: Mutex<Option<ControlServer>>,
+}
+
+impl telio {
+    fn new(device: Device) -> Self {
+        Self {
+            device: Arc::new(Mutex::new(device)),
+            #[cfg(unix)]
+            control: Mutex::new(None),
+        }
+    }
+
+    /// Stop control server, waiting for its connections to close
+    fn stop_control(&self) {
+        #[cfg(unix)]
+        {
+            // Dropping waits for the connections, which must not block other calls
+            let control = self
+                .control
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .take();
+            drop(control);
+        }
+    }
+}
 
 /// cbindgen:ignore
 static PANIC_HOOK: Once = Once::new();
@@ -157,12 +215,15 @@ fn telio_new_common(
     #[cfg(target_os = "android")] protect_cb: Option<telio_protect_cb>,
 ) -> telio_result {
     if let Err(err) = log::set_boxed_logger(Box::new(logger)) {
-        error!("{}", err)
+        telio_log_error!("{}", err)
     }
 
-    log::set_max_level(
-        (<types::telio_log_level as Into<Level>>::into(log_level) as Level).to_level_filter(),
-    );
+    let level =
+        (<types::telio_log_level as Into<Level>>::into(log_level) as Level).to_level_filter();
+    if let Ok(mut base) = LOG_OUTPUT.level.write() {
+        *base = level;
+    }
+    set_log_filter(LogFilter::new(level));
     let event_dispatcher = move |e: Box<Event>| {
         let _ = CString::new(
             e.to_json()
@@ -176,7 +237,7 @@ fn telio_new_common(
         let events = event_dispatcher;
         panic::set_hook(Box::new(move |info| {
             // We need it on the logs as well ...
-            error!("{}", info);
+            telio_log_error!("{}", info);
 
             let err = {
                 let message = {
@@ -218,6 +279,12 @@ fn telio_new_common(
         Default::default()
     };
 
+    // Log output is process-wide, reset whatever a previous instance configured
+    ffi_try!(configure_logging(
+        level,
+        &features.logging.clone().unwrap_or_default()
+    ));
+
     ffi_catch_panic!({
         // TODO: Update windows ffi to take in void*, for protect
         #[cfg(not(target_os = "android"))]
@@ -232,7 +299,7 @@ fn telio_new_common(
 
         let device = ffi_try!(Device::new(features, event_dispatcher, protect));
 
-        unsafe { *dev = Box::into_raw(Box::new(telio(Mutex::new(device)))) };
+        unsafe { *dev = Box::into_raw(Box::new(telio::new(device))) };
 
         TELIO_RES_OK
     })
@@ -242,7 +309,8 @@ fn telio_new_common(
 /// Completely stop and uninit telio lib.
 pub extern "C" fn telio_destroy(dev: *mut telio) {
     let dev = unsafe { Box::from_raw(dev) };
-    let mut dev = match dev.0.lock() {
+    dev.stop_control();
+    let mut dev = match dev.device.lock() {
         Ok(dev) => dev,
         Err(poisoned) => {
             telio_log_debug!("main telio lock has been poisoned");
@@ -257,7 +325,14 @@ pub extern "C" fn telio_destroy(dev: *mut telio) {
 /// Explicitly deallocate telio object and shutdown async rt.
 pub extern "C" fn telio_destroy_hard(dev: *mut telio) -> telio_result {
     let dev_b = unsafe { Box::from_raw(dev) };
-    let device = dev_b.0.into_inner().unwrap_or_else(|e| e.into_inner());
+    dev_b.stop_control();
+    let device = match Arc::try_unwrap(dev_b.device) {
+        Ok(device) => device.into_inner().unwrap_or_else(|e| e.into_inner()),
+        Err(_) => {
+            telio_log_debug!("telio_destroy_hard: device is still in use");
+            return TELIO_RES_ERROR;
+        }
+    };
 
     let res = device.try_shutdown(Duration::from_millis(1000));
 
@@ -276,6 +351,58 @@ pub extern "C" fn telio_get_default_adapter() -> telio_adapter_type {
     AdapterType::default().into()
 }
 
+#[no_mangle]
+/// Set callback receiving analytics events as JSON strings, used when `callback` sink
+/// is selected in `lana` features. Must be called before `telio_new`.
+///
+/// # Parameters
+/// - `analytics`: Analytics callback
+pub extern "C" fn telio_set_analytics_cb(analytics: telio_event_cb) -> telio_result {
+    let sink = CallbackSink::new(move |json: &str| {
+        let _ = CString::new(json)
+            .map(|s| unsafe { (analytics.cb)(analytics.ctx, s.as_ptr()) })
+            .map_err(|e| telio_log_warn!("Failed to create CString: {:?}", e));
+    });
+    telio_lana::set_app_sink(Some(std::sync::Arc::new(sink)));
+    TELIO_RES_OK
+}
+
+#[no_mangle]
+/// Change per module log levels at runtime, applies to all instances.
+///
+/// # Parameters
+/// - `filter`: Comma separated directives, e.g. `telio_relay=trace,telio_traversal=debug`.
+///             Directive without module sets the level for all other modules,
+///             otherwise the level passed to `telio_new` is used.
+pub extern "C" fn telio_set_log_filter(filter: *const c_char) -> telio_result {
+    ffi_catch_panic!({
+        if filter.is_null() {
+            return TELIO_RES_INVALID_STRING;
+        }
+        let filter = ffi_try!(unsafe { CStr::from_ptr(filter) }
+            .to_str()
+            .map_err(|_| TELIO_RES_INVALID_STRING));
+        let level = ffi_try!(LOG_OUTPUT.level.read().map_err(|_| TELIO_RES_LOCK_ERROR));
+        let filter = ffi_try!(LogFilter::parse(*level, filter).map_err(|e| {
+            telio_log_warn!("telio_set_log_filter: {}", e);
+            TELIO_RES_BAD_CONFIG
+        }));
+        set_log_filter(filter);
+        TELIO_RES_OK
+    })
+}
+
+#[no_mangle]
+/// Change redaction of keys, IPs and hostnames in log messages at runtime,
+/// applies to all instances.
+///
+/// # Parameters
+/// - `privacy`: Privacy level of subsequent log messages
+pub extern "C" fn telio_set_log_privacy(privacy: telio_log_privacy) -> telio_result {
+    redact::set_privacy(privacy.into());
+    TELIO_RES_OK
+}
+
 #[no_mangle]
 /// Start telio with specified adapter.
 ///
@@ -286,7 +413,7 @@ pub extern "C" fn telio_start(
     adapter: telio_adapter_type,
 ) -> telio_result {
     ffi_catch_panic!({
-        let mut dev = ffi_try!(dev.0.lock().map_err(|_| TELIO_RES_LOCK_ERROR));
+        let mut dev = ffi_try!(dev.device.lock().map_err(|_| TELIO_RES_LOCK_ERROR));
 
         let cstr = unsafe { CStr::from_ptr(private_key) };
         let private_key = ffi_try!(cstr.to_str().map_err(|_| TELIO_RES_INVALID_STRING));
@@ -296,6 +423,9 @@ pub extern "C" fn telio_start(
             adapter: adapter.into(),
             name: None,
             tun: None,
+            packet_io: None,
+            mtu: None,
+            ..Default::default()
         })
         .telio_log_result("telio_start")
     })
@@ -312,7 +442,7 @@ pub extern "C" fn telio_start_named(
     name: *const c_char,
 ) -> telio_result {
     ffi_catch_panic!({
-        let mut dev = ffi_try!(dev.0.lock().map_err(|_| TELIO_RES_LOCK_ERROR));
+        let mut dev = ffi_try!(dev.device.lock().map_err(|_| TELIO_RES_LOCK_ERROR));
 
         let cstr = unsafe { CStr::from_ptr(private_key) };
         let private_key = ffi_try!(cstr.to_str().map_err(|_| TELIO_RES_INVALID_STRING));
@@ -324,6 +454,9 @@ pub extern "C" fn telio_start_named(
             adapter: adapter.into(),
             name: Some(name),
             tun: None,
+            packet_io: None,
+            mtu: None,
+            ..Default::default()
         })
         .telio_log_result("telio_start_named")
     })
@@ -347,7 +480,7 @@ pub extern "C" fn telio_start_with_tun(
     tun: c_int,
 ) -> telio_result {
     ffi_catch_panic!({
-        let mut dev = ffi_try!(dev.0.lock().map_err(|_| TELIO_RES_LOCK_ERROR));
+        let mut dev = ffi_try!(dev.device.lock().map_err(|_| TELIO_RES_LOCK_ERROR));
         let cstr = unsafe { CStr::from_ptr(private_key) };
         let private_key = ffi_try!(cstr.to_str().map_err(|_| TELIO_RES_INVALID_STRING));
         let private_key = ffi_try!(private_key.parse().map_err(|_| TELIO_RES_INVALID_STRING));
@@ -356,6 +489,9 @@ pub extern "C" fn telio_start_with_tun(
             adapter: adapter.into(),
             name: None,
             tun: Some(tun),
+            packet_io: None,
+            mtu: None,
+            ..Default::default()
         })
         .telio_log_result("telio_start_with_tun")
     })
@@ -365,7 +501,7 @@ pub extern "C" fn telio_start_with_tun(
 /// Stop telio device.
 pub extern "C" fn telio_stop(dev: &telio) -> telio_result {
     ffi_catch_panic!({
-        let mut dev = match dev.0.lock() {
+        let mut dev = match dev.device.lock() {
             Ok(dev) => dev,
             Err(poisoned) => poisoned.into_inner(),
         };
@@ -374,10 +510,73 @@ pub extern "C" fn telio_stop(dev: &telio) -> telio_result {
     })
 }
 
+#[no_mangle]
+/// Start additional WireGuard interface next to the one started by `telio_start*`.
+///
+/// Interface has its own private key, fwmark, meshnet and exit node, and is addressed by
+/// returned handle in `telio_interface_*` calls. Default interface has handle 0.
+///
+/// # Parameters
+/// - `interface`: Handle of started interface is written here.
+/// - `private_key`: Base64 encoded WireGuard private key of the interface.
+/// - `adapter`: Adapter type.
+/// - `name`: Name of the tunnel, may be NULL to name it after the returned handle.
+/// - `mtu`: MTU of the tunnel, 0 to use the default one.
+///
+pub extern "C" fn telio_add_interface(
+    dev: &telio,
+    interface: *mut c_uint,
+    private_key: *const c_char,
+    adapter: telio_adapter_type,
+    name: *const c_char,
+    mtu: c_uint,
+) -> telio_result {
+    ffi_catch_panic!({
+        let mut dev = ffi_try!(dev.device.lock().map_err(|_| TELIO_RES_LOCK_ERROR));
+
+        if interface.is_null() {
+            telio_log_error!("Interface handle pointer is NULL");
+            return TELIO_RES_ERROR;
+        }
+
+        let cstr = unsafe { CStr::from_ptr(private_key) };
+        let private_key = ffi_try!(cstr.to_str().map_err(|_| TELIO_RES_INVALID_STRING));
+        let private_key = ffi_try!(private_key.parse().map_err(|_| TELIO_RES_INVALID_STRING));
+        let name = if !name.is_null() {
+            let cstr_name = unsafe { CStr::from_ptr(name) };
+            Some(ffi_try!(cstr_name.to_str().map_err(|_| TELIO_RES_INVALID_STRING)).to_owned())
+        } else {
+            None
+        };
+
+        let handle = ffi_try!(dev.add_interface(&DeviceConfig {
+            private_key,
+            adapter: adapter.into(),
+            name,
+            tun: None,
+            packet_io: None,
+            mtu: Some(mtu).filter(|mtu| *mtu > 0),
+            ..Default::default()
+        }));
+        unsafe { *interface = handle };
+        TELIO_RES_OK
+    })
+}
+
+#[no_mangle]
+/// Stop interface started by `telio_add_interface`.
+pub extern "C" fn telio_remove_interface(dev: &telio, interface: c_uint) -> telio_result {
+    ffi_catch_panic!({
+        let mut dev = ffi_try!(dev.device.lock().map_err(|_| TELIO_RES_LOCK_ERROR));
+        dev.remove_interface(interface)
+            .telio_log_result("telio_remove_interface")
+    })
+}
+
 #[no_mangle]
 /// get device luid.
 pub extern "C" fn telio_get_adapter_luid(dev: &telio) -> u64 {
-    match dev.0.lock() {
+    match dev.device.lock() {
         Ok(mut d) => d.get_adapter_luid(),
         Err(e) => {
             telio_log_error!("telio_get_adapter_luid() failed {:?}", e);
@@ -391,23 +590,43 @@ pub extern "C" fn telio_get_adapter_luid(dev: &telio) -> u64 {
 ///
 /// If private_key is not set, device will never connect.
 ///
+/// Key can be rotated on running device: meshnet peers, preshared keys, traversal
+/// and magic DNS records are kept, relay reconnects to the same server with the
+/// new key and peers handshake again once they learn it from meshnet config.
+///
 /// # Parameters
 /// - `private_key`: Base64 encoded WireGuard private key, must not be NULL.
 ///
 pub extern "C" fn telio_set_private_key(dev: &telio, private_key: *const c_char) -> telio_result {
+    telio_interface_set_private_key(dev, DEFAULT_INTERFACE, private_key)
+}
+
+#[no_mangle]
+/// Sets private key for interface started by `telio_add_interface`.
+pub extern "C" fn telio_interface_set_private_key(
+    dev: &telio,
+    interface: c_uint,
+    private_key: *const c_char,
+) -> telio_result {
     ffi_catch_panic!({
-        let dev = ffi_try!(dev.0.lock().map_err(|_| TELIO_RES_LOCK_ERROR));
+        let dev = ffi_try!(dev.device.lock().map_err(|_| TELIO_RES_LOCK_ERROR));
         let cstr = unsafe { CStr::from_ptr(private_key) };
         let private_key = ffi_try!(cstr.to_str().map_err(|_| TELIO_RES_INVALID_STRING));
         let private_key = ffi_try!(private_key.parse().map_err(|_| TELIO_RES_INVALID_STRING));
-        ffi_try!(dev.set_private_key(&private_key));
+        ffi_try!(dev.set_interface_private_key(interface, &private_key));
         TELIO_RES_OK
     })
 }
 
 #[no_mangle]
 pub extern "C" fn telio_get_private_key(dev: &telio) -> *mut c_char {
-    let dev = match dev.0.lock() {
+    telio_interface_get_private_key(dev, DEFAULT_INTERFACE)
+}
+
+#[no_mangle]
+/// Gets private key of interface started by `telio_add_interface`.
+pub extern "C" fn telio_interface_get_private_key(dev: &telio, interface: c_uint) -> *mut c_char {
+    let dev = match dev.device.lock() {
         Ok(dev) => dev,
         Err(err) => {
             telio_log_error!("telio_get_private_key: dev.get_private_key: {}", err);
@@ -415,7 +634,7 @@ pub extern "C" fn telio_get_private_key(dev: &telio) -> *mut c_char {
         }
     };
 
-    match dev.get_private_key() {
+    match dev.get_interface_private_key(interface) {
         Ok(key) => key_to_c_zero_terminated_string_unmanaged(&key.0),
         Err(err) => {
             telio_log_error!("telio_get_private_key: dev.get_private_key: {}", err);
@@ -432,9 +651,20 @@ pub extern "C" fn telio_get_private_key(dev: &telio) -> *mut c_char {
 /// - `fwmark`: unsigned 32-bit integer
 ///
 pub extern "C" fn telio_set_fwmark(dev: &telio, fwmark: c_uint) -> telio_result {
+    telio_interface_set_fwmark(dev, DEFAULT_INTERFACE, fwmark)
+}
+
+#[no_mangle]
+#[cfg(target_os = "linux")]
+/// Sets fwmark for interface started by `telio_add_interface`.
+pub extern "C" fn telio_interface_set_fwmark(
+    dev: &telio,
+    interface: c_uint,
+    fwmark: c_uint,
+) -> telio_result {
     ffi_catch_panic!({
-        let dev = ffi_try!(dev.0.lock().map_err(|_| TELIO_RES_LOCK_ERROR));
-        ffi_try!(dev.set_fwmark(fwmark));
+        let dev = ffi_try!(dev.device.lock().map_err(|_| TELIO_RES_LOCK_ERROR));
+        ffi_try!(dev.set_interface_fwmark(interface, fwmark));
         TELIO_RES_OK
     })
 }
@@ -452,12 +682,31 @@ pub extern "C" fn telio_notify_network_change(
     #![allow(unused_variables)]
 
     ffi_catch_panic!({
-        let dev = ffi_try!(dev.0.lock().map_err(|_| TELIO_RES_LOCK_ERROR));
+        let dev = ffi_try!(dev.device.lock().map_err(|_| TELIO_RES_LOCK_ERROR));
         dev.notify_network_change()
             .telio_log_result("telio_notify_network_change")
     })
 }
 
+#[no_mangle]
+/// Tune hole punching timers, without restarting meshnet.
+///
+/// # Parameters
+/// - `traversal`: JSON string of traversal features, same as `traversal` field of features
+///                passed to `telio_new`. Omitted timers are reset to their defaults.
+pub extern "C" fn telio_set_traversal(dev: &telio, traversal: *const c_char) -> telio_result {
+    ffi_catch_panic!({
+        let dev = ffi_try!(dev.device.lock().map_err(|_| TELIO_RES_LOCK_ERROR));
+
+        let traversal_str = ffi_try!(unsafe { CStr::from_ptr(traversal) }
+            .to_str()
+            .map_err(|_| TELIO_RES_INVALID_STRING));
+        let traversal: FeatureTraversal = ffi_try!(serde_json::from_str(traversal_str));
+        dev.set_traversal(traversal)
+            .telio_log_result("telio_set_traversal")
+    })
+}
+
 #[no_mangle]
 /// Connects to an exit node. (VPN if endpoint is not NULL, Peer if endpoint is NULL)
 ///
@@ -499,9 +748,23 @@ pub extern "C" fn telio_connect_to_exit_node(
     public_key: *const c_char,
     allowed_ips: *const c_char,
     endpoint: *const c_char,
+) -> telio_result {
+    telio_interface_connect_to_exit_node(dev, DEFAULT_INTERFACE, public_key, allowed_ips, endpoint)
+}
+
+#[no_mangle]
+/// Connects interface started by `telio_add_interface` to exit node.
+///
+/// Parameters are the same as of `telio_connect_to_exit_node`.
+pub extern "C" fn telio_interface_connect_to_exit_node(
+    dev: &telio,
+    interface: c_uint,
+    public_key: *const c_char,
+    allowed_ips: *const c_char,
+    endpoint: *const c_char,
 ) -> telio_result {
     ffi_catch_panic!({
-        let dev = ffi_try!(dev.0.lock().map_err(|_| TELIO_RES_LOCK_ERROR));
+        let dev = ffi_try!(dev.device.lock().map_err(|_| TELIO_RES_LOCK_ERROR));
         let public_key = if !public_key.is_null() {
             let cstr = ffi_try!(unsafe { CStr::from_ptr(public_key) }
                 .to_str()
@@ -547,7 +810,7 @@ pub extern "C" fn telio_connect_to_exit_node(
             allowed_ips,
             endpoint,
         };
-        dev.connect_exit_node(&node)
+        dev.connect_interface_exit_node(interface, &node)
             .telio_log_result("telio_connect_to_exit_node")
     })
 }
@@ -574,7 +837,7 @@ pub extern "C" fn telio_enable_magic_dns(
     forward_servers: *const c_char,
 ) -> telio_result {
     ffi_catch_panic!({
-        let dev = ffi_try!(dev.0.lock().map_err(|_| TELIO_RES_BAD_CONFIG));
+        let dev = ffi_try!(dev.device.lock().map_err(|_| TELIO_RES_BAD_CONFIG));
         let servers_str = ffi_try!(unsafe { CStr::from_ptr(forward_servers) }
             .to_str()
             .map_err(|_| TELIO_RES_INVALID_STRING));
@@ -588,7 +851,7 @@ pub extern "C" fn telio_enable_magic_dns(
 /// Disables magic DNS if it was enabled.
 pub extern "C" fn telio_disable_magic_dns(dev: &telio) -> telio_result {
     ffi_catch_panic!({
-        let dev = ffi_try!(dev.0.lock().map_err(|_| TELIO_RES_BAD_CONFIG));
+        let dev = ffi_try!(dev.device.lock().map_err(|_| TELIO_RES_BAD_CONFIG));
 
         dev.disable_magic_dns()
             .telio_log_result("telio_disable_magic_dns")
@@ -604,9 +867,19 @@ pub extern "C" fn telio_disable_magic_dns(dev: &telio) -> telio_result {
 pub extern "C" fn telio_disconnect_from_exit_node(
     dev: &telio,
     public_key: *const c_char,
+) -> telio_result {
+    telio_interface_disconnect_from_exit_node(dev, DEFAULT_INTERFACE, public_key)
+}
+
+#[no_mangle]
+/// Disconnects interface started by `telio_add_interface` from specified exit node.
+pub extern "C" fn telio_interface_disconnect_from_exit_node(
+    dev: &telio,
+    interface: c_uint,
+    public_key: *const c_char,
 ) -> telio_result {
     ffi_catch_panic!({
-        let dev = ffi_try!(dev.0.lock().map_err(|_| TELIO_RES_LOCK_ERROR));
+        let dev = ffi_try!(dev.device.lock().map_err(|_| TELIO_RES_LOCK_ERROR));
         let public_key = if !public_key.is_null() {
             let cstr = ffi_try!(unsafe { CStr::from_ptr(public_key) }
                 .to_str()
@@ -617,7 +890,7 @@ pub extern "C" fn telio_disconnect_from_exit_node(
             return TELIO_RES_ERROR;
         };
 
-        dev.disconnect_exit_node(&public_key)
+        dev.disconnect_interface_exit_node(interface, &public_key)
             .telio_log_result("telio_disconnect_from_exit_node")
     })
 }
@@ -625,10 +898,19 @@ pub extern "C" fn telio_disconnect_from_exit_node(
 #[no_mangle]
 /// Disconnects from all exit nodes with no parameters required.
 pub extern "C" fn telio_disconnect_from_exit_nodes(dev: &telio) -> telio_result {
+    telio_interface_disconnect_from_exit_nodes(dev, DEFAULT_INTERFACE)
+}
+
+#[no_mangle]
+/// Disconnects interface started by `telio_add_interface` from all exit nodes.
+pub extern "C" fn telio_interface_disconnect_from_exit_nodes(
+    dev: &telio,
+    interface: c_uint,
+) -> telio_result {
     ffi_catch_panic!({
-        let dev = ffi_try!(dev.0.lock().map_err(|_| TELIO_RES_LOCK_ERROR));
+        let dev = ffi_try!(dev.device.lock().map_err(|_| TELIO_RES_LOCK_ERROR));
 
-        dev.disconnect_exit_nodes()
+        dev.disconnect_interface_exit_nodes(interface)
             .telio_log_result("telio_disconnect_from_exit_nodes")
     })
 }
@@ -641,18 +923,29 @@ pub extern "C" fn telio_disconnect_from_exit_nodes(dev: &telio) -> telio_result
 /// - `cfg`: Output of GET /v1/meshnet/machines/{machineIdentifier}/map
 ///
 pub extern "C" fn telio_set_meshnet(dev: &telio, cfg: *const c_char) -> telio_result {
+    telio_interface_set_meshnet(dev, DEFAULT_INTERFACE, cfg)
+}
+
+#[no_mangle]
+/// Sets meshnet config of interface started by `telio_add_interface`, NULL disables it.
+pub extern "C" fn telio_interface_set_meshnet(
+    dev: &telio,
+    interface: c_uint,
+    cfg: *const c_char,
+) -> telio_result {
     ffi_catch_panic!({
-        let dev = ffi_try!(dev.0.lock().map_err(|_| TELIO_RES_LOCK_ERROR));
+        let dev = ffi_try!(dev.device.lock().map_err(|_| TELIO_RES_LOCK_ERROR));
 
         if cfg.is_null() {
             telio_log_debug!("Stopping meshnet due to empty config");
-            dev.set_config(&None).telio_log_result("telio_set_meshnet")
+            dev.set_interface_config(interface, &None)
+                .telio_log_result("telio_set_meshnet")
         } else {
             let cfg_str = ffi_try!(unsafe { CStr::from_ptr(cfg) }
                 .to_str()
                 .map_err(|_| TELIO_RES_INVALID_STRING));
             let cfg: Config = ffi_try!(serde_json::from_str(cfg_str));
-            dev.set_config(&Some(cfg))
+            dev.set_interface_config(interface, &Some(cfg))
                 .telio_log_result("telio_set_meshnet")
         }
     })
@@ -662,7 +955,7 @@ pub extern "C" fn telio_set_meshnet(dev: &telio, cfg: *const c_char) -> telio_re
 /// Disables the meshnet functionality by closing all the connections.
 pub extern "C" fn telio_set_meshnet_off(dev: &telio) -> telio_result {
     ffi_catch_panic!({
-        let dev = ffi_try!(dev.0.lock().map_err(|_| TELIO_RES_LOCK_ERROR));
+        let dev = ffi_try!(dev.device.lock().map_err(|_| TELIO_RES_LOCK_ERROR));
 
         dev.set_config(&None)
             .telio_log_result("telio_set_meshnet_off")
@@ -717,35 +1010,246 @@ pub extern "C" fn telio_get_commit_sha() -> *mut c_char {
 }
 
 #[no_mangle]
+/// Get nodes of all interfaces as JSON array. Nodes of interfaces started by
+/// `telio_add_interface` carry handle of their interface in `interface` field.
 pub extern "C" fn telio_get_status_map(dev: &telio) -> *mut c_char {
-    log::trace!("acquiring dev lock");
-    let dev = match dev.0.lock() {
+    telio_log_trace!("acquiring dev lock");
+    let dev = match dev.device.lock() {
         Ok(dev) => dev,
         Err(err) => {
-            log::error!("telio_get_status_map: dev lock: {}", err);
+            telio_log_error!("telio_get_status_map: dev lock: {}", err);
             return std::ptr::null_mut();
         }
     };
-    log::trace!("retrieving external nodes");
+    telio_log_trace!("retrieving external nodes");
     let nodes = match dev.external_nodes() {
         Ok(nodes) => nodes,
         Err(err) => {
-            log::error!("telio_get_status_map: external_nodes: {}", err);
+            telio_log_error!("telio_get_status_map: external_nodes: {}", err);
             return std::ptr::null_mut();
         }
     };
-    log::trace!("serializing");
+    telio_log_trace!("serializing");
     let json = match serde_json::to_string(&nodes) {
         Ok(json) => json,
         Err(err) => {
-            log::error!("telio_get_status_map: to_string: {}", err);
+            telio_log_error!("telio_get_status_map: to_string: {}", err);
             return std::ptr::null_mut();
         }
     };
-    log::trace!("converting to char pointer");
+    telio_log_trace!("converting to char pointer");
     bytes_to_zero_terminated_unmanaged_bytes(json.as_bytes())
 }
 
+#[no_mangle]
+/// Get connectivity diagnostics of peer's direct path, for attaching to support tickets.
+///
+/// # Parameters
+/// - `public_key`: Base64 encoded WireGuard public key of the peer.
+///
+/// Returns JSON report or NULL on failure, including NULL or invalid `public_key`.
+pub extern "C" fn telio_get_peer_diagnostics(
+    dev: &telio,
+    public_key: *const c_char,
+) -> *mut c_char {
+    let dev = match dev.device.lock() {
+        Ok(dev) => dev,
+        Err(err) => {
+            telio_log_error!("telio_get_peer_diagnostics: dev lock: {}", err);
+            return std::ptr::null_mut();
+        }
+    };
+
+    if public_key.is_null() {
+        telio_log_error!("telio_get_peer_diagnostics: public key is NULL");
+        return std::ptr::null_mut();
+    }
+
+    let cstr = unsafe { CStr::from_ptr(public_key) };
+    let public_key = match cstr.to_str().ok().and_then(|key| key.parse().ok()) {
+        Some(key) => key,
+        None => {
+            telio_log_error!("telio_get_peer_diagnostics: invalid public key");
+            return std::ptr::null_mut();
+        }
+    };
+
+    match dev.get_peer_diagnostics(&public_key) {
+        Ok(json) => bytes_to_zero_terminated_unmanaged_bytes(json.as_bytes()),
+        Err(err) => {
+            telio_log_error!("telio_get_peer_diagnostics: {}", err);
+            std::ptr::null_mut()
+        }
+    }
+}
+
+#[no_mangle]
+/// Get traffic statistics of peers: total bytes, rates over 10s, 60s and 300s windows,
+/// handshake count and seconds since the last handshake.
+///
+/// Returns JSON array or NULL on failure.
+pub extern "C" fn telio_get_peer_stats(dev: &telio) -> *mut c_char {
+    telio_get_interface_peer_stats(dev, DEFAULT_INTERFACE)
+}
+
+#[no_mangle]
+/// Get traffic statistics of peers of interface started by `telio_add_interface`.
+pub extern "C" fn telio_get_interface_peer_stats(dev: &telio, interface: c_uint) -> *mut c_char {
+    let dev = match dev.device.lock() {
+        Ok(dev) => dev,
+        Err(err) => {
+            telio_log_error!("telio_get_peer_stats: dev lock: {}", err);
+            return std::ptr::null_mut();
+        }
+    };
+
+    match dev.get_interface_peer_stats(interface) {
+        Ok(json) => bytes_to_zero_terminated_unmanaged_bytes(json.as_bytes()),
+        Err(err) => {
+            telio_log_error!("telio_get_peer_stats: {}", err);
+            std::ptr::null_mut()
+        }
+    }
+}
+
+#[no_mangle]
+/// Get metrics of peers, relay and nurse in OpenMetrics text format, for Prometheus scraping.
+/// Samples of peers are labeled by their public key and handle of their interface.
+///
+/// Returns exposition text or NULL on failure.
+pub extern "C" fn telio_get_metrics(dev: &telio) -> *mut c_char {
+    let dev = match dev.device.lock() {
+        Ok(dev) => dev,
+        Err(err) => {
+            telio_log_error!("telio_get_metrics: dev lock: {}", err);
+            return std::ptr::null_mut();
+        }
+    };
+
+    match dev.get_metrics() {
+        Ok(text) => bytes_to_zero_terminated_unmanaged_bytes(text.as_bytes()),
+        Err(err) => {
+            telio_log_error!("telio_get_metrics: {}", err);
+            std::ptr::null_mut()
+        }
+    }
+}
+
+#[no_mangle]
+/// Get meshnet connectivity matrix, aggregated by nurse during its last heartbeat collection.
+/// Lists internal nodes of the meshnet and the state of the link between each pair of them.
+///
+/// Returns JSON string, `null` if nurse has not collected the matrix yet, or NULL on failure.
+pub extern "C" fn telio_get_connectivity_matrix(dev: &telio) -> *mut c_char {
+    let dev = match dev.device.lock() {
+        Ok(dev) => dev,
+        Err(err) => {
+            telio_log_error!("telio_get_connectivity_matrix: dev lock: {}", err);
+            return std::ptr::null_mut();
+        }
+    };
+
+    match dev.get_connectivity_matrix() {
+        Ok(json) => bytes_to_zero_terminated_unmanaged_bytes(json.as_bytes()),
+        Err(err) => {
+            telio_log_error!("telio_get_connectivity_matrix: {}", err);
+            std::ptr::null_mut()
+        }
+    }
+}
+
+#[no_mangle]
+/// Get events and state transitions recorded in the journal, enabled by `journal` feature.
+///
+/// # Parameters
+/// - `since`: Only entries recorded at or after this time, in milliseconds since UNIX epoch.
+/// - `filter`: Comma separated kinds of entries (`relay`, `node`, `error`, `state`), NULL or empty for all.
+///
+/// Returns JSON array of entries or NULL on failure.
+pub extern "C" fn telio_get_event_history(
+    dev: &telio,
+    since: u64,
+    filter: *const c_char,
+) -> *mut c_char {
+    let dev = match dev.device.lock() {
+        Ok(dev) => dev,
+        Err(err) => {
+            telio_log_error!("telio_get_event_history: dev lock: {}", err);
+            return std::ptr::null_mut();
+        }
+    };
+
+    let filter = if filter.is_null() {
+        ""
+    } else {
+        match unsafe { CStr::from_ptr(filter) }.to_str() {
+            Ok(filter) => filter,
+            Err(_) => {
+                telio_log_error!("telio_get_event_history: invalid filter");
+                return std::ptr::null_mut();
+            }
+        }
+    };
+    let kinds: Vec<&str> = filter
+        .split(',')
+        .map(str::trim)
+        .filter(|kind| !kind.is_empty())
+        .collect();
+
+    match dev.get_event_history(since, &kinds) {
+        Ok(json) => bytes_to_zero_terminated_unmanaged_bytes(json.as_bytes()),
+        Err(err) => {
+            telio_log_error!("telio_get_event_history: {}", err);
+            std::ptr::null_mut()
+        }
+    }
+}
+
+#[no_mangle]
+/// Start JSON-RPC control server on a Unix domain socket, so the device can be controlled
+/// from other processes, e.g. by `tcli --control <path>`. Socket is accessible only to the
+/// owner of the process. Replaces server started earlier, stopped by `telio_destroy`.
+/// Not supported on Windows.
+///
+/// # Parameters
+/// - `path`: Path of the socket, socket left at the path by a previous run is replaced.
+pub extern "C" fn telio_start_control_server(dev: &telio, path: *const c_char) -> telio_result {
+    ffi_catch_panic!({
+        if path.is_null() {
+            return TELIO_RES_INVALID_STRING;
+        }
+        let path = ffi_try!(unsafe { CStr::from_ptr(path) }
+            .to_str()
+            .map_err(|_| TELIO_RES_INVALID_STRING));
+
+        #[cfg(unix)]
+        {
+            // Old server must release the socket first, when it is at the same path
+            dev.stop_control();
+            let server = ffi_try!(ControlServer::start(path, dev.device.clone()).map_err(|e| {
+                telio_log_warn!("Failed to start control server at {}: {}", path, e);
+                TELIO_RES_ERROR
+            }));
+            *ffi_try!(dev.control.lock().map_err(|_| TELIO_RES_LOCK_ERROR)) = Some(server);
+            TELIO_RES_OK
+        }
+        #[cfg(not(unix))]
+        {
+            let _ = (dev, path);
+            TELIO_RES_ERROR
+        }
+    })
+}
+
+#[no_mangle]
+/// Stop control server started by `telio_start_control_server`, closing its connections.
+pub extern "C" fn telio_stop_control_server(dev: &telio) -> telio_result {
+    ffi_catch_panic!({
+        dev.stop_control();
+        TELIO_RES_OK
+    })
+}
+
 #[no_mangle]
 /// Get last error's message length, including trailing null
 pub extern "C" fn telio_get_last_error(_dev: &telio) -> *mut c_char {
@@ -760,7 +1264,7 @@ pub extern "C" fn telio_get_last_error(_dev: &telio) -> *mut c_char {
 /// For testing only.
 pub extern "C" fn __telio_generate_stack_panic(dev: &telio) -> telio_result {
     ffi_catch_panic!({
-        let dev = ffi_try!(dev.0.lock().map_err(|_| TELIO_RES_LOCK_ERROR));
+        let dev = ffi_try!(dev.device.lock().map_err(|_| TELIO_RES_LOCK_ERROR));
 
         if dev.is_running() {
             panic!("runtime_panic_test_call_stack");
@@ -775,7 +1279,7 @@ pub extern "C" fn __telio_generate_stack_panic(dev: &telio) -> telio_result {
 /// For testing only.
 pub extern "C" fn __telio_generate_thread_panic(dev: &telio) -> telio_result {
     ffi_catch_panic!({
-        let dev = ffi_try!(dev.0.lock().map_err(|_| TELIO_RES_LOCK_ERROR));
+        let dev = ffi_try!(dev.device.lock().map_err(|_| TELIO_RES_LOCK_ERROR));
 
         if dev.is_running() {
             let res = dev._panic();
@@ -818,9 +1322,43 @@ fn filter_log_message(msg: String) -> Option<String> {
     None
 }
 
+fn set_log_filter(filter: LogFilter) {
+    log::set_max_level(filter.max_level());
+    if let Ok(mut current) = LOG_OUTPUT.filter.write() {
+        *current = filter;
+    }
+}
+
+fn configure_logging(level: LevelFilter, logging: &FeatureLogging) -> Result<(), telio_result> {
+    if let Some(filter) = &logging.filter {
+        set_log_filter(LogFilter::parse(level, filter).map_err(|e| {
+            telio_log_warn!("Invalid log filter: {}", e);
+            TELIO_RES_BAD_CONFIG
+        })?);
+    }
+
+    LOG_OUTPUT.json.store(logging.json, Ordering::Relaxed);
+    redact::set_privacy(telio_log_privacy::from(logging.privacy).into());
+
+    let file = match &logging.file {
+        Some(file) => Some(
+            RotatingFile::open(&file.path, file.max_size, file.max_files).map_err(|e| {
+                telio_log_warn!("Failed to open log file {}: {}", file.path, e);
+                TELIO_RES_BAD_CONFIG
+            })?,
+        ),
+        None => None,
+    };
+    *LOG_OUTPUT.file.lock().map_err(|_| TELIO_RES_LOCK_ERROR)? = file;
+
+    Ok(())
+}
+
 impl log::Log for telio_logger_cb {
     fn enabled(&self, metadata: &Metadata) -> bool {
-        metadata.level() <= log::max_level()
+        LOG_OUTPUT.filter.read().map_or(false, |filter| {
+            filter.enabled(metadata.target(), metadata.level())
+        })
     }
 
     fn log(&self, record: &Record) {
@@ -828,13 +1366,22 @@ impl log::Log for telio_logger_cb {
             return;
         }
 
-        if let Some(filtered_msg) = filter_log_message(format!(
-            "{:#?}:{:#?} {}",
-            record.file().unwrap_or("unknown file"),
-            record.line().unwrap_or(0),
-            record.args()
-        )) {
-            if let Ok(cstr) = CString::new(filtered_msg) {
+        // Repeated messages are suppressed by their text form, as JSON lines carry timestamp
+        if let Some(filtered_msg) = filter_log_message(format_text(record)) {
+            let msg = if LOG_OUTPUT.json.load(Ordering::Relaxed) {
+                format_json(record)
+            } else {
+                filtered_msg
+            };
+
+            if let Ok(mut file) = LOG_OUTPUT.file.lock() {
+                if let Some(file) = file.as_mut() {
+                    // Failure cannot be logged, it would come back here
+                    let _ = file.write_line(&msg);
+                }
+            }
+
+            if let Ok(cstr) = CString::new(msg) {
                 unsafe { (self.cb)(self.ctx, record.level().into(), cstr.as_ptr()) };
             }
         }
@@ -872,3 +1419,146 @@ fn bytes_to_zero_terminated_unmanaged_bytes(bytes: &[u8]) -> *mut c_char {
     buf[bytes.len()] = 0; //set last byte to 0 for null terminated C string
     buf.as_ptr() as *mut c_char
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use telio_model::api_config::{FeatureJournal, Features};
+
+    #[test]
+    fn test_peer_diagnostics_reject_invalid_key() {
+        let dev = telio::new(Device::new_without_callback(Features::default(), None).unwrap());
+
+        assert!(telio_get_peer_diagnostics(&dev, std::ptr::null()).is_null());
+
+        let key = CString::new("not a key").unwrap();
+        assert!(telio_get_peer_diagnostics(&dev, key.as_ptr()).is_null());
+
+        // Valid key, but device is not started
+        let key = CString::new(crate::crypto::SecretKey::gen().public().to_string()).unwrap();
+        assert!(telio_get_peer_diagnostics(&dev, key.as_ptr()).is_null());
+    }
+
+    fn take_string(ptr: *mut c_char) -> String {
+        let string = unsafe { CStr::from_ptr(ptr) }.to_str().unwrap().to_owned();
+        unsafe { libc::free(ptr as *mut libc::c_void) };
+        string
+    }
+
+    #[test]
+    fn test_unnamed_interfaces_next_to_default_one() {
+        let dev = telio::new(Device::new_without_callback(Features::default(), None).unwrap());
+        let keys: Vec<_> = (0..3).map(|_| crate::crypto::SecretKey::gen()).collect();
+        let c_keys: Vec<_> = keys
+            .iter()
+            .map(|key| CString::new(key.to_string()).unwrap())
+            .collect();
+
+        assert!(matches!(
+            telio_start(&dev, c_keys[0].as_ptr(), TELIO_ADAPTER_BORING_TUN),
+            TELIO_RES_OK
+        ));
+
+        // Unnamed interfaces do not collide with the default one, nor with each other
+        let mut handles = [0; 2];
+        for (handle, key) in handles.iter_mut().zip(&c_keys[1..]) {
+            assert!(matches!(
+                telio_add_interface(
+                    &dev,
+                    handle,
+                    key.as_ptr(),
+                    TELIO_ADAPTER_BORING_TUN,
+                    std::ptr::null(),
+                    1280,
+                ),
+                TELIO_RES_OK
+            ));
+        }
+        assert_eq!(handles, [1, 2]);
+
+        for (handle, key) in [DEFAULT_INTERFACE, 1, 2].iter().zip(&keys) {
+            assert_eq!(
+                take_string(telio_interface_get_private_key(&dev, *handle)),
+                key.to_string()
+            );
+        }
+
+        let exit_node = CString::new(crate::crypto::SecretKey::gen().public().to_string()).unwrap();
+        assert!(matches!(
+            telio_interface_disconnect_from_exit_node(&dev, 1, exit_node.as_ptr()),
+            TELIO_RES_OK
+        ));
+        assert!(matches!(
+            telio_interface_disconnect_from_exit_node(&dev, 3, exit_node.as_ptr()),
+            TELIO_RES_ERROR
+        ));
+
+        assert!(matches!(telio_remove_interface(&dev, 1), TELIO_RES_OK));
+        assert!(take_string(telio_interface_get_private_key(&dev, 1)).is_empty());
+        assert!(matches!(telio_stop(&dev), TELIO_RES_OK));
+    }
+
+    #[test]
+    fn test_event_history_is_filtered() {
+        let path = std::env::temp_dir().join(format!("ffi-{}.journal", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let features = Features {
+            journal: Some(FeatureJournal {
+                path: path.to_string_lossy().into_owned(),
+                max_entries: 100,
+            }),
+            ..Default::default()
+        };
+        let dev = telio::new(Device::new_without_callback(features, None).unwrap());
+
+        let history = |since: u64, filter: Option<&str>| {
+            let filter = filter.map(|filter| CString::new(filter).unwrap());
+            let filter = filter.as_ref().map_or(std::ptr::null(), |f| f.as_ptr());
+            let json = take_string(telio_get_event_history(&dev, since, filter));
+            serde_json::from_str::<Vec<serde_json::Value>>(&json).unwrap()
+        };
+
+        // Only opening of the journal is recorded so far
+        assert_eq!(history(0, None).len(), 1);
+        assert_eq!(history(0, Some("")).len(), 1);
+        assert_eq!(history(0, Some("relay, state"))[0]["kind"], "state");
+        assert!(history(0, Some("node,error")).is_empty());
+        assert!(history(u64::MAX, None).is_empty());
+
+        drop(dev);
+        let _ = std::fs::remove_file(&path);
+
+        // Journal is not enabled
+        let dev = telio::new(Device::new_without_callback(Features::default(), None).unwrap());
+        assert!(telio_get_event_history(&dev, 0, std::ptr::null()).is_null());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_control_server_is_started_and_stopped() {
+        let path = std::env::temp_dir().join(format!("telio-ffi-{}.sock", std::process::id()));
+        let c_path = CString::new(path.to_str().unwrap()).unwrap();
+        let dev = telio::new(Device::new_without_callback(Features::default(), None).unwrap());
+
+        assert!(matches!(
+            telio_start_control_server(&dev, std::ptr::null()),
+            TELIO_RES_INVALID_STRING
+        ));
+        assert!(matches!(
+            telio_start_control_server(&dev, c_path.as_ptr()),
+            TELIO_RES_OK
+        ));
+        // Starting again replaces the server on the same socket
+        assert!(matches!(
+            telio_start_control_server(&dev, c_path.as_ptr()),
+            TELIO_RES_OK
+        ));
+        let mut client = crate::control::Client::connect(&path).unwrap();
+        client.subscribe(&[], &[]).unwrap();
+
+        // Open connection does not keep the server from stopping
+        assert!(matches!(telio_stop_control_server(&dev), TELIO_RES_OK));
+        assert!(!path.exists());
+        assert!(client.get_version().is_err());
+    }
+}
@@ -1,6 +1,8 @@
 use libc::c_char;
 use log::Level;
 use telio_crypto::KeyDecodeError;
+use telio_model::api_config::LogPrivacy;
+use telio_utils::redact::PrivacyLevel;
 
 use std::ffi::c_void;
 
@@ -49,6 +51,22 @@ pub enum telio_adapter_type {
     TELIO_ADAPTER_WIREGUARD_GO_TUN,
     /// WindowsNativeWireguardNt implementation
     TELIO_ADAPTER_WINDOWS_NATIVE_TUN,
+    /// Userland rust implementation, without kernel TUN device when used with packet I/O
+    TELIO_ADAPTER_USERSPACE_WG,
+}
+
+#[derive(Clone, Copy, Debug)]
+#[allow(non_camel_case_types)]
+#[allow(clippy::upper_case_acronyms)]
+#[repr(C)]
+/// Redaction of sensitive data in log messages.
+pub enum telio_log_privacy {
+    /// Keys, IPs and hostnames are logged as they are.
+    TELIO_LOG_PRIVACY_OFF,
+    /// Keys and hostnames are hashed, IPs truncated to their network part.
+    TELIO_LOG_PRIVACY_STANDARD,
+    /// Keys, IPs and hostnames are hashed.
+    TELIO_LOG_PRIVACY_STRICT,
 }
 
 #[allow(non_camel_case_types)]
@@ -116,6 +134,7 @@ pub struct telio_protect_cb {
 pub extern "C" fn __telio_force_export(
     _: telio_result,
     _: telio_adapter_type,
+    _: telio_log_privacy,
     _: telio_event_cb,
     _: telio_logger_cb,
     #[cfg(target_os = "android")] _: telio_protect_cb,
@@ -164,6 +183,9 @@ impl From<DevError> for telio_result {
         // TODO: Map more error types.
         match _err {
             DevError::AlreadyStarted => TELIO_RES_ALREADY_STARTED,
+            DevError::BadTraversalFeatures(_) | DevError::BadPathsFeatures(_) => {
+                TELIO_RES_BAD_CONFIG
+            }
             _ => TELIO_RES_ERROR,
         }
     }
@@ -174,6 +196,9 @@ impl From<&DevError> for telio_result {
         // TODO: Map more error types.
         match _err {
             DevError::AlreadyStarted => TELIO_RES_ALREADY_STARTED,
+            DevError::BadTraversalFeatures(_) | DevError::BadPathsFeatures(_) => {
+                TELIO_RES_BAD_CONFIG
+            }
             _ => TELIO_RES_ERROR,
         }
     }
@@ -233,7 +258,8 @@ map_enum! {
     BoringTun = TELIO_ADAPTER_BORING_TUN,
     WireguardGo = TELIO_ADAPTER_WIREGUARD_GO_TUN,
     LinuxNativeWg = TELIO_ADAPTER_LINUX_NATIVE_TUN,
-    WindowsNativeWg = TELIO_ADAPTER_WINDOWS_NATIVE_TUN
+    WindowsNativeWg = TELIO_ADAPTER_WINDOWS_NATIVE_TUN,
+    UserspaceWg = TELIO_ADAPTER_USERSPACE_WG
 }
 
 // Deprecated slog crate had 6 levels
@@ -258,6 +284,20 @@ map_enum! {
     TELIO_LOG_TRACE = Trace,
 }
 
+map_enum! {
+    PrivacyLevel <=> telio_log_privacy,
+    Off = TELIO_LOG_PRIVACY_OFF,
+    Standard = TELIO_LOG_PRIVACY_STANDARD,
+    Strict = TELIO_LOG_PRIVACY_STRICT,
+}
+
+map_enum! {
+    LogPrivacy -> telio_log_privacy,
+    Off = TELIO_LOG_PRIVACY_OFF,
+    Standard = TELIO_LOG_PRIVACY_STANDARD,
+    Strict = TELIO_LOG_PRIVACY_STRICT,
+}
+
 unsafe impl Sync for telio_event_cb {}
 unsafe impl Send for telio_event_cb {}
 
@@ -5,6 +5,10 @@ mod ffi;
 /// cbindgen:ignore
 pub mod device;
 
+/// cbindgen:ignore
+#[cfg(unix)]
+pub mod control;
+
 /// cbindgen:ignore
 pub use telio_crypto as crypto;
 